@@ -43,6 +43,10 @@ struct Opts {
     #[argh(switch, short = 'f')]
     overwrite: bool,
 
+    /// render in segments and resume interrupted renders from the last completed segment, default: false
+    #[argh(switch, short = 'r')]
+    resume: bool,
+
     /// number of parallel renders, default: 1
     #[argh(option, short = 'j', default = "1")]
     parallel_renders: i32,
@@ -168,6 +172,9 @@ pub fn run(open_file: &mut String) -> bool {
             outp = outp.replace('\'', "\"");
             gyroflow_core::util::merge_json(additional_data.get_mut("output").unwrap(), &serde_json::from_str(&outp).expect("Invalid json"));
         }
+        if opts.resume {
+            additional_data["output"]["resumable"] = serde_json::Value::Bool(true);
+        }
 
         queue.set_parallel_renders(opts.parallel_renders.max(1));
         queue.set_when_done(opts.when_done);
@@ -487,6 +494,8 @@ fn setup_defaults(stab: Arc<StabilizationManager>, queue: &mut RenderQueue) -> s
             "export_trims_separately":settings.get("exportTrimsSeparately").unwrap_or(&"false".into()).parse::<bool>().unwrap(),
             "audio_codec":           audio_codecs.get(settings.get("audioCodec").unwrap_or(&"0".into()).parse::<usize>().unwrap()).unwrap_or(&"AAC"),
             "interpolation":         interpolations.get(settings.get("interpolationMethod").unwrap_or(&"2".into()).parse::<usize>().unwrap()).unwrap_or(&"Lanczos4"),
+            "parallel_segments":     settings.get("parallelSegments").and_then(|x| x.parse::<f64>().ok()).unwrap_or(1.0) as usize,
+            "resumable":             settings.get("resumableRenders").unwrap_or(&"false".into()).parse::<bool>().unwrap_or_default(),
         },
         "synchronization": {
             "initial_offset":     0,
@@ -14,7 +14,6 @@ use qml_video_rs::video_item::MDKVideoItem;
 
 use crate::core;
 use crate::core::StabilizationManager;
-#[cfg(feature = "opencv")]
 use crate::core::calibration::LensCalibrator;
 use crate::core::synchronization::AutosyncProcess;
 use crate::core::stabilization::KernelParamsFlags;
@@ -54,6 +53,7 @@ pub struct Controller {
     video_file_loaded: qt_method!(fn(&self, player: QJSValue)),
     load_telemetry: qt_method!(fn(&self, url: QUrl, is_video: bool, player: QJSValue, sample_index: i32)),
     load_lens_profile: qt_method!(fn(&mut self, url_or_id: QString)),
+    self_calibrate_lens: qt_method!(fn(&self)),
     export_lens_profile: qt_method!(fn(&mut self, url: QUrl, info: QJsonObject, upload: bool)),
     export_lens_profile_filename: qt_method!(fn(&mut self, info: QJsonObject) -> QString),
 
@@ -127,6 +127,13 @@ pub struct Controller {
     zooming_method: qt_property!(i32; WRITE set_zooming_method),
 
     lens_correction_amount: qt_property!(f64; WRITE set_lens_correction_amount),
+    output_projection: qt_property!(i32; WRITE set_output_projection),
+    spherical: qt_property!(bool; WRITE set_spherical),
+    motion_blur_shutter_angle: qt_property!(f64; WRITE set_motion_blur_shutter_angle),
+    motion_blur_samples: qt_property!(u32; WRITE set_motion_blur_samples),
+    deblur: qt_property!(bool; WRITE set_deblur),
+    deblur_iterations: qt_property!(u32; WRITE set_deblur_iterations),
+    deblur_exposure_ms: qt_property!(f64; WRITE set_deblur_exposure_ms),
     set_video_speed: qt_method!(fn(&self, v: f64, s: bool, z: bool)),
 
     input_horizontal_stretch: qt_property!(f64; WRITE set_input_horizontal_stretch),
@@ -152,6 +159,8 @@ pub struct Controller {
     set_video_rotation: qt_method!(fn(&self, angle: f64)),
 
     set_trim_ranges: qt_method!(fn(&self, trim_ranges: QString)),
+    get_trim_range_overrides: qt_method!(fn(&self, index: usize) -> QString),
+    set_trim_range_overrides: qt_method!(fn(&self, index: usize, overrides: QString)),
 
     set_output_size: qt_method!(fn(&self, width: usize, height: usize)),
 
@@ -185,6 +194,19 @@ pub struct Controller {
     get_min_fov: qt_method!(fn(&self) -> f64),
 
     init_calibrator: qt_method!(fn(&mut self)),
+    set_calibration_pattern: qt_method!(fn(&self, target: i32, columns: usize, rows: usize)),
+    set_calibration_distortion_model: qt_method!(fn(&self, id: String)),
+    set_calibration_use_opencv: qt_method!(fn(&self, v: bool)),
+    set_calibration_show_coverage: qt_method!(fn(&self, v: bool)),
+    calibration_report: qt_method!(fn(&self) -> QString),
+    add_calibration_source: qt_method!(fn(&self, name: String, focal_length: f64) -> bool),
+    clear_calibration_sources: qt_method!(fn(&self)),
+    calibration_sources: qt_method!(fn(&self) -> QJsonArray),
+    export_joint_lens_profiles: qt_method!(fn(&self, url: QUrl, info: QJsonObject, upload: bool)),
+    export_calibration_report: qt_method!(fn(&self, url: QUrl)),
+    estimate_calibration_rolling_shutter: qt_method!(fn(&self)),
+    calib_rolling_shutter_estimated: qt_signal!(frame_readout_time: f64, horizontal: bool, rms: f64, rms_global: f64),
+    has_opencv: qt_method!(fn(&self) -> bool),
 
     get_urls_from_gyroflow_file: qt_method!(fn(&mut self, url: QUrl) -> QStringList),
     import_gyroflow_file: qt_method!(fn(&mut self, url: QUrl)),
@@ -230,6 +252,17 @@ pub struct Controller {
     set_keyframe: qt_method!(fn(&self, typ: String, timestamp_us: i64, value: f64)),
     set_keyframe_easing: qt_method!(fn(&self, typ: String, timestamp_us: i64, easing: String)),
     keyframe_easing: qt_method!(fn(&self, typ: String, timestamp_us: i64) -> String),
+    set_keyframe_bezier_handles: qt_method!(fn(&self, typ: String, timestamp_us: i64, handles: String)),
+    set_keyframe_expression: qt_method!(fn(&self, typ: String, expression: String) -> String),
+    keyframe_expression: qt_method!(fn(&self, typ: String) -> String),
+    set_keyframe_telemetry_binding: qt_method!(fn(&self, typ: String, binding: String) -> String),
+    keyframe_telemetry_binding: qt_method!(fn(&self, typ: String) -> String),
+    available_telemetry_channels: qt_method!(fn(&self) -> QJsonArray),
+    export_keyframes: qt_method!(fn(&self, url: QUrl, types: String)),
+    copy_keyframes: qt_method!(fn(&self, types: String)),
+    import_keyframes: qt_method!(fn(&self, url: QUrl, time_offset_ms: f64, time_scale: f64)),
+    paste_keyframes: qt_method!(fn(&self, time_offset_ms: f64, time_scale: f64)),
+    keyframe_bezier_handles: qt_method!(fn(&self, typ: String, timestamp_us: i64) -> String),
     remove_keyframe: qt_method!(fn(&self, typ: String, timestamp_us: i64)),
     clear_keyframes_type: qt_method!(fn(&self, typ: String)),
     keyframe_value_at_video_timestamp: qt_method!(fn(&self, typ: String, timestamp_ms: f64) -> QJSValue),
@@ -805,6 +838,26 @@ impl Controller {
         self.lens_profile_loaded(QString::from(json), QString::from(filepath), QString::from(checksum));
         self.request_recompute();
     }
+    fn self_calibrate_lens(&self) {
+        let loaded = util::qt_queued_callback_mut(self, |this, profile: core::lens_profile::LensProfile| {
+            let json = profile.get_json().unwrap_or_default();
+            *this.stabilizer.lens.write() = profile;
+            this.lens_loaded = true;
+            this.lens_changed();
+            this.lens_profile_loaded(QString::from(json), QString::default(), QString::default());
+            this.request_recompute();
+        });
+        let err = util::qt_queued_callback_mut(self, |this, msg: String| {
+            this.error(QString::from("An error occured: %1"), QString::from(msg), QString::default());
+        });
+        let stab = self.stabilizer.clone();
+        core::run_threaded(move || {
+            match stab.self_calibrate_lens() {
+                Ok(profile) => loaded(profile),
+                Err(e) => err(e.to_string())
+            }
+        });
+    }
     fn load_default_preset(&mut self) {
         // Assumes regular filesystem
         let local_path = gyroflow_core::lens_profile_database::LensProfileDatabase::get_path().join("default.gyroflow");
@@ -936,7 +989,12 @@ impl Controller {
             vid.onProcessTexture(Box::new(move |_frame, timestamp_ms, width, height, backend_id, ptr1, ptr2, ptr3, ptr4, ptr5| -> bool {
                 if width < 4 || height < 4 || backend_id == 0 { return false; }
 
-                if !stab.params.read().stab_enabled { return false; }
+                {
+                    let params = stab.params.read();
+                    if !params.stab_enabled { return false; }
+                    // Deblur needs the input frame in the CPU memory, so use the pixels path
+                    if params.deblur && params.deblur_iterations > 0 && !params.spherical { return false; }
+                }
 
                 let _time = std::time::Instant::now();
 
@@ -1339,6 +1397,13 @@ impl Controller {
     wrap_simple_method!(set_of_method,          v: u32; recompute; chart_data_changed);
 
     wrap_simple_method!(set_lens_correction_amount,    v: f64; recompute; zooming_data_changed);
+    wrap_simple_method!(set_output_projection,         v: i32; recompute; zooming_data_changed);
+    wrap_simple_method!(set_spherical,                 v: bool; recompute; zooming_data_changed);
+    wrap_simple_method!(set_motion_blur_shutter_angle, v: f64; recompute);
+    wrap_simple_method!(set_motion_blur_samples,       v: u32; recompute);
+    wrap_simple_method!(set_deblur,                    v: bool; recompute);
+    wrap_simple_method!(set_deblur_iterations,         v: u32; recompute);
+    wrap_simple_method!(set_deblur_exposure_ms,        v: f64; recompute);
     wrap_simple_method!(set_input_horizontal_stretch,  v: f64; recompute);
     wrap_simple_method!(set_lens_is_asymmetrical,      v: bool; recompute);
     wrap_simple_method!(set_input_vertical_stretch,    v: f64; recompute);
@@ -1375,6 +1440,19 @@ impl Controller {
         self.request_recompute();
         self.chart_data_changed();
     }
+    fn get_trim_range_overrides(&self, index: usize) -> QString {
+        QString::from(serde_json::to_string(&self.stabilizer.trim_range_overrides(index)).unwrap_or_default())
+    }
+    fn set_trim_range_overrides(&self, index: usize, overrides: QString) {
+        match serde_json::from_str(&overrides.to_string()) {
+            Ok(overrides) => {
+                self.stabilizer.set_trim_range_overrides(index, overrides);
+                self.request_recompute();
+                self.chart_data_changed();
+            },
+            Err(e) => ::log::error!("Invalid trim range overrides: {e:?}")
+        }
+    }
 
     fn offset_at_video_timestamp(&self, timestamp_us: i64) -> f64 {
         self.stabilizer.offset_at_video_timestamp(timestamp_us)
@@ -1427,240 +1505,285 @@ impl Controller {
     }
 
     pub fn init_calibrator(&self) {
-        #[cfg(feature = "opencv")]
-        {
-            self.stabilizer.params.write().is_calibrator = true;
-            *self.stabilizer.lens_calibrator.write() = Some(LensCalibrator::new());
-            self.stabilizer.set_smoothing_method(2); // Plain 3D
-            self.stabilizer.set_smoothing_param("time_constant", 2.0);
+        self.stabilizer.params.write().is_calibrator = true;
+        let mut calibrator = LensCalibrator::new();
+        // Keep the videos added for joint calibration when loading the next one
+        if let Some(ref prev) = *self.stabilizer.lens_calibrator.read() {
+            calibrator.sources = prev.sources.clone();
         }
+        *self.stabilizer.lens_calibrator.write() = Some(calibrator);
+        self.stabilizer.set_smoothing_method(2); // Plain 3D
+        self.stabilizer.set_smoothing_param("time_constant", 2.0);
+    }
+
+    fn set_calibration_pattern(&self, target: i32, columns: usize, rows: usize) {
+        self.stabilizer.set_calibration_pattern(core::calibration::CalibrationTarget::from(target), columns.max(2), rows.max(2));
+    }
+    fn set_calibration_distortion_model(&self, id: String) {
+        self.stabilizer.set_calibration_distortion_model(&id);
+    }
+    fn set_calibration_use_opencv(&self, v: bool) {
+        self.stabilizer.set_calibration_use_opencv(v);
+    }
+    fn set_calibration_show_coverage(&self, v: bool) {
+        self.stabilizer.set_calibration_show_coverage(v);
+        self.request_recompute();
+    }
+    fn calibration_report(&self) -> QString {
+        QString::from(self.stabilizer.calibration_report().map(|x| x.to_json()).unwrap_or_default())
+    }
+    fn export_calibration_report(&self, url: QUrl) {
+        let contents = self.stabilizer.calibration_report().map(|x| x.to_json()).unwrap_or_default();
+        if let Err(e) = filesystem::write(&util::qurl_to_encoded(url), contents.as_bytes()) {
+            self.error(QString::from("An error occured: %1"), QString::from(e.to_string()), QString::default());
+        }
+    }
+    fn has_opencv(&self) -> bool {
+        cfg!(feature = "opencv")
+    }
+
+    fn estimate_calibration_rolling_shutter(&self) {
+        let estimated = util::qt_queued_callback_mut(self, |this, est: core::calibration::rolling_shutter::RollingShutterEstimate| {
+            this.calib_rolling_shutter_estimated(est.frame_readout_time, est.horizontal, est.rms, est.rms_global);
+        });
+        let err = util::qt_queued_callback_mut(self, |this, msg: String| {
+            this.error(QString::from("An error occured: %1"), QString::from(msg), QString::default());
+        });
+        let stab = self.stabilizer.clone();
+        core::run_threaded(move || {
+            match stab.estimate_calibration_rolling_shutter() {
+                Ok(est) => {
+                    ::log::info!("Estimated frame readout time: {:.3} ms, horizontal: {}, rms: {:.4} (global shutter: {:.4}), frames: {}", est.frame_readout_time, est.horizontal, est.rms, est.rms_global, est.frames);
+                    // `frame_readout_time` is set by the UI
+                    stab.params.write().horizontal_rs = est.horizontal;
+                    estimated(est);
+                }
+                Err(e) => err(e.to_string())
+            }
+        });
     }
 
     fn start_autocalibrate(&mut self, max_points: usize, every_nth_frame: usize, iterations: usize, max_sharpness: f64, custom_timestamp_ms: f64, no_marker: bool) {
-        #[cfg(feature = "opencv")]
-        {
-            rendering::clear_log();
+        rendering::clear_log();
 
-            self.calib_in_progress = true;
-            self.calib_in_progress_changed();
-            self.calib_progress(0.0, 0.0, 0, 0, 0, 0.0);
+        self.calib_in_progress = true;
+        self.calib_in_progress_changed();
+        self.calib_progress(0.0, 0.0, 0, 0, 0, 0.0);
 
-            let stab = self.stabilizer.clone();
+        let stab = self.stabilizer.clone();
 
-            let (fps, frame_count, trim_ranges_ms, trim_ratio, input_horizontal_stretch, input_vertical_stretch) = {
-                let params = stab.params.read();
-                let lens = stab.lens.read();
-                let input_horizontal_stretch = if lens.input_horizontal_stretch > 0.01 { lens.input_horizontal_stretch } else { 1.0 };
-                let input_vertical_stretch = if lens.input_vertical_stretch > 0.01 { lens.input_vertical_stretch } else { 1.0 };
-                (params.fps, params.frame_count, params.trim_ranges.iter().map(|x| (x.0 * params.duration_ms, x.1 * params.duration_ms)).collect(), params.get_trim_ratio(), input_horizontal_stretch, input_vertical_stretch)
-            };
+        let (fps, frame_count, trim_ranges_ms, trim_ratio, input_horizontal_stretch, input_vertical_stretch) = {
+            let params = stab.params.read();
+            let lens = stab.lens.read();
+            let input_horizontal_stretch = if lens.input_horizontal_stretch > 0.01 { lens.input_horizontal_stretch } else { 1.0 };
+            let input_vertical_stretch = if lens.input_vertical_stretch > 0.01 { lens.input_vertical_stretch } else { 1.0 };
+            (params.fps, params.frame_count, params.trim_ranges.iter().map(|x| (x.0 * params.duration_ms, x.1 * params.duration_ms)).collect(), params.get_trim_ratio(), input_horizontal_stretch, input_vertical_stretch)
+        };
 
-            let is_forced = custom_timestamp_ms > -0.5;
-            let ranges = if is_forced {
-                vec![(custom_timestamp_ms - 1.0, custom_timestamp_ms + 1.0)]
-            } else {
-                trim_ranges_ms
+        let is_forced = custom_timestamp_ms > -0.5;
+        let ranges = if is_forced {
+            vec![(custom_timestamp_ms - 1.0, custom_timestamp_ms + 1.0)]
+        } else {
+            trim_ranges_ms
+        };
+
+        let cal = stab.lens_calibrator.clone();
+        if max_points > 0 {
+            let mut lock = cal.write();
+            let cal = lock.as_mut().unwrap();
+            let saved: BTreeMap<i32, core::calibration::Detected> = {
+                let lock = cal.image_points.read();
+                cal.forced_frames.iter().filter_map(|f| Some((*f, lock.get(f)?.clone()))).collect()
             };
+            *cal.image_points.write() = saved;
+            cal.max_images = max_points;
+            cal.iterations = iterations;
+            cal.max_sharpness = max_sharpness;
+        }
 
-            let cal = stab.lens_calibrator.clone();
-            if max_points > 0 {
-                let mut lock = cal.write();
-                let cal = lock.as_mut().unwrap();
-                let saved: BTreeMap<i32, core::calibration::Detected> = {
-                    let lock = cal.image_points.read();
-                    cal.forced_frames.iter().filter_map(|f| Some((*f, lock.get(f)?.clone()))).collect()
-                };
-                *cal.image_points.write() = saved;
-                cal.max_images = max_points;
-                cal.iterations = iterations;
-                cal.max_sharpness = max_sharpness;
+        let progress = util::qt_queued_callback_mut(self, |this, (ready, total, good, rms, sharpness): (usize, usize, usize, f64, f64)| {
+            this.calib_in_progress = ready < total;
+            this.calib_in_progress_changed();
+            this.calib_progress(ready as f64 / total as f64, rms, ready, total, good, sharpness);
+            if rms > 0.0 {
+                this.update_calib_model();
             }
+        });
+        let err = util::qt_queued_callback_mut(self, |this, (msg, mut arg): (String, String)| {
+            arg.push_str("\n\n");
+            arg.push_str(&rendering::get_log());
 
-            let progress = util::qt_queued_callback_mut(self, |this, (ready, total, good, rms, sharpness): (usize, usize, usize, f64, f64)| {
-                this.calib_in_progress = ready < total;
-                this.calib_in_progress_changed();
-                this.calib_progress(ready as f64 / total as f64, rms, ready, total, good, sharpness);
-                if rms > 0.0 {
-                    this.update_calib_model();
-                }
-            });
-            let err = util::qt_queued_callback_mut(self, |this, (msg, mut arg): (String, String)| {
-                arg.push_str("\n\n");
-                arg.push_str(&rendering::get_log());
-
-                this.error(QString::from(msg), QString::from(arg), QString::default());
-
-                this.calib_in_progress = false;
-                this.calib_in_progress_changed();
-            });
+            this.error(QString::from(msg), QString::from(arg), QString::default());
 
-            self.cancel_flag.store(false, SeqCst);
-            let cancel_flag = self.cancel_flag.clone();
+            this.calib_in_progress = false;
+            this.calib_in_progress_changed();
+        });
 
-            let total = ((frame_count as f64 * trim_ratio) / every_nth_frame as f64) as usize;
-            let total_read = Arc::new(AtomicUsize::new(0));
-            let processed = Arc::new(AtomicUsize::new(0));
+        self.cancel_flag.store(false, SeqCst);
+        let cancel_flag = self.cancel_flag.clone();
 
-            let processing_resolution = self.processing_resolution;
+        let total = ((frame_count as f64 * trim_ratio) / every_nth_frame as f64) as usize;
+        let total_read = Arc::new(AtomicUsize::new(0));
+        let processed = Arc::new(AtomicUsize::new(0));
 
-            let input_file = stab.input_file.read().clone();
-            core::run_threaded(move || {
+        let processing_resolution = self.processing_resolution;
 
-                let mut decoder_options = ffmpeg_next::Dictionary::new();
-                if input_file.image_sequence_fps > 0.0 {
-                    let fps = rendering::fps_to_rational(input_file.image_sequence_fps);
-                    decoder_options.set("framerate", &format!("{}/{}", fps.numerator(), fps.denominator()));
-                }
-                if input_file.image_sequence_start > 0 {
-                    decoder_options.set("start_number", &format!("{}", input_file.image_sequence_start));
-                }
-                if processing_resolution > 0 {
-                    decoder_options.set("scale", &format!("{}x{}", (processing_resolution * 16) / 9, processing_resolution));
-                }
+        let input_file = stab.input_file.read().clone();
+        core::run_threaded(move || {
 
-                ::log::debug!("Decoder options: {:?}", decoder_options);
-                let gpu_decoding = *rendering::GPU_DECODING.read();
-                let fs_base = gyroflow_core::filesystem::get_engine_base();
-                match VideoProcessor::from_file(&fs_base, &input_file.url, gpu_decoding, 0, Some(decoder_options)) {
-                    Ok(mut proc) => {
-                        let progress = progress.clone();
-                        let err2 = err.clone();
-                        let cal = cal.clone();
-                        let total_read = total_read.clone();
-                        let processed = processed.clone();
-                        let cancel_flag2 = cancel_flag.clone();
-                        let dims = proc.get_org_dimensions();
+            let mut decoder_options = ffmpeg_next::Dictionary::new();
+            if input_file.image_sequence_fps > 0.0 {
+                let fps = rendering::fps_to_rational(input_file.image_sequence_fps);
+                decoder_options.set("framerate", &format!("{}/{}", fps.numerator(), fps.denominator()));
+            }
+            if input_file.image_sequence_start > 0 {
+                decoder_options.set("start_number", &format!("{}", input_file.image_sequence_start));
+            }
+            if processing_resolution > 0 {
+                decoder_options.set("scale", &format!("{}x{}", (processing_resolution * 16) / 9, processing_resolution));
+            }
 
-                        proc.on_frame(move |timestamp_us, input_frame, _output_frame, converter, _rate_control| {
-                            let frame = core::frame_at_timestamp(timestamp_us as f64 / 1000.0, fps);
+            ::log::debug!("Decoder options: {:?}", decoder_options);
+            let gpu_decoding = *rendering::GPU_DECODING.read();
+            let fs_base = gyroflow_core::filesystem::get_engine_base();
+            match VideoProcessor::from_file(&fs_base, &input_file.url, gpu_decoding, 0, Some(decoder_options)) {
+                Ok(mut proc) => {
+                    let progress = progress.clone();
+                    let err2 = err.clone();
+                    let cal = cal.clone();
+                    let total_read = total_read.clone();
+                    let processed = processed.clone();
+                    let cancel_flag2 = cancel_flag.clone();
+                    let dims = proc.get_org_dimensions();
+
+                    proc.on_frame(move |timestamp_us, input_frame, _output_frame, converter, _rate_control| {
+                        let frame = core::frame_at_timestamp(timestamp_us as f64 / 1000.0, fps);
+
+                        if is_forced && total_read.load(SeqCst) > 0 {
+                            return Ok(());
+                        }
 
-                            if is_forced && total_read.load(SeqCst) > 0 {
-                                return Ok(());
+                        if (frame % every_nth_frame as i32) == 0 {
+                            let mut width = (input_frame.width() as f64 * input_horizontal_stretch).round() as u32;
+                            let mut height = (input_frame.height() as f64 * input_vertical_stretch).round() as u32;
+                            let mut org_size = (width, height);
+                            let mut pt_scale = 1.0;
+                            if processing_resolution > 0 && height > processing_resolution as u32 {
+                                pt_scale = height as f32 / processing_resolution as f32;
+                                width = (width as f32 / pt_scale).round() as u32;
+                                height = (height as f32 / pt_scale).round() as u32;
                             }
+                            match converter.scale(input_frame, ffmpeg_next::format::Pixel::RGB24, width, height) {
+                                Ok(small_frame) => {
+                                    let (width, height, rgb_stride, rgb_pixels) = (small_frame.plane_width(0), small_frame.plane_height(0), small_frame.stride(0), small_frame.data(0));
+                                    // The target is detected in grayscale, color is used to estimate the vignetting and chromatic aberration
+                                    let stride = width as usize;
+                                    let pixels: Vec<u8> = (0..height as usize).flat_map(|y| {
+                                        rgb_pixels[y * rgb_stride..][..stride * 3].chunks_exact(3).map(|p| ((p[0] as u32 * 54 + p[1] as u32 * 183 + p[2] as u32 * 19) >> 8) as u8)
+                                    }).collect();
+
+                                    total_read.fetch_add(1, SeqCst);
+                                    let mut lock = cal.write();
+                                    let cal = lock.as_mut().unwrap();
+                                    if is_forced {
+                                        cal.forced_frames.insert(frame);
+                                    }
+                                    cal.no_marker = no_marker;
 
-                            if (frame % every_nth_frame as i32) == 0 {
-                                let mut width = (input_frame.width() as f64 * input_horizontal_stretch).round() as u32;
-                                let mut height = (input_frame.height() as f64 * input_vertical_stretch).round() as u32;
-                                let mut org_size = (width, height);
-                                let mut pt_scale = 1.0;
-                                if processing_resolution > 0 && height > processing_resolution as u32 {
-                                    pt_scale = height as f32 / processing_resolution as f32;
-                                    width = (width as f32 / pt_scale).round() as u32;
-                                    height = (height as f32 / pt_scale).round() as u32;
-                                }
-                                match converter.scale(input_frame, ffmpeg_next::format::Pixel::GRAY8, width, height) {
-                                    Ok(mut small_frame) => {
-                                        let (width, height, stride, pixels) = (small_frame.plane_width(0), small_frame.plane_height(0), small_frame.stride(0), small_frame.data_mut(0));
-
-                                        total_read.fetch_add(1, SeqCst);
-                                        let mut lock = cal.write();
-                                        let cal = lock.as_mut().unwrap();
-                                        if is_forced {
-                                            cal.forced_frames.insert(frame);
-                                        }
-                                        cal.no_marker = no_marker;
-
-                                        if let Some(dims) = &dims {
-                                            let (w, h) = (dims.0.load(SeqCst), dims.1.load(SeqCst));
-                                            if w > 0 && h > 0 {
-                                                pt_scale = h as f32 / height as f32;
-                                                org_size = (w as u32, h as u32);
-                                            }
+                                    if let Some(dims) = &dims {
+                                        let (w, h) = (dims.0.load(SeqCst), dims.1.load(SeqCst));
+                                        if w > 0 && h > 0 {
+                                            pt_scale = h as f32 / height as f32;
+                                            org_size = (w as u32, h as u32);
                                         }
-                                        cal.feed_frame(timestamp_us, frame, (width, height), org_size, stride, pt_scale, pixels, cancel_flag2.clone(), total, processed.clone(), progress.clone());
-                                    },
-                                    Err(e) => {
-                                        err2(("An error occured: %1".to_string(), e.to_string()))
                                     }
+                                    cal.feed_frame(timestamp_us, frame, (width, height), org_size, stride, pt_scale, &pixels, Some((rgb_pixels, rgb_stride)), cancel_flag2.clone(), total, processed.clone(), progress.clone());
+                                },
+                                Err(e) => {
+                                    err2(("An error occured: %1".to_string(), e.to_string()))
                                 }
                             }
-                            Ok(())
-                        });
-                        if let Err(e) = proc.start_decoder_only(ranges, cancel_flag.clone()) {
-                            err(("An error occured: %1".to_string(), e.to_string()));
                         }
-                    }
-                    Err(error) => {
-                        err(("An error occured: %1".to_string(), error.to_string()));
+                        Ok(())
+                    });
+                    if let Err(e) = proc.start_decoder_only(ranges, cancel_flag.clone()) {
+                        err(("An error occured: %1".to_string(), e.to_string()));
                     }
                 }
-                // Don't lock the UI trying to draw chessboards while we calibrate
-                stab.params.write().is_calibrator = false;
-
-                while processed.load(SeqCst) < total_read.load(SeqCst) {
-                    std::thread::sleep(std::time::Duration::from_millis(500));
+                Err(error) => {
+                    err(("An error occured: %1".to_string(), error.to_string()));
                 }
+            }
+            // Don't lock the UI trying to draw chessboards while we calibrate
+            stab.params.write().is_calibrator = false;
 
-                let mut lock = cal.write();
-                let cal = lock.as_mut().unwrap();
-                if let Err(e) = cal.calibrate(is_forced) {
-                    err(("An error occured: %1".to_string(), format!("{:?}", e)));
-                } else {
-                    if cal.rms < 100.0 {
-                        stab.lens.write().set_from_calibrator(cal);
-                    }
-                    ::log::debug!("rms: {}, used_frames: {:?}, camera_matrix: {}, coefficients: {}", cal.rms, cal.used_points.keys(), cal.k, cal.d);
+            while processed.load(SeqCst) < total_read.load(SeqCst) {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+            }
+
+            let mut lock = cal.write();
+            let cal = lock.as_mut().unwrap();
+            if let Err(e) = cal.calibrate(is_forced) {
+                err(("An error occured: %1".to_string(), format!("{:?}", e)));
+            } else {
+                if cal.rms < 100.0 {
+                    stab.lens.write().set_from_calibrator(cal);
                 }
+                ::log::debug!("rms: {}, used_frames: {:?}, camera_matrix: {}, coefficients: {:?}", cal.rms, cal.used_points.keys(), cal.k, cal.d);
+            }
 
-                let good = cal.image_points.read().len();
-                progress((total, total, good, cal.rms, *cal.sum_sharpness.read() / good.max(1) as f64));
+            let good = cal.image_points.read().len();
+            progress((total, total, good, cal.rms, *cal.sum_sharpness.read() / good.max(1) as f64));
 
-                stab.params.write().is_calibrator = true;
-            });
-        }
+            stab.params.write().is_calibrator = true;
+        });
     }
 
     fn update_calib_model(&mut self) {
-        #[cfg(feature = "opencv")]
-        {
-            let cal = self.stabilizer.lens_calibrator.clone();
+        let cal = self.stabilizer.lens_calibrator.clone();
 
-            let used_points = cal.read().as_ref().map(|x| x.used_points.clone()).unwrap_or_default();
+        let used_points = cal.read().as_ref().map(|x| x.used_points.clone()).unwrap_or_default();
 
-            self.calib_model = RefCell::new(used_points.values().map(|v| CalibrationItem {
-                timestamp_us: v.timestamp_us,
-                sharpness: v.avg_sharpness,
-                is_forced: v.is_forced
-            }).collect());
+        self.calib_model = RefCell::new(used_points.values().map(|v| CalibrationItem {
+            timestamp_us: v.timestamp_us,
+            sharpness: v.avg_sharpness,
+            is_forced: v.is_forced
+        }).collect());
 
-            util::qt_queued_callback(self, |this, _| {
-                this.calib_model_updated();
-            })(());
-        }
+        util::qt_queued_callback(self, |this, _| {
+            this.calib_model_updated();
+        })(());
     }
 
     fn add_calibration_point(&mut self, timestamp_us: i64, no_marker: bool) {
         self.start_autocalibrate(0, 1, 1, 1000.0, timestamp_us as f64 / 1000.0, no_marker);
     }
     fn remove_calibration_point(&mut self, timestamp_us: i64) {
-        #[cfg(feature = "opencv")]
+        let cal = self.stabilizer.lens_calibrator.clone();
+        let mut rms = 0.0;
         {
-            let cal = self.stabilizer.lens_calibrator.clone();
-            let mut rms = 0.0;
-            {
-                let mut lock = cal.write();
-                let cal = lock.as_mut().unwrap();
-                let mut frame_to_remove = None;
-                for x in &cal.used_points {
-                    if x.1.timestamp_us == timestamp_us {
-                        frame_to_remove = Some(*x.0);
-                        break;
-                    }
-                }
-                if let Some(f) = frame_to_remove {
-                    cal.forced_frames.remove(&f);
-                    cal.used_points.remove(&f);
-                }
-                if cal.calibrate(true).is_ok() {
-                    rms = cal.rms;
-                    self.stabilizer.lens.write().set_from_calibrator(cal);
-                    ::log::debug!("rms: {}, used_frames: {:?}, camera_matrix: {}, coefficients: {}", cal.rms, cal.used_points.keys(), cal.k, cal.d);
+            let mut lock = cal.write();
+            let cal = lock.as_mut().unwrap();
+            let mut frame_to_remove = None;
+            for x in &cal.used_points {
+                if x.1.timestamp_us == timestamp_us {
+                    frame_to_remove = Some(*x.0);
+                    break;
                 }
             }
-            self.update_calib_model();
-            if rms > 0.0 {
-                self.calib_progress(1.0, rms, 1, 1, 1, 0.0);
+            if let Some(f) = frame_to_remove {
+                cal.forced_frames.remove(&f);
+                cal.used_points.remove(&f);
             }
+            if cal.calibrate(true).is_ok() {
+                rms = cal.rms;
+                self.stabilizer.lens.write().set_from_calibrator(cal);
+                ::log::debug!("rms: {}, used_frames: {:?}, camera_matrix: {}, coefficients: {:?}", cal.rms, cal.used_points.keys(), cal.k, cal.d);
+            }
+        }
+        self.update_calib_model();
+        if rms > 0.0 {
+            self.calib_progress(1.0, rms, 1, 1, 1, 0.0);
         }
     }
 
@@ -1668,7 +1791,6 @@ impl Controller {
         let info_json = info.to_json().to_string();
 
         if let Ok(mut profile) = core::lens_profile::LensProfile::from_json(&info_json) {
-            #[cfg(feature = "opencv")]
             if let Some(ref cal) = *self.stabilizer.lens_calibrator.read() {
                 profile.set_from_calibrator(cal);
             }
@@ -1686,7 +1808,6 @@ impl Controller {
 
         match core::lens_profile::LensProfile::from_json(&info_json) {
             Ok(mut profile) => {
-                #[cfg(feature = "opencv")]
                 if let Some(ref cal) = *self.stabilizer.lens_calibrator.read() {
                     profile.set_from_calibrator(cal);
                 }
@@ -1695,11 +1816,7 @@ impl Controller {
                     Ok(json) => {
                         ::log::debug!("Lens profile json: {}", json);
                         if upload {
-                            core::run_threaded(move || {
-                                if let Ok(Ok(body)) = ureq::post("https://api.gyroflow.xyz/upload_profile").set("Content-Type", "application/json; charset=utf-8").send_string(&json).map(|x| x.into_string()) {
-                                    ::log::debug!("Lens profile uploaded: {}", body.as_str());
-                                }
-                            });
+                            upload_lens_profile(json);
                         }
                     }
                     Err(e) => { self.error(QString::from("An error occured: %1"), QString::from(format!("{:?}", e)), QString::default()); }
@@ -1709,6 +1826,61 @@ impl Controller {
         }
     }
 
+    fn add_calibration_source(&self, name: String, focal_length: f64) -> bool {
+        if let Some(ref mut cal) = *self.stabilizer.lens_calibrator.write() {
+            return cal.add_source(name, if focal_length > 0.0 { Some(focal_length) } else { None });
+        }
+        false
+    }
+    fn clear_calibration_sources(&self) {
+        if let Some(ref mut cal) = *self.stabilizer.lens_calibrator.write() {
+            cal.sources.clear();
+        }
+    }
+    fn calibration_sources(&self) -> QJsonArray {
+        let sources = self.stabilizer.lens_calibrator.read().as_ref().map(|cal| {
+            cal.sources.iter().map(|x| serde_json::json!({
+                "name": x.name,
+                "width": x.width,
+                "height": x.height,
+                "focal_length": x.focal_length,
+                "frames": x.points.len()
+            })).collect::<Vec<_>>()
+        }).unwrap_or_default();
+        util::serde_json_to_qt_array(&serde_json::Value::Array(sources))
+    }
+    fn export_joint_lens_profiles(&self, url: QUrl, info: QJsonObject, upload: bool) {
+        let url = util::qurl_to_encoded(url);
+        let info_json = info.to_json().to_string();
+
+        let result = (|| -> Result<usize, String> {
+            let template = core::lens_profile::LensProfile::from_json(&info_json).map_err(|e| e.to_string())?;
+            let lock = self.stabilizer.lens_calibrator.read();
+            let cal = lock.as_ref().ok_or_else(|| "Calibrator is not initialized".to_string())?;
+            let joint = cal.calibrate_joint().map_err(|e| e.to_string())?;
+            ::log::debug!("Joint calibration rms: {}, modes: {:?}", joint.rms, joint.modes);
+
+            let mut profiles = template.from_joint_calibration(cal, &joint);
+            let count = profiles.len();
+            let base_url = url.strip_suffix(".json").unwrap_or(&url).to_string();
+            for profile in profiles.iter_mut() {
+                let file_url = if count > 1 { format!("{}_{}x{}.json", base_url, profile.calib_dimension.w, profile.calib_dimension.h) } else { url.clone() };
+                let json = profile.save_to_file(&file_url).map_err(|e| format!("{:?}", e))?;
+                ::log::debug!("Lens profile json: {}", json);
+                // Same limit as for the single profile export
+                if upload && profile.fisheye_params.RMS_error < 100.0 {
+                    upload_lens_profile(json);
+                } else if upload {
+                    ::log::warn!("Not uploading {file_url}, the reprojection error is too high: {}", profile.fisheye_params.RMS_error);
+                }
+            }
+            Ok(count)
+        })();
+        if let Err(e) = result {
+            self.error(QString::from("An error occured: %1"), QString::from(e), QString::default());
+        }
+    }
+
     fn load_profiles(&self, reload_from_disk: bool) {
         let loaded = util::qt_queued_callback_mut(self, |this, all_names: QVariantList| {
             this.all_profiles_loaded(all_names)
@@ -1859,6 +2031,99 @@ impl Controller {
         }
         String::new()
     }
+    fn set_keyframe_bezier_handles(&self, typ: String, timestamp_us: i64, handles: String) {
+        // handles: JSON object { "in": { "influence": 0.33, "speed": 0.0 }, "out": { ... } }, missing or null handle means no handle
+        if let Ok(kf) = KeyframeType::from_str(&typ) {
+            if let Ok(v) = serde_json::from_str::<serde_json::Value>(&handles) {
+                let handle_in  = v.get("in") .and_then(|x| serde_json::from_value::<BezierHandle>(x.clone()).ok());
+                let handle_out = v.get("out").and_then(|x| serde_json::from_value::<BezierHandle>(x.clone()).ok());
+                self.stabilizer.set_keyframe_bezier_handles(&kf, timestamp_us, handle_in, handle_out);
+                self.keyframes_changed();
+                self.request_recompute();
+                self.chart_data_changed();
+            }
+        }
+    }
+    fn keyframe_bezier_handles(&self, typ: String, timestamp_us: i64) -> String {
+        if let Ok(kf) = KeyframeType::from_str(&typ) {
+            if let Some((handle_in, handle_out)) = self.stabilizer.keyframe_bezier_handles(&kf, timestamp_us) {
+                return serde_json::json!({ "in": handle_in, "out": handle_out }).to_string();
+            }
+        }
+        String::new()
+    }
+    fn set_keyframe_expression(&self, typ: String, expression: String) -> String {
+        if let Ok(kf) = KeyframeType::from_str(&typ) {
+            if let Err(e) = self.stabilizer.set_keyframe_expression(&kf, &expression) {
+                return e.to_string();
+            }
+            self.keyframes_changed();
+            self.request_recompute();
+            self.chart_data_changed();
+        }
+        String::new()
+    }
+    fn keyframe_expression(&self, typ: String) -> String {
+        KeyframeType::from_str(&typ).ok().and_then(|kf| self.stabilizer.keyframe_expression(&kf)).unwrap_or_default()
+    }
+    fn set_keyframe_telemetry_binding(&self, typ: String, binding: String) -> String {
+        if let Ok(kf) = KeyframeType::from_str(&typ) {
+            let binding = if binding.is_empty() {
+                None
+            } else {
+                match serde_json::from_str::<TelemetryBinding>(&binding) {
+                    Ok(x) => Some(x),
+                    Err(e) => return e.to_string()
+                }
+            };
+            self.stabilizer.set_keyframe_telemetry_binding(&kf, binding);
+            self.keyframes_changed();
+            self.request_recompute();
+            self.chart_data_changed();
+        }
+        String::new()
+    }
+    fn keyframe_telemetry_binding(&self, typ: String) -> String {
+        KeyframeType::from_str(&typ).ok()
+            .and_then(|kf| self.stabilizer.keyframe_telemetry_binding(&kf))
+            .and_then(|x| serde_json::to_string(&x).ok())
+            .unwrap_or_default()
+    }
+    fn available_telemetry_channels(&self) -> QJsonArray {
+        util::serde_json_to_qt_array(&serde_json::json!(self.stabilizer.available_telemetry_channels()))
+    }
+    // `types` is a comma separated list of keyframe types, empty means all
+    fn export_keyframes(&self, url: QUrl, types: String) {
+        let types: Vec<KeyframeType> = types.split(',').filter_map(|x| KeyframeType::from_str(x.trim()).ok()).collect();
+        let contents = self.stabilizer.export_keyframes(&types);
+        if let Err(e) = filesystem::write(&util::qurl_to_encoded(url), contents.as_bytes()) {
+            self.error(QString::from("An error occured: %1"), QString::from(e.to_string()), QString::default());
+        }
+    }
+    fn copy_keyframes(&self, types: String) {
+        let types: Vec<KeyframeType> = types.split(',').filter_map(|x| KeyframeType::from_str(x.trim()).ok()).collect();
+        util::copy_to_clipboard(QString::from(self.stabilizer.export_keyframes(&types)));
+    }
+    fn import_keyframes(&self, url: QUrl, time_offset_ms: f64, time_scale: f64) {
+        match filesystem::read(&util::qurl_to_encoded(url)) {
+            Ok(data) => self.import_keyframes_data(&data, time_offset_ms, time_scale),
+            Err(e) => self.error(QString::from("An error occured: %1"), QString::from(e.to_string()), QString::default())
+        }
+    }
+    fn paste_keyframes(&self, time_offset_ms: f64, time_scale: f64) {
+        self.import_keyframes_data(util::clipboard_text().to_string().as_bytes(), time_offset_ms, time_scale);
+    }
+    fn import_keyframes_data(&self, data: &[u8], time_offset_ms: f64, time_scale: f64) {
+        match self.stabilizer.import_keyframes(data, time_offset_ms, time_scale) {
+            Ok(imported) => {
+                ::log::info!("Imported keyframes: {imported:?}");
+                self.keyframes_changed();
+                self.request_recompute();
+                self.chart_data_changed();
+            }
+            Err(e) => self.error(QString::from("An error occured: %1"), QString::from(e.to_string()), QString::default())
+        }
+    }
     fn remove_keyframe(&self, typ: String, timestamp_us: i64) {
         if let Ok(kf) = KeyframeType::from_str(&typ) {
             self.stabilizer.remove_keyframe(&kf, timestamp_us);
@@ -2135,3 +2400,11 @@ impl Filesystem {
         }
     }
 }
+
+fn upload_lens_profile(json: String) {
+    core::run_threaded(move || {
+        if let Ok(Ok(body)) = ureq::post("https://api.gyroflow.xyz/upload_profile").set("Content-Type", "application/json; charset=utf-8").send_string(&json).map(|x| x.into_string()) {
+            ::log::debug!("Lens profile uploaded: {}", body.as_str());
+        }
+    });
+}
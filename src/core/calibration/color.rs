@@ -0,0 +1,264 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Estimation of the vignetting and lateral chromatic aberration from the calibration target.
+// The white squares of the chessboard get darker towards the edges of the frame. The falloff 1 + v1*r^2 + v2*r^4 + v3*r^6 is fitted
+// together with the brightness of the target in each frame, so the exposure can change during the video.
+// Uneven lighting of the target can't be separated from the vignetting, so the target should be evenly lit.
+// Lateral chromatic aberration scales the red and blue channels radially relative to green. The edges between the squares are located
+// in each channel and the radial scale is fitted to the shift of the red and blue edges.
+
+use nalgebra::{ Matrix3, Vector3 };
+use serde::Serialize;
+
+use super::CalibrationTarget;
+
+const MIN_WHITE_SQUARES: usize = 50;
+const MIN_EDGES: usize = 50;
+const VIGNETTING_ITERATIONS: usize = 20;
+// Squares with any channel brighter than this are clipped and can't be used for the vignetting
+const SATURATION_LEVEL: f64 = 250.0;
+// Minimum sum of the squared gradient across an edge, edges with lower contrast are too noisy
+const MIN_EDGE_CONTRAST: f64 = 100.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct WhiteSquare {
+    pub pos: (f64, f64),
+    pub luma: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EdgeShift {
+    pub pos: (f64, f64),
+    pub normal: (f64, f64),
+    // Position of the edge in the red and blue channels relative to green, along `normal`, in pixels
+    pub red: f64,
+    pub blue: f64,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct ColorSamples {
+    pub white_squares: Vec<WhiteSquare>,
+    pub edges: Vec<EdgeShift>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+pub struct LensColorEstimate {
+    pub vignetting: Option<[f64; 3]>, // v1, v2, v3, with r normalized to half of the image diagonal
+    pub chromatic_aberration: Option<[f64; 2]>, // Radial scale - 1 of the red and blue channels
+    pub white_squares: usize,
+    pub edges: usize,
+}
+
+struct RgbImage<'a> {
+    data: &'a [u8],
+    size: (u32, u32),
+    stride: usize,
+}
+impl RgbImage<'_> {
+    fn sample(&self, p: (f64, f64), channel: usize) -> Option<f64> {
+        let (x0, y0) = (p.0.floor(), p.1.floor());
+        if x0 < 0.0 || y0 < 0.0 || x0 + 1.0 >= self.size.0 as f64 || y0 + 1.0 >= self.size.1 as f64 {
+            return None;
+        }
+        let (fx, fy) = (p.0 - x0, p.1 - y0);
+        let px = |x: usize, y: usize| self.data.get(y * self.stride + x * 3 + channel).map(|v| *v as f64);
+        let (x0, y0) = (x0 as usize, y0 as usize);
+        let top    = px(x0, y0)?     * (1.0 - fx) + px(x0 + 1, y0)?     * fx;
+        let bottom = px(x0, y0 + 1)? * (1.0 - fx) + px(x0 + 1, y0 + 1)? * fx;
+        Some(top * (1.0 - fy) + bottom * fy)
+    }
+
+    // Average color in a circle
+    fn mean(&self, center: (f64, f64), radius: f64) -> Option<[f64; 3]> {
+        let r = radius.ceil() as i64;
+        let (cx, cy) = (center.0.round() as i64, center.1.round() as i64);
+        if cx - r < 0 || cy - r < 0 || cx + r >= self.size.0 as i64 || cy + r >= self.size.1 as i64 {
+            return None;
+        }
+        let mut sum = [0.0; 3];
+        let mut count = 0;
+        for y in cy - r..=cy + r {
+            for x in cx - r..=cx + r {
+                if ((x - cx).pow(2) + (y - cy).pow(2)) as f64 > radius * radius { continue; }
+                for (c, s) in sum.iter_mut().enumerate() {
+                    *s += *self.data.get(y as usize * self.stride + x as usize * 3 + c)? as f64;
+                }
+                count += 1;
+            }
+        }
+        if count == 0 { return None; }
+        Some(sum.map(|x| x / count as f64))
+    }
+
+    // Sub-pixel position of an edge along `normal`, as the centroid of the squared gradient
+    fn edge_position(&self, pos: (f64, f64), normal: (f64, f64), half_width: f64, channel: usize) -> Option<f64> {
+        const STEP: f64 = 0.25;
+        let n = (2.0 * half_width / STEP) as usize;
+        let values = (0..=n).map(|i| {
+            let t = -half_width + i as f64 * STEP;
+            self.sample((pos.0 + normal.0 * t, pos.1 + normal.1 * t), channel)
+        }).collect::<Option<Vec<f64>>>()?;
+
+        let (mut sum, mut weights) = (0.0, 0.0);
+        for i in 1..values.len() {
+            let g = (values[i] - values[i - 1]).powi(2);
+            sum += g * (-half_width + (i as f64 - 0.5) * STEP);
+            weights += g;
+        }
+        if weights < MIN_EDGE_CONTRAST * STEP * STEP { return None; }
+        Some(sum / weights)
+    }
+}
+
+// Samples the white squares and the edges of the target in one frame.
+// `corners` are in the coordinates of `rgb`, the samples are multiplied by `pt_scale` to the coordinates of the original video
+pub fn sample_frame(rgb: &[u8], size: (u32, u32), stride: usize, target: CalibrationTarget, grid_size: (usize, usize), corners: &[(f32, f32)], ids: &[usize], pt_scale: f64) -> ColorSamples {
+    let mut ret = ColorSamples::default();
+    let (columns, rows) = grid_size;
+    if target == CalibrationTarget::AsymmetricCircles || columns < 2 || rows < 2 {
+        return ret;
+    }
+    let mut grid = vec![None; columns * rows];
+    for (i, pt) in corners.iter().enumerate() {
+        let id = if ids.is_empty() { i } else { ids.get(i).copied().unwrap_or(usize::MAX) };
+        if let Some(x) = grid.get_mut(id) {
+            *x = Some((pt.0 as f64, pt.1 as f64));
+        }
+    }
+    let at = |x: usize, y: usize| -> Option<(f64, f64)> { grid[y * columns + x] };
+    let dist = |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).hypot(a.1 - b.1);
+    let scaled = |p: (f64, f64)| (p.0 * pt_scale, p.1 * pt_scale);
+    let image = RgbImage { data: rgb, size, stride };
+
+    // ChArUco markers are inside the white squares, so only the chessboard can be used for the vignetting.
+    // Whether the even or odd squares are white depends on the orientation of the board
+    if target == CalibrationTarget::Chessboard {
+        let mut squares: [Vec<(WhiteSquare, bool)>; 2] = [Vec::new(), Vec::new()];
+        for y in 0..rows - 1 {
+            for x in 0..columns - 1 {
+                if let (Some(a), Some(b), Some(c), Some(d)) = (at(x, y), at(x + 1, y), at(x, y + 1), at(x + 1, y + 1)) {
+                    let center = ((a.0 + b.0 + c.0 + d.0) / 4.0, (a.1 + b.1 + c.1 + d.1) / 4.0);
+                    let radius = dist(a, d).min(dist(b, c)) * 0.2;
+                    if radius < 1.0 { continue; }
+                    if let Some(rgb) = image.mean(center, radius) {
+                        let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+                        let clipped = rgb.iter().any(|x| *x > SATURATION_LEVEL);
+                        squares[(x + y) % 2].push((WhiteSquare { pos: scaled(center), luma }, clipped));
+                    }
+                }
+            }
+        }
+        let avg = |v: &[(WhiteSquare, bool)]| v.iter().map(|x| x.0.luma).sum::<f64>() / v.len().max(1) as f64;
+        let white = if avg(&squares[0]) > avg(&squares[1]) { 0 } else { 1 };
+        ret.white_squares = squares[white].iter().filter(|x| !x.1).map(|x| x.0).collect();
+    }
+
+    // Each segment between neighboring corners is an edge between a black and a white square.
+    // The edge is located in the middle part of the segment, away from the corners
+    for y in 0..rows {
+        for x in 0..columns {
+            let Some(a) = at(x, y) else { continue; };
+            let neighbors = [
+                if x + 1 < columns { at(x + 1, y) } else { None },
+                if y + 1 < rows    { at(x, y + 1) } else { None },
+            ];
+            for b in neighbors.into_iter().flatten() {
+                let len = dist(a, b);
+                let half_width = (len * 0.3).min(10.0);
+                if half_width < 2.0 { continue; }
+                let normal = ((a.1 - b.1) / len, (b.0 - a.0) / len);
+
+                let mut positions = [0.0; 3];
+                let mut valid = true;
+                for t in [0.35, 0.5, 0.65] {
+                    let p = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+                    for (c, pos) in positions.iter_mut().enumerate() {
+                        match image.edge_position(p, normal, half_width, c) {
+                            Some(e) => *pos += e / 3.0,
+                            None => valid = false
+                        }
+                    }
+                }
+                if valid {
+                    let mid = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+                    ret.edges.push(EdgeShift {
+                        pos: scaled(mid),
+                        normal,
+                        red:  (positions[0] - positions[1]) * pt_scale,
+                        blue: (positions[2] - positions[1]) * pt_scale,
+                    });
+                }
+            }
+        }
+    }
+    ret
+}
+
+pub fn estimate(frames: &[&ColorSamples], center: (f64, f64), size: (usize, usize)) -> LensColorEstimate {
+    let mut ret = LensColorEstimate::default();
+    if size.0 == 0 || size.1 == 0 {
+        return ret;
+    }
+    let half_diagonal2 = ((size.0 * size.0 + size.1 * size.1) as f64) / 4.0;
+
+    // Vignetting
+    let squares: Vec<Vec<(f64, f64)>> = frames.iter().map(|f| {
+        f.white_squares.iter().map(|s| (((s.pos.0 - center.0).powi(2) + (s.pos.1 - center.1).powi(2)) / half_diagonal2, s.luma)).collect::<Vec<_>>()
+    }).filter(|x| x.len() >= 4).collect();
+    ret.white_squares = squares.iter().map(|x| x.len()).sum();
+    let max_r2 = squares.iter().flatten().map(|x| x.0).fold(0.0, f64::max);
+    if ret.white_squares >= MIN_WHITE_SQUARES && max_r2 > 0.25 {
+        let falloff = |v: &[f64; 3], r2: f64| 1.0 + r2 * (v[0] + r2 * (v[1] + r2 * v[2]));
+        let mut v = [0.0; 3];
+        for _ in 0..VIGNETTING_ITERATIONS {
+            // Brightness of the target in each frame without the vignetting, then linear least squares for the coefficients
+            let brightness: Vec<f64> = squares.iter().map(|f| f.iter().map(|(r2, l)| l / falloff(&v, *r2)).sum::<f64>() / f.len() as f64).collect();
+            let mut ata = Matrix3::<f64>::zeros();
+            let mut atb = Vector3::<f64>::zeros();
+            for (f, b) in squares.iter().zip(brightness.iter()) {
+                if *b <= 0.0 { continue; }
+                for (r2, l) in f {
+                    let row = Vector3::new(*r2, r2 * r2, r2 * r2 * r2);
+                    ata += row * row.transpose();
+                    atb += row * (l / b - 1.0);
+                }
+            }
+            let Some(solution) = ata.cholesky().map(|c| c.solve(&atb)) else { break; };
+            v = [solution[0], solution[1], solution[2]];
+        }
+        // Falloff has to be positive and not brighter at the edges
+        let edge = falloff(&v, max_r2);
+        if v.iter().all(|x| x.is_finite()) && edge > 0.1 && edge <= 1.05 {
+            ret.vignetting = Some(v);
+        }
+    }
+
+    // Chromatic aberration: shift = scale * (radial distance along the normal of the edge)
+    let edges: Vec<&EdgeShift> = frames.iter().flat_map(|f| f.edges.iter()).collect();
+    ret.edges = edges.len();
+    if edges.len() >= MIN_EDGES {
+        let radial = |e: &EdgeShift| (e.pos.0 - center.0) * e.normal.0 + (e.pos.1 - center.1) * e.normal.1;
+        let fit = |shift: &dyn Fn(&EdgeShift) -> f64| -> Option<f64> {
+            let mut inliers = edges.clone();
+            let mut scale = 0.0;
+            // Second pass without the outliers of the first one
+            for pass in 0..2 {
+                let den: f64 = inliers.iter().map(|e| radial(e).powi(2)).sum();
+                if den <= 0.0 { return None; }
+                scale = inliers.iter().map(|e| shift(e) * radial(e)).sum::<f64>() / den;
+                if pass == 0 {
+                    let sigma = (inliers.iter().map(|e| (shift(e) - scale * radial(e)).powi(2)).sum::<f64>() / inliers.len() as f64).sqrt();
+                    inliers.retain(|e| (shift(e) - scale * radial(e)).abs() <= 3.0 * sigma);
+                    if inliers.len() < MIN_EDGES { return None; }
+                }
+            }
+            if scale.is_finite() { Some(scale) } else { None }
+        };
+        if let (Some(red), Some(blue)) = (fit(&|e| e.red), fit(&|e| e.blue)) {
+            ret.chromatic_aberration = Some([red, blue]);
+        }
+    }
+
+    log::debug!("Lens color estimate: {:?}", ret);
+    ret
+}
@@ -0,0 +1,405 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Chessboard corner detection without OpenCV.
+// Inner corners of the chessboard are saddle points of the image intensity, so we find them using the Hessian,
+// verify them by sampling a circle around each candidate, then grow a grid from a seed corner and refine all points to sub-pixel accuracy.
+
+use std::collections::{ HashMap, VecDeque };
+use nalgebra::{ Matrix2, Vector2 };
+
+pub struct GrayImage {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<f32>,
+}
+impl GrayImage {
+    pub fn from_pixels(pixels: &[u8], width: usize, height: usize, stride: usize) -> Self {
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            data.extend(pixels[y * stride..y * stride + width].iter().map(|x| *x as f32));
+        }
+        Self { width, height, data }
+    }
+
+    #[inline]
+    fn at(&self, x: usize, y: usize) -> f32 {
+        self.data[y * self.width + x]
+    }
+
+    // Bilinear interpolation, coordinates are clamped to the image
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let x = x.clamp(0.0, (self.width - 1) as f32);
+        let y = y.clamp(0.0, (self.height - 1) as f32);
+        let x0 = (x as usize).min(self.width - 2);
+        let y0 = (y as usize).min(self.height - 2);
+        let fx = x - x0 as f32;
+        let fy = y - y0 as f32;
+        let top = self.at(x0, y0) * (1.0 - fx) + self.at(x0 + 1, y0) * fx;
+        let bottom = self.at(x0, y0 + 1) * (1.0 - fx) + self.at(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    pub fn gaussian_blur(&self, sigma: f32) -> Self {
+        let radius = (sigma * 3.0).ceil() as i32;
+        let mut kernel: Vec<f32> = (-radius..=radius).map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp()).collect();
+        let sum: f32 = kernel.iter().sum();
+        kernel.iter_mut().for_each(|k| *k /= sum);
+
+        let (w, h) = (self.width as i32, self.height as i32);
+        let mut tmp = vec![0.0f32; self.data.len()];
+        for y in 0..h {
+            for x in 0..w {
+                tmp[(y * w + x) as usize] = kernel.iter().enumerate().map(|(i, k)| k * self.at((x + i as i32 - radius).clamp(0, w - 1) as usize, y as usize)).sum();
+            }
+        }
+        let mut data = vec![0.0f32; self.data.len()];
+        for y in 0..h {
+            for x in 0..w {
+                data[(y * w + x) as usize] = kernel.iter().enumerate().map(|(i, k)| k * tmp[((y + i as i32 - radius).clamp(0, h - 1) * w + x) as usize]).sum();
+            }
+        }
+        Self { width: self.width, height: self.height, data }
+    }
+}
+
+// Finds `columns` x `rows` inner corners of a chessboard.
+// Returned points are ordered row by row, like in OpenCV's `findChessboardCorners`
+pub fn find_chessboard_corners(img: &GrayImage, columns: usize, rows: usize) -> Option<Vec<(f32, f32)>> {
+    if img.width < 16 || img.height < 16 || columns < 2 || rows < 2 {
+        return None;
+    }
+    let blurred = img.gaussian_blur(1.5);
+    let candidates = find_saddle_points(&blurred, columns * rows * 4);
+    if candidates.len() < columns * rows {
+        return None;
+    }
+
+    // Candidates are sorted by response, so start from the strongest ones, as they are most likely on the board
+    for seed in 0..candidates.len().min(10) {
+        if let Some(grid) = grow_grid(&candidates, seed, columns, rows) {
+            let mut points: Vec<(f32, f32)> = grid.iter().map(|i| (candidates[*i].x, candidates[*i].y)).collect();
+            let spacing = (candidates[grid[0]] - candidates[grid[1]]).norm();
+            let win = ((spacing / 4.0).round() as usize).clamp(2, 11);
+            refine_corners(img, &mut points, win);
+            return Some(points);
+        }
+    }
+    None
+}
+
+// Sub-pixel corner refinement, equivalent to OpenCV's `cornerSubPix`.
+// At the exact corner location, every image gradient in the window is orthogonal to the vector from the corner to the gradient's position
+pub fn refine_corners(img: &GrayImage, points: &mut [(f32, f32)], win: usize) {
+    let win = win as i32;
+    let sigma2 = (win * win) as f32 / 2.0;
+    for pt in points.iter_mut() {
+        let mut p = Vector2::new(pt.0, pt.1);
+        for _ in 0..20 {
+            let mut a = Matrix2::<f32>::zeros();
+            let mut b = Vector2::<f32>::zeros();
+            for dy in -win..=win {
+                for dx in -win..=win {
+                    let (x, y) = (p.x + dx as f32, p.y + dy as f32);
+                    let gx = (img.sample(x + 1.0, y) - img.sample(x - 1.0, y)) / 2.0;
+                    let gy = (img.sample(x, y + 1.0) - img.sample(x, y - 1.0)) / 2.0;
+                    let weight = (-((dx * dx + dy * dy) as f32) / sigma2).exp();
+                    let gg = Matrix2::new(gx * gx, gx * gy, gx * gy, gy * gy) * weight;
+                    a += gg;
+                    b += gg * Vector2::new(x, y);
+                }
+            }
+            let Some(new_p) = a.try_inverse().map(|inv| inv * b) else { break; };
+            if (new_p - p).norm() > win as f32 {
+                break; // Diverged, keep the previous position
+            }
+            let shift = (new_p - p).norm_squared();
+            p = new_p;
+            if shift < 1e-4 {
+                break;
+            }
+        }
+        *pt = (p.x, p.y);
+    }
+}
+
+// Saddle points have negative determinant of the Hessian. Returns up to `max_count` strongest ones which pass the circle test
+fn find_saddle_points(img: &GrayImage, max_count: usize) -> Vec<Vector2<f32>> {
+    let (w, h) = (img.width, img.height);
+    let mut response = vec![0.0f32; w * h];
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let c = img.at(x, y);
+            let dxx = img.at(x + 1, y) + img.at(x - 1, y) - 2.0 * c;
+            let dyy = img.at(x, y + 1) + img.at(x, y - 1) - 2.0 * c;
+            let dxy = (img.at(x + 1, y + 1) + img.at(x - 1, y - 1) - img.at(x + 1, y - 1) - img.at(x - 1, y + 1)) / 4.0;
+            response[y * w + x] = (dxy * dxy - dxx * dyy).max(0.0);
+        }
+    }
+    let max_response = response.iter().copied().fold(0.0f32, f32::max);
+    if max_response <= 0.0 {
+        return Vec::new();
+    }
+    let threshold = max_response * 0.02;
+
+    const NMS_RADIUS: usize = 3;
+    const BORDER: usize = 8;
+    let mut maxima = Vec::new();
+    for y in BORDER..h.saturating_sub(BORDER) {
+        for x in BORDER..w.saturating_sub(BORDER) {
+            let r = response[y * w + x];
+            if r < threshold {
+                continue;
+            }
+            let is_max = (y - NMS_RADIUS..=y + NMS_RADIUS).all(|yy| (x - NMS_RADIUS..=x + NMS_RADIUS).all(|xx| {
+                let r2 = response[yy * w + xx];
+                r2 < r || (r2 == r && (yy, xx) >= (y, x))
+            }));
+            if is_max {
+                maxima.push((r, x, y));
+            }
+        }
+    }
+    maxima.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    maxima.into_iter()
+        .map(|(_, x, y)| Vector2::new(x as f32, y as f32))
+        .filter(|p| is_chessboard_corner(img, *p))
+        .take(max_count)
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgePattern {
+    Chessboard,
+    Charuco, // Chessboard corners with markers close to them
+    Circles,
+}
+
+// Average width in pixels of the dark-bright edges around the detected points, on the same scale as OpenCV's `estimateChessboardSharpness`.
+// Edges are measured on a circle around the chessboard corners, and along rays from the center for the circle grids
+pub fn estimate_sharpness(img: &GrayImage, points: &[(f32, f32)], pattern: EdgePattern) -> f64 {
+    const STEP: f32 = 0.25;
+    if points.len() < 2 { return 100.0; }
+
+    // Sampled area is relative to the distance between the points, so it doesn't reach the neighbors or the ChArUco markers
+    let mut distances: Vec<f32> = points.iter().enumerate().map(|(i, a)| {
+        points.iter().enumerate().filter(|(j, _)| *j != i).map(|(_, b)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()).fold(f32::MAX, f32::min)
+    }).collect();
+    distances.sort_by(|a, b| a.total_cmp(b));
+    let spacing = distances[distances.len() / 2];
+
+    let mut widths = Vec::new();
+    for &(x, y) in points {
+        if pattern == EdgePattern::Circles {
+            let count = (spacing * 0.5 / STEP).ceil() as usize;
+            for i in 0..16 {
+                let (sin, cos) = (i as f32 / 16.0 * std::f32::consts::TAU).sin_cos();
+                let profile: Vec<f32> = (0..count).map(|j| img.sample(x + cos * j as f32 * STEP, y + sin * j as f32 * STEP)).collect();
+                widths.extend(edge_widths(&profile, STEP, false));
+            }
+        } else {
+            // ChArUco markers start at about 0.18 of the square from the corner
+            let radius = (spacing * if pattern == EdgePattern::Charuco { 0.15 } else { 0.3 }).max(3.0);
+            let count = (std::f32::consts::TAU * radius / STEP).ceil() as usize;
+            let profile: Vec<f32> = (0..count).map(|j| {
+                let (sin, cos) = (j as f32 / count as f32 * std::f32::consts::TAU).sin_cos();
+                img.sample(x + cos * radius, y + sin * radius)
+            }).collect();
+            widths.extend(edge_widths(&profile, STEP, true));
+        }
+    }
+    if widths.is_empty() { return 100.0; }
+    widths.iter().map(|x| *x as f64).sum::<f64>() / widths.len() as f64
+}
+
+// Distances between the 10% and 90% levels of every transition in the intensity profile
+fn edge_widths(profile: &[f32], step: f32, closed: bool) -> Vec<f32> {
+    let n = profile.len() as isize;
+    let (min, max) = profile.iter().fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+    if max - min < 20.0 { return Vec::new(); }
+    let (lo, mid, hi) = (min + (max - min) * 0.1, (min + max) / 2.0, max - (max - min) * 0.1);
+
+    let at = |i: isize| -> Option<f32> {
+        if closed { Some(profile[i.rem_euclid(n) as usize]) } else if (0..n).contains(&i) { Some(profile[i as usize]) } else { None }
+    };
+    let in_band = |i: isize| at(i).map_or(false, |v| v > lo && v < hi);
+    let crossings = if closed { n } else { n - 1 };
+    (0..crossings).filter(|&i| (at(i).unwrap() > mid) != (at(i + 1).unwrap() > mid)).filter_map(|i| {
+        let (mut a, mut b) = (i, i + 1);
+        while in_band(a) && i - a < n / 2 { a -= 1; }
+        while in_band(b) && b - i < n / 2 { b += 1; }
+        // Skip the edges which aren't fully inside the profile
+        if at(a).is_none() || at(b).is_none() || in_band(a) || in_band(b) { return None; }
+        Some((b - a) as f32 * step)
+    }).collect()
+}
+
+// A chessboard corner has exactly 4 transitions between dark and bright on a circle around it
+fn is_chessboard_corner(img: &GrayImage, p: Vector2<f32>) -> bool {
+    const SAMPLES: usize = 32;
+    [3.0, 5.0, 7.0].iter().any(|radius: &f32| {
+        let values: Vec<f32> = (0..SAMPLES).map(|i| {
+            let a = i as f32 / SAMPLES as f32 * std::f32::consts::TAU;
+            img.sample(p.x + a.cos() * radius, p.y + a.sin() * radius)
+        }).collect();
+        let (min, max) = values.iter().fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+        if max - min < 20.0 {
+            return false;
+        }
+        let mid = (min + max) / 2.0;
+        let transitions = (0..SAMPLES).filter(|i| (values[*i] > mid) != (values[(i + 1) % SAMPLES] > mid)).count();
+        transitions == 4
+    })
+}
+
+// Assigns grid coordinates to candidates, starting from `seed` and predicting each next corner from its neighbors
+fn grow_grid(candidates: &[Vector2<f32>], seed: usize, columns: usize, rows: usize) -> Option<Vec<usize>> {
+    let origin = candidates[seed];
+    let nearest = |from: Vector2<f32>, filter: &dyn Fn(Vector2<f32>) -> bool| -> Option<usize> {
+        (0..candidates.len())
+            .filter(|i| *i != seed && filter(candidates[*i] - from))
+            .min_by(|a, b| (candidates[*a] - from).norm_squared().total_cmp(&(candidates[*b] - from).norm_squared()))
+    };
+    let u = candidates[nearest(origin, &|_| true)?] - origin;
+    let v = candidates[nearest(origin, &|d| (d.normalize().dot(&u.normalize())).abs() < 0.5)?] - origin;
+    if v.norm() > u.norm() * 2.0 {
+        return None;
+    }
+
+    let mut grid: HashMap<(i32, i32), usize> = HashMap::new();
+    let mut used = vec![false; candidates.len()];
+    grid.insert((0, 0), seed);
+    used[seed] = true;
+
+    let mut queue = VecDeque::from([(0i32, 0i32)]);
+    while let Some((i, j)) = queue.pop_front() {
+        let p = candidates[grid[&(i, j)]];
+        for (di, dj) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let next = (i + di, j + dj);
+            if grid.contains_key(&next) {
+                continue;
+            }
+            // Extrapolate from the previous corner in the same direction, or use the step of a neighboring corner
+            let step = if let Some(prev) = grid.get(&(i - di, j - dj)) {
+                p - candidates[*prev]
+            } else if let Some((a, b)) = [(i + dj, j + di), (i - dj, j - di)].iter().find_map(|(ni, nj)| Some((grid.get(&(*ni, *nj))?, grid.get(&(ni + di, nj + dj))?))) {
+                candidates[*b] - candidates[*a]
+            } else {
+                u * di as f32 + v * dj as f32
+            };
+            let predicted = p + step;
+            let max_dist = step.norm() * 0.3;
+            let found = (0..candidates.len())
+                .filter(|k| !used[*k])
+                .map(|k| (k, (candidates[k] - predicted).norm()))
+                .filter(|(_, d)| *d < max_dist)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((k, _)) = found {
+                grid.insert(next, k);
+                used[k] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let min_i = grid.keys().map(|k| k.0).min()?;
+    let max_i = grid.keys().map(|k| k.0).max()?;
+    let min_j = grid.keys().map(|k| k.1).min()?;
+    let max_j = grid.keys().map(|k| k.1).max()?;
+    let (size_i, size_j) = ((max_i - min_i + 1) as usize, (max_j - min_j + 1) as usize);
+    if grid.len() != columns * rows || size_i * size_j != columns * rows {
+        return None;
+    }
+
+    // Map the grid axes to columns and rows
+    let transpose = if columns == rows {
+        // Square grid, use the axis which is closer to horizontal as columns
+        u.x.abs() < v.x.abs()
+    } else if size_i == columns && size_j == rows {
+        false
+    } else if size_i == rows && size_j == columns {
+        true
+    } else {
+        return None;
+    };
+
+    let mut ret = Vec::with_capacity(columns * rows);
+    for r in 0..rows as i32 {
+        for c in 0..columns as i32 {
+            let key = if transpose { (min_i + r, min_j + c) } else { (min_i + c, min_j + r) };
+            ret.push(*grid.get(&key)?);
+        }
+    }
+
+    // Order rows top to bottom and columns left to right
+    let first_row = candidates[ret[columns - 1]] - candidates[ret[0]];
+    let first_col = candidates[ret[(rows - 1) * columns]] - candidates[ret[0]];
+    if first_row.x < 0.0 {
+        ret.chunks_mut(columns).for_each(|row| row.reverse());
+    }
+    if first_col.y < 0.0 {
+        let reversed: Vec<usize> = ret.chunks(columns).rev().flatten().copied().collect();
+        ret = reversed;
+    }
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Chessboard with `columns` x `rows` inner corners and `square` pixels large squares, rotated by `angle` around the image center.
+    // Returns the image rendered with 4x4 supersampling, and the exact corner positions ordered row by row
+    fn render_board(columns: usize, rows: usize, square: f32, angle: f32, size: (usize, usize)) -> (GrayImage, Vec<(f32, f32)>) {
+        let (sin, cos) = angle.sin_cos();
+        let center = (size.0 as f32 / 2.0, size.1 as f32 / 2.0);
+        let origin = ((columns - 1) as f32 / 2.0, (rows - 1) as f32 / 2.0);
+        let to_image = |u: f32, v: f32| {
+            let (x, y) = ((u - origin.0) * square, (v - origin.1) * square);
+            (center.0 + x * cos - y * sin, center.1 + x * sin + y * cos)
+        };
+        let to_board = |x: f32, y: f32| {
+            let (x, y) = (x - center.0, y - center.1);
+            ((x * cos + y * sin) / square + origin.0, (-x * sin + y * cos) / square + origin.1)
+        };
+
+        let mut data = Vec::with_capacity(size.0 * size.1);
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                let mut sum = 0.0;
+                for s in 0..16 {
+                    // Pixel centers are at integer coordinates
+                    let (u, v) = to_board(x as f32 + (s % 4) as f32 / 4.0 - 0.375, y as f32 + (s / 4) as f32 / 4.0 - 0.375);
+                    let on_board = u >= -1.0 && u < columns as f32 && v >= -1.0 && v < rows as f32;
+                    let is_dark = on_board && (u.floor() as i32 + v.floor() as i32).rem_euclid(2) == 0;
+                    sum += if is_dark { 20.0 } else { 230.0 };
+                }
+                data.push(sum / 16.0);
+            }
+        }
+        let corners = (0..rows).flat_map(|r| (0..columns).map(move |c| (c, r))).map(|(c, r)| to_image(c as f32, r as f32)).collect();
+        (GrayImage { width: size.0, height: size.1, data }, corners)
+    }
+
+    #[test]
+    fn finds_corners_of_synthetic_board() {
+        for angle in [0.0f32, 0.15, -0.3] {
+            let (img, expected) = render_board(9, 6, 40.0, angle, (640, 480));
+            let found = find_chessboard_corners(&img, 9, 6).unwrap_or_else(|| panic!("Board not found at angle {angle}"));
+            assert_eq!(found.len(), expected.len());
+            for (a, b) in found.iter().zip(expected.iter()) {
+                let dist = ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+                assert!(dist < 0.15, "Corner {a:?} is {dist} px from {b:?} at angle {angle}");
+            }
+        }
+    }
+
+    #[test]
+    fn sharpness_increases_with_blur() {
+        let (img, corners) = render_board(9, 6, 40.0, 0.1, (640, 480));
+        let sharp = estimate_sharpness(&img, &corners, EdgePattern::Chessboard);
+        let blurry = estimate_sharpness(&img.gaussian_blur(3.0), &corners, EdgePattern::Chessboard);
+        assert!(sharp < 3.0, "Sharp board: {sharp}");
+        assert!(blurry > sharp * 2.0, "Sharp: {sharp}, blurry: {blurry}");
+    }
+}
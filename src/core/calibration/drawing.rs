@@ -3,18 +3,18 @@
 
 use crate::gpu::drawing::*;
 
+const LINE_COLORS: &[Color] = &[
+    Color::Red,     // #ff0000
+    Color::Blue2,   // #0080ff
+    Color::Yellow2, // #C8C800
+    Color::Green,   // #00ff00
+    Color::Blue3,   // #00C8C8
+    Color::Blue,    // #0000ff
+    Color::Magenta  // #ff00ff
+];
+
 // Ported from OpenCV: https://github.com/opencv/opencv/blob/4.x/modules/calib3d/src/calibinit.cpp#L2078
 pub fn draw_chessboard_corners(org_width: usize, org_height: usize, w: usize, h: usize, drawing: &mut DrawCanvas, pattern_size: (usize, usize), corners: &[(f32, f32)], found: bool, inverted: bool) {
-    const LINE_COLORS: &[Color] = &[
-        Color::Red,     // #ff0000
-        Color::Blue2,   // #0080ff
-        Color::Yellow2, // #C8C800
-        Color::Green,   // #00ff00
-        Color::Blue3,   // #00C8C8
-        Color::Blue,    // #0000ff
-        Color::Magenta  // #ff00ff
-    ];
-
     let ratio_w = w as f32 / org_width as f32;
     let ratio_h = h as f32 / org_height as f32;
     let r = 10.0 * ratio_w;
@@ -53,6 +53,57 @@ pub fn draw_chessboard_corners(org_width: usize, org_height: usize, w: usize, h:
     }
 }
 
+// Partially visible board (ChArUco), `ids` are the corner indices in the full pattern
+pub fn draw_partial_corners(org_width: usize, org_height: usize, w: usize, h: usize, drawing: &mut DrawCanvas, pattern_size: (usize, usize), corners: &[(f32, f32)], ids: &[usize], inverted: bool) {
+    let ratio_w = w as f32 / org_width as f32;
+    let ratio_h = h as f32 / org_height as f32;
+    let r = 10.0 * ratio_w;
+    let mut prev: Option<(usize, (f32, f32))> = None;
+    for (pt, id) in corners.iter().zip(ids.iter()) {
+        let row = id / pattern_size.0.max(1);
+        let color = LINE_COLORS[row % LINE_COLORS.len()];
+        let mut pt = ((pt.0 * ratio_w).round(), (pt.1 * ratio_h).round());
+        if inverted {
+            pt.1 = h as f32 - pt.1;
+        }
+        // Connect only neighbouring corners in the same row
+        if let Some((prev_id, prev_pt)) = prev {
+            if prev_id + 1 == *id && prev_id / pattern_size.0.max(1) == row {
+                line(drawing, prev_pt, pt, color);
+            }
+        }
+        line(drawing, (pt.0 - r, pt.1 - r), (pt.0 + r, pt.1 + r), color);
+        line(drawing, (pt.0 - r, pt.1 + r), (pt.0 + r, pt.1 - r), color);
+        circle(drawing, pt, r + 1.0, color);
+        prev = Some((*id, pt));
+    }
+}
+
+// Heatmap of the cells where corners of the used frames landed. Red cells have no corners, yellow have only few of them
+pub fn draw_coverage(w: usize, h: usize, drawing: &mut DrawCanvas, report: &super::report::CalibrationReport, inverted: bool) {
+    let (gw, gh) = report.coverage_grid;
+    if gw == 0 || gh == 0 || report.coverage.len() != gw * gh {
+        return;
+    }
+    let covered = report.coverage.iter().filter(|x| **x > 0).count().max(1);
+    let avg = report.coverage.iter().sum::<u32>() as f32 / covered as f32;
+    let step = drawing.scale.max(1);
+    for cy in 0..gh {
+        for cx in 0..gw {
+            let count = report.coverage[cy * gw + cx];
+            let color = if count == 0 { Color::Red } else if (count as f32) < avg * 0.25 { Color::Yellow } else { continue; };
+            let (x0, x1) = (cx * w / gw, (cx + 1) * w / gw);
+            let (y0, y1) = (cy * h / gh, (cy + 1) * h / gh);
+            for y in (y0..y1).step_by(step) {
+                let y = if inverted { h - y } else { y };
+                for x in (x0..x1).step_by(step) {
+                    drawing.put_pixel(x as i32, y as i32, color, Alpha::Alpha25, Stage::OnInput, false, 1);
+                }
+            }
+        }
+    }
+}
+
 fn line(drawing: &mut DrawCanvas, p1: (f32, f32), p2: (f32, f32), color: Color) {
     let points = line_drawing::Bresenham::new((p1.0 as isize, p1.1 as isize), (p2.0 as isize, p2.1 as isize));
     draw_pixels(drawing, color, points);
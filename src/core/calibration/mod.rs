@@ -4,39 +4,127 @@
 /// The basic idea here is to find chessboard every 10 frames and save all points to a map.
 /// Then we pick a random 10 frames from that map and calculate the calibration.
 /// Repeat that 1000 times, with new random set of frames each time and return the set which resulted in the lowest RMS
+///
+/// Besides the chessboard, ChArUco boards and asymmetric circle grids are supported.
+/// ChArUco corners are identified by the markers, so the board doesn't need to be fully visible in the frame.
+///
+/// Without OpenCV, chessboard corners are detected by `corners` and the camera is calibrated with the solver in `solver`.
+///
+/// Multiple videos (different resolutions or crops of the same sensor, or focal lengths of a zoom lens) can be calibrated together.
+/// After calibrating each video, it's added with `add_source` and `calibrate_joint` solves for a shared lens model with per-video scale and offset.
+///
+/// If the video has gyro data, the frame readout time can be estimated from the skew of the detected targets with `estimate_rolling_shutter`.
+/// Vignetting and lateral chromatic aberration are estimated in `color` from the white squares and the edges of the target, if the frames are fed with color.
+/// Without a target, `self_calibration` estimates a provisional lens profile from the optical flow and the gyro rotation between frames.
 
 #[cfg(feature = "use-opencv")]
 use opencv::{
-    core::{ Mat, Size, Point2f, Vector, Point3d, TermCriteria, TermCriteria_Type, CV_8UC1 },
-    prelude::MatTraitConst,
-    calib3d::{ CALIB_CB_MARKER, Fisheye_CALIB_RECOMPUTE_EXTRINSIC, Fisheye_CALIB_FIX_SKEW }
+    core::{ Mat, Size, Point2f, Vector, Point3d, Point3f, TermCriteria, TermCriteria_Type, CV_8UC1 },
+    prelude::{ MatTraitConst, CharucoDetectorTraitConst },
+    calib3d::{ CALIB_CB_MARKER, CALIB_CB_ASYMMETRIC_GRID, Fisheye_CALIB_RECOMPUTE_EXTRINSIC, Fisheye_CALIB_FIX_SKEW, CALIB_ZERO_TANGENT_DIST, CALIB_FIX_K2, CALIB_FIX_K3 }
 };
 
 use rand::prelude::IteratorRandom;
-use std::{ ffi::c_void, collections::{ BTreeSet, BTreeMap, HashSet } };
+use std::collections::{ BTreeSet, BTreeMap, HashSet };
 use std::sync::atomic::{ AtomicBool, AtomicUsize, Ordering::SeqCst };
 use std::sync::Arc;
-use nalgebra::{ Matrix3, Vector4 };
+use nalgebra::{ Matrix3, Vector3 };
 use parking_lot::RwLock;
 use rayon::iter::{ ParallelIterator, IntoParallelIterator };
 
 use crate::stabilization::distortion_models::DistortionModel;
 
 pub mod drawing;
+pub mod corners;
+pub mod solver;
+pub mod report;
+pub mod rolling_shutter;
+pub mod self_calibration;
+pub mod color;
+
+#[derive(thiserror::Error, Debug)]
+pub enum CalibrationError {
+    #[cfg(feature = "use-opencv")]
+    #[error("OpenCV error: {0}")]
+    OpenCV(#[from] opencv::Error),
+    #[error("Calibration target not found")]
+    TargetNotFound,
+    #[error("{0} is not supported by the built-in calibrator")]
+    Unsupported(&'static str),
+    #[error("Unable to calibrate camera")]
+    CalibrationFailed,
+    #[error("Not enough data: {0}")]
+    NotEnoughData(&'static str),
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum CalibrationTarget {
+    #[default]
+    Chessboard,
+    Charuco, // `columns` x `rows` inner corners, so the board has one more square in each direction
+    AsymmetricCircles,
+}
+impl From<i32> for CalibrationTarget {
+    fn from(v: i32) -> Self {
+        match v {
+            0 => Self::Chessboard,
+            1 => Self::Charuco,
+            2 => Self::AsymmetricCircles,
+            _ => { log::error!("Invalid calibration target: {v}"); Self::Chessboard }
+        }
+    }
+}
+
+// Minimum number of ChArUco corners in a frame to use it for calibration
+const MIN_CHARUCO_CORNERS: usize = 12;
 
 #[derive(Clone, Default, Debug)]
 pub struct Detected {
     pub points: Vec<(f32, f32)>,
+    pub ids: Vec<usize>, // Indices in `objp` for partially visible boards, empty if all points were detected
     pub frame: i32,
     pub timestamp_us: i64,
     pub avg_sharpness: f64,
     pub is_forced: bool
 }
+// Detections of a calibrated video, kept for the joint calibration
+#[derive(Clone, Default, Debug)]
+pub struct CalibrationSource {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub focal_length: Option<f64>,
+    pub objp: Vec<(f64, f64)>,
+    pub points: BTreeMap<i32, Detected>, // frame, Detected. Frames used for the calibration of this video
+}
+
+#[derive(Clone, Debug)]
+pub struct JointCalibrationMode {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub focal_length: Option<f64>,
+    pub num_images: usize,
+    pub rms: f64,
+    pub k: Matrix3<f64>,
+    pub d: Vec<f64>, // Coefficients of `distortion_model`
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct JointCalibration {
+    pub rms: f64, // Highest RMS of all focal lengths
+    pub modes: Vec<JointCalibrationMode>, // In the same order as `LensCalibrator::sources`
+}
+
 #[derive(Default)]
 pub struct LensCalibrator {
     pub rows: usize,
     pub columns: usize,
 
+    pub target: CalibrationTarget,
+    pub charuco_dictionary: i32, // cv::aruco::PredefinedDictionaryType
+    pub charuco_marker_ratio: f32, // marker size / square size
+
     pub width: usize,
     pub height: usize,
 
@@ -48,7 +136,22 @@ pub struct LensCalibrator {
     pub objp: Vec<(f64, f64)>,
 
     pub k: Matrix3<f64>,
-    pub d: Vector4<f64>,
+    pub d: Vec<f64>, // Coefficients of `distortion_model`
+    pub raw_d: Vec<f64>, // Coefficients in OpenCV layout, before converting to `distortion_model`
+
+    pub distortion_model: Option<String>, // None means OpenCV Fisheye
+
+    pub use_opencv: bool, // Use OpenCV for detection and calibration if available, otherwise the built-in implementation
+
+    pub report: Option<report::CalibrationReport>,
+    pub show_coverage: bool,
+
+    pub rolling_shutter: Option<rolling_shutter::RollingShutterEstimate>,
+
+    pub lens_color: Option<color::LensColorEstimate>,
+    pub color_samples: Arc<RwLock<BTreeMap<i32, color::ColorSamples>>>, // frame, ColorSamples
+
+    pub sources: Vec<CalibrationSource>,
 
     pub sum_sharpness: Arc<RwLock<f64>>,
     pub r_limit: f64,
@@ -75,6 +178,9 @@ impl LensCalibrator {
             columns: 14,
             rows: 8,
 
+            charuco_dictionary: 2, // DICT_4X4_250
+            charuco_marker_ratio: 0.75,
+
             max_images: 10,
             iterations: 1000,
 
@@ -84,41 +190,72 @@ impl LensCalibrator {
             width: 0,
             height: 0,
 
+            use_opencv: cfg!(feature = "use-opencv"),
+
             ..Default::default()
         };
 
-        for y in 0..ret.rows {
-            for x in 0..ret.columns {
-                ret.objp.push((x as f64, y as f64));
-            }
-        }
+        ret.update_object_points();
 
         ret
     }
 
+    pub fn set_pattern(&mut self, target: CalibrationTarget, columns: usize, rows: usize) {
+        if self.target != target || self.columns != columns || self.rows != rows {
+            self.target = target;
+            self.columns = columns;
+            self.rows = rows;
+            self.update_object_points();
+            self.clear();
+        }
+    }
+
+    fn update_object_points(&mut self) {
+        self.objp.clear();
+        for y in 0..self.rows {
+            for x in 0..self.columns {
+                if self.target == CalibrationTarget::AsymmetricCircles {
+                    // Every other row is shifted by half of the spacing
+                    self.objp.push(((2 * x + y % 2) as f64, y as f64));
+                } else {
+                    self.objp.push((x as f64, y as f64));
+                }
+            }
+        }
+    }
+
     pub fn clear(&mut self) {
         self.all_matches.write().clear();
         self.image_points.write().clear();
         self.used_points.clear();
+        self.report = None;
+        self.rolling_shutter = None;
+        self.color_samples.write().clear();
+        self.lens_color = None;
     }
 
-    pub fn feed_frame<F>(&mut self, timestamp_us: i64, frame: i32, size: (u32, u32), org_size: (u32, u32), stride: usize, pt_scale: f32, pixels: &[u8], cancel_flag: Arc<AtomicBool>, total: usize, processed_imgs: Arc<AtomicUsize>, progress: F)
+    // `rgb_pixels` is an optional RGB24 copy of the frame (pixels, stride) with the same size, used to estimate the vignetting and chromatic aberration
+    pub fn feed_frame<F>(&mut self, timestamp_us: i64, frame: i32, size: (u32, u32), org_size: (u32, u32), stride: usize, pt_scale: f32, pixels: &[u8], rgb_pixels: Option<(&[u8], usize)>, cancel_flag: Arc<AtomicBool>, total: usize, processed_imgs: Arc<AtomicUsize>, progress: F)
     where F: Fn((usize, usize, usize, f64, f64)) + Send + Sync + Clone + 'static {
 
         self.width = org_size.0 as usize;
         self.height = org_size.1 as usize;
-        let grid_size = Size::new(self.columns as i32, self.rows as i32);
+        let grid_size = (self.columns, self.rows);
         let max_sharpness = self.max_sharpness;
 
         let mut pixels = pixels.to_vec();
+        let rgb_pixels = rgb_pixels.map(|(p, s)| (p.to_vec(), s));
         let img_points = self.image_points.clone();
+        let color_samples = self.color_samples.clone();
         let all_matches = self.all_matches.clone();
         let is_forced = self.forced_frames.contains(&frame);
         let sum_sharpness = self.sum_sharpness.clone();
 
         let digital_lens = self.digital_lens.as_ref().map(|x| DistortionModel::from_name(&x));
         let digital_lens_params_opt = self.digital_lens_params.clone();
-        let no_marker = self.no_marker;
+        let target = self.target;
+        #[cfg(feature = "use-opencv")]
+        let (use_opencv, no_marker, charuco_dictionary, charuco_marker_ratio) = (self.use_opencv, self.no_marker, self.charuco_dictionary, self.charuco_marker_ratio);
 
         if let Some(detected) = all_matches.read().get(&frame) {
             if detected.avg_sharpness < max_sharpness {
@@ -130,7 +267,7 @@ impl LensCalibrator {
         }
 
         crate::run_threaded(move || {
-            let avg_sharpness = (|| -> Result<f64, opencv::Error> {
+            let avg_sharpness = (|| -> Result<f64, CalibrationError> {
                 if cancel_flag.load(std::sync::atomic::Ordering::Relaxed) {
                     return Ok(0.0);
                 }
@@ -142,23 +279,21 @@ impl LensCalibrator {
                     *px = (*px as f64 * contrast + brightness).min(255.0) as u8;
                 }
 
-                let inp1 = unsafe { Mat::new_size_with_data(Size::new(size.0 as i32, size.1 as i32), CV_8UC1, pixels.as_ptr() as *mut c_void, stride as usize)? };
-                let mut inp = unsafe { Mat::new_size_with_data(Size::new(size.0 as i32, size.1 as i32), CV_8UC1, pixels.as_ptr() as *mut c_void, stride as usize)? };
-
-                let _ = opencv::imgproc::equalize_hist(&inp1, &mut inp);
-
-                let mut corners = Mat::default();
-
-                let mut flags = CALIB_CB_MARKER;
-                if no_marker {
-                    flags = 0;
-                }
-
-                if opencv::calib3d::find_chessboard_corners_sb(&inp, grid_size, &mut corners, flags)? {
-                    if corners.rows() > 0 {
-                        let sharpness = opencv::calib3d::estimate_chessboard_sharpness(&inp, grid_size, &corners, 0.8, false, &mut Mat::default()).unwrap_or_default();
-                        let avg_sharpness = *sharpness.get(0).unwrap_or(&100.0);
-                        let mut points = Vec::with_capacity(corners.rows() as usize);
+                #[cfg(feature = "use-opencv")]
+                let detected = if use_opencv {
+                    detect_opencv(&mut pixels, size, stride, target, grid_size, no_marker, charuco_dictionary, charuco_marker_ratio)?
+                } else {
+                    detect_rust(&pixels, size, stride, target, grid_size)?
+                };
+                #[cfg(not(feature = "use-opencv"))]
+                let detected = detect_rust(&pixels, size, stride, target, grid_size)?;
+
+                if let Some((corners, ids, avg_sharpness)) = detected {
+                    if !corners.is_empty() {
+                        if let Some((rgb, rgb_stride)) = &rgb_pixels {
+                            color_samples.write().insert(frame, color::sample_frame(rgb, size, *rgb_stride, target, grid_size, &corners, &ids, pt_scale as f64));
+                        }
+                        let mut points = Vec::with_capacity(corners.len());
 
                         let mut digital_lens_params = [0f32; 4];
                         if let Some(p) = digital_lens_params_opt {
@@ -176,54 +311,55 @@ impl LensCalibrator {
                             ..Default::default()
                         };
 
-                        for (_pos, mut pt) in corners.iter::<Point2f>()? {
+                        for mut pt in corners {
                             if let Some(digital) = &digital_lens {
-                                if let Some(mut pt2) = digital.undistort_point((pt.x,  pt.y), &kernel_params) {
+                                if let Some(pt2) = digital.undistort_point(pt, &kernel_params) {
                                     // TODO
                                     // Move from center to the left, because we trim the right part making it 4:3
                                     //pt2.0 -= 0.125; // (16-4) / (9-3) / 16
 
-                                    pt = Point2f::new(pt2.0, pt2.1);
+                                    pt = pt2;
                                 }
                             }
-                            points.push((pt.x * pt_scale, pt.y * pt_scale));
+                            points.push((pt.0 * pt_scale, pt.1 * pt_scale));
                         }
                         log::debug!("avg sharpness: {:.5}, max: {:.5}", avg_sharpness, max_sharpness);
                         if avg_sharpness < max_sharpness || is_forced {
-                            img_points.write().insert(frame, Detected { points: points.clone(), timestamp_us, frame, avg_sharpness, is_forced });
+                            img_points.write().insert(frame, Detected { points: points.clone(), ids: ids.clone(), timestamp_us, frame, avg_sharpness, is_forced });
                             *sum_sharpness.write() += avg_sharpness;
                         }
-                        all_matches.write().insert(frame, Detected { points, timestamp_us, avg_sharpness, frame, is_forced });
+                        all_matches.write().insert(frame, Detected { points, ids, timestamp_us, avg_sharpness, frame, is_forced });
                         return Ok(avg_sharpness);
                     }
                 }
-                Err(opencv::Error::new(0, "Chessboard not found".to_string()))
+                Err(CalibrationError::TargetNotFound)
             })();
             progress((processed_imgs.fetch_add(1, SeqCst) + 1, total, img_points.read().len(), 0.0, avg_sharpness.unwrap_or(0.0)));
         });
     }
 
-    pub fn calibrate(&mut self, only_used: bool) -> Result<(), opencv::Error> {
-        let calib_criteria = TermCriteria::new(TermCriteria_Type::EPS as i32 | TermCriteria_Type::COUNT as i32, 30, 1e-6)?;
-
+    pub fn calibrate(&mut self, only_used: bool) -> Result<(), CalibrationError> {
         let found_frames: BTreeSet<i32> = if only_used {
             self.used_points.keys().copied().collect()
         } else {
             self.image_points.read().keys().copied().collect()
         };
 
-        let find_min = |a: (f64, Matrix3::<f64>, Vector4::<f64>, Vec<i32>), b: (f64, Matrix3::<f64>, Vector4::<f64>, Vec<i32>)| -> (f64, Matrix3::<f64>, Vector4::<f64>, Vec<i32>) { if a.0 < b.0 { a } else { b } };
+        let find_min = |a: (f64, Matrix3::<f64>, Vec::<f64>, Vec<i32>), b: (f64, Matrix3::<f64>, Vec::<f64>, Vec<i32>)| -> (f64, Matrix3::<f64>, Vec::<f64>, Vec<i32>) { if a.0 < b.0 { a } else { b } };
 
         let image_points = self.image_points.read().clone();
-        let mut width = self.width as i32;
+        let mut width = self.width;
         if let Some(digital) = self.digital_lens.as_ref().map(|x| DistortionModel::from_name(&x)) {
             // TODO
-            //width = (width as f32 / 1.33333333).round() as i32;
+            //width = (width as f32 / 1.33333333).round() as usize;
         }
-        let size = Size::new(width, self.height as i32);
+        let size = (width, self.height);
         let objp = self.objp.clone();
         let max_images = self.max_images;
         let forced_frames = self.forced_frames.clone();
+        let distortion_model = self.distortion_model.clone().unwrap_or_else(|| "opencv_fisheye".into());
+        #[cfg(feature = "use-opencv")]
+        let use_opencv = self.use_opencv;
 
         let mut iterations = self.iterations;
         if found_frames.len() <= max_images || max_images == 0 || only_used {
@@ -261,53 +397,295 @@ impl LensCalibrator {
             let final_frames: Vec<i32> = candidate_frames.iter().chain(forced_frames.iter()).filter_map(|k| Some(image_points.get(k)?.frame)).collect();
 
             if final_frames.len() == 1 {
-                return (999.0000, Matrix3::<f64>::default(), Vector4::<f64>::default(), final_frames);
+                return (999.0000, Matrix3::<f64>::default(), Vec::new(), final_frames);
             }
 
-            let imgpoints = Vector::<Vector<Point2f>>::from_iter(
-                final_frames.iter().filter_map(|k| Some(Vector::from_iter(
-                    image_points.get(k)?.points.iter().map(|(x, y)| Point2f::new(*x as f32, *y as f32))
-                ))
-            ));
-            let objpoints = Vector::<Vector<Point3d>>::from_iter(
-                (0..imgpoints.len()).into_iter().map(|_| Vector::<Point3d>::from_iter(
-                    objp.iter().map(|(x, y)| Point3d::new(*x, *y, 0.0))
-                ))
-            );
-
-            let mut k  = Mat::default(); let mut d  = Mat::default();
-            let mut rv = Mat::default(); let mut tv = Mat::default();
-            // let mut nop = Mat::default();
-
-            // match opencv::calib3d::calibrate_camera_ro(&objpoints, &imgpoints, size, 13, &mut k, &mut d, &mut rv, &mut tv, &mut nop, Fisheye_CALIB_RECOMPUTE_EXTRINSIC | Fisheye_CALIB_FIX_SKEW, calib_criteria) {
-            match opencv::calib3d::calibrate(&objpoints, &imgpoints, size, &mut k, &mut d, &mut rv, &mut tv, Fisheye_CALIB_RECOMPUTE_EXTRINSIC | Fisheye_CALIB_FIX_SKEW, calib_criteria) {
-                Ok(rms) => {
-                    if let Ok(k) = cv_to_mat3(k) {
-                        if let Ok(d) = cv_to_vec4(d) {
-                            return (rms, k, d, final_frames);
-                        }
-                    }
+            let detections: Vec<&Detected> = final_frames.iter().filter_map(|k| image_points.get(k)).collect();
+
+            #[cfg(feature = "use-opencv")]
+            let result = if use_opencv {
+                calibrate_opencv(&detections, &objp, size, &distortion_model)
+            } else {
+                calibrate_rust(&detections, &objp, size, &distortion_model)
+            };
+            #[cfg(not(feature = "use-opencv"))]
+            let result = calibrate_rust(&detections, &objp, size, &distortion_model);
+
+            match result {
+                Ok((rms, k, d)) => {
+                    return (rms, k, d, final_frames);
                 },
                 Err(e) => {
                     log::warn!("Failed to calibrate! {:?}", e);
                 }
             }
-            (999.0000, Matrix3::<f64>::default(), Vector4::<f64>::default(), Vec::new())
+            (999.0000, Matrix3::<f64>::default(), Vec::new(), Vec::new())
         }).reduce_with(find_min);
 
         if let Some((rms, k, d, used_frames)) = result {
             self.k = k;
-            self.d = d;
+            self.d = convert_coefficients(&distortion_model, d.clone(), &k, size);
             self.rms = rms;
+            self.raw_d = d.clone();
             self.used_points = used_frames.into_iter().filter_map(|f| Some((f, image_points.get(&f)?.clone()))).collect();
+            self.report = Some(self.build_report(&d, &distortion_model));
+
+            let color_samples = self.color_samples.read();
+            let color_frames: Vec<&color::ColorSamples> = image_points.keys().filter_map(|f| color_samples.get(f)).collect();
+            self.lens_color = if color_frames.is_empty() { None } else { Some(color::estimate(&color_frames, (k[(0, 2)], k[(1, 2)]), size)) };
 
             Ok(())
         } else {
-            Err(opencv::Error::new(0, "Unable to calibrate camera".to_string()))
+            Err(CalibrationError::CalibrationFailed)
+        }
+    }
+
+    // Keeps the frames used in the current calibration for `calibrate_joint`
+    pub fn add_source(&mut self, name: String, focal_length: Option<f64>) -> bool {
+        if self.used_points.is_empty() || self.width == 0 || self.height == 0 {
+            return false;
+        }
+        self.sources.push(CalibrationSource {
+            name,
+            width: self.width,
+            height: self.height,
+            focal_length,
+            objp: self.objp.clone(),
+            points: self.used_points.clone(),
+        });
+        true
+    }
+
+    // Sources with the same focal length share the lens model and differ only by scale and offset.
+    // Different focal lengths are calibrated separately
+    pub fn calibrate_joint(&self) -> Result<JointCalibration, CalibrationError> {
+        let distortion_model = self.distortion_model.clone().unwrap_or_else(|| "opencv_fisheye".into());
+        let model = camera_model(&distortion_model);
+
+        let mut groups: BTreeMap<Option<i64>, Vec<usize>> = BTreeMap::new();
+        for (i, source) in self.sources.iter().enumerate() {
+            groups.entry(source.focal_length.map(|x| (x * 1000.0).round() as i64)).or_default().push(i);
+        }
+
+        let mut modes: Vec<Option<JointCalibrationMode>> = vec![None; self.sources.len()];
+        let mut rms = 0.0f64;
+        for indices in groups.values() {
+            let points: Vec<Vec<(Vec<(f64, f64)>, Vec<(f64, f64)>)>> = indices.iter().map(|i| {
+                let source = &self.sources[*i];
+                source.points.values().map(|detected| {
+                    (object_points(&source.objp, detected), detected.points.iter().map(|(x, y)| (*x as f64, *y as f64)).collect())
+                }).collect()
+            }).collect();
+            let solver_sources: Vec<solver::Source> = indices.iter().zip(points.iter()).map(|(i, views)| solver::Source {
+                views: views.iter().map(|(o, i)| solver::View { object_points: o, image_points: i, point_rotations: &[] }).collect(),
+                size: (self.sources[*i].width, self.sources[*i].height)
+            }).collect();
+
+            let result = solver::calibrate_joint(&solver_sources, model).ok_or(CalibrationError::CalibrationFailed)?;
+            rms = rms.max(result.rms);
+            for (j, i) in indices.iter().enumerate() {
+                let source = &self.sources[*i];
+                let k = result.transforms[j].apply(&result.k);
+                modes[*i] = Some(JointCalibrationMode {
+                    name: source.name.clone(),
+                    width: source.width,
+                    height: source.height,
+                    focal_length: source.focal_length,
+                    num_images: source.points.len(),
+                    rms: result.per_source_rms[j],
+                    d: convert_coefficients(&distortion_model, result.d.clone(), &k, (source.width, source.height)),
+                    k
+                });
+            }
+        }
+        Ok(JointCalibration { rms, modes: modes.into_iter().flatten().collect() })
+    }
+
+    // Fits the frame readout time to all detected frames. `angular_velocity` returns the camera angular velocity (rad/ms) for the frame number and timestamp
+    pub fn estimate_rolling_shutter<F>(&mut self, angular_velocity: F, max_readout_time: f64) -> Result<rolling_shutter::RollingShutterEstimate, CalibrationError>
+    where F: Fn(i32, i64) -> Option<Vector3<f64>> {
+        if self.raw_d.is_empty() {
+            return Err(CalibrationError::CalibrationFailed);
+        }
+        let model = camera_model(self.distortion_model.as_deref().unwrap_or("opencv_fisheye"));
+        let frames: Vec<rolling_shutter::RollingShutterFrame> = self.all_matches.read().values().filter_map(|detected| {
+            Some(rolling_shutter::RollingShutterFrame {
+                angular_velocity: angular_velocity(detected.frame, detected.timestamp_us)?,
+                object_points: object_points(&self.objp, detected),
+                image_points: detected.points.iter().map(|(x, y)| (*x as f64, *y as f64)).collect(),
+            })
+        }).collect();
+
+        let estimate = rolling_shutter::estimate(&frames, &self.k, &self.raw_d, model, (self.width, self.height), max_readout_time).ok_or(CalibrationError::CalibrationFailed)?;
+        self.rolling_shutter = Some(estimate);
+        Ok(estimate)
+    }
+
+    // `d` are the coefficients in OpenCV layout, before converting to the target distortion model
+    fn build_report(&self, d: &[f64], distortion_model: &str) -> report::CalibrationReport {
+        let model = camera_model(distortion_model);
+        let frames = self.used_points.values().map(|detected| {
+            let objpoints = object_points(&self.objp, detected);
+            let imgpoints: Vec<(f64, f64)> = detected.points.iter().map(|(x, y)| (*x as f64, *y as f64)).collect();
+            let view = solver::View { object_points: &objpoints, image_points: &imgpoints, point_rotations: &[] };
+            let errors = solver::reprojection_errors(&view, &self.k, d, model).unwrap_or_default();
+            report::FrameReport {
+                frame: detected.frame,
+                timestamp_us: detected.timestamp_us,
+                rms: (errors.iter().map(|x| x * x).sum::<f64>() / errors.len().max(1) as f64).sqrt(),
+                max_error: errors.iter().copied().fold(0.0, f64::max),
+                points: detected.points.len(),
+                is_forced: detected.is_forced,
+                is_outlier: false
+            }
+        }).collect();
+        let points = self.used_points.values().flat_map(|x| x.points.iter().copied());
+        report::CalibrationReport::new((self.width, self.height), self.rms, frames, points)
+    }
+}
+
+// Detected corners, their indices in `objp` if only part of the board is visible, and sharpness
+type Detection = Option<(Vec<(f32, f32)>, Vec<usize>, f64)>;
+
+#[cfg(feature = "use-opencv")]
+fn detect_opencv(pixels: &mut [u8], size: (u32, u32), stride: usize, target: CalibrationTarget, grid_size: (usize, usize), no_marker: bool, charuco_dictionary: i32, charuco_marker_ratio: f32) -> Result<Detection, CalibrationError> {
+    let grid_size = Size::new(grid_size.0 as i32, grid_size.1 as i32);
+    let inp1 = unsafe { Mat::new_size_with_data(Size::new(size.0 as i32, size.1 as i32), CV_8UC1, pixels.as_mut_ptr() as *mut std::ffi::c_void, stride as usize)? };
+    let mut inp = unsafe { Mat::new_size_with_data(Size::new(size.0 as i32, size.1 as i32), CV_8UC1, pixels.as_mut_ptr() as *mut std::ffi::c_void, stride as usize)? };
+
+    let _ = opencv::imgproc::equalize_hist(&inp1, &mut inp);
+
+    let mut corners = Mat::default();
+
+    // Sharpness is estimated by OpenCV only for the full chessboard
+    let detected: Option<(Vec<usize>, Option<f64>)> = match target {
+        CalibrationTarget::Chessboard => {
+            let mut flags = CALIB_CB_MARKER;
+            if no_marker {
+                flags = 0;
+            }
+            if opencv::calib3d::find_chessboard_corners_sb(&inp, grid_size, &mut corners, flags)? && corners.rows() > 0 {
+                let sharpness = opencv::calib3d::estimate_chessboard_sharpness(&inp, grid_size, &corners, 0.8, false, &mut Mat::default()).unwrap_or_default();
+                Some((Vec::new(), Some(*sharpness.get(0).unwrap_or(&100.0))))
+            } else {
+                None
+            }
+        },
+        CalibrationTarget::Charuco => {
+            let dictionary = opencv::objdetect::get_predefined_dictionary_i32(charuco_dictionary)?;
+            let board_size = Size::new(grid_size.width + 1, grid_size.height + 1);
+            let board = opencv::objdetect::CharucoBoard::new_def(board_size, 1.0, charuco_marker_ratio, &dictionary)?;
+            let detector = opencv::objdetect::CharucoDetector::new_def(&board)?;
+            let mut ids = Vector::<i32>::new();
+            detector.detect_board_def(&inp, &mut corners, &mut ids)?;
+            if ids.len() >= MIN_CHARUCO_CORNERS && corners.rows() as usize == ids.len() {
+                Some((ids.iter().map(|x| x as usize).collect(), None))
+            } else {
+                None
+            }
+        },
+        CalibrationTarget::AsymmetricCircles => {
+            let blob_detector = opencv::features2d::SimpleBlobDetector::create_def()?;
+            if opencv::calib3d::find_circles_grid_1(&inp, grid_size, &mut corners, CALIB_CB_ASYMMETRIC_GRID, &blob_detector.into())? && corners.rows() > 0 {
+                Some((Vec::new(), None))
+            } else {
+                None
+            }
         }
+    };
+
+    Ok(match detected {
+        Some((ids, sharpness)) if corners.rows() > 0 => {
+            let points: Vec<(f32, f32)> = corners.iter::<Point2f>()?.map(|(_, pt)| (pt.x, pt.y)).collect();
+            let sharpness = sharpness.unwrap_or_else(|| {
+                let img = corners::GrayImage::from_pixels(pixels, size.0 as usize, size.1 as usize, stride);
+                let pattern = if target == CalibrationTarget::AsymmetricCircles { corners::EdgePattern::Circles } else { corners::EdgePattern::Charuco };
+                corners::estimate_sharpness(&img, &points, pattern)
+            });
+            Some((points, ids, sharpness))
+        },
+        _ => None
+    })
+}
+
+// The corners are found the same way with and without the markers, so `no_marker` doesn't apply here.
+// ChArUco and circle grids are only available with OpenCV, the UI doesn't allow them with the built-in detector
+fn detect_rust(pixels: &[u8], size: (u32, u32), stride: usize, target: CalibrationTarget, grid_size: (usize, usize)) -> Result<Detection, CalibrationError> {
+    if target != CalibrationTarget::Chessboard {
+        return Err(CalibrationError::Unsupported("This calibration target"));
+    }
+    let img = corners::GrayImage::from_pixels(pixels, size.0 as usize, size.1 as usize, stride);
+    Ok(corners::find_chessboard_corners(&img, grid_size.0, grid_size.1).map(|points| {
+        let sharpness = corners::estimate_sharpness(&img, &points, corners::EdgePattern::Chessboard);
+        (points, Vec::new(), sharpness)
+    }))
+}
+
+// Object points corresponding to the detected points
+fn object_points(objp: &[(f64, f64)], detected: &Detected) -> Vec<(f64, f64)> {
+    if detected.ids.is_empty() {
+        objp.to_vec()
+    } else {
+        detected.ids.iter().filter_map(|id| objp.get(*id)).copied().collect()
     }
 }
 
+#[cfg(feature = "use-opencv")]
+fn calibrate_opencv(detections: &[&Detected], objp: &[(f64, f64)], size: (usize, usize), distortion_model: &str) -> Result<(f64, Matrix3<f64>, Vec<f64>), CalibrationError> {
+    let calib_criteria = TermCriteria::new(TermCriteria_Type::EPS as i32 | TermCriteria_Type::COUNT as i32, 30, 1e-6)?;
+    let size = Size::new(size.0 as i32, size.1 as i32);
+
+    let mut imgpoints = Vector::<Vector<Point2f>>::new();
+    let mut objpoints = Vector::<Vector<Point3d>>::new();
+    for detected in detections {
+        imgpoints.push(Vector::from_iter(detected.points.iter().map(|(x, y)| Point2f::new(*x as f32, *y as f32))));
+        objpoints.push(Vector::from_iter(object_points(objp, detected).into_iter().map(|(x, y)| Point3d::new(x, y, 0.0))));
+    }
+
+    let mut k  = Mat::default(); let mut d  = Mat::default();
+    let mut rv = Mat::default(); let mut tv = Mat::default();
+    // let mut nop = Mat::default();
+
+    // Non-fisheye models are fitted with the standard OpenCV model, with unused coefficients fixed to 0
+    let standard_flags = match distortion_model {
+        "opencv_standard" => Some(0),
+        "poly3"           => Some(CALIB_ZERO_TANGENT_DIST | CALIB_FIX_K2 | CALIB_FIX_K3),
+        "poly5"           => Some(CALIB_ZERO_TANGENT_DIST | CALIB_FIX_K3),
+        "ptlens"          => Some(CALIB_ZERO_TANGENT_DIST),
+        _ => None
+    };
+
+    // let rms = opencv::calib3d::calibrate_camera_ro(&objpoints, &imgpoints, size, 13, &mut k, &mut d, &mut rv, &mut tv, &mut nop, Fisheye_CALIB_RECOMPUTE_EXTRINSIC | Fisheye_CALIB_FIX_SKEW, calib_criteria)?;
+    let rms = if let Some(flags) = standard_flags {
+        let objpoints = Vector::<Vector<Point3f>>::from_iter(objpoints.iter().map(|v| Vector::from_iter(v.iter().map(|p| Point3f::new(p.x as f32, p.y as f32, p.z as f32)))));
+        opencv::calib3d::calibrate_camera(&objpoints, &imgpoints, size, &mut k, &mut d, &mut rv, &mut tv, flags, calib_criteria)?
+    } else {
+        opencv::calib3d::calibrate(&objpoints, &imgpoints, size, &mut k, &mut d, &mut rv, &mut tv, Fisheye_CALIB_RECOMPUTE_EXTRINSIC | Fisheye_CALIB_FIX_SKEW, calib_criteria)?
+    };
+    Ok((rms, cv_to_mat3(k)?, cv_to_vec(d)?))
+}
+
+// Same coefficients as in the OpenCV path: k1, k2, p1, p2, k3 for the standard model, with unused ones fixed to 0
+fn camera_model(distortion_model: &str) -> solver::CameraModel {
+    match distortion_model {
+        "opencv_standard" => solver::CameraModel::Standard { fixed: [false, false, false, false, false] },
+        "poly3"           => solver::CameraModel::Standard { fixed: [false, true,  true,  true,  true] },
+        "poly5"           => solver::CameraModel::Standard { fixed: [false, false, true,  true,  true] },
+        "ptlens"          => solver::CameraModel::Standard { fixed: [false, false, true,  true,  false] },
+        _ => solver::CameraModel::Fisheye
+    }
+}
+
+fn calibrate_rust(detections: &[&Detected], objp: &[(f64, f64)], size: (usize, usize), distortion_model: &str) -> Result<(f64, Matrix3<f64>, Vec<f64>), CalibrationError> {
+    let model = camera_model(distortion_model);
+    let objpoints: Vec<Vec<(f64, f64)>> = detections.iter().map(|detected| object_points(objp, detected)).collect();
+    let imgpoints: Vec<Vec<(f64, f64)>> = detections.iter().map(|detected| detected.points.iter().map(|(x, y)| (*x as f64, *y as f64)).collect()).collect();
+    let views: Vec<solver::View> = objpoints.iter().zip(imgpoints.iter()).map(|(o, i)| solver::View { object_points: o, image_points: i, point_rotations: &[] }).collect();
+
+    let result = solver::calibrate(&views, size, model).ok_or(CalibrationError::CalibrationFailed)?;
+    Ok((result.rms, result.k, result.d))
+}
+
 #[cfg(feature = "use-opencv")]
 fn cv_to_mat3(r1: Mat) -> Result<Matrix3<f64>, opencv::Error> {
     if r1.typ() != opencv::core::CV_64FC1 {
@@ -321,16 +699,154 @@ fn cv_to_mat3(r1: Mat) -> Result<Matrix3<f64>, opencv::Error> {
 }
 
 #[cfg(feature = "use-opencv")]
-fn cv_to_vec4(v: Mat) -> Result<Vector4<f64>, opencv::Error> {
+fn cv_to_vec(v: Mat) -> Result<Vec<f64>, opencv::Error> {
     if v.typ() != opencv::core::CV_64FC1 {
         return Err(opencv::Error::new(0, "Invalid matrix type".to_string()));
     }
-    Ok(Vector4::new(
-        *v.at::<f64>(0)?,
-        *v.at::<f64>(1)?,
-        *v.at::<f64>(2)?,
-        *v.at::<f64>(3)?
-    ))
+    let mut ret = Vec::with_capacity(v.total());
+    for i in 0..v.total() as i32 {
+        ret.push(*v.at::<f64>(i)?);
+    }
+    Ok(ret)
 }
 
-// https://github.com/Tangram-Vision/Tangram-Vision-Blog/blob/main/2021.05.28_CalibrationFromScratch/src/main.rs
\ No newline at end of file
+// Converts OpenCV coefficients to the layout of the target distortion model
+fn convert_coefficients(distortion_model: &str, d: Vec<f64>, k: &Matrix3<f64>, size: (usize, usize)) -> Vec<f64> {
+    let get = |i: usize| d.get(i).copied().unwrap_or_default();
+    match distortion_model {
+        "opencv_standard" => vec![get(0), get(1), get(2), get(3), get(4)], // k1, k2, p1, p2, k3
+        "poly3"           => vec![get(0)],
+        "poly5"           => vec![get(0), get(1)],
+        "ptlens"          => {
+            // Max normalized radius, at the image corner
+            let r_max = ((size.0 as f64 / 2.0 / k[(0, 0)]).powi(2) + (size.1 as f64 / 2.0 / k[(1, 1)]).powi(2)).sqrt();
+            let abc = fit_ptlens(&[get(0), get(1), get(4)], r_max);
+            vec![abc.x, abc.y, abc.z]
+        },
+        "kannala_brandt"  => d, // k1..k4 of the fisheye model, higher orders stay at 0
+        _ => d
+    }
+}
+
+// PTLens uses odd powers of r, which can't be fitted by OpenCV directly.
+// Fit `a*r^3 + b*r^2 + c*r` to the radial distortion `k1*r^2 + k2*r^4 + k3*r^6` with least squares
+pub fn fit_ptlens(k: &[f64; 3], r_max: f64) -> Vector3<f64> {
+    let mut ata = Matrix3::<f64>::zeros();
+    let mut atb = Vector3::<f64>::zeros();
+    const SAMPLES: usize = 100;
+    for i in 1..=SAMPLES {
+        let r = r_max * i as f64 / SAMPLES as f64;
+        let r2 = r * r;
+        let row = Vector3::new(r2 * r, r2, r);
+        let target = k[0] * r2 + k[1] * r2 * r2 + k[2] * r2 * r2 * r2;
+        ata += row * row.transpose();
+        atb += row * target;
+    }
+    ata.lu().solve(&atb).unwrap_or_default()
+}
+
+// https://github.com/Tangram-Vision/Tangram-Vision-Blog/blob/main/2021.05.28_CalibrationFromScratch/src/main.rs
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{ Rotation3, Vector3 };
+
+    const SIZE: (usize, usize) = (1920, 1080);
+
+    fn board(columns: usize, rows: usize) -> Vec<(f64, f64)> {
+        (0..rows).flat_map(|y| (0..columns).map(move |x| (x as f64, y as f64))).collect()
+    }
+
+    // The board seen from several angles, projected with the known intrinsics and distortion.
+    // `noise` adds a deterministic error in pixels to every point
+    fn synthetic_detections(model: solver::CameraModel, k: &Matrix3<f64>, d: &[f64], objp: &[(f64, f64)], noise: f64) -> Vec<Detected> {
+        let poses = [
+            // rotation x, y, z, translation x, y, z
+            ( 0.0,   0.0,   0.0,   0.0,  0.0, 18.0),
+            ( 0.35,  0.0,   0.1,  -3.0,  1.0, 20.0),
+            (-0.3,   0.25,  0.0,   3.0, -1.5, 17.0),
+            ( 0.0,  -0.4,  -0.15,  4.0,  2.0, 19.0),
+            ( 0.25,  0.3,   0.3,  -4.0, -2.0, 22.0),
+            (-0.2,  -0.25, -0.2,   0.0,  2.5, 16.0),
+            ( 0.1,   0.45,  0.05, -5.0,  0.0, 21.0),
+        ];
+        let (cx, cy) = objp.iter().fold((0.0, 0.0), |acc, p| (acc.0 + p.0, acc.1 + p.1));
+        let center = (cx / objp.len() as f64, cy / objp.len() as f64);
+        let mut seed = 12345u32;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            ((seed >> 16) & 0x7fff) as f64 / 32767.0 * 2.0 - 1.0
+        };
+
+        poses.iter().enumerate().map(|(i, &(rx, ry, rz, tx, ty, tz))| {
+            let r = Rotation3::from_euler_angles(rx, ry, rz);
+            let t = Vector3::new(tx, ty, tz);
+            let points = objp.iter().map(|&(x, y)| {
+                let p = r * Vector3::new(x - center.0, y - center.1, 0.0) + t;
+                let n = model.distort(p, d);
+                ((k[(0, 0)] * n.x + k[(0, 2)] + random() * noise) as f32, (k[(1, 1)] * n.y + k[(1, 2)] + random() * noise) as f32)
+            }).collect();
+            Detected { points, frame: i as i32, ..Default::default() }
+        }).collect()
+    }
+
+    fn assert_close(name: &str, a: f64, b: f64, tolerance: f64) {
+        assert!((a - b).abs() <= tolerance, "{name}: {a} vs {b}, tolerance {tolerance}");
+    }
+
+    fn assert_result(result: &(f64, Matrix3<f64>, Vec<f64>), k: &Matrix3<f64>, d: &[f64], tolerance_px: f64, tolerance_d: f64) {
+        for (name, (row, col)) in [("fx", (0, 0)), ("fy", (1, 1)), ("cx", (0, 2)), ("cy", (1, 2))] {
+            assert_close(name, result.1[(row, col)], k[(row, col)], tolerance_px);
+        }
+        for (i, (a, b)) in result.2.iter().zip(d.iter()).enumerate() {
+            assert_close(&format!("d[{i}]"), *a, *b, tolerance_d);
+        }
+    }
+
+    #[test]
+    fn rust_solver_recovers_known_fisheye_lens() {
+        let k = Matrix3::new(1000.0, 0.0, 955.0, 0.0, 1000.0, 545.0, 0.0, 0.0, 1.0);
+        let d = [0.05, -0.02, 0.01, -0.005];
+        let objp = board(14, 8);
+        let detections = synthetic_detections(solver::CameraModel::Fisheye, &k, &d, &objp, 0.0);
+        let refs: Vec<&Detected> = detections.iter().collect();
+
+        let result = calibrate_rust(&refs, &objp, SIZE, "opencv_fisheye").unwrap();
+        assert!(result.0 < 0.01, "rms: {}", result.0);
+        assert_result(&result, &k, &d, 0.5, 2e-3);
+    }
+
+    #[test]
+    fn rust_solver_recovers_known_standard_lens() {
+        let k = Matrix3::new(1200.0, 0.0, 965.0, 0.0, 1200.0, 535.0, 0.0, 0.0, 1.0);
+        let d = [-0.12, 0.04, 0.0005, -0.0003, 0.0];
+        let objp = board(14, 8);
+        let detections = synthetic_detections(camera_model("opencv_standard"), &k, &d, &objp, 0.0);
+        let refs: Vec<&Detected> = detections.iter().collect();
+
+        let result = calibrate_rust(&refs, &objp, SIZE, "opencv_standard").unwrap();
+        assert!(result.0 < 0.01, "rms: {}", result.0);
+        assert_result(&result, &k, &d, 0.5, 2e-3);
+    }
+
+    // Both paths minimize the same reprojection error, so with noisy points they have to converge to the same solution
+    #[cfg(feature = "use-opencv")]
+    #[test]
+    fn rust_solver_matches_opencv() {
+        let objp = board(14, 8);
+        let cases = [
+            ("opencv_fisheye",  Matrix3::new(1000.0, 0.0, 955.0, 0.0, 1000.0, 545.0, 0.0, 0.0, 1.0), vec![0.05, -0.02, 0.01, -0.005]),
+            ("opencv_standard", Matrix3::new(1200.0, 0.0, 965.0, 0.0, 1200.0, 535.0, 0.0, 0.0, 1.0), vec![-0.12, 0.04, 0.0005, -0.0003, 0.0]),
+        ];
+        for (name, k, d) in cases {
+            let detections = synthetic_detections(camera_model(name), &k, &d, &objp, 0.3);
+            let refs: Vec<&Detected> = detections.iter().collect();
+
+            let rust = calibrate_rust(&refs, &objp, SIZE, name).unwrap();
+            let opencv = calibrate_opencv(&refs, &objp, SIZE, name).unwrap();
+            assert_close("rms", rust.0, opencv.0, 0.01);
+            assert_result(&rust, &opencv.1, &opencv.2, 0.5, 2e-3);
+        }
+    }
+}
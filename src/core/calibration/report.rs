@@ -0,0 +1,129 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Calibration quality report: reprojection error of each used frame, coverage of the sensor by the detected corners
+// and suggestions which frames to drop or which areas of the frame need more data.
+
+use serde::Serialize;
+
+// Number of cells in the coverage heatmap (horizontal, vertical)
+pub const COVERAGE_GRID: (usize, usize) = (16, 9);
+
+// Frames with error this many times larger than the median are considered outliers
+const OUTLIER_FACTOR: f64 = 2.5;
+// Regions with less than this fraction of cells containing corners lack coverage
+const MIN_REGION_COVERAGE: f64 = 0.5;
+const MIN_RECOMMENDED_FRAMES: usize = 8;
+const MAX_RECOMMENDED_RMS: f64 = 1.0;
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct FrameReport {
+    pub frame: i32,
+    pub timestamp_us: i64,
+    pub rms: f64,       // Reprojection error in pixels
+    pub max_error: f64, // Largest error of a single corner in pixels
+    pub points: usize,
+    pub is_forced: bool,
+    pub is_outlier: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Suggestion {
+    pub kind: &'static str, // drop_frame, missing_coverage, few_frames, high_error
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_us: Option<i64>,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct CalibrationReport {
+    pub rms: f64,
+    pub frames: Vec<FrameReport>,
+    pub coverage_grid: (usize, usize),
+    pub coverage: Vec<u32>,  // Number of corners in each cell, row by row
+    pub coverage_ratio: f64, // Fraction of cells with at least one corner
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl CalibrationReport {
+    pub fn new(size: (usize, usize), rms: f64, mut frames: Vec<FrameReport>, points: impl Iterator<Item = (f32, f32)>) -> Self {
+        let (gw, gh) = COVERAGE_GRID;
+        let mut coverage = vec![0u32; gw * gh];
+        if size.0 > 0 && size.1 > 0 {
+            for (x, y) in points {
+                let cx = ((x as f64 / size.0 as f64 * gw as f64).floor() as isize).clamp(0, gw as isize - 1) as usize;
+                let cy = ((y as f64 / size.1 as f64 * gh as f64).floor() as isize).clamp(0, gh as isize - 1) as usize;
+                coverage[cy * gw + cx] += 1;
+            }
+        }
+        let coverage_ratio = coverage.iter().filter(|x| **x > 0).count() as f64 / coverage.len() as f64;
+
+        let mut suggestions = Vec::new();
+
+        let mut sorted: Vec<f64> = frames.iter().map(|x| x.rms).collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        if let Some(median) = sorted.get(sorted.len() / 2).copied() {
+            let threshold = (median * OUTLIER_FACTOR).max(median + 0.5);
+            for f in frames.iter_mut() {
+                f.is_outlier = f.rms > threshold;
+                if f.is_outlier {
+                    suggestions.push(Suggestion {
+                        kind: "drop_frame",
+                        message: format!("Frame {} has reprojection error {:.3} px (median {:.3} px), consider removing it", f.frame, f.rms, median),
+                        timestamp_us: Some(f.timestamp_us),
+                    });
+                }
+            }
+        }
+
+        // Split the grid into 3x3 regions and check how many cells in each of them contain any corners
+        const REGIONS: [&str; 9] = ["top left", "top", "top right", "left", "center", "right", "bottom left", "bottom", "bottom right"];
+        for (i, name) in REGIONS.iter().enumerate() {
+            let (rx, ry) = (i % 3, i / 3);
+            let (x0, x1) = (rx * gw / 3, (rx + 1) * gw / 3);
+            let (y0, y1) = (ry * gh / 3, (ry + 1) * gh / 3);
+            let cells = (x1 - x0) * (y1 - y0);
+            let covered = (y0..y1).flat_map(|y| (x0..x1).map(move |x| (x, y))).filter(|(x, y)| coverage[y * gw + x] > 0).count();
+            let ratio = covered as f64 / cells.max(1) as f64;
+            if ratio < MIN_REGION_COVERAGE {
+                suggestions.push(Suggestion {
+                    kind: "missing_coverage",
+                    message: format!("The {} of the frame has little coverage ({:.0}%), add frames with the pattern in that area", name, ratio * 100.0),
+                    timestamp_us: None,
+                });
+            }
+        }
+
+        if frames.len() < MIN_RECOMMENDED_FRAMES {
+            suggestions.push(Suggestion {
+                kind: "few_frames",
+                message: format!("Only {} frames were used, at least {} are recommended", frames.len(), MIN_RECOMMENDED_FRAMES),
+                timestamp_us: None,
+            });
+        }
+        if rms > MAX_RECOMMENDED_RMS {
+            suggestions.push(Suggestion {
+                kind: "high_error",
+                message: format!("Reprojection error {:.3} px is high, the profile should not be uploaded until it's below {:.1} px", rms, MAX_RECOMMENDED_RMS),
+                timestamp_us: None,
+            });
+        }
+
+        Self {
+            rms,
+            frames,
+            coverage_grid: COVERAGE_GRID,
+            coverage,
+            coverage_ratio,
+            suggestions,
+        }
+    }
+
+    // Whether the profile has any problems which should be fixed before uploading it to the database
+    pub fn has_issues(&self) -> bool {
+        !self.suggestions.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
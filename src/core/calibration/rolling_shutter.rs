@@ -0,0 +1,100 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Estimation of the frame readout time from the calibration video.
+// When the camera rotates, each row of a rolling shutter sensor is captured at a different time, which skews the lines of the target.
+// For a candidate readout time, each detected corner is rotated to the middle of the frame using the gyro angular velocity
+// and the pose of the target is refitted with fixed intrinsics. The readout time and direction with the lowest reprojection error is used.
+
+use nalgebra::{ Matrix3, Rotation3, Vector3 };
+use rayon::iter::{ ParallelIterator, IntoParallelRefIterator };
+use serde::Serialize;
+
+use super::solver::{ self, CameraModel, View };
+
+// Frames rotating slower than this (rad/ms) don't contain any information about the readout time
+const MIN_ANGULAR_VELOCITY: f64 = 0.1 / 1000.0;
+const MIN_FRAMES: usize = 3;
+const GRID_STEPS: usize = 40;
+const REFINE_ITERATIONS: usize = 30;
+
+pub struct RollingShutterFrame {
+    pub object_points: Vec<(f64, f64)>,
+    pub image_points: Vec<(f64, f64)>,
+    // Angular velocity in rad/ms, in the same coordinate system as the rotations in `FrameTransform` (x right, y down, z forward)
+    pub angular_velocity: Vector3<f64>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+pub struct RollingShutterEstimate {
+    pub frame_readout_time: f64, // ms, negative if the sensor is read from bottom to top (or right to left if `horizontal`)
+    pub horizontal: bool,
+    pub rms: f64,        // Reprojection error with the estimated readout time
+    pub rms_global: f64, // Reprojection error assuming a global shutter
+    pub frames: usize,
+}
+
+pub fn estimate(frames: &[RollingShutterFrame], k: &Matrix3<f64>, d: &[f64], model: CameraModel, size: (usize, usize), max_readout_time: f64) -> Option<RollingShutterEstimate> {
+    let frames: Vec<&RollingShutterFrame> = frames.iter().filter(|f| f.angular_velocity.norm() > MIN_ANGULAR_VELOCITY && f.object_points.len() >= 4).collect();
+    if frames.len() < MIN_FRAMES || size.0 == 0 || size.1 == 0 || max_readout_time <= 0.0 {
+        return None;
+    }
+
+    let cost = |readout_time: f64, horizontal: bool| -> f64 {
+        let (sum, count) = frames.par_iter().map(|f| {
+            let len = if horizontal { size.0 } else { size.1 } as f64;
+            let rotations: Vec<Matrix3<f64>> = f.image_points.iter().map(|p| {
+                let dt = readout_time * ((if horizontal { p.0 } else { p.1 }) / len - 0.5);
+                Rotation3::new(f.angular_velocity * dt).into_inner()
+            }).collect();
+            let view = View { object_points: &f.object_points, image_points: &f.image_points, point_rotations: &rotations };
+            solver::reprojection_errors(&view, k, d, model)
+                .map(|e| (e.iter().map(|x| x * x).sum::<f64>(), e.len()))
+                .unwrap_or_default()
+        }).reduce(|| (0.0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+        (sum / count.max(1) as f64).sqrt()
+    };
+
+    let rms_global = cost(0.0, false);
+    let mut best = RollingShutterEstimate { frame_readout_time: 0.0, horizontal: false, rms: rms_global, rms_global, frames: frames.len() };
+
+    let step = 2.0 * max_readout_time / GRID_STEPS as f64;
+    for horizontal in [false, true] {
+        // Coarse search, then golden section search around the best value
+        let (mut grid_best, mut grid_rms) = (0.0, rms_global);
+        for i in 0..=GRID_STEPS {
+            let t = -max_readout_time + step * i as f64;
+            let rms = cost(t, horizontal);
+            if rms < grid_rms {
+                grid_best = t;
+                grid_rms = rms;
+            }
+        }
+
+        let ratio = (5.0f64.sqrt() - 1.0) / 2.0;
+        let (mut a, mut b) = (grid_best - step, grid_best + step);
+        let mut x1 = b - ratio * (b - a);
+        let mut x2 = a + ratio * (b - a);
+        let (mut f1, mut f2) = (cost(x1, horizontal), cost(x2, horizontal));
+        for _ in 0..REFINE_ITERATIONS {
+            if f1 < f2 {
+                b = x2; x2 = x1; f2 = f1;
+                x1 = b - ratio * (b - a);
+                f1 = cost(x1, horizontal);
+            } else {
+                a = x1; x1 = x2; f1 = f2;
+                x2 = a + ratio * (b - a);
+                f2 = cost(x2, horizontal);
+            }
+        }
+        let (t, rms) = if f1 < f2 { (x1, f1) } else { (x2, f2) };
+        let (t, rms) = if rms < grid_rms { (t, rms) } else { (grid_best, grid_rms) };
+
+        log::debug!("Rolling shutter estimate ({}): {:.3} ms, rms: {:.4} (global shutter: {:.4})", if horizontal { "horizontal" } else { "vertical" }, t, rms, rms_global);
+        if rms < best.rms {
+            best.frame_readout_time = t;
+            best.horizontal = horizontal;
+            best.rms = rms;
+        }
+    }
+    Some(best)
+}
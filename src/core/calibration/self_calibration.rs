@@ -0,0 +1,195 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Lens calibration from ordinary footage, without a calibration target.
+// Between two close frames the camera mostly rotates and the rotation is known from the gyro. For a pure rotation,
+// a feature in one frame is the projection of the rotated ray of the same feature in the other frame, which only depends on the intrinsics.
+// Focal length and fisheye coefficients are fitted to the optical flow points with Levenberg-Marquardt.
+// Moving objects and parallax don't follow the rotation, so the residuals are weighted with the Huber loss.
+
+use nalgebra::{ DMatrix, DVector, Matrix3, Vector3 };
+use rayon::iter::{ ParallelIterator, IntoParallelRefIterator };
+
+use super::solver::CameraModel;
+
+const MAX_ITERATIONS: usize = 50;
+// Residuals larger than this (in pixels) are down-weighted
+const HUBER_DELTA: f64 = 1.5;
+// Correspondences with error larger than this after the initial search are ignored
+const OUTLIER_THRESHOLD: f64 = 20.0;
+// Error in pixels of the correspondences which can't be projected with the current parameters (behind the camera or beyond 90°),
+// so the optimizer can't reduce the error by pushing the points out of the valid region
+const INVALID_POINT_ERROR: f64 = 200.0;
+// Number of fisheye coefficients estimated. Higher orders are poorly constrained by optical flow
+const NUM_COEFFS: usize = 2;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Correspondence {
+    pub a: (f64, f64), // Pixel position in the first frame
+    pub b: (f64, f64), // Pixel position in the second frame
+    pub rotation: Matrix3<f64>, // Rotates the ray of `b` to the camera of the first frame
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SelfCalibrationResult {
+    pub k: Matrix3<f64>,
+    pub d: Vec<f64>, // OpenCV fisheye coefficients
+    pub rms: f64,    // Transfer error of the inliers in pixels
+    // Standard deviation of the focal length (pixels) and each coefficient, from the covariance of the fit
+    pub focal_length_std: f64,
+    pub d_std: Vec<f64>,
+    pub points: usize,  // Number of correspondences used
+    pub inliers: usize,
+}
+
+pub fn calibrate(correspondences: &[Correspondence], size: (usize, usize)) -> Option<SelfCalibrationResult> {
+    if correspondences.len() < 50 || size.0 == 0 || size.1 == 0 {
+        return None;
+    }
+    let cx = size.0 as f64 / 2.0;
+    let cy = size.1 as f64 / 2.0;
+    let diagonal = (size.0 as f64).hypot(size.1 as f64);
+
+    // Coarse search of the focal length without distortion, using the median error which ignores outliers
+    let mut best = (diagonal, f64::MAX);
+    for i in 0..=60 {
+        let f = diagonal * (0.2 + 2.8 * i as f64 / 60.0);
+        let mut params = DVector::<f64>::zeros(1 + NUM_COEFFS);
+        params[0] = f;
+        let mut errors: Vec<f64> = correspondences.par_iter().map(|c| transfer_error(c, &params, cx, cy).map(|e| e.norm()).unwrap_or(f64::MAX)).collect();
+        errors.sort_by(|a, b| a.total_cmp(b));
+        let median = errors[errors.len() / 2];
+        if median < best.1 {
+            best = (f, median);
+        }
+    }
+
+    let mut params = DVector::<f64>::zeros(1 + NUM_COEFFS);
+    params[0] = best.0;
+
+    // Refine in two passes, the second one without the outliers of the first one
+    let mut inliers: Vec<&Correspondence> = correspondences.iter().collect();
+    for pass in 0..2 {
+        params = optimize(&inliers, params, cx, cy);
+        if pass == 0 {
+            inliers.retain(|c| transfer_error(c, &params, cx, cy).map(|e| e.norm() < OUTLIER_THRESHOLD).unwrap_or_default());
+            if inliers.len() < 50 {
+                return None;
+            }
+        }
+    }
+
+    // Covariance of the parameters: sigma^2 * (J^T W J)^-1
+    let residuals = weighted_residuals(&inliers, &params, cx, cy);
+    let jac = jacobian(&inliers, &params, &residuals, cx, cy);
+    let dof = (residuals.len() as f64 - params.len() as f64).max(1.0);
+    let sigma2 = residuals.norm_squared() / dof;
+    let covariance = (jac.transpose() * &jac).try_inverse()? * sigma2;
+
+    let errors: Vec<f64> = inliers.iter().filter_map(|c| transfer_error(c, &params, cx, cy).map(|e| e.norm_squared())).collect();
+    let f = params[0];
+    Some(SelfCalibrationResult {
+        k: Matrix3::new(f, 0.0, cx, 0.0, f, cy, 0.0, 0.0, 1.0),
+        d: (0..4).map(|i| if i < NUM_COEFFS { params[1 + i] } else { 0.0 }).collect(),
+        rms: (errors.iter().sum::<f64>() / errors.len().max(1) as f64).sqrt(),
+        focal_length_std: covariance[(0, 0)].max(0.0).sqrt(),
+        d_std: (0..4).map(|i| if i < NUM_COEFFS { covariance[(1 + i, 1 + i)].max(0.0).sqrt() } else { 0.0 }).collect(),
+        points: correspondences.len(),
+        inliers: inliers.len(),
+    })
+}
+
+fn optimize(correspondences: &[&Correspondence], mut params: DVector<f64>, cx: f64, cy: f64) -> DVector<f64> {
+    let n = params.len();
+    let mut lambda = 1e-3;
+    let mut residuals = weighted_residuals(correspondences, &params, cx, cy);
+    let mut err = residuals.norm_squared();
+    for _ in 0..MAX_ITERATIONS {
+        let jac = jacobian(correspondences, &params, &residuals, cx, cy);
+        let jtj = jac.transpose() * &jac;
+        let jtr = jac.transpose() * &residuals;
+
+        let mut improved = false;
+        for _ in 0..10 {
+            let mut a = jtj.clone();
+            for i in 0..n { a[(i, i)] += lambda * jtj[(i, i)].max(1e-9); }
+            let Some(delta) = a.cholesky().map(|c| c.solve(&-&jtr)) else { lambda *= 10.0; continue; };
+            let new_params = &params + delta;
+            let new_residuals = weighted_residuals(correspondences, &new_params, cx, cy);
+            let new_err = new_residuals.norm_squared();
+            if new_params[0] > 0.0 && new_err.is_finite() && new_err < err {
+                improved = (err - new_err) >= 1e-10 * err.max(1e-12);
+                params = new_params;
+                residuals = new_residuals;
+                err = new_err;
+                lambda = (lambda / 10.0).max(1e-12);
+                break;
+            }
+            lambda *= 10.0;
+        }
+        if !improved {
+            break;
+        }
+    }
+    params
+}
+
+fn jacobian(correspondences: &[&Correspondence], params: &DVector<f64>, residuals: &DVector<f64>, cx: f64, cy: f64) -> DMatrix<f64> {
+    let mut jac = DMatrix::<f64>::zeros(residuals.len(), params.len());
+    let mut p = params.clone();
+    for col in 0..params.len() {
+        let h = 1e-6 * params[col].abs().max(1e-2);
+        p[col] = params[col] + h;
+        jac.column_mut(col).copy_from(&((weighted_residuals(correspondences, &p, cx, cy) - residuals) / h));
+        p[col] = params[col];
+    }
+    jac
+}
+
+// Residuals scaled by the square root of the Huber weight. The weights are computed from the current error, so this is iteratively reweighted least squares
+fn weighted_residuals(correspondences: &[&Correspondence], params: &DVector<f64>, cx: f64, cy: f64) -> DVector<f64> {
+    let res: Vec<(f64, f64)> = correspondences.par_iter().map(|c| {
+        let e = transfer_error(c, params, cx, cy).unwrap_or(nalgebra::Vector2::new(INVALID_POINT_ERROR, 0.0));
+        let norm = e.norm();
+        let w = if norm > HUBER_DELTA { (HUBER_DELTA / norm).sqrt() } else { 1.0 };
+        (e.x * w, e.y * w)
+    }).collect();
+    DVector::from_iterator(res.len() * 2, res.into_iter().flat_map(|(x, y)| [x, y]))
+}
+
+// Difference between the point in the first frame and the rotated point from the second frame
+fn transfer_error(c: &Correspondence, params: &DVector<f64>, cx: f64, cy: f64) -> Option<nalgebra::Vector2<f64>> {
+    let f = params[0];
+    let mut d = [0.0; 4];
+    d[..NUM_COEFFS].copy_from_slice(&params.as_slice()[1..1 + NUM_COEFFS]);
+    let ray = c.rotation * unproject(c.b, f, cx, cy, &d)?;
+    if ray.z <= 1e-6 {
+        return None;
+    }
+    let uv = CameraModel::Fisheye.distort(ray, &d);
+    Some(nalgebra::Vector2::new(f * uv.x + cx - c.a.0, f * uv.y + cy - c.a.1))
+}
+
+fn unproject(p: (f64, f64), f: f64, cx: f64, cy: f64, d: &[f64; 4]) -> Option<Vector3<f64>> {
+    let x = (p.0 - cx) / f;
+    let y = (p.1 - cy) / f;
+    let theta_d = (x * x + y * y).sqrt();
+    if theta_d < 1e-12 {
+        return Some(Vector3::new(0.0, 0.0, 1.0));
+    }
+    // Solve theta_d = theta * (1 + k1 * theta^2 + k2 * theta^4 + ...) with Newton's method
+    let mut theta = theta_d;
+    for _ in 0..10 {
+        let t2 = theta * theta;
+        let f = theta * (1.0 + t2 * (d[0] + t2 * (d[1] + t2 * (d[2] + t2 * d[3])))) - theta_d;
+        let df = 1.0 + t2 * (3.0 * d[0] + t2 * (5.0 * d[1] + t2 * (7.0 * d[2] + t2 * 9.0 * d[3])));
+        if df.abs() < 1e-9 {
+            break;
+        }
+        theta -= f / df;
+    }
+    if !theta.is_finite() || theta <= 0.0 || theta >= std::f64::consts::FRAC_PI_2 {
+        return None;
+    }
+    let scale = theta.sin() / theta_d;
+    Some(Vector3::new(x * scale, y * scale, theta.cos()))
+}
@@ -0,0 +1,451 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Camera calibration from planar targets without OpenCV.
+// Initial intrinsics and poses are estimated from homographies (Zhang's method),
+// then all parameters are refined with Levenberg-Marquardt minimizing the reprojection error.
+
+use std::ops::AddAssign;
+use nalgebra::{ DMatrix, DVector, Matrix3, Rotation3, SMatrix, Vector2, Vector3 };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraModel {
+    // OpenCV fisheye: k1, k2, k3, k4
+    Fisheye,
+    // OpenCV standard: k1, k2, p1, p2, k3. Coefficients marked as fixed stay at 0
+    Standard { fixed: [bool; 5] },
+}
+impl CameraModel {
+    fn num_coeffs(&self) -> usize {
+        match self {
+            Self::Fisheye => 4,
+            Self::Standard { .. } => 5,
+        }
+    }
+    fn is_fixed(&self, i: usize) -> bool {
+        match self {
+            Self::Fisheye => false,
+            Self::Standard { fixed } => fixed[i],
+        }
+    }
+
+    pub fn distort(&self, p: Vector3<f64>, d: &[f64]) -> Vector2<f64> {
+        let x = p.x / p.z;
+        let y = p.y / p.z;
+        match self {
+            Self::Fisheye => {
+                let r = (x * x + y * y).sqrt();
+                if r < 1e-12 {
+                    return Vector2::new(x, y);
+                }
+                let theta = r.atan();
+                let theta2 = theta * theta;
+                let theta_d = theta * (1.0 + theta2 * (d[0] + theta2 * (d[1] + theta2 * (d[2] + theta2 * d[3]))));
+                let scale = theta_d / r;
+                Vector2::new(x * scale, y * scale)
+            },
+            Self::Standard { .. } => {
+                let r2 = x * x + y * y;
+                let radial = 1.0 + r2 * (d[0] + r2 * (d[1] + r2 * d[4]));
+                Vector2::new(
+                    x * radial + 2.0 * d[2] * x * y + d[3] * (r2 + 2.0 * x * x),
+                    y * radial + d[2] * (r2 + 2.0 * y * y) + 2.0 * d[3] * x * y
+                )
+            }
+        }
+    }
+}
+
+pub struct View<'a> {
+    pub object_points: &'a [(f64, f64)], // Points on the target plane (z = 0)
+    pub image_points: &'a [(f64, f64)],  // Detected points in pixels
+    // Rotation of the camera when each point was captured, relative to the pose of the view (rolling shutter). Empty if all points were captured at once
+    pub point_rotations: &'a [Matrix3<f64>],
+}
+
+#[derive(Clone, Debug)]
+pub struct CalibrationResult {
+    pub rms: f64,
+    pub k: Matrix3<f64>,
+    pub d: Vec<f64>,
+    pub per_view_rms: Vec<f64>,
+}
+
+// Several sources (e.g. different resolutions or crops of the same sensor) calibrated together.
+// Pixel coordinates of each source are `scale * p + offset`, where `p` are the pixel coordinates of the first source
+pub struct Source<'a> {
+    pub views: Vec<View<'a>>,
+    pub size: (usize, usize),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SourceTransform {
+    pub scale: f64,
+    pub offset: (f64, f64),
+}
+impl SourceTransform {
+    // Camera matrix of the source, from the camera matrix of the first source
+    pub fn apply(&self, k: &Matrix3<f64>) -> Matrix3<f64> {
+        Matrix3::new(
+            k[(0, 0)] * self.scale, 0.0, k[(0, 2)] * self.scale + self.offset.0,
+            0.0, k[(1, 1)] * self.scale, k[(1, 2)] * self.scale + self.offset.1,
+            0.0, 0.0, 1.0
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct JointCalibrationResult {
+    pub rms: f64,
+    pub k: Matrix3<f64>, // Camera matrix of the first source
+    pub d: Vec<f64>,
+    pub transforms: Vec<SourceTransform>,
+    pub per_source_rms: Vec<f64>,
+}
+
+const MAX_ITERATIONS: usize = 100;
+
+pub fn calibrate(views: &[View], size: (usize, usize), model: CameraModel) -> Option<CalibrationResult> {
+    let views: Vec<(&View, usize)> = views.iter().filter(|v| is_valid(v)).map(|v| (v, 0)).collect();
+    if views.len() < 2 {
+        return None;
+    }
+
+    let cx = size.0 as f64 / 2.0;
+    let cy = size.1 as f64 / 2.0;
+    let f = initial_focal_length(&views, size, model, cx, cy);
+
+    // Parameters: fx, fy, cx, cy, distortion coefficients, then rotation (axis-angle) and translation of each view
+    let nd = model.num_coeffs();
+    let ni = 4 + nd;
+    let mut params = DVector::<f64>::zeros(ni + views.len() * 6);
+    params[0] = f; params[1] = f; params[2] = cx; params[3] = cy;
+    for (i, (v, _)) in views.iter().enumerate() {
+        let (r, t) = initial_pose(v, model, f, cx, cy)?;
+        params.fixed_rows_mut::<3>(ni + i * 6).copy_from(&r.scaled_axis());
+        params.fixed_rows_mut::<3>(ni + i * 6 + 3).copy_from(&t);
+    }
+
+    // Intrinsic parameters which are optimized. Skew is always 0
+    let free_intrinsics: Vec<usize> = (0..ni).filter(|i| *i < 4 || !model.is_fixed(i - 4)).collect();
+    let err = optimize(&views, &mut params, &free_intrinsics, model, ni);
+
+    let total_points: usize = views.iter().map(|(v, _)| v.object_points.len()).sum();
+    let per_view_rms = views.iter().enumerate().map(|(i, (v, _))| {
+        (view_residuals(v, 0, &params, model, ni, i).norm_squared() / v.object_points.len() as f64).sqrt()
+    }).collect();
+
+    Some(CalibrationResult {
+        rms: (err / total_points as f64).sqrt(),
+        k: Matrix3::new(params[0], 0.0, params[2], 0.0, params[1], params[3], 0.0, 0.0, 1.0),
+        d: params.rows(4, nd).iter().copied().collect(),
+        per_view_rms,
+    })
+}
+
+// Solves for the camera model shared by all sources, and the transform of each source relative to the first one
+pub fn calibrate_joint(sources: &[Source], model: CameraModel) -> Option<JointCalibrationResult> {
+    if sources.is_empty() {
+        return None;
+    }
+    // Calibrate each source separately for the initial estimation
+    let individual = sources.iter().map(|s| calibrate(&s.views, s.size, model)).collect::<Option<Vec<_>>>()?;
+    let reference = &individual[0];
+
+    // Parameters: fx, fy, cx, cy, distortion coefficients, scale and offset of each source except the first one, then pose of each view
+    let nd = model.num_coeffs();
+    let ni = 4 + nd + (sources.len() - 1) * 3;
+    let views: Vec<(&View, usize)> = sources.iter().enumerate().flat_map(|(si, s)| s.views.iter().filter(|v| is_valid(v)).map(move |v| (v, si))).collect();
+    let mut params = DVector::<f64>::zeros(ni + views.len() * 6);
+    params[0] = reference.k[(0, 0)]; params[1] = reference.k[(1, 1)]; params[2] = reference.k[(0, 2)]; params[3] = reference.k[(1, 2)];
+    params.rows_mut(4, nd).copy_from_slice(&reference.d);
+    for (si, cal) in individual.iter().enumerate().skip(1) {
+        let o = 4 + nd + (si - 1) * 3;
+        let scale = (cal.k[(0, 0)] / reference.k[(0, 0)] + cal.k[(1, 1)] / reference.k[(1, 1)]) / 2.0;
+        params[o] = scale;
+        params[o + 1] = cal.k[(0, 2)] - reference.k[(0, 2)] * scale;
+        params[o + 2] = cal.k[(1, 2)] - reference.k[(1, 2)] * scale;
+    }
+    for (i, (v, si)) in views.iter().enumerate() {
+        let k = &individual[*si].k;
+        let (r, t) = initial_pose(v, model, k[(0, 0)], k[(0, 2)], k[(1, 2)])?;
+        params.fixed_rows_mut::<3>(ni + i * 6).copy_from(&r.scaled_axis());
+        params.fixed_rows_mut::<3>(ni + i * 6 + 3).copy_from(&t);
+    }
+
+    let free_intrinsics: Vec<usize> = (0..ni).filter(|i| *i < 4 || *i >= 4 + nd || !model.is_fixed(i - 4)).collect();
+    let err = optimize(&views, &mut params, &free_intrinsics, model, ni);
+
+    let total_points: usize = views.iter().map(|(v, _)| v.object_points.len()).sum();
+    let per_source_rms = (0..sources.len()).map(|si| {
+        let (sum, count) = views.iter().enumerate().filter(|(_, (_, s))| *s == si).fold((0.0, 0), |(sum, count), (i, (v, s))| {
+            (sum + view_residuals(v, *s, &params, model, ni, i).norm_squared(), count + v.object_points.len())
+        });
+        (sum / count.max(1) as f64).sqrt()
+    }).collect();
+
+    Some(JointCalibrationResult {
+        rms: (err / total_points as f64).sqrt(),
+        k: Matrix3::new(params[0], 0.0, params[2], 0.0, params[1], params[3], 0.0, 0.0, 1.0),
+        d: params.rows(4, nd).iter().copied().collect(),
+        transforms: (0..sources.len()).map(|si| if si == 0 {
+            SourceTransform { scale: 1.0, offset: (0.0, 0.0) }
+        } else {
+            let o = 4 + nd + (si - 1) * 3;
+            SourceTransform { scale: params[o], offset: (params[o + 1], params[o + 2]) }
+        }).collect(),
+        per_source_rms,
+    })
+}
+
+fn is_valid(v: &View) -> bool {
+    v.object_points.len() >= 4 && v.object_points.len() == v.image_points.len()
+}
+
+// Levenberg-Marquardt. `params` are the `ni` intrinsic parameters followed by the pose of each view. Returns the sum of squared errors
+fn optimize(views: &[(&View, usize)], params: &mut DVector<f64>, free_intrinsics: &[usize], model: CameraModel, ni: usize) -> f64 {
+    let nfi = free_intrinsics.len();
+    let n = nfi + views.len() * 6;
+
+    let mut lambda = 1e-3;
+    let mut err = total_error(views, params, model, ni);
+    for _ in 0..MAX_ITERATIONS {
+        // Build normal equations J^T J and J^T r, view by view, as each view depends only on the intrinsics and its own pose
+        let mut jtj = DMatrix::<f64>::zeros(n, n);
+        let mut jtr = DVector::<f64>::zeros(n);
+        for (vi, (v, si)) in views.iter().enumerate() {
+            let residuals = view_residuals(v, *si, params, model, ni, vi);
+            let m = residuals.len();
+            let mut jac = DMatrix::<f64>::zeros(m, nfi + 6);
+            let mut p = params.clone();
+            for (col, idx) in free_intrinsics.iter().copied().chain(ni + vi * 6..ni + vi * 6 + 6).enumerate() {
+                let h = 1e-7 * params[idx].abs().max(1e-2);
+                p[idx] = params[idx] + h;
+                let r2 = view_residuals(v, *si, &p, model, ni, vi);
+                p[idx] = params[idx];
+                jac.column_mut(col).copy_from(&((r2 - &residuals) / h));
+            }
+            let jt = jac.transpose();
+            let a = &jt * &jac;
+            let g = &jt * &residuals;
+            let o = nfi + vi * 6;
+            jtj.view_mut((0, 0), (nfi, nfi)).add_assign(&a.view((0, 0), (nfi, nfi)));
+            jtj.view_mut((0, o), (nfi, 6)).add_assign(&a.view((0, nfi), (nfi, 6)));
+            jtj.view_mut((o, 0), (6, nfi)).add_assign(&a.view((nfi, 0), (6, nfi)));
+            jtj.view_mut((o, o), (6, 6)).add_assign(&a.view((nfi, nfi), (6, 6)));
+            jtr.rows_mut(0, nfi).add_assign(&g.rows(0, nfi));
+            jtr.rows_mut(o, 6).add_assign(&g.rows(nfi, 6));
+        }
+
+        // Try increasing damping until the error decreases
+        let mut improved = false;
+        for _ in 0..10 {
+            let mut a = jtj.clone();
+            for i in 0..n { a[(i, i)] += lambda * jtj[(i, i)].max(1e-9); }
+            let Some(delta) = a.cholesky().map(|c| c.solve(&-&jtr)) else { lambda *= 10.0; continue; };
+
+            let mut new_params = params.clone();
+            for (col, idx) in free_intrinsics.iter().enumerate() {
+                new_params[*idx] += delta[col];
+            }
+            new_params.rows_mut(ni, views.len() * 6).add_assign(&delta.rows(nfi, views.len() * 6));
+
+            let new_err = total_error(views, &new_params, model, ni);
+            if new_err.is_finite() && new_err < err {
+                let converged = (err - new_err) < 1e-12 * err.max(1e-12) || delta.norm() < 1e-12;
+                *params = new_params;
+                err = new_err;
+                lambda = (lambda / 10.0).max(1e-12);
+                improved = !converged;
+                break;
+            }
+            lambda *= 10.0;
+        }
+        if !improved {
+            break;
+        }
+    }
+    err
+}
+
+// Reprojection error of each point of the view, with fixed intrinsics. Only the pose of the target is optimized
+pub fn reprojection_errors(view: &View, k: &Matrix3<f64>, d: &[f64], model: CameraModel) -> Option<Vec<f64>> {
+    let nd = model.num_coeffs();
+    if d.len() < nd || view.object_points.len() < 4 || view.object_points.len() != view.image_points.len() {
+        return None;
+    }
+    let ni = 4 + nd;
+    let mut params = DVector::<f64>::zeros(ni + 6);
+    params[0] = k[(0, 0)]; params[1] = k[(1, 1)]; params[2] = k[(0, 2)]; params[3] = k[(1, 2)];
+    params.rows_mut(4, nd).copy_from_slice(&d[..nd]);
+    let (r, t) = initial_pose(view, model, params[0], params[2], params[3])?;
+    params.fixed_rows_mut::<3>(ni).copy_from(&r.scaled_axis());
+    params.fixed_rows_mut::<3>(ni + 3).copy_from(&t);
+
+    let mut lambda = 1e-3;
+    let mut residuals = view_residuals(view, 0, &params, model, ni, 0);
+    let mut err = residuals.norm_squared();
+    for _ in 0..MAX_ITERATIONS {
+        let mut jac = DMatrix::<f64>::zeros(residuals.len(), 6);
+        let mut p = params.clone();
+        for col in 0..6 {
+            let idx = ni + col;
+            let h = 1e-7 * params[idx].abs().max(1e-2);
+            p[idx] = params[idx] + h;
+            jac.column_mut(col).copy_from(&((view_residuals(view, 0, &p, model, ni, 0) - &residuals) / h));
+            p[idx] = params[idx];
+        }
+        let jtj = jac.transpose() * &jac;
+        let jtr = jac.transpose() * &residuals;
+
+        let mut improved = false;
+        for _ in 0..10 {
+            let mut a = jtj.clone();
+            for i in 0..6 { a[(i, i)] += lambda * jtj[(i, i)].max(1e-9); }
+            let Some(delta) = a.cholesky().map(|c| c.solve(&-&jtr)) else { lambda *= 10.0; continue; };
+            let mut new_params = params.clone();
+            new_params.rows_mut(ni, 6).add_assign(&delta);
+            let new_residuals = view_residuals(view, 0, &new_params, model, ni, 0);
+            let new_err = new_residuals.norm_squared();
+            if new_err.is_finite() && new_err < err {
+                improved = (err - new_err) >= 1e-12 * err.max(1e-12);
+                params = new_params;
+                residuals = new_residuals;
+                err = new_err;
+                lambda = (lambda / 10.0).max(1e-12);
+                break;
+            }
+            lambda *= 10.0;
+        }
+        if !improved {
+            break;
+        }
+    }
+    Some((0..view.object_points.len()).map(|i| residuals[i * 2].hypot(residuals[i * 2 + 1])).collect())
+}
+
+fn project(obj: (f64, f64), source: usize, r: &Rotation3<f64>, t: &Vector3<f64>, point_rotation: Option<&Matrix3<f64>>, params: &DVector<f64>, model: CameraModel) -> Vector2<f64> {
+    let mut p = r * Vector3::new(obj.0, obj.1, 0.0) + t;
+    if let Some(pr) = point_rotation {
+        p = pr.tr_mul(&p);
+    }
+    let nd = model.num_coeffs();
+    let uv = model.distort(p, params.rows(4, nd).as_slice());
+    let pt = Vector2::new(params[0] * uv.x + params[2], params[1] * uv.y + params[3]);
+    if source > 0 {
+        let o = 4 + nd + (source - 1) * 3;
+        return pt * params[o] + Vector2::new(params[o + 1], params[o + 2]);
+    }
+    pt
+}
+
+fn view_residuals(v: &View, source: usize, params: &DVector<f64>, model: CameraModel, ni: usize, vi: usize) -> DVector<f64> {
+    let r = Rotation3::new(params.fixed_rows::<3>(ni + vi * 6).into_owned());
+    let t = params.fixed_rows::<3>(ni + vi * 6 + 3).into_owned();
+    let mut ret = DVector::<f64>::zeros(v.object_points.len() * 2);
+    for (i, (obj, img)) in v.object_points.iter().zip(v.image_points.iter()).enumerate() {
+        let pt = project(*obj, source, &r, &t, v.point_rotations.get(i), params, model);
+        ret[i * 2]     = pt.x - img.0;
+        ret[i * 2 + 1] = pt.y - img.1;
+    }
+    ret
+}
+
+fn total_error(views: &[(&View, usize)], params: &DVector<f64>, model: CameraModel, ni: usize) -> f64 {
+    views.iter().enumerate().map(|(i, (v, si))| view_residuals(v, *si, params, model, ni, i).norm_squared()).sum()
+}
+
+// Normalized (undistorted) coordinates for the initial estimation. Distortion is assumed to be 0,
+// but for fisheye lenses the equidistant projection is a much closer approximation than pinhole
+fn normalize_point(p: (f64, f64), model: CameraModel, f: f64, cx: f64, cy: f64) -> (f64, f64) {
+    let x = (p.0 - cx) / f;
+    let y = (p.1 - cy) / f;
+    if model == CameraModel::Fisheye {
+        let theta = (x * x + y * y).sqrt();
+        if theta > 1e-12 {
+            let scale = theta.min(1.5).tan() / theta;
+            return (x * scale, y * scale);
+        }
+    }
+    (x, y)
+}
+
+fn initial_focal_length(views: &[(&View, usize)], size: (usize, usize), model: CameraModel, cx: f64, cy: f64) -> f64 {
+    let diagonal = (size.0 as f64).hypot(size.1 as f64);
+    if model == CameraModel::Fisheye {
+        // Same as OpenCV's fisheye::calibrate initialization
+        return diagonal / std::f64::consts::PI;
+    }
+    // Closed form solution with known principal point, square pixels and no skew
+    let mut estimates: Vec<f64> = views.iter().filter_map(|(v, _)| {
+        let img: Vec<(f64, f64)> = v.image_points.iter().map(|p| (p.0 - cx, p.1 - cy)).collect();
+        let h = find_homography(v.object_points, &img)?;
+        let (h1, h2) = (h.column(0), h.column(1));
+        let f2 = [
+            -(h1.x * h2.x + h1.y * h2.y) / (h1.z * h2.z),
+            -(h1.x * h1.x + h1.y * h1.y - h2.x * h2.x - h2.y * h2.y) / (h1.z * h1.z - h2.z * h2.z)
+        ];
+        let valid: Vec<f64> = f2.into_iter().filter(|x| x.is_finite() && *x > 0.0).collect();
+        if valid.is_empty() { None } else { Some((valid.iter().sum::<f64>() / valid.len() as f64).sqrt()) }
+    }).collect();
+    if estimates.is_empty() {
+        return diagonal;
+    }
+    estimates.sort_by(|a, b| a.total_cmp(b));
+    estimates[estimates.len() / 2]
+}
+
+fn initial_pose(v: &View, model: CameraModel, f: f64, cx: f64, cy: f64) -> Option<(Rotation3<f64>, Vector3<f64>)> {
+    let img: Vec<(f64, f64)> = v.image_points.iter().map(|p| normalize_point(*p, model, f, cx, cy)).collect();
+    let h = find_homography(v.object_points, &img)?;
+    let lambda = 1.0 / h.column(0).norm();
+    let mut r1 = h.column(0) * lambda;
+    let mut r2 = h.column(1) * lambda;
+    let mut t = h.column(2) * lambda;
+    if t.z < 0.0 {
+        // The homography is only known up to the sign, the target must be in front of the camera
+        r1 = -r1;
+        r2 = -r2;
+        t = -t;
+    }
+    let r = Matrix3::from_columns(&[r1, r2, r1.cross(&r2)]);
+
+    // Closest rotation matrix
+    let svd = r.svd(true, true);
+    let mut rot = svd.u? * svd.v_t?;
+    if rot.determinant() < 0.0 {
+        rot = -rot;
+    }
+    Some((Rotation3::from_matrix_unchecked(rot), t))
+}
+
+// Normalized DLT
+pub fn find_homography(src: &[(f64, f64)], dst: &[(f64, f64)]) -> Option<Matrix3<f64>> {
+    if src.len() < 4 || src.len() != dst.len() {
+        return None;
+    }
+    fn normalization(pts: &[(f64, f64)]) -> Matrix3<f64> {
+        let n = pts.len() as f64;
+        let (mx, my) = pts.iter().fold((0.0, 0.0), |a, p| (a.0 + p.0 / n, a.1 + p.1 / n));
+        let dist = pts.iter().map(|p| (p.0 - mx).hypot(p.1 - my)).sum::<f64>() / n;
+        let s = if dist > 1e-12 { std::f64::consts::SQRT_2 / dist } else { 1.0 };
+        Matrix3::new(s, 0.0, -s * mx, 0.0, s, -s * my, 0.0, 0.0, 1.0)
+    }
+    let ts = normalization(src);
+    let td = normalization(dst);
+    let mut a = DMatrix::<f64>::zeros(src.len() * 2, 9);
+    for (i, (s, d)) in src.iter().zip(dst.iter()).enumerate() {
+        let s = ts * Vector3::new(s.0, s.1, 1.0);
+        let d = td * Vector3::new(d.0, d.1, 1.0);
+        let (x, y, u, v) = (s.x, s.y, d.x, d.y);
+        a.row_mut(i * 2)    .copy_from_slice(&[-x, -y, -1.0, 0.0, 0.0, 0.0, u * x, u * y, u]);
+        a.row_mut(i * 2 + 1).copy_from_slice(&[0.0, 0.0, 0.0, -x, -y, -1.0, v * x, v * y, v]);
+    }
+    // Solution is the eigenvector of A^T A with the smallest eigenvalue
+    let ata = a.transpose() * a;
+    let eigen = SMatrix::<f64, 9, 9>::from_iterator(ata.iter().copied()).symmetric_eigen();
+    let (min_idx, _) = eigen.eigenvalues.iter().enumerate().min_by(|a, b| a.1.total_cmp(b.1))?;
+    let h = eigen.eigenvectors.column(min_idx);
+    let hn = Matrix3::new(h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7], h[8]);
+    let h = td.try_inverse()? * hn * ts;
+    if h[(2, 2)].abs() > 1e-12 { Some(h / h[(2, 2)]) } else { Some(h) }
+}
@@ -0,0 +1,388 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Small math expression language used for expression-driven keyframes, e.g. `1.0 + 0.1 * sin(t * 2)`
+//
+// Supported syntax:
+// - numbers, `+ - * / % ^`, parentheses, unary minus
+// - variables: `t` (seconds), `time_ms`, `frame`, `fps`, `duration` (seconds), `speed` (video speed),
+//   `gyro_x`, `gyro_y`, `gyro_z`, `gyro` (angular velocity in deg/s, `gyro` is the magnitude), `pi`, `e`,
+//   `gps_speed` (km/h), `altitude` (meters), `lens_position`,
+//   and any keyframe type name (e.g. `Fov`, `VideoSpeed`) which evaluates to its keyframed value
+// - functions: see `Function::from_name`
+// - optional `name =` prefix, which has to be the keyframe type the expression is assigned to
+
+use std::str::FromStr;
+use crate::keyframes::{ KeyframeType, TelemetryChannel };
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ExpressionError {
+    #[error("Unexpected character '{0}' at position {1}")]
+    UnexpectedCharacter(char, usize),
+
+    #[error("Unexpected end of expression")]
+    UnexpectedEnd,
+
+    #[error("Unexpected token at position {0}")]
+    UnexpectedToken(usize),
+
+    #[error("Unknown variable: {0}")]
+    UnknownVariable(String),
+
+    #[error("Unknown function: {0}")]
+    UnknownFunction(String),
+
+    #[error("Function {0} expects {1} arguments, got {2}")]
+    InvalidArgumentCount(String, String, usize),
+
+    #[error("Expression is empty")]
+    Empty,
+
+    #[error("Expression is assigned to {0}, but used for {1}")]
+    MismatchedName(String, String),
+
+    #[error("Expression is nested too deeply")]
+    TooDeep,
+}
+
+const MAX_DEPTH: usize = 64; // Parentheses, function calls and unary operators
+const MAX_TREE_DEPTH: usize = 1024; // Depth of the parsed tree, which also grows with every operator of a chain like `a + b + c`
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variable {
+    Time,
+    TimeMs,
+    Frame,
+    Fps,
+    Duration,
+    VideoSpeed,
+    GyroX,
+    GyroY,
+    GyroZ,
+    GyroMagnitude,
+    Telemetry(TelemetryChannel),
+    Keyframe(KeyframeType),
+}
+impl Variable {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "t"        => Self::Time,
+            "time_ms"  => Self::TimeMs,
+            "frame"    => Self::Frame,
+            "fps"      => Self::Fps,
+            "duration" => Self::Duration,
+            "speed"    => Self::VideoSpeed,
+            "gyro_x"   => Self::GyroX,
+            "gyro_y"   => Self::GyroY,
+            "gyro_z"   => Self::GyroZ,
+            "gyro"     => Self::GyroMagnitude,
+            "gps_speed"     => Self::Telemetry(TelemetryChannel::GpsSpeed),
+            "altitude"      => Self::Telemetry(TelemetryChannel::GpsAltitude),
+            "lens_position" => Self::Telemetry(TelemetryChannel::LensPosition),
+            _ => Self::Keyframe(KeyframeType::from_str(name).ok()?)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Sin, Cos, Tan, Asin, Acos, Atan, Atan2,
+    Abs, Sqrt, Exp, Ln, Log10, Pow,
+    Min, Max, Clamp, Floor, Ceil, Round, Sign,
+    Lerp, Smoothstep,
+}
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "sin"   => Self::Sin,   "cos"  => Self::Cos,  "tan"  => Self::Tan,
+            "asin"  => Self::Asin,  "acos" => Self::Acos, "atan" => Self::Atan, "atan2" => Self::Atan2,
+            "abs"   => Self::Abs,   "sqrt" => Self::Sqrt, "exp"  => Self::Exp,  "ln" => Self::Ln, "log10" => Self::Log10, "pow" => Self::Pow,
+            "min"   => Self::Min,   "max"  => Self::Max,  "clamp" => Self::Clamp,
+            "floor" => Self::Floor, "ceil" => Self::Ceil, "round" => Self::Round, "sign" => Self::Sign,
+            "lerp"  => Self::Lerp,  "smoothstep" => Self::Smoothstep,
+            _ => { return None; }
+        })
+    }
+    // (min, max) number of arguments
+    fn arity(&self) -> (usize, usize) {
+        match self {
+            Self::Atan2 | Self::Pow => (2, 2),
+            Self::Min | Self::Max => (2, usize::MAX),
+            Self::Clamp | Self::Lerp | Self::Smoothstep => (3, 3),
+            _ => (1, 1)
+        }
+    }
+    fn call(&self, a: &[f64]) -> f64 {
+        match self {
+            Self::Sin   => a[0].sin(),
+            Self::Cos   => a[0].cos(),
+            Self::Tan   => a[0].tan(),
+            Self::Asin  => a[0].asin(),
+            Self::Acos  => a[0].acos(),
+            Self::Atan  => a[0].atan(),
+            Self::Atan2 => a[0].atan2(a[1]),
+            Self::Abs   => a[0].abs(),
+            Self::Sqrt  => a[0].sqrt(),
+            Self::Exp   => a[0].exp(),
+            Self::Ln    => a[0].ln(),
+            Self::Log10 => a[0].log10(),
+            Self::Pow   => a[0].powf(a[1]),
+            Self::Min   => a.iter().copied().fold(f64::INFINITY, f64::min),
+            Self::Max   => a.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Self::Clamp => a[0].max(a[1]).min(a[2]),
+            Self::Floor => a[0].floor(),
+            Self::Ceil  => a[0].ceil(),
+            Self::Round => a[0].round(),
+            Self::Sign  => if a[0] > 0.0 { 1.0 } else if a[0] < 0.0 { -1.0 } else { 0.0 },
+            Self::Lerp  => a[0] + (a[1] - a[0]) * a[2],
+            Self::Smoothstep => {
+                let x = ((a[2] - a[0]) / (a[1] - a[0])).clamp(0.0, 1.0);
+                x * x * (3.0 - 2.0 * x)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f64),
+    Variable(Variable),
+    Negate(Box<Node>),
+    Binary(char, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub source: String,
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(source: &str, target: &KeyframeType) -> Result<Self, ExpressionError> {
+        let tokens = Self::tokenize(source)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0, depth: 0, tree_depth: 0 };
+
+        // Skip the optional `name =` prefix
+        if let (Some((Token::Ident(name), _)), Some((Token::Op('='), _))) = (tokens.get(0), tokens.get(1)) {
+            if KeyframeType::from_str(name).ok().as_ref() != Some(target) {
+                return Err(ExpressionError::MismatchedName(name.clone(), format!("{target:?}")));
+            }
+            parser.pos = 2;
+        }
+        if parser.pos >= tokens.len() {
+            return Err(ExpressionError::Empty);
+        }
+
+        let root = parser.expression()?;
+        if let Some((_, pos)) = tokens.get(parser.pos) {
+            return Err(ExpressionError::UnexpectedToken(*pos));
+        }
+        Ok(Self { source: source.to_owned(), root })
+    }
+
+    pub fn eval<F: Fn(&Variable) -> f64>(&self, get_variable: &F) -> f64 {
+        Self::eval_node(&self.root, get_variable)
+    }
+
+    pub fn variables(&self) -> Vec<Variable> {
+        fn collect(node: &Node, out: &mut Vec<Variable>) {
+            match node {
+                Node::Variable(v) => { if !out.contains(v) { out.push(*v); } },
+                Node::Negate(a) => collect(a, out),
+                Node::Binary(_, a, b) => { collect(a, out); collect(b, out); },
+                Node::Call(_, args) => args.iter().for_each(|x| collect(x, out)),
+                Node::Number(_) => { }
+            }
+        }
+        let mut ret = Vec::new();
+        collect(&self.root, &mut ret);
+        ret
+    }
+
+    fn eval_node<F: Fn(&Variable) -> f64>(node: &Node, get_variable: &F) -> f64 {
+        match node {
+            Node::Number(v) => *v,
+            Node::Variable(v) => get_variable(v),
+            Node::Negate(a) => -Self::eval_node(a, get_variable),
+            Node::Binary(op, a, b) => {
+                let a = Self::eval_node(a, get_variable);
+                let b = Self::eval_node(b, get_variable);
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    '%' => a % b,
+                    '^' => a.powf(b),
+                    _ => unreachable!()
+                }
+            }
+            Node::Call(f, args) => {
+                let args = args.iter().map(|x| Self::eval_node(x, get_variable)).collect::<Vec<_>>();
+                f.call(&args)
+            }
+        }
+    }
+
+    fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ExpressionError> {
+        let chars = source.char_indices().collect::<Vec<_>>();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let (pos, c) = chars[i];
+            if c.is_whitespace() {
+                i += 1;
+            } else if c.is_ascii_digit() || c == '.' {
+                let start = i;
+                while i < chars.len() && (chars[i].1.is_ascii_digit() || chars[i].1 == '.' ||
+                    ((chars[i].1 == 'e' || chars[i].1 == 'E') && i + 1 < chars.len() && (chars[i + 1].1.is_ascii_digit() || chars[i + 1].1 == '-' || chars[i + 1].1 == '+')) ||
+                    ((chars[i].1 == '-' || chars[i].1 == '+') && (chars[i - 1].1 == 'e' || chars[i - 1].1 == 'E'))) {
+                    i += 1;
+                }
+                let text = chars[start..i].iter().map(|x| x.1).collect::<String>();
+                tokens.push((Token::Number(text.parse::<f64>().map_err(|_| ExpressionError::UnexpectedCharacter(c, pos))?), pos));
+            } else if c.is_alphabetic() || c == '_' {
+                let start = i;
+                while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+                    i += 1;
+                }
+                tokens.push((Token::Ident(chars[start..i].iter().map(|x| x.1).collect()), pos));
+            } else if "+-*/%^(),=".contains(c) {
+                tokens.push((Token::Op(c), pos));
+                i += 1;
+            } else {
+                return Err(ExpressionError::UnexpectedCharacter(c, pos));
+            }
+        }
+        Ok(tokens)
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [(Token, usize)],
+    pos: usize,
+    depth: usize, // Nesting of the recursive calls, limited so a malicious input can't overflow the stack
+    tree_depth: usize, // Depth of the tree built so far, evaluating it is recursive too
+}
+impl<'a> Parser<'a> {
+    fn peek_op(&self) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some((Token::Op(c), _)) => Some(*c),
+            _ => None
+        }
+    }
+    fn expect_op(&mut self, op: char) -> Result<(), ExpressionError> {
+        match self.tokens.get(self.pos) {
+            Some((Token::Op(c), _)) if *c == op => { self.pos += 1; Ok(()) },
+            Some((_, pos)) => Err(ExpressionError::UnexpectedToken(*pos)),
+            None => Err(ExpressionError::UnexpectedEnd)
+        }
+    }
+
+    fn enter_tree(&mut self) -> Result<(), ExpressionError> {
+        self.tree_depth += 1;
+        if self.tree_depth > MAX_TREE_DEPTH {
+            return Err(ExpressionError::TooDeep);
+        }
+        Ok(())
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Node, ExpressionError> {
+        let tree_depth = self.tree_depth;
+        let mut node = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek_op() {
+            self.pos += 1;
+            // The chain is built as a left-deep tree, so every operator is one more level of the tree, but not of the parser's recursion
+            self.enter_tree()?;
+            node = Node::Binary(op, Box::new(node), Box::new(self.term()?));
+        }
+        self.tree_depth = tree_depth;
+        Ok(node)
+    }
+    // term := unary (('*' | '/' | '%') unary)*
+    fn term(&mut self) -> Result<Node, ExpressionError> {
+        let tree_depth = self.tree_depth;
+        let mut node = self.unary()?;
+        while let Some(op @ ('*' | '/' | '%')) = self.peek_op() {
+            self.pos += 1;
+            self.enter_tree()?;
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+        self.tree_depth = tree_depth;
+        Ok(node)
+    }
+    // unary := ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<Node, ExpressionError> {
+        // Every nested level of the grammar goes through here
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(ExpressionError::TooDeep);
+        }
+        self.enter_tree()?;
+        let node = match self.peek_op() {
+            Some('-') => { self.pos += 1; self.unary().map(|x| Node::Negate(Box::new(x))) },
+            Some('+') => { self.pos += 1; self.unary() },
+            _ => self.power()
+        };
+        self.depth -= 1;
+        self.tree_depth -= 1;
+        node
+    }
+    // power := primary ('^' unary)?
+    fn power(&mut self) -> Result<Node, ExpressionError> {
+        let node = self.primary()?;
+        if self.peek_op() == Some('^') {
+            self.pos += 1;
+            return Ok(Node::Binary('^', Box::new(node), Box::new(self.unary()?)));
+        }
+        Ok(node)
+    }
+    // primary := number | variable | function '(' args ')' | '(' expression ')'
+    fn primary(&mut self) -> Result<Node, ExpressionError> {
+        let (token, pos) = self.tokens.get(self.pos).ok_or(ExpressionError::UnexpectedEnd)?;
+        self.pos += 1;
+        match token {
+            Token::Number(v) => Ok(Node::Number(*v)),
+            Token::Op('(') => {
+                let node = self.expression()?;
+                self.expect_op(')')?;
+                Ok(node)
+            }
+            Token::Ident(name) if self.peek_op() == Some('(') => {
+                let func = Function::from_name(name).ok_or_else(|| ExpressionError::UnknownFunction(name.clone()))?;
+                self.pos += 1;
+                let mut args = Vec::new();
+                if self.peek_op() != Some(')') {
+                    args.push(self.expression()?);
+                    while self.peek_op() == Some(',') {
+                        self.pos += 1;
+                        args.push(self.expression()?);
+                    }
+                }
+                self.expect_op(')')?;
+                let (min, max) = func.arity();
+                if args.len() < min || args.len() > max {
+                    let expected = if min == max { min.to_string() } else if max == usize::MAX { format!("at least {min}") } else { format!("{min}-{max}") };
+                    return Err(ExpressionError::InvalidArgumentCount(name.clone(), expected, args.len()));
+                }
+                Ok(Node::Call(func, args))
+            }
+            Token::Ident(name) => {
+                match name.as_str() {
+                    "pi" => Ok(Node::Number(std::f64::consts::PI)),
+                    "e"  => Ok(Node::Number(std::f64::consts::E)),
+                    _ => Ok(Node::Variable(Variable::from_name(name).ok_or_else(|| ExpressionError::UnknownVariable(name.clone()))?))
+                }
+            }
+            _ => Err(ExpressionError::UnexpectedToken(*pos))
+        }
+    }
+}
@@ -70,6 +70,28 @@ pub enum BufferSource<'a> {
     },
 }
 impl<'a> BufferDescription<'a> {
+    /// Same description with a shorter lifetime, the CPU buffer is reborrowed and the GPU handles are copied
+    pub fn reborrow(&mut self) -> BufferDescription<'_> {
+        let data = match &mut self.data {
+            BufferSource::None => BufferSource::None,
+            BufferSource::Cpu { buffer } => BufferSource::Cpu { buffer: &mut **buffer },
+            #[cfg(feature = "use-opencl")]
+            BufferSource::OpenCL { texture, queue } => BufferSource::OpenCL { texture: *texture, queue: *queue },
+            #[cfg(target_os = "windows")]
+            BufferSource::DirectX11 { texture, device, device_context } => BufferSource::DirectX11 { texture: *texture, device: *device, device_context: *device_context },
+            BufferSource::OpenGL { texture, context } => BufferSource::OpenGL { texture: *texture, context: *context },
+            #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+            BufferSource::Vulkan { texture, device, physical_device, instance } => BufferSource::Vulkan { texture: *texture, device: *device, physical_device: *physical_device, instance: *instance },
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            BufferSource::Metal { texture, command_queue } => BufferSource::Metal { texture: *texture, command_queue: *command_queue },
+            #[cfg(any(target_os = "macos", target_os = "ios"))]
+            BufferSource::MetalBuffer { buffer, command_queue } => BufferSource::MetalBuffer { buffer: *buffer, command_queue: *command_queue },
+            #[cfg(any(target_os = "windows", target_os = "linux"))]
+            BufferSource::CUDABuffer { buffer } => BufferSource::CUDABuffer { buffer: *buffer },
+        };
+        BufferDescription { size: self.size, rect: self.rect, rotation: self.rotation, data, texture_copy: self.texture_copy }
+    }
+
     pub fn get_checksum(&self) -> u32 {
         let mut hasher = crc32fast::Hasher::new();
         hasher.write_usize(self.size.0);
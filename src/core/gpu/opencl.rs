@@ -201,6 +201,7 @@ impl OclWrapper {
                        .replace("DATA_CONVERT", ocl_names.1)
                        .replace("DATA_TYPE", ocl_names.0)
                        .replace("PIXEL_BYTES", &format!("{}", params.bytes_per_pixel))
+                       .replace("COLOR_CORRECTION", if ocl_names.2 == "float4" { "1" } else { "0" })
                        .replace("INTERPOLATION", &format!("{}", params.interpolation));
 
         if (params.flags & 8) == 0 { // Drawing not enabled
@@ -281,7 +282,7 @@ impl OclWrapper {
                 .devices(ctx.device)
                 .build(&ctx.context)?;
 
-            let max_matrix_count = 12 * if (params.flags & 16) == 16 { params.width } else { params.height };
+            let max_matrix_count = 12 * if (params.flags & 16) == 16 { params.width } else { params.height } * (1 + params.motion_blur_samples.max(0));
             let flags = MemFlags::new().read_only().host_write_only();
 
             let buf_params   = Buffer::builder().queue(ocl_queue.clone()).flags(flags).len(std::mem::size_of::<KernelParams>()).build()?;
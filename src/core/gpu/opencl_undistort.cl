@@ -45,6 +45,11 @@ typedef struct {
     float reserved1;                 // 8
     float reserved2;                 // 12
     float pixel_value_limit;         // 16
+    float4 vignetting;               // 16 - v1, v2, v3 of the falloff 1 + v1*r^2 + v2*r^4 + v3*r^6, r normalized to half of the input diagonal
+    float4 chromatic_aberration;     // 16 - radial scale - 1 of each channel, relative to the lens center
+    float2 output_f;                 // 8  - focal length of the output camera in pixels
+    int output_projection;           // 12 - 0: rectilinear, 1: equidistant fisheye, 2: stereographic, 3: equirectangular
+    int motion_blur_samples;         // 16 - sub-frame warps averaged for the motion blur, 0 or 1 = disabled. Their matrices follow the main ones
 } KernelParams;
 
 #if INTERPOLATION == 2 // Bilinear
@@ -216,13 +221,67 @@ DATA_TYPEF sample_input_at(float2 uv, __global const uchar *srcptr, __global Ker
     return min(sum, (DATA_TYPEF)(params->pixel_value_limit));
 }
 
-float2 rotate_and_distort(float2 pos, uint idx, __global KernelParams *params, __global const float *matrices) {
+// Lateral chromatic aberration and vignetting of the lens, corrected at the source position
+DATA_TYPEF sample_corrected_input_at(float2 uv, __global const uchar *srcptr, __global KernelParams *params, __global const uchar *drawing, DATA_TYPEF bg) {
+    DATA_TYPEF pixel = sample_input_at(uv, srcptr, params, drawing, bg);
+#if COLOR_CORRECTION // Only for RGB formats
+    float4 ca = params->chromatic_aberration;
+    float4 v = params->vignetting;
+    if (all(ca == 0.0f) && all(v == 0.0f)) {
+        return pixel;
+    }
+    float2 center = params->c;
+    if (params->input_horizontal_stretch > 0.001f) { center.x /= params->input_horizontal_stretch; }
+    if (params->input_vertical_stretch   > 0.001f) { center.y /= params->input_vertical_stretch; }
+
+    if (ca.x != 0.0f) { pixel.x = sample_input_at(center + (uv - center) * (1.0f + ca.x), srcptr, params, drawing, bg).x; }
+    if (ca.y != 0.0f) { pixel.y = sample_input_at(center + (uv - center) * (1.0f + ca.y), srcptr, params, drawing, bg).y; }
+    if (ca.z != 0.0f) { pixel.z = sample_input_at(center + (uv - center) * (1.0f + ca.z), srcptr, params, drawing, bg).z; }
+
+    if (any(v != 0.0f)) {
+        float2 size = (float2)((float)params->width, (float)params->height);
+        float r2 = dot(uv - center, uv - center) / (dot(size, size) / 4.0f);
+        float gain = fmax(0.1f, 1.0f + r2 * (v.x + r2 * (v.y + r2 * v.z)));
+        pixel.xyz = fmin(pixel.xyz / gain, (float3)(params->pixel_value_limit));
+    }
+#endif
+    return pixel;
+}
+
+// Ray of the output pixel for the selected output projection, in homogeneous pixel coordinates of the rectilinear output camera
+// so it can be multiplied by the rotation matrices as is. Points outside of the projection return a zero vector
+float3 output_projection_ray(float2 pos, __global KernelParams *params) {
+    if (params->output_projection == 0) return (float3)(pos, 1.0f);
+
+    float2 out_c = (float2)(params->output_width / 2.0f, params->output_height / 2.0f);
+    float2 n = (pos - out_c) / params->output_f;
+
+    float3 dir;
+    if (params->output_projection == 3) { // Equirectangular
+        if (fabs(n.y) > M_PI_2_F) return (float3)(0.0f, 0.0f, 0.0f);
+        dir = (float3)(cos(n.y) * sin(n.x), sin(n.y), cos(n.y) * cos(n.x));
+    } else {
+        float r = length(n);
+        float theta = params->output_projection == 2? 2.0f * atan(r / 2.0f) : r; // Stereographic : Equidistant
+        if (theta > M_PI_F) return (float3)(0.0f, 0.0f, 0.0f);
+        float scale = r > 0.0f? sin(theta) / r : 1.0f;
+        dir = (float3)(n * scale, cos(theta));
+    }
+
+    return (float3)(params->output_f * dir.xy + out_c * dir.z, dir.z);
+}
+
+float2 rotate_and_distort(float3 pos, uint idx, __global KernelParams *params, __global const float *matrices) {
     __global const float *matrix = &matrices[idx];
-    float _x = (pos.x * matrix[0]) + (pos.y * matrix[1]) + matrix[2] + params->translation3d.x;
-    float _y = (pos.x * matrix[3]) + (pos.y * matrix[4]) + matrix[5] + params->translation3d.y;
-    float _w = (pos.x * matrix[6]) + (pos.y * matrix[7]) + matrix[8] + params->translation3d.z;
-    if (_w > 0) {
-        if (params->r_limit > 0.0f && length((float2)(_x, _y) / _w) > params->r_limit) {
+    float _x = (pos.x * matrix[0]) + (pos.y * matrix[1]) + (pos.z * matrix[2]) + params->translation3d.x;
+    float _y = (pos.x * matrix[3]) + (pos.y * matrix[4]) + (pos.z * matrix[5]) + params->translation3d.y;
+    float _w = (pos.x * matrix[6]) + (pos.y * matrix[7]) + (pos.z * matrix[8]) + params->translation3d.z;
+    if (params->flags & 32) { // Spherical input
+        return (float2)(params->width  * (0.5f + atan2(_x, _w) / (2.0f * M_PI_F)),
+                        params->height * (0.5f + atan2(_y, sqrt(_x * _x + _w * _w)) / M_PI_F));
+    }
+    if (_w > 0 || (params->flags & 64)) { // Wide FOV lens
+        if (params->r_limit > 0.0f && (_w <= 0 || length((float2)(_x, _y) / _w) > params->r_limit)) {
             return (float2)(-99999.0f, -99999.0f);
         }
         float2 uv = params->f * distort_point(_x, _y, _w, params) + params->c;
@@ -239,6 +298,71 @@ float2 rotate_and_distort(float2 pos, uint idx, __global KernelParams *params, _
     return (float2)(-99999.0f, -99999.0f);
 }
 
+// Source pixel for the output position, using the matrices starting at `offset`
+DATA_TYPEF sample_stabilized_at(float3 ray, float2 out_pos, int offset, __global const uchar *srcptr, __global KernelParams *params, __global const float *matrices, __global const uchar *drawing, DATA_TYPEF bg) {
+    ///////////////////////////////////////////////////////////////////
+    // Calculate source `y` for rolling shutter
+    int sy = 0;
+    if ((params->flags & 16) == 16) { // Horizontal RS
+        sy = min((int)params->width, max(0, (int)round(out_pos.x)));
+    } else {
+        sy = min((int)params->height, max(0, (int)round(out_pos.y)));
+    }
+    if (params->matrix_count > 1) {
+        int idx = offset + (params->matrix_count / 2) * 12; // Use middle matrix
+        float2 uv = rotate_and_distort(ray, idx, params, matrices);
+        if (uv.x > -99998.0f) {
+            if ((params->flags & 16) == 16) { // Horizontal RS
+                sy = min((int)params->width, max(0, (int)round(uv.x)));
+            } else {
+                sy = min((int)params->height, max(0, (int)round(uv.y)));
+            }
+        }
+    }
+    ///////////////////////////////////////////////////////////////////
+
+    int idx = offset + min(sy, params->matrix_count - 1) * 12;
+    float2 uv = rotate_and_distort(ray, idx, params, matrices);
+    if (uv.x > -99998.0f) {
+        switch (params->background_mode) {
+            case 1: { // edge repeat
+                uv = max((float2)(0, 0), min((float2)(params->width - 1, params->height - 1), uv));
+            } break;
+            case 2: { // edge mirror
+                int rx = round(uv.x);
+                int ry = round(uv.y);
+                int width3 = (params->width - 3);
+                int height3 = (params->height - 3);
+                if (rx > width3)  uv.x = width3  - (rx - width3);
+                if (rx < 3)       uv.x = 3 + params->width - (width3  + rx);
+                if (ry > height3) uv.y = height3 - (ry - height3);
+                if (ry < 3)       uv.y = 3 + params->height - (height3 + ry);
+            } break;
+            case 3: { // margin with feather
+                float widthf  = (params->width  - 1);
+                float heightf = (params->height - 1);
+
+                float feather = max(0.0001f, params->background_margin_feather * heightf);
+                float2 pt2 = uv;
+                float alpha = 1.0f;
+                if ((uv.x > widthf - feather) || (uv.x < feather) || (uv.y > heightf - feather) || (uv.y < feather)) {
+                    alpha = fmax(0.0f, fmin(1.0f, fmin(fmin(widthf - uv.x, heightf - uv.y), fmin(uv.x, uv.y)) / feather));
+                    pt2 /= (float2)(widthf, heightf);
+                    pt2 = ((pt2 - 0.5f) * (1.0f - params->background_margin)) + 0.5f;
+                    pt2 *= (float2)(widthf, heightf);
+                }
+
+                DATA_TYPEF c1 = sample_corrected_input_at(uv,  srcptr, params, drawing, bg);
+                DATA_TYPEF c2 = sample_corrected_input_at(pt2, srcptr, params, drawing, bg);
+                return c1 * alpha + c2 * (1.0f - alpha);
+            } break;
+        }
+
+        return sample_corrected_input_at(uv, srcptr, params, drawing, bg);
+    }
+    return bg;
+}
+
 // Adapted from OpenCV: initUndistortRectifyMap + remap
 // https://github.com/opencv/opencv/blob/2b60166e5c65f1caccac11964ad760d847c536e4/modules/calib3d/src/fisheye.cpp#L465-L567
 // https://github.com/opencv/opencv/blob/2b60166e5c65f1caccac11964ad760d847c536e4/modules/imgproc/src/opencl/remap.cl#L390-L498
@@ -268,7 +392,7 @@ __kernel void undistort_image(__global const uchar *srcptr, __global uchar *dstp
 
         ///////////////////////////////////////////////////////////////////
         // Add lens distortion back
-        if (params->lens_correction_amount < 1.0f) {
+        if (params->lens_correction_amount < 1.0f && params->output_projection == 0) {
             float2 factor = (float2)max(1.0f - params->lens_correction_amount, 0.001f); // FIXME: this is close but wrong
             float2 out_c = (float2)(params->output_width / 2.0f, params->output_height / 2.0f);
             float2 out_f = (params->f / params->fov) / factor;
@@ -286,73 +410,18 @@ __kernel void undistort_image(__global const uchar *srcptr, __global uchar *dstp
         }
         ///////////////////////////////////////////////////////////////////
 
-        ///////////////////////////////////////////////////////////////////
-        // Calculate source `y` for rolling shutter
-        int sy = 0;
-        if ((params->flags & 16) == 16) { // Horizontal RS
-            sy = min((int)params->width, max(0, (int)round(out_pos.x)));
-        } else {
-            sy = min((int)params->height, max(0, (int)round(out_pos.y)));
-        }
-        if (params->matrix_count > 1) {
-            int idx = (params->matrix_count / 2) * 12; // Use middle matrix
-            float2 uv = rotate_and_distort(out_pos, idx, params, matrices);
-            if (uv.x > -99998.0f) {
-                if ((params->flags & 16) == 16) { // Horizontal RS
-                    sy = min((int)params->width, max(0, (int)round(uv.x)));
-                } else {
-                    sy = min((int)params->height, max(0, (int)round(uv.y)));
-                }
-            }
-        }
-        ///////////////////////////////////////////////////////////////////
+        float3 ray = output_projection_ray(out_pos, params);
 
         DATA_TYPE final_pix;
-
-        int idx = min(sy, params->matrix_count - 1) * 12;
-        float2 uv = rotate_and_distort(out_pos, idx, params, matrices);
-        if (uv.x > -99998.0f) {
-            switch (params->background_mode) {
-                case 1: { // edge repeat
-                    uv = max((float2)(0, 0), min((float2)(params->width - 1, params->height - 1), uv));
-                } break;
-                case 2: { // edge mirror
-                    int rx = round(uv.x);
-                    int ry = round(uv.y);
-                    int width3 = (params->width - 3);
-                    int height3 = (params->height - 3);
-                    if (rx > width3)  uv.x = width3  - (rx - width3);
-                    if (rx < 3)       uv.x = 3 + params->width - (width3  + rx);
-                    if (ry > height3) uv.y = height3 - (ry - height3);
-                    if (ry < 3)       uv.y = 3 + params->height - (height3 + ry);
-                } break;
-                case 3: { // margin with feather
-                    float widthf  = (params->width  - 1);
-                    float heightf = (params->height - 1);
-
-                    float feather = max(0.0001f, params->background_margin_feather * heightf);
-                    float2 pt2 = uv;
-                    float alpha = 1.0f;
-                    if ((uv.x > widthf - feather) || (uv.x < feather) || (uv.y > heightf - feather) || (uv.y < feather)) {
-                        alpha = fmax(0.0f, fmin(1.0f, fmin(fmin(widthf - uv.x, heightf - uv.y), fmin(uv.x, uv.y)) / feather));
-                        pt2 /= (float2)(widthf, heightf);
-                        pt2 = ((pt2 - 0.5f) * (1.0f - params->background_margin)) + 0.5f;
-                        pt2 *= (float2)(widthf, heightf);
-                    }
-
-                    DATA_TYPEF c1 = sample_input_at(uv,  srcptr, params, drawing, bg);
-                    DATA_TYPEF c2 = sample_input_at(pt2, srcptr, params, drawing, bg);
-                    final_pix = DATA_CONVERT(c1 * alpha + c2 * (1.0f - alpha));
-                    draw_pixel(&final_pix, x, y, false, max(params->width, params->output_width), params, drawing);
-                    draw_safe_area(&final_pix, x, y, params);
-                    *out_pix = final_pix;
-                    return;
-                } break;
+        if (params->motion_blur_samples > 1) {
+            // Average of the sub-frame warps, their matrices follow the main ones
+            DATA_TYPEF sum = (DATA_TYPEF)(0.0f);
+            for (int i = 1; i <= params->motion_blur_samples; ++i) {
+                sum += sample_stabilized_at(ray, out_pos, i * params->matrix_count * 12, srcptr, params, matrices, drawing, bg);
             }
-
-            final_pix = DATA_CONVERT(sample_input_at(uv, srcptr, params, drawing, bg));
+            final_pix = DATA_CONVERT(sum / (float)params->motion_blur_samples);
         } else {
-            final_pix = DATA_CONVERT(bg);
+            final_pix = DATA_CONVERT(sample_stabilized_at(ray, out_pos, 0, srcptr, params, matrices, drawing, bg));
         }
         draw_pixel(&final_pix, x, y, false, max(params->width, params->output_width), params, drawing);
         draw_safe_area(&final_pix, x, y, params);
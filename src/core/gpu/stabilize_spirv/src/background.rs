@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright © 2023 Adrian <adrian.eddy at gmail>
 
-use glam::{ vec2, Vec2, Vec4 };
+use glam::{ vec2, Vec2, Vec3, Vec4 };
 use super::types::*;
 use super::interpolate::*;
 
@@ -14,7 +14,7 @@ pub fn sample_with_background_at(mut uv: Vec2, coeffs: &[f32], input: &ImageType
                 uv.x.max(0.0).min(width_f  - 1.0),
                 uv.y.max(0.0).min(height_f - 1.0),
             );
-            sample_input_at(uv, coeffs, input, params, sampler)
+            sample_corrected_input_at(uv, coeffs, input, params, sampler)
         },
         2 => { // Edge mirror
             let rx = fast_round(uv.x) as f32;
@@ -25,7 +25,7 @@ pub fn sample_with_background_at(mut uv: Vec2, coeffs: &[f32], input: &ImageType
             if rx < 3.0     { uv.x = 3.0 + width_f - (width3  + rx); }
             if ry > height3 { uv.y = height3 - (ry - height3); }
             if ry < 3.0     { uv.y = 3.0 + height_f - (height3 + ry); }
-            sample_input_at(uv, coeffs, input, params, sampler)
+            sample_corrected_input_at(uv, coeffs, input, params, sampler)
         },
         3 => { // Margin with feather
             let size = vec2(width_f - 1.0, height_f - 1.0);
@@ -38,10 +38,35 @@ pub fn sample_with_background_at(mut uv: Vec2, coeffs: &[f32], input: &ImageType
                 pt2 = ((((pt2 / size) - 0.5) * (1.0 - params.background_margin)) + 0.5) * size;
             }
 
-            let c1 = sample_input_at(uv, coeffs, input, params, sampler);
-            let c2 = sample_input_at(pt2, coeffs, input, params, sampler);
+            let c1 = sample_corrected_input_at(uv, coeffs, input, params, sampler);
+            let c2 = sample_corrected_input_at(pt2, coeffs, input, params, sampler);
             c1 * alpha + c2 * (1.0 - alpha)
         },
-        _ => { sample_input_at(uv, coeffs, input, params, sampler) }
+        _ => { sample_corrected_input_at(uv, coeffs, input, params, sampler) }
     }
 }
+
+// Lateral chromatic aberration and vignetting of the lens, corrected at the source position
+pub fn sample_corrected_input_at(uv: Vec2, coeffs: &[f32], input: &ImageType, params: &KernelParams, sampler: SamplerType) -> Vec4 {
+    let mut pixel = sample_input_at(uv, coeffs, input, params, sampler);
+    if params.chromatic_aberration == Vec4::ZERO && params.vignetting == Vec4::ZERO {
+        return pixel;
+    }
+    let mut center = params.c;
+    if params.input_horizontal_stretch > 0.001 { center.x /= params.input_horizontal_stretch; }
+    if params.input_vertical_stretch   > 0.001 { center.y /= params.input_vertical_stretch; }
+
+    let ca = params.chromatic_aberration;
+    if ca.x != 0.0 { pixel.x = sample_input_at(center + (uv - center) * (1.0 + ca.x), coeffs, input, params, sampler).x; }
+    if ca.y != 0.0 { pixel.y = sample_input_at(center + (uv - center) * (1.0 + ca.y), coeffs, input, params, sampler).y; }
+    if ca.z != 0.0 { pixel.z = sample_input_at(center + (uv - center) * (1.0 + ca.z), coeffs, input, params, sampler).z; }
+
+    let v = params.vignetting;
+    if v != Vec4::ZERO {
+        let size = vec2(params.width as f32, params.height as f32);
+        let r2 = (uv - center).length_squared() / (size.length_squared() / 4.0);
+        let gain = (1.0 + r2 * (v.x + r2 * (v.y + r2 * v.z))).max(0.1);
+        pixel = (pixel.truncate() / gain).min(Vec3::splat(params.pixel_value_limit)).extend(pixel.w);
+    }
+    pixel
+}
@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// One-parameter division model: r_u = r_d / (1 + k1*r_d^2)
+
+use crate::types::*;
+use crate::glam::{ Vec2, vec2, Vec3 };
+pub struct Division { }
+
+impl Division {
+    pub fn undistort_point(point: Vec2, params: &KernelParams) -> Vec2 {
+        let denom = 1.0 + params.k1.x * point.length_squared();
+        if denom <= 0.0 {
+            // Beyond the horizon of the lens
+            return vec2(-99999.0, -99999.0);
+        }
+
+        point / denom
+    }
+
+    pub fn distort_point(point: Vec3, params: &KernelParams) -> Vec2 {
+        let pt = vec2(point.x / point.z, point.y / point.z);
+
+        // Numerically stable smaller root of k1*ru*rd^2 - rd + ru = 0
+        let scale = 2.0 / (1.0 + (1.0 - 4.0 * params.k1.x * pt.length_squared()).max(0.0).sqrt());
+
+        pt * scale
+    }
+
+    #[cfg(not(target_arch = "spirv"))]
+    pub fn adjust_lens_profile(_calib_w: &mut usize, _calib_h: &mut usize/*, lens_model: &mut String*/) { }
+}
@@ -0,0 +1,67 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Kannala-Brandt generic camera model with 8 radial coefficients: theta_d = theta * (1 + k1*theta^2 + k2*theta^4 + ... + k8*theta^16)
+
+use crate::types::*;
+use crate::glam::{ Vec2, vec2, Vec3, vec4 };
+pub struct KannalaBrandt { }
+
+impl KannalaBrandt {
+    fn poly(theta2: f32, params: &KernelParams) -> f32 {
+        let theta4 = theta2*theta2;
+        let theta8 = theta4*theta4;
+        let lo = vec4(theta2, theta4, theta4*theta2, theta8);
+        1.0 + params.k1.dot(lo) + params.k2.dot(lo * theta8)
+    }
+    fn dpoly(theta2: f32, params: &KernelParams) -> f32 {
+        let theta4 = theta2*theta2;
+        let theta8 = theta4*theta4;
+        let lo = vec4(theta2, theta4, theta4*theta2, theta8);
+        1.0 + (params.k1 * vec4(3.0, 5.0, 7.0, 9.0)).dot(lo) + (params.k2 * vec4(11.0, 13.0, 15.0, 17.0)).dot(lo * theta8)
+    }
+
+    pub fn undistort_point(point: Vec2, params: &KernelParams) -> Vec2 {
+        const EPS: f32 = 1e-6;
+
+        let theta_d = point.length().min(core::f32::consts::PI);
+        if theta_d < EPS { return point; }
+
+        let mut converged = false;
+        let mut theta = theta_d;
+
+        // compensate distortion iteratively
+        let mut i = 0; while i < 10 {
+        // for _ in 0..10 {
+            let theta2 = theta*theta;
+            // new_theta = theta - theta_fix, theta_fix = f0(theta) / f0'(theta)
+            let theta_fix = ((theta * Self::poly(theta2, params) - theta_d) / Self::dpoly(theta2, params)).max(-0.9).min(0.9);
+
+            theta = theta - theta_fix;
+            if theta_fix.abs() < EPS {
+                converged = true;
+                break;
+            }
+            i += 1;
+        }
+
+        // Rays at or beyond 90° can't be represented on the undistorted plane
+        if converged && theta > 0.0 && theta < core::f32::consts::FRAC_PI_2 {
+            return point * (theta.tan() / theta_d);
+        }
+        vec2(-99999.0, -99999.0)
+    }
+
+    pub fn distort_point(point: Vec3, params: &KernelParams) -> Vec2 {
+        let r = vec2(point.x, point.y).length();
+        if r == 0.0 { return vec2(0.0, 0.0); }
+
+        // Angle from the optical axis, valid also for points behind the camera plane
+        let theta = r.atan2(point.z);
+        let theta_d = theta * Self::poly(theta*theta, params);
+
+        vec2(point.x, point.y) * (theta_d / r)
+    }
+
+    #[cfg(not(target_arch = "spirv"))]
+    pub fn adjust_lens_profile(_calib_w: &mut usize, _calib_h: &mut usize/*, lens_model: &mut String*/) { }
+}
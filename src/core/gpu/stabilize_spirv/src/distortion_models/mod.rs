@@ -7,10 +7,14 @@ mod poly3;
 mod poly5;
 mod ptlens;
 mod insta360;
+mod kannala_brandt;
+mod division;
+mod scaramuzza;
 
 mod gopro_superview;
 mod gopro_hyperview;
 mod digital_stretch;
+mod nonlinear_stretch;
 
 use crate::KernelParams;
 use crate::glam::{ Vec2, Vec3 };
@@ -69,6 +73,14 @@ impl_models! {
     gopro_superview::GoProSuperview,
     gopro_hyperview::GoProHyperview,
     digital_stretch::DigitalStretch,
+
+    // Physical lenses added later, appended to keep the indices above stable in compiled shaders
+    kannala_brandt::KannalaBrandt,
+    division::Division,
+    scaramuzza::Scaramuzza,
+
+    // Digital lenses added later
+    nonlinear_stretch::NonlinearStretch,
 }
 
 mod none {
@@ -0,0 +1,55 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Generic non-linear stretch: [horizontal stretch, vertical stretch, a, b], horizontal curve g(r) = r * (1 + a*r^2 + b*r^4) / (1 + a + b)
+
+use crate::types::*;
+use crate::glam::{ Vec2, vec2, Vec3 };
+
+pub struct NonlinearStretch { }
+
+impl NonlinearStretch {
+    fn stretch_factors(params: &KernelParams) -> Vec2 {
+        vec2(if params.digital_lens_params.x > 0.01 { params.digital_lens_params.x } else { 1.0 },
+             if params.digital_lens_params.y > 0.01 { params.digital_lens_params.y } else { 1.0 })
+    }
+
+    /// `uv` range: (0, 0)...(width, height)
+    /// From processed to real
+    pub fn undistort_point(point: Vec2, params: &KernelParams) -> Vec2 {
+        let size = vec2(params.width as f32, params.height as f32);
+        let s = Self::stretch_factors(params);
+        let a = params.digital_lens_params.z;
+        let b = params.digital_lens_params.w;
+        let norm = (1.0 + a + b).max(1e-6);
+
+        let mut point = (point / size) * 2.0 - 1.0;
+        let mut r = point.x;
+        let mut i = 0; while i < 8 {
+            let r2 = r * r;
+            let poly  = r * (1.0 + a * r2 + b * r2 * r2) / norm;
+            let dpoly = ((1.0 + 3.0 * a * r2 + 5.0 * b * r2 * r2) / norm).max(1e-3);
+            r -= ((poly - point.x) / dpoly).max(-0.5).min(0.5);
+            i += 1;
+        }
+        point.x = r;
+
+        ((point / s) * 0.5 + 0.5) * size
+    }
+
+    /// `uv` range: (0, 0)...(width, height)
+    /// From real to processed
+    pub fn distort_point(point: Vec3, params: &KernelParams) -> Vec2 {
+        let size = vec2(params.width as f32, params.height as f32);
+        let a = params.digital_lens_params.z;
+        let b = params.digital_lens_params.w;
+
+        let mut point = ((vec2(point.x, point.y) / size) * 2.0 - 1.0) * Self::stretch_factors(params);
+        let r2 = point.x * point.x;
+        point.x = point.x * (1.0 + a * r2 + b * r2 * r2) / (1.0 + a + b).max(1e-6);
+
+        (point * 0.5 + 0.5) * size
+    }
+
+    #[cfg(not(target_arch = "spirv"))]
+    pub fn adjust_lens_profile(_calib_w: &mut usize, _calib_h: &mut usize/*, lens_model: &mut String*/) { }
+}
@@ -0,0 +1,56 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Scaramuzza omnidirectional camera model (OCamCalib): the ray of a distorted point at radius rho is (x, y, f(rho)),
+// with f(rho) = 1 + k1*rho^2 + k2*rho^3 + k3*rho^4 + k4*rho^5, in coordinates normalized by the focal length.
+
+use crate::types::*;
+use crate::glam::{ Vec2, vec2, Vec3 };
+pub struct Scaramuzza { }
+
+const NEWTON_EPS: f32 = 1e-6;
+
+impl Scaramuzza {
+    fn poly(rho: f32, params: &KernelParams) -> f32 {
+        1.0 + rho * rho * (params.k1.x + rho * (params.k1.y + rho * (params.k1.z + rho * params.k1.w)))
+    }
+    fn dpoly(rho: f32, params: &KernelParams) -> f32 {
+        rho * (2.0 * params.k1.x + rho * (3.0 * params.k1.y + rho * (4.0 * params.k1.z + rho * 5.0 * params.k1.w)))
+    }
+
+    pub fn undistort_point(point: Vec2, params: &KernelParams) -> Vec2 {
+        let z = Self::poly(point.length(), params);
+        if z <= NEWTON_EPS {
+            // The ray is at or beyond 90° from the optical axis
+            return vec2(-99999.0, -99999.0);
+        }
+
+        point / z
+    }
+
+    pub fn distort_point(point: Vec3, params: &KernelParams) -> Vec2 {
+        let r = vec2(point.x, point.y).length();
+        if r == 0.0 { return vec2(0.0, 0.0); }
+
+        // Find rho where (rho, f(rho)) is parallel to (r, z): r*f(rho) - z*rho = 0
+        let len = vec2(r, point.z).length();
+        let s = r / len;
+        let c = point.z / len;
+        let mut rho = s.atan2(c);
+        let mut i = 0; while i < 10 {
+        // for _ in 0..10 {
+            let d = s * Self::dpoly(rho, params) - c;
+            if d == 0.0 { break; }
+            let rho_fix = (s * Self::poly(rho, params) - c * rho) / d;
+            rho -= rho_fix;
+            if rho_fix.abs() < NEWTON_EPS {
+                break;
+            }
+            i += 1;
+        }
+
+        vec2(point.x, point.y) * (rho.max(0.0) / r)
+    }
+
+    #[cfg(not(target_arch = "spirv"))]
+    pub fn adjust_lens_profile(_calib_w: &mut usize, _calib_h: &mut usize/*, lens_model: &mut String*/) { }
+}
@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright © 2023 Adrian <adrian.eddy at gmail>
 
-use glam::{ Vec2, vec2, Vec3 };
+use glam::{ Vec2, vec2, Vec3, vec3 };
 use super::types::*;
 
 #[inline(never)]
@@ -25,3 +25,29 @@ pub fn digital_lens_undistort(point: Vec2, params: &KernelParams) -> Vec2 {
 pub fn digital_lens_distort(point: Vec3, params: &KernelParams) -> Vec2 {
     params.digital_lens.distort_point(point, params)
 }
+
+// Ray of the output pixel for the selected output projection, in homogeneous pixel coordinates of the rectilinear output camera
+// so it can be multiplied by the rotation matrices as is. Points outside of the projection return a zero vector
+pub fn output_projection_ray(pos: Vec2, params: &KernelParams) -> Vec3 {
+    if params.output_projection == 0 { return vec3(pos.x, pos.y, 1.0); }
+
+    let out_c = vec2(params.output_width as f32 / 2.0, params.output_height as f32 / 2.0);
+    let n = (pos - out_c) / params.output_f;
+
+    let dir = if params.output_projection == 3 { // Equirectangular
+        if n.y.abs() > core::f32::consts::FRAC_PI_2 { return Vec3::ZERO; }
+        vec3(n.y.cos() * n.x.sin(), n.y.sin(), n.y.cos() * n.x.cos())
+    } else {
+        let r = n.length();
+        let theta = if params.output_projection == 2 { // Stereographic
+            2.0 * (r / 2.0).atan()
+        } else { // Equidistant
+            r
+        };
+        if theta > core::f32::consts::PI { return Vec3::ZERO; }
+        let scale = if r > 0.0 { theta.sin() / r } else { 1.0 };
+        vec3(n.x * scale, n.y * scale, theta.cos())
+    };
+
+    vec3(params.output_f.x * dir.x + out_c.x * dir.z, params.output_f.y * dir.y + out_c.y * dir.z, dir.z)
+}
@@ -1,7 +1,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 // Copyright © 2023 Adrian <adrian.eddy at gmail>
 
-use glam::{ vec2, Vec2, vec3, Vec4 };
+use glam::{ vec2, Vec2, vec3, Vec3, Vec4 };
 use super::drawing::*;
 use super::types::*;
 use super::lens::*;
@@ -18,15 +18,21 @@ fn get_mtrx_param(_size_for_rs: f32, matrices: &MatricesType, _sampler: SamplerT
     }
 }
 
-pub fn rotate_and_distort(pos: Vec2, idx: i32, params: &KernelParams, matrices: &MatricesType, sampler: SamplerType) -> Vec2 {
+pub fn rotate_and_distort(pos: Vec3, idx: i32, params: &KernelParams, matrices: &MatricesType, sampler: SamplerType) -> Vec2 {
     let size_for_rs = if (params.flags & 16) == 16 { params.width as f32 } else { params.height as f32 };
     let point_3d = vec3(
-        (pos.x * get_mtrx_param(size_for_rs, matrices, sampler, idx, 0)) + (pos.y * get_mtrx_param(size_for_rs, matrices, sampler, idx, 1)) + get_mtrx_param(size_for_rs, matrices, sampler, idx, 2) + params.translation3d.x,
-        (pos.x * get_mtrx_param(size_for_rs, matrices, sampler, idx, 3)) + (pos.y * get_mtrx_param(size_for_rs, matrices, sampler, idx, 4)) + get_mtrx_param(size_for_rs, matrices, sampler, idx, 5) + params.translation3d.y,
-        (pos.x * get_mtrx_param(size_for_rs, matrices, sampler, idx, 6)) + (pos.y * get_mtrx_param(size_for_rs, matrices, sampler, idx, 7)) + get_mtrx_param(size_for_rs, matrices, sampler, idx, 8) + params.translation3d.z
+        (pos.x * get_mtrx_param(size_for_rs, matrices, sampler, idx, 0)) + (pos.y * get_mtrx_param(size_for_rs, matrices, sampler, idx, 1)) + (pos.z * get_mtrx_param(size_for_rs, matrices, sampler, idx, 2)) + params.translation3d.x,
+        (pos.x * get_mtrx_param(size_for_rs, matrices, sampler, idx, 3)) + (pos.y * get_mtrx_param(size_for_rs, matrices, sampler, idx, 4)) + (pos.z * get_mtrx_param(size_for_rs, matrices, sampler, idx, 5)) + params.translation3d.y,
+        (pos.x * get_mtrx_param(size_for_rs, matrices, sampler, idx, 6)) + (pos.y * get_mtrx_param(size_for_rs, matrices, sampler, idx, 7)) + (pos.z * get_mtrx_param(size_for_rs, matrices, sampler, idx, 8)) + params.translation3d.z
     );
-    if point_3d.z > 0.0 {
-        if params.r_limit > 0.0 && vec2(point_3d.x / point_3d.z, point_3d.y / point_3d.z).length_squared() > params.r_limit.powi(2) {
+    if (params.flags & 32) == 32 { // Spherical input
+        return vec2(
+            params.width  as f32 * (0.5 + point_3d.x.atan2(point_3d.z) / (2.0 * core::f32::consts::PI)),
+            params.height as f32 * (0.5 + point_3d.y.atan2(vec2(point_3d.x, point_3d.z).length()) / core::f32::consts::PI)
+        );
+    }
+    if point_3d.z > 0.0 || (params.flags & 64) == 64 { // Wide FOV lens
+        if params.r_limit > 0.0 && (point_3d.z <= 0.0 || vec2(point_3d.x / point_3d.z, point_3d.y / point_3d.z).length_squared() > params.r_limit.powi(2)) {
             return vec2(-99999.0, -99999.0);
         }
         let mut uv = params.f * lens_distort(point_3d, params) + params.c;
@@ -43,6 +49,32 @@ pub fn rotate_and_distort(pos: Vec2, idx: i32, params: &KernelParams, matrices:
     vec2(-99999.0, -99999.0)
 }
 
+// Source position for the output position, using the matrices starting at row `offset`
+fn source_position_at(ray: Vec3, out_pos: Vec2, offset: i32, params: &KernelParams, matrices: &MatricesType, sampler: SamplerType) -> Vec2 {
+    ///////////////////////////////////////////////////////////////////
+    // Calculate source `y` for rolling shutter
+    let mut sy = if (params.flags & 16) == 16 { // Horizontal RS
+        (fast_round(out_pos.x) as f32).min(params.width as f32).max(0.0)
+    } else {
+        (fast_round(out_pos.y) as f32).min(params.height as f32).max(0.0)
+    };
+    if params.matrix_count > 1 {
+        let idx = offset + params.matrix_count / 2;
+        let pt = rotate_and_distort(ray, idx, params, matrices, sampler);
+        if pt.x > -99998.0 {
+            if (params.flags & 16) == 16 { // Horizontal RS
+                sy = (fast_round(pt.x) as f32).min(params.width as f32).max(0.0);
+            } else {
+                sy = (fast_round(pt.y) as f32).min(params.height as f32).max(0.0);
+            }
+        }
+    }
+    ///////////////////////////////////////////////////////////////////
+
+    let idx = offset + sy.min(params.matrix_count as f32 - 1.0) as i32;
+    rotate_and_distort(ray, idx, params, matrices, sampler)
+}
+
 pub fn undistort(uv: Vec2, params: &KernelParams, matrices: &MatricesType, coeffs: &[f32], _lens_data: &[f32], drawing: &DrawingType, input: &ImageType, sampler: SamplerType) -> Vec4 {
     let bg = params.background * params.max_pixel_value;
 
@@ -62,7 +94,7 @@ pub fn undistort(uv: Vec2, params: &KernelParams, matrices: &MatricesType, coeff
 
     ///////////////////////////////////////////////////////////////////
     // Add lens distortion back
-    if params.lens_correction_amount < 1.0 {
+    if params.lens_correction_amount < 1.0 && params.output_projection == 0 {
         let factor = (1.0 - params.lens_correction_amount).max(0.001); // FIXME: this is close but wrong
         let out_c = vec2(params.output_width as f32 / 2.0, params.output_height as f32 / 2.0);
         let out_f = params.f / params.fov / factor;
@@ -83,31 +115,21 @@ pub fn undistort(uv: Vec2, params: &KernelParams, matrices: &MatricesType, coeff
     }
     ///////////////////////////////////////////////////////////////////
 
-    ///////////////////////////////////////////////////////////////////
-    // Calculate source `y` for rolling shutter
-    let mut sy = if (params.flags & 16) == 16 { // Horizontal RS
-        (fast_round(out_pos.x) as f32).min(params.width as f32).max(0.0)
-    } else {
-        (fast_round(out_pos.y) as f32).min(params.height as f32).max(0.0)
-    };
-    if params.matrix_count > 1 {
-        let idx = params.matrix_count / 2;
-        let pt = rotate_and_distort(out_pos, idx, params, matrices, sampler);
-        if pt.x > -99998.0 {
-            if (params.flags & 16) == 16 { // Horizontal RS
-                sy = (fast_round(pt.x) as f32).min(params.width as f32).max(0.0);
-            } else {
-                sy = (fast_round(pt.y) as f32).min(params.height as f32).max(0.0);
-            }
-        }
-    }
-    ///////////////////////////////////////////////////////////////////
+    let ray = output_projection_ray(out_pos, params);
 
     let mut pixel = bg;
 
-    let idx = sy.min(params.matrix_count as f32 - 1.0) as i32;
-    let uv = rotate_and_distort(out_pos, idx, params, matrices, sampler);
-    if uv.x > -99998.0 {
+    let uv = source_position_at(ray, out_pos, 0, params, matrices, sampler);
+    if params.motion_blur_samples > 1 {
+        // Average of the sub-frame warps, their matrices follow the main ones
+        let mut sum = Vec4::ZERO;
+        let mut i = 1; while i <= params.motion_blur_samples {
+            let uv = source_position_at(ray, out_pos, i * params.matrix_count, params, matrices, sampler);
+            sum += if uv.x > -99998.0 { sample_with_background_at(uv, coeffs, input, params, sampler) } else { bg };
+            i += 1;
+        }
+        pixel = sum / params.motion_blur_samples as f32;
+    } else if uv.x > -99998.0 {
         pixel = sample_with_background_at(uv, coeffs, input, params, sampler);
     }
     pixel = process_final_pixel(pixel, uv, org_out_pos, params, coeffs, drawing, sampler);
@@ -70,6 +70,11 @@ pub struct KernelParams {
     pub distortion_model:         crate::distortion_models::DistortionModel, // 8
     pub digital_lens:             crate::distortion_models::DistortionModel, // 12
     pub pixel_value_limit:        f32, // 16
+    pub vignetting:               Vec4, // 16 - v1, v2, v3 of the falloff 1 + v1*r^2 + v2*r^4 + v3*r^6, r normalized to half of the input diagonal
+    pub chromatic_aberration:     Vec4, // 16 - radial scale - 1 of each channel, relative to the lens center
+    pub output_f:                 Vec2, // 8  - focal length of the output camera in pixels
+    pub output_projection:        i32, // 12 - 0: rectilinear, 1: equidistant fisheye, 2: stereographic, 3: equirectangular
+    pub motion_blur_samples:      i32, // 16 - sub-frame warps averaged for the motion blur, 0 or 1 = disabled. Their matrices follow the main ones
 }
 
 // #[inline] pub fn fast_floor(x: f32) -> i32 { x as i32 }
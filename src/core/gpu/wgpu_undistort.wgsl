@@ -38,6 +38,11 @@ struct KernelParams {
     reserved1:                f32, // 8
     reserved2:                f32, // 12
     pixel_value_limit:        f32, // 16
+    vignetting:         vec4<f32>, // 16 - v1, v2, v3 of the falloff 1 + v1*r^2 + v2*r^4 + v3*r^6, r normalized to half of the input diagonal
+    chromatic_aberration: vec4<f32>, // 16 - radial scale - 1 of each channel, relative to the lens center
+    output_f:           vec2<f32>, // 8  - focal length of the output camera in pixels
+    output_projection:        i32, // 12 - 0: rectilinear, 1: equidistant fisheye, 2: stereographic, 3: equirectangular
+    motion_blur_samples:      i32, // 16 - sub-frame warps averaged for the motion blur, 0 or 1 = disabled. Their matrices follow the main ones
 }
 
 @group(0) @binding(0) @fragment var<uniform> params: KernelParams;
@@ -195,13 +200,67 @@ fn sample_input_at(uv_param: vec2<f32>) -> vec4<f32> {
     );
 }
 
-fn rotate_and_distort(pos: vec2<f32>, idx: u32, f: vec2<f32>, c: vec2<f32>, k1: vec4<f32>, k2: vec4<f32>, k3: vec4<f32>) -> vec2<f32> {
-    let _x = (pos.x * matrices[idx + 0u]) + (pos.y * matrices[idx + 1u]) + matrices[idx + 2u] + params.translation3d.x;
-    let _y = (pos.x * matrices[idx + 3u]) + (pos.y * matrices[idx + 4u]) + matrices[idx + 5u] + params.translation3d.y;
-    let _w = (pos.x * matrices[idx + 6u]) + (pos.y * matrices[idx + 7u]) + matrices[idx + 8u] + params.translation3d.z;
+// Lateral chromatic aberration and vignetting of the lens, corrected at the source position
+fn sample_corrected_input_at(uv: vec2<f32>) -> vec4<f32> {
+    var pixel = sample_input_at(uv);
+    let ca = params.chromatic_aberration;
+    let v = params.vignetting;
+    if (all(ca == vec4<f32>(0.0)) && all(v == vec4<f32>(0.0))) {
+        return pixel;
+    }
+    var center = params.c;
+    if (params.input_horizontal_stretch > 0.001) { center.x /= params.input_horizontal_stretch; }
+    if (params.input_vertical_stretch   > 0.001) { center.y /= params.input_vertical_stretch; }
+
+    if (ca.x != 0.0) { pixel.x = sample_input_at(center + (uv - center) * (1.0 + ca.x)).x; }
+    if (ca.y != 0.0) { pixel.y = sample_input_at(center + (uv - center) * (1.0 + ca.y)).y; }
+    if (ca.z != 0.0) { pixel.z = sample_input_at(center + (uv - center) * (1.0 + ca.z)).z; }
+
+    if (any(v != vec4<f32>(0.0))) {
+        let size = vec2<f32>(f32(params.width), f32(params.height));
+        let r2 = dot(uv - center, uv - center) / (dot(size, size) / 4.0);
+        let gain = max(0.1, 1.0 + r2 * (v.x + r2 * (v.y + r2 * v.z)));
+        pixel = vec4<f32>(min(pixel.xyz / gain, vec3<f32>(params.pixel_value_limit)), pixel.w);
+    }
+    return pixel;
+}
+
+// Ray of the output pixel for the selected output projection, in homogeneous pixel coordinates of the rectilinear output camera
+// so it can be multiplied by the rotation matrices as is. Points outside of the projection return a zero vector
+fn output_projection_ray(pos: vec2<f32>) -> vec3<f32> {
+    if (params.output_projection == 0) { return vec3<f32>(pos, 1.0); }
 
-    if (_w > 0.0) {
-        if (params.r_limit > 0.0 && length(vec2<f32>(_x, _y) / _w) > params.r_limit) {
+    let out_c = vec2<f32>(f32(params.output_width) / 2.0, f32(params.output_height) / 2.0);
+    let n = (pos - out_c) / params.output_f;
+
+    var dir: vec3<f32>;
+    if (params.output_projection == 3) { // Equirectangular
+        if (abs(n.y) > 1.5707963267948966) { return vec3<f32>(0.0, 0.0, 0.0); }
+        dir = vec3<f32>(cos(n.y) * sin(n.x), sin(n.y), cos(n.y) * cos(n.x));
+    } else {
+        let r = length(n);
+        var theta = r; // Equidistant
+        if (params.output_projection == 2) { theta = 2.0 * atan(r / 2.0); } // Stereographic
+        if (theta > 3.141592653589793) { return vec3<f32>(0.0, 0.0, 0.0); }
+        var scale = 1.0;
+        if (r > 0.0) { scale = sin(theta) / r; }
+        dir = vec3<f32>(n * scale, cos(theta));
+    }
+
+    return vec3<f32>(params.output_f * dir.xy + out_c * dir.z, dir.z);
+}
+
+fn rotate_and_distort(pos: vec3<f32>, idx: u32, f: vec2<f32>, c: vec2<f32>, k1: vec4<f32>, k2: vec4<f32>, k3: vec4<f32>) -> vec2<f32> {
+    let _x = (pos.x * matrices[idx + 0u]) + (pos.y * matrices[idx + 1u]) + (pos.z * matrices[idx + 2u]) + params.translation3d.x;
+    let _y = (pos.x * matrices[idx + 3u]) + (pos.y * matrices[idx + 4u]) + (pos.z * matrices[idx + 5u]) + params.translation3d.y;
+    let _w = (pos.x * matrices[idx + 6u]) + (pos.y * matrices[idx + 7u]) + (pos.z * matrices[idx + 8u]) + params.translation3d.z;
+
+    if (bool(params.flags & 32)) { // Spherical input
+        return vec2<f32>(f32(params.width)  * (0.5 + atan2(_x, _w) / (2.0 * 3.14159265359)),
+                         f32(params.height) * (0.5 + atan2(_y, sqrt(_x * _x + _w * _w)) / 3.14159265359));
+    }
+    if (_w > 0.0 || bool(params.flags & 64)) { // Wide FOV lens
+        if (params.r_limit > 0.0 && (_w <= 0.0 || length(vec2<f32>(_x, _y) / _w) > params.r_limit)) {
             return vec2<f32>(-99999.0, -99999.0);
         }
         var uv = f * distort_point(_x, _y, _w) + c;
@@ -218,48 +277,8 @@ fn rotate_and_distort(pos: vec2<f32>, idx: u32, f: vec2<f32>, c: vec2<f32>, k1:
     return vec2<f32>(-99999.0, -99999.0);
 }
 
-// Adapted from OpenCV: initUndistortRectifyMap + remap
-// https://github.com/opencv/opencv/blob/2b60166e5c65f1caccac11964ad760d847c536e4/modules/calib3d/src/fisheye.cpp#L465-L567
-// https://github.com/opencv/opencv/blob/2b60166e5c65f1caccac11964ad760d847c536e4/modules/imgproc/src/opencl/remap.cl#L390-L498
-fn undistort(position: vec2<f32>) -> vec4<SCALAR> {
-    let bg = vec4<f32>(params.background.x, params.background.y, params.background.z, params.background.w) * params.max_pixel_value;
-
-    if (bool(params.flags & 4)) { // Fill with background
-        return vec4<SCALAR>(bg);
-    }
-
-    var out_pos = vec2<f32>(
-        map_coord(position.x, f32(params.output_rect.x), f32(params.output_rect.x + params.output_rect.z), 0.0, f32(params.output_width) ),
-        map_coord(position.y, f32(params.output_rect.y), f32(params.output_rect.y + params.output_rect.w), 0.0, f32(params.output_height))
-    );
-
-    let p = out_pos;
-
-    if (out_pos.x < 0.0 || out_pos.y < 0.0 || out_pos.x > f32(params.output_width) || out_pos.y > f32(params.output_height)) { return vec4<SCALAR>(bg); }
-
-    out_pos = out_pos + params.translation2d;
-
-    ///////////////////////////////////////////////////////////////////
-    // Add lens distortion back
-    if (params.lens_correction_amount < 1.0) {
-        let factor = max(1.0 - params.lens_correction_amount, 0.001); // FIXME: this is close but wrong
-        let out_c = vec2<f32>(f32(params.output_width) / 2.0, f32(params.output_height) / 2.0);
-        let out_f = (params.f / params.fov) / factor;
-
-        var new_out_pos = out_pos;
-
-        if (bool(params.flags & 2)) { // Has digital lens
-            new_out_pos = digital_undistort_point(new_out_pos);
-        }
-
-        new_out_pos = (new_out_pos - out_c) / out_f;
-        new_out_pos = undistort_point(new_out_pos);
-        new_out_pos = out_f * new_out_pos + out_c;
-
-        out_pos = new_out_pos * (1.0 - params.lens_correction_amount) + (out_pos * params.lens_correction_amount);
-    }
-    ///////////////////////////////////////////////////////////////////
-
+// Source pixel for the output position, using the matrices starting at `offset`
+fn sample_stabilized_at(ray: vec3<f32>, out_pos: vec2<f32>, offset: u32, bg: vec4<f32>) -> vec4<f32> {
     ///////////////////////////////////////////////////////////////////
     // Calculate source `y` for rolling shutter
     var sy = 0u;
@@ -269,8 +288,8 @@ fn undistort(position: vec2<f32>) -> vec4<SCALAR> {
         sy = u32(min(params.height, max(0, i32(floor(0.5 + out_pos.y)))));
     }
     if (params.matrix_count > 1) {
-        let idx: u32 = u32((params.matrix_count / 2) * 12); // Use middle matrix
-        let uv = rotate_and_distort(out_pos, idx, params.f, params.c, params.k1, params.k2, params.k3);
+        let idx: u32 = offset + u32((params.matrix_count / 2) * 12); // Use middle matrix
+        let uv = rotate_and_distort(ray, idx, params.f, params.c, params.k1, params.k2, params.k3);
         if (uv.x > -99998.0) {
             if (bool(params.flags & 16)) { // Horizontal RS
                 sy = u32(min(params.width, max(0, i32(floor(0.5 + uv.x)))));
@@ -281,11 +300,9 @@ fn undistort(position: vec2<f32>) -> vec4<SCALAR> {
     }
     ///////////////////////////////////////////////////////////////////
 
-    let idx: u32 = min(sy, u32(params.matrix_count - 1)) * 12u;
+    let idx: u32 = offset + min(sy, u32(params.matrix_count - 1)) * 12u;
 
-    var pixel: vec4<f32> = bg;
-
-    var uv = rotate_and_distort(out_pos, idx, params.f, params.c, params.k1, params.k2, params.k3);
+    var uv = rotate_and_distort(ray, idx, params.f, params.c, params.k1, params.k2, params.k3);
     if (uv.x > -99998.0) {
         let width_f = f32(params.width);
         let height_f = f32(params.height);
@@ -315,15 +332,70 @@ fn undistort(position: vec2<f32>) -> vec4<SCALAR> {
                 pt2 *= vec2<f32>(width_f, height_f);
             }
 
-            let c1 = sample_input_at(uv);
-            let c2 = sample_input_at(pt2);
-            pixel = c1 * alpha + c2 * (1.0 - alpha);
-            pixel = draw_pixel(pixel, u32(p.x), u32(p.y), false);
-            pixel = draw_safe_area(pixel, p.x, p.y);
-            return vec4<SCALAR>(pixel);
+            let c1 = sample_corrected_input_at(uv);
+            let c2 = sample_corrected_input_at(pt2);
+            return c1 * alpha + c2 * (1.0 - alpha);
+        }
+
+        return sample_corrected_input_at(uv);
+    }
+    return bg;
+}
+
+// Adapted from OpenCV: initUndistortRectifyMap + remap
+// https://github.com/opencv/opencv/blob/2b60166e5c65f1caccac11964ad760d847c536e4/modules/calib3d/src/fisheye.cpp#L465-L567
+// https://github.com/opencv/opencv/blob/2b60166e5c65f1caccac11964ad760d847c536e4/modules/imgproc/src/opencl/remap.cl#L390-L498
+fn undistort(position: vec2<f32>) -> vec4<SCALAR> {
+    let bg = vec4<f32>(params.background.x, params.background.y, params.background.z, params.background.w) * params.max_pixel_value;
+
+    if (bool(params.flags & 4)) { // Fill with background
+        return vec4<SCALAR>(bg);
+    }
+
+    var out_pos = vec2<f32>(
+        map_coord(position.x, f32(params.output_rect.x), f32(params.output_rect.x + params.output_rect.z), 0.0, f32(params.output_width) ),
+        map_coord(position.y, f32(params.output_rect.y), f32(params.output_rect.y + params.output_rect.w), 0.0, f32(params.output_height))
+    );
+
+    let p = out_pos;
+
+    if (out_pos.x < 0.0 || out_pos.y < 0.0 || out_pos.x > f32(params.output_width) || out_pos.y > f32(params.output_height)) { return vec4<SCALAR>(bg); }
+
+    out_pos = out_pos + params.translation2d;
+
+    ///////////////////////////////////////////////////////////////////
+    // Add lens distortion back
+    if (params.lens_correction_amount < 1.0 && params.output_projection == 0) {
+        let factor = max(1.0 - params.lens_correction_amount, 0.001); // FIXME: this is close but wrong
+        let out_c = vec2<f32>(f32(params.output_width) / 2.0, f32(params.output_height) / 2.0);
+        let out_f = (params.f / params.fov) / factor;
+
+        var new_out_pos = out_pos;
+
+        if (bool(params.flags & 2)) { // Has digital lens
+            new_out_pos = digital_undistort_point(new_out_pos);
         }
 
-        pixel = sample_input_at(uv);
+        new_out_pos = (new_out_pos - out_c) / out_f;
+        new_out_pos = undistort_point(new_out_pos);
+        new_out_pos = out_f * new_out_pos + out_c;
+
+        out_pos = new_out_pos * (1.0 - params.lens_correction_amount) + (out_pos * params.lens_correction_amount);
+    }
+    ///////////////////////////////////////////////////////////////////
+
+    let ray = output_projection_ray(out_pos);
+
+    var pixel: vec4<f32>;
+    if (params.motion_blur_samples > 1) {
+        // Average of the sub-frame warps, their matrices follow the main ones
+        pixel = vec4<f32>(0.0);
+        for (var i: i32 = 1; i <= params.motion_blur_samples; i = i + 1) {
+            pixel += sample_stabilized_at(ray, out_pos, u32(i * params.matrix_count * 12), bg);
+        }
+        pixel /= f32(params.motion_blur_samples);
+    } else {
+        pixel = sample_stabilized_at(ray, out_pos, 0u, bg);
     }
     pixel = draw_pixel(pixel, u32(p.x), u32(p.y), false);
     pixel = draw_safe_area(pixel, p.x, p.y);
@@ -14,8 +14,9 @@ use crate::keyframes::KeyframeManager;
 use crate::filesystem;
 
 use super::imu_integration::*;
-use super::smoothing::SmoothingAlgorithm;
+use super::smoothing::{ SmoothingAlgorithm, TrimRangeSmoothing };
 use crate::StabilizationParams;
+use crate::stabilization_params::TrimRangeOverrides;
 
 pub type Quat64 = UnitQuaternion<f64>;
 pub type TimeIMU = telemetry_parser::util::IMUData;
@@ -47,9 +48,13 @@ pub struct FileMetadata {
     pub lens_profile:        Option<serde_json::Value>,
     pub lens_positions:      BTreeMap<i64, f64>,
     pub lens_params:         BTreeMap<i64, LensParams>,
+    pub focus_distances:     BTreeMap<i64, f64>, // meters
+    pub gps_speed:           BTreeMap<i64, f64>, // km/h
+    pub gps_altitude:        BTreeMap<i64, f64>, // meters
     pub has_accurate_timestamps: bool,
     pub additional_data:     serde_json::Value,
     pub per_frame_time_offsets: Vec<f64>,
+    pub per_frame_exposure_times: Vec<f64>, // ms
     pub per_frame_data:      Vec<serde_json::Value>,
 }
 impl FileMetadata {
@@ -67,9 +72,13 @@ impl FileMetadata {
             lens_profile:            self.lens_profile.clone(),
             lens_positions:          Default::default(),
             lens_params:             Default::default(),
+            focus_distances:         Default::default(),
+            gps_speed:               Default::default(),
+            gps_altitude:            Default::default(),
             has_accurate_timestamps: self.has_accurate_timestamps.clone(),
             additional_data:         self.additional_data.clone(),
             per_frame_time_offsets:  Default::default(),
+            per_frame_exposure_times: Default::default(),
             per_frame_data:          Default::default(),
         }
     }
@@ -174,6 +183,9 @@ impl GyroSource {
         let mut frame_rate = None;
         let mut lens_positions = BTreeMap::new();
         let mut lens_params = BTreeMap::new();
+        let mut focus_distances = BTreeMap::new();
+        let mut gps_speed = BTreeMap::new();
+        let mut gps_altitude = BTreeMap::new();
         let mut additional_data = serde_json::Value::Object(serde_json::Map::new());
 
         if input.camera_type() == "BlackBox" {
@@ -233,6 +245,9 @@ impl GyroSource {
                             lens_positions.insert(timestamp_us, *v as f64);
                             lens_info.focal_length = Some(*v);
                         }
+                        if let Some(v) = map.get_t(TagId::FocusDistance) as Option<&f32> {
+                            focus_distances.insert(timestamp_us, *v as f64);
+                        }
                     }
                     if lens_info.focal_length.is_none() {
                         if let Some(md) = tag_map.get(&GroupId::Custom("LensDistortion".into())) {
@@ -257,6 +272,17 @@ impl GyroSource {
                             crate::util::merge_json(&mut additional_data, v);
                         }
                     }
+                    if let Some(map) = tag_map.get(&GroupId::GPS) {
+                        if let Some(arr) = map.get_t(TagId::Data) as Option<&Vec<telemetry_parser::tags_impl::GpsData>> {
+                            // Samples are evenly distributed within the packet
+                            let step = if arr.len() > 1 { info.duration_ms / arr.len() as f64 } else { 0.0 };
+                            for (i, v) in arr.iter().enumerate().filter(|(_, v)| v.is_acquired) {
+                                let ts = ((info.timestamp_ms + step * i as f64) * 1000.0).round() as i64;
+                                gps_speed.insert(ts, v.speed);
+                                gps_altitude.insert(ts, v.altitude);
+                            }
+                        }
+                    }
                     if let Some(map) = tag_map.get(&GroupId::Custom("FovAdaptationScore".into())) {
                         if let Some(v) = map.get_t(TagId::Data) as Option<&Vec<f32>> {
                             for v in v {
@@ -350,6 +376,9 @@ impl GyroSource {
             gravity_vectors,
             lens_positions,
             lens_params,
+            focus_distances,
+            gps_speed,
+            gps_altitude,
             raw_imu,
             frame_readout_time: input.frame_readout_time(),
             frame_rate,
@@ -358,6 +387,7 @@ impl GyroSource {
             has_accurate_timestamps,
             additional_data,
             per_frame_time_offsets: Default::default(),
+            per_frame_exposure_times: Default::default(),
             per_frame_data: Default::default(),
         };
 
@@ -385,6 +415,7 @@ impl GyroSource {
                         let frame_offset = first_frame_ts - (exposure_time / 2.0) + (md.frame_readout_time.unwrap_or_default() / 2.0) + model_offset + offset_diff - offset;
 
                         md.per_frame_time_offsets.push(frame_offset / sampling_frequency * sample_rate);
+                        md.per_frame_exposure_times.push(exposure_time / sampling_frequency * sample_rate);
                     });
                     // --------------------------------- Sony ---------------------------------
                     // --------------------------------- Insta360 ---------------------------------
@@ -405,6 +436,7 @@ impl GyroSource {
                                     // The additional 0.9 ms is a mystery
                                     let diff = (video_ts - x.t) * 1000.0;
                                     md.per_frame_time_offsets.push(-(x.v * 1000.0 / 2.0) - 0.9 - diff - zero_ref.unwrap());
+                                    md.per_frame_exposure_times.push(x.v * 1000.0);
 
                                     video_ts += 1.0 / fps;
                                 }
@@ -510,16 +542,38 @@ impl GyroSource {
         }
     }
 
-    pub fn recompute_smoothness(&self, alg: &dyn SmoothingAlgorithm, horizon_lock: super::smoothing::horizon::HorizonLock, stabilization_params: &StabilizationParams, keyframes: &KeyframeManager) -> (TimeQuat, TimeQuat, (f64, f64, f64)) {
-        let mut smoothed_quaternions = self.quaternions.clone();
-        if true {
-            // Lock horizon, then smooth
-            horizon_lock.lock(&mut smoothed_quaternions, &self.quaternions, &self.file_metadata.gravity_vectors, self.use_gravity_vectors, self.integration_method, keyframes, stabilization_params);
-            smoothed_quaternions = alg.smooth(&smoothed_quaternions, self.duration_ms, stabilization_params, keyframes);
-        } else {
-            // Smooth, then lock horizon
-            smoothed_quaternions = alg.smooth(&smoothed_quaternions, self.duration_ms, stabilization_params, keyframes);
-            horizon_lock.lock(&mut smoothed_quaternions, &self.quaternions, &self.file_metadata.gravity_vectors, self.use_gravity_vectors, self.integration_method, keyframes, stabilization_params);
+    pub fn recompute_smoothness(&self, alg: &dyn SmoothingAlgorithm, horizon_lock: super::smoothing::horizon::HorizonLock, trim_range_smoothing: &[TrimRangeSmoothing], stabilization_params: &StabilizationParams, keyframes: &KeyframeManager) -> (TimeQuat, TimeQuat, (f64, f64, f64)) {
+        let smooth = |alg: &dyn SmoothingAlgorithm, horizon_lock: &super::smoothing::horizon::HorizonLock| -> TimeQuat {
+            let mut smoothed_quaternions = self.quaternions.clone();
+            if true {
+                // Lock horizon, then smooth
+                horizon_lock.lock(&mut smoothed_quaternions, &self.quaternions, &self.file_metadata.gravity_vectors, self.use_gravity_vectors, self.integration_method, keyframes, stabilization_params);
+                smoothed_quaternions = alg.smooth(&smoothed_quaternions, self.duration_ms, stabilization_params, keyframes);
+            } else {
+                // Smooth, then lock horizon
+                smoothed_quaternions = alg.smooth(&smoothed_quaternions, self.duration_ms, stabilization_params, keyframes);
+                horizon_lock.lock(&mut smoothed_quaternions, &self.quaternions, &self.file_metadata.gravity_vectors, self.use_gravity_vectors, self.integration_method, keyframes, stabilization_params);
+            }
+            smoothed_quaternions
+        };
+
+        let mut smoothed_quaternions = smooth(alg, &horizon_lock);
+
+        if !trim_range_smoothing.is_empty() {
+            // Each trim range with its own settings is smoothed separately and blended over the global result
+            let per_range = trim_range_smoothing.iter().map(|x| smooth(x.alg.as_ref(), &x.horizon_lock)).collect::<Vec<_>>();
+            for (ts, sq) in smoothed_quaternions.iter_mut() {
+                let timestamp_ms = *ts as f64 / 1000.0;
+                let mut total_weight = (1.0 - trim_range_smoothing.iter().map(|x| TrimRangeOverrides::blend_weight(x.range_ms, x.transition_ms, timestamp_ms)).sum::<f64>()).max(0.0);
+                for (range, quats) in trim_range_smoothing.iter().zip(per_range.iter()) {
+                    let weight = TrimRangeOverrides::blend_weight(range.range_ms, range.transition_ms, timestamp_ms);
+                    if weight <= 0.0 { continue; }
+                    if let Some(q) = quats.get(ts) {
+                        total_weight += weight;
+                        *sq = sq.slerp(q, weight / total_weight);
+                    }
+                }
+            }
         }
 
         let max_angles = crate::Smoothing::get_max_angles(&self.quaternions, &smoothed_quaternions, stabilization_params);
@@ -769,6 +823,16 @@ impl GyroSource {
     pub fn      org_quat_at_timestamp(&self, timestamp_ms: f64) -> Quat64 { self.quat_at_timestamp(&self.quaternions,          timestamp_ms) }
     pub fn smoothed_quat_at_timestamp(&self, timestamp_ms: f64) -> Quat64 { self.quat_at_timestamp(&self.smoothed_quaternions, timestamp_ms) }
 
+    // Rotation of the camera at `timestamp_ms` relative to `reference_ms`, in the image coordinate system used by `FrameTransform`.
+    // Maps the ray of a point captured at `timestamp_ms` to the camera at `reference_ms`
+    pub fn relative_rotation(&self, timestamp_ms: f64, reference_ms: f64) -> Matrix3<f64> {
+        let quat = self.org_quat_at_timestamp(reference_ms).inverse() * self.org_quat_at_timestamp(timestamp_ms);
+        let mut r = *quat.to_rotation_matrix().matrix();
+        r[(0, 1)] *= -1.0; r[(0, 2)] *= -1.0;
+        r[(1, 0)] *= -1.0; r[(2, 0)] *= -1.0;
+        r
+    }
+
     pub fn offset_at_timestamp(offsets: &BTreeMap<i64, f64>, timestamp_ms: f64) -> f64 {
         match offsets.len() {
             0 => 0.0,
@@ -810,7 +874,11 @@ impl GyroSource {
                 gravity_vectors:        self.file_metadata.gravity_vectors.clone(),
                 lens_positions:         self.file_metadata.lens_positions.clone(),
                 lens_params:            self.file_metadata.lens_params.clone(),
+                focus_distances:        self.file_metadata.focus_distances.clone(),
+                gps_speed:              self.file_metadata.gps_speed.clone(),
+                gps_altitude:           self.file_metadata.gps_altitude.clone(),
                 per_frame_time_offsets: self.file_metadata.per_frame_time_offsets.clone(),
+                per_frame_exposure_times: self.file_metadata.per_frame_exposure_times.clone(),
                 per_frame_data:         self.file_metadata.per_frame_data.clone(),
                 additional_data:        self.file_metadata.additional_data.clone(),
                 ..Default::default()
@@ -839,6 +907,7 @@ impl GyroSource {
         hasher.write_usize(self.file_metadata.image_orientations.as_ref().map(|v| v.len()).unwrap_or_default());
         hasher.write_usize(self.file_metadata.lens_positions.len());
         hasher.write_usize(self.file_metadata.lens_params.len());
+        hasher.write_usize(self.file_metadata.focus_distances.len());
         hasher.write_u32(if self.use_gravity_vectors { 1 } else { 0 });
         hasher.write_usize(self.integration_method);
         for (ts, v) in &self.offsets {
@@ -2,7 +2,8 @@
 // Copyright © 2022 Adrian <adrian.eddy at gmail>
 
 use std::{ collections::BTreeMap, collections::btree_map::Entry, str::FromStr };
-use crate::gyro_source::GyroSource;
+use crate::gyro_source::{ GyroSource, TimeVec };
+use crate::expression::{ Expression, ExpressionError, Variable };
 use std::sync::{ Arc, Mutex }; // parking_lot::Mutex can't be used across catch_unwind
 
 macro_rules! define_keyframes {
@@ -51,25 +52,107 @@ pub enum Easing {
     NoEasing, // Linear
     EaseIn,
     EaseOut,
-    EaseInOut
+    EaseInOut,
+    Hold // Keep the value until the next keyframe
+}
+
+// After Effects-style handle: `influence` is the fraction of the segment duration (0-1) covered by the handle,
+// `speed` is the slope relative to the linear interpolation of the segment (1.0 = linear, 0.0 = flat)
+#[derive(Debug, Copy, Clone, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
+pub struct BezierHandle {
+    pub influence: f64,
+    pub speed: f64
+}
+impl BezierHandle {
+    pub const LINEAR: Self = Self { influence: 1.0 / 3.0, speed: 1.0 };
+    pub const EASE:   Self = Self { influence: 1.0 / 3.0, speed: 0.0 };
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, ::serde::Serialize, ::serde::Deserialize)]
+pub enum TelemetryChannel {
+    GpsSpeed,      // km/h
+    GpsAltitude,   // meters
+    LensPosition,  // focal length or lens position, as reported by the camera
+    GyroMagnitude, // deg/s
+}
+impl TelemetryChannel {
+    pub const ALL: [Self; 4] = [Self::GpsSpeed, Self::GpsAltitude, Self::LensPosition, Self::GyroMagnitude];
+}
+
+// Drives a keyframe value from a telemetry channel.
+// `curve` maps the telemetry value to the parameter value with linear interpolation between points (sorted by input),
+// values outside of the curve are clamped. Empty curve passes the telemetry value through.
+#[derive(Debug, Clone, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
+pub struct TelemetryBinding {
+    pub channel: TelemetryChannel,
+    #[serde(default)]
+    pub curve: Vec<(f64, f64)>,
+    #[serde(default)]
+    pub smoothing_ms: f64, // Average the telemetry over this time window, useful for noisy GPS data
+}
+impl TelemetryBinding {
+    pub fn map(&self, v: f64) -> f64 {
+        let idx = self.curve.partition_point(|x| x.0 < v);
+        match (self.curve.get(idx.wrapping_sub(1)), self.curve.get(idx)) {
+            (Some(a), Some(b)) if b.0 > a.0 => a.1 + (b.1 - a.1) * ((v - a.0) / (b.0 - a.0)),
+            (_, Some(b)) => b.1,
+            (Some(a), None) => a.1,
+            (None, None) => v
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum KeyframeImportError {
+    #[error("Line {0}: unknown keyframe type {1}")]
+    UnknownType(usize, String),
+
+    #[error("Line {0}: invalid number {1}")]
+    InvalidNumber(usize, String),
+
+    #[error("Line {0}: invalid easing {1}")]
+    InvalidEasing(usize, String),
+
+    #[error("Line {0}: expected at least {1} columns")]
+    MissingColumns(usize, usize),
+
+    #[error("No keyframes found")]
+    NoKeyframes,
+
+    #[error("Invalid time scale {0}, it has to be greater than 0")]
+    InvalidTimeScale(f64),
+
+    #[error("JSON error {0:?}")]
+    JSONError(#[from] serde_json::Error),
 }
 
 #[derive(Debug, Copy, Clone, Default, ::serde::Serialize, ::serde::Deserialize)]
 pub struct Keyframe {
     pub value: f64,
-    pub easing: Easing
+    pub easing: Easing,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handle_in: Option<BezierHandle>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handle_out: Option<BezierHandle>,
 }
 
 #[derive(Default, Clone)]
 pub struct KeyframeManager {
     keyframes: BTreeMap<KeyframeType, BTreeMap<i64, Keyframe>>,
+    expressions: BTreeMap<KeyframeType, Expression>,
     gyro_offsets: BTreeMap<i64, f64>,
+    gyro_rates: Arc<TimeVec>, // deg/s, used by expressions
+    telemetry_bindings: BTreeMap<KeyframeType, TelemetryBinding>,
+    telemetry: Arc<BTreeMap<TelemetryChannel, BTreeMap<i64, f64>>>, // Keyed by video timestamp in us
     custom_provider: Option<Arc<Mutex<dyn FnMut(&KeyframeManager, &KeyframeType, f64) -> Option<f64> + Send + 'static>>>,
     pub timestamp_scale: Option<f64>,
+    pub fps: f64,
+    pub duration_ms: f64,
+    pub video_speed: f64,
 }
 
 impl KeyframeManager {
-    pub fn new() -> Self { Self::default() }
+    pub fn new() -> Self { Self { video_speed: 1.0, ..Default::default() } }
 
     pub fn set_custom_provider(&mut self, cb: impl FnMut(&KeyframeManager, &KeyframeType, f64) -> Option<f64> + Send + 'static) {
         self.custom_provider = Some(Arc::new(Mutex::new(cb)));
@@ -77,7 +160,8 @@ impl KeyframeManager {
     pub fn set(&mut self, typ: &KeyframeType, timestamp_us: i64, value: f64) {
         let kf = Keyframe {
             value,
-            easing: Easing::EaseInOut
+            easing: Easing::EaseInOut,
+            ..Default::default()
         };
         if let Some(x) = self.keyframes.get_mut(typ) {
             match x.entry(timestamp_us) {
@@ -98,6 +182,18 @@ impl KeyframeManager {
     pub fn easing(&self, typ: &KeyframeType, timestamp_us: i64) -> Option<Easing> {
         Some(self.keyframes.get(typ)?.get(&timestamp_us)?.easing)
     }
+    pub fn set_bezier_handles(&mut self, typ: &KeyframeType, timestamp_us: i64, handle_in: Option<BezierHandle>, handle_out: Option<BezierHandle>) {
+        if let Some(x) = self.keyframes.get_mut(typ) {
+            if let Some(kf) = x.get_mut(&timestamp_us) {
+                kf.handle_in = handle_in;
+                kf.handle_out = handle_out;
+            }
+        }
+    }
+    pub fn bezier_handles(&self, typ: &KeyframeType, timestamp_us: i64) -> Option<(Option<BezierHandle>, Option<BezierHandle>)> {
+        let kf = self.keyframes.get(typ)?.get(&timestamp_us)?;
+        Some((kf.handle_in, kf.handle_out))
+    }
     pub fn remove(&mut self, typ: &KeyframeType, timestamp_us: i64) {
         if let Some(x) = self.keyframes.get_mut(typ) {
             x.remove(&timestamp_us);
@@ -117,7 +213,7 @@ impl KeyframeManager {
                 }
             }
         }
-        self.is_keyframed_internally(typ)
+        self.expressions.contains_key(typ) || self.telemetry_bindings.contains_key(typ) || self.is_keyframed_internally(typ)
     }
     pub fn value_at_video_timestamp(&self, typ: &KeyframeType, timestamp_ms: f64) -> Option<f64> {
         if let Some(custom) = &self.custom_provider {
@@ -127,6 +223,23 @@ impl KeyframeManager {
                 }
             }
         }
+        if let Some(expr) = self.expressions.get(typ) {
+            let v = self.eval_expression(expr, timestamp_ms);
+            if v.is_finite() {
+                return Some(v);
+            }
+        }
+        if let Some(binding) = self.telemetry_bindings.get(typ) {
+            let scaled_ms = timestamp_ms * self.timestamp_scale.unwrap_or(1.0);
+            if let Some(v) = self.telemetry_value(binding.channel, scaled_ms, binding.smoothing_ms) {
+                return Some(binding.map(v));
+            }
+        }
+        self.keyframed_value_at_video_timestamp(typ, timestamp_ms)
+    }
+
+    // Value from the keyframes only, without custom provider, expressions and telemetry bindings
+    pub fn keyframed_value_at_video_timestamp(&self, typ: &KeyframeType, timestamp_ms: f64) -> Option<f64> {
         let keyframes = self.keyframes.get(typ)?;
         match keyframes.len() {
             0 => None,
@@ -140,9 +253,15 @@ impl KeyframeManager {
                             if *offs1.0 == lookup_ts {
                                 return Some(offs1.1.value);
                             }
+                            if offs1.1.easing == Easing::Hold {
+                                return Some(offs1.1.value);
+                            }
                             if let Some(offs2) = keyframes.range(lookup_ts..).next() {
                                 let time_delta = (offs2.0 - offs1.0) as f64;
                                 let alpha = (timestamp_us - offs1.0) as f64 / time_delta;
+                                if offs1.1.handle_out.is_some() || offs2.1.handle_in.is_some() {
+                                    return Some(Keyframe::interpolate_bezier(offs1.1, offs2.1, alpha));
+                                }
                                 let e = Easing::get(&offs1.1.easing, &offs2.1.easing, alpha);
                                 return Some(e.interpolate(offs1.1.value, offs2.1.value, alpha));
                             }
@@ -155,6 +274,87 @@ impl KeyframeManager {
         }
     }
 
+    fn eval_expression(&self, expr: &Expression, timestamp_ms: f64) -> f64 {
+        let scaled_ms = timestamp_ms * self.timestamp_scale.unwrap_or(1.0);
+        let gyro_rate = || -> nalgebra::Vector3<f64> {
+            // Expressions use video timestamps, convert to gyro time
+            let gyro_ts = ((scaled_ms - GyroSource::offset_at_timestamp(&self.gyro_offsets, scaled_ms)) * 1000.0).round() as i64;
+            let prev = self.gyro_rates.range(..=gyro_ts).next_back();
+            let next = self.gyro_rates.range(gyro_ts..).next();
+            match (prev, next) {
+                (Some(a), Some(b)) if b.0 > a.0 => a.1 + (b.1 - a.1) * ((gyro_ts - a.0) as f64 / (b.0 - a.0) as f64),
+                (Some(a), _) => *a.1,
+                (_, Some(b)) => *b.1,
+                _ => nalgebra::Vector3::zeros()
+            }
+        };
+        expr.eval(&|v: &Variable| -> f64 {
+            match v {
+                Variable::Time          => scaled_ms / 1000.0,
+                Variable::TimeMs        => scaled_ms,
+                Variable::Frame         => crate::frame_at_timestamp(scaled_ms, self.fps) as f64,
+                Variable::Fps           => self.fps,
+                Variable::Duration      => self.duration_ms / 1000.0,
+                Variable::VideoSpeed    => self.keyframed_value_at_video_timestamp(&KeyframeType::VideoSpeed, timestamp_ms).unwrap_or(self.video_speed),
+                Variable::GyroX         => gyro_rate().x,
+                Variable::GyroY         => gyro_rate().y,
+                Variable::GyroZ         => gyro_rate().z,
+                Variable::GyroMagnitude => gyro_rate().norm(),
+                Variable::Telemetry(ch) => self.telemetry_value(*ch, scaled_ms, 0.0).unwrap_or(f64::NAN),
+                // Other expressions are not evaluated here, which prevents reference cycles
+                Variable::Keyframe(typ) => self.keyframed_value_at_video_timestamp(typ, timestamp_ms).unwrap_or(f64::NAN),
+            }
+        })
+    }
+
+    pub fn set_expression(&mut self, typ: &KeyframeType, source: &str) -> Result<(), ExpressionError> {
+        if source.trim().is_empty() {
+            self.expressions.remove(typ);
+            return Ok(());
+        }
+        self.expressions.insert(*typ, Expression::parse(source, typ)?);
+        Ok(())
+    }
+    pub fn expression(&self, typ: &KeyframeType) -> Option<&str> {
+        self.expressions.get(typ).map(|x| x.source.as_str())
+    }
+
+    pub fn telemetry_value(&self, channel: TelemetryChannel, timestamp_ms: f64, smoothing_ms: f64) -> Option<f64> {
+        let data = self.telemetry.get(&channel)?;
+        let ts = (timestamp_ms * 1000.0).round() as i64;
+        if smoothing_ms > 0.0 {
+            let half = (smoothing_ms * 500.0).round() as i64;
+            let (sum, count) = data.range(ts - half..=ts + half).fold((0.0, 0usize), |(s, c), (_, v)| (s + v, c + 1));
+            if count > 0 {
+                return Some(sum / count as f64);
+            }
+        }
+        let prev = data.range(..=ts).next_back();
+        let next = data.range(ts..).next();
+        match (prev, next) {
+            (Some(a), Some(b)) if b.0 > a.0 => Some(a.1 + (b.1 - a.1) * ((ts - a.0) as f64 / (b.0 - a.0) as f64)),
+            (Some(a), _) => Some(*a.1),
+            (_, Some(b)) => Some(*b.1),
+            _ => None
+        }
+    }
+    pub fn available_telemetry_channels(&self) -> Vec<TelemetryChannel> {
+        self.telemetry.iter().filter(|(_, v)| !v.is_empty()).map(|(k, _)| *k).collect()
+    }
+
+    pub fn set_telemetry_binding(&mut self, typ: &KeyframeType, binding: Option<TelemetryBinding>) {
+        match binding {
+            Some(mut binding) => {
+                binding.curve.sort_by(|a, b| a.0.total_cmp(&b.0));
+                self.telemetry_bindings.insert(*typ, binding);
+            },
+            None => { self.telemetry_bindings.remove(typ); }
+        }
+    }
+    pub fn telemetry_binding(&self, typ: &KeyframeType) -> Option<&TelemetryBinding> {
+        self.telemetry_bindings.get(typ)
+    }
+
     pub fn value_at_gyro_timestamp(&self, typ: &KeyframeType, mut timestamp_ms: f64) -> Option<f64> {
         timestamp_ms += GyroSource::offset_at_timestamp(&self.gyro_offsets, timestamp_ms);
         self.value_at_video_timestamp(typ, timestamp_ms)
@@ -170,6 +370,29 @@ impl KeyframeManager {
 
     pub fn update_gyro(&mut self, gyro: &GyroSource) {
         self.gyro_offsets = gyro.get_offsets().clone();
+        self.gyro_rates = Arc::new(gyro.raw_imu.iter().filter_map(|x| {
+            let g = x.gyro?;
+            Some(((x.timestamp_ms * 1000.0).round() as i64, nalgebra::Vector3::new(g[0], g[1], g[2])))
+        }).collect());
+
+        let mut telemetry = BTreeMap::new();
+        let md = &gyro.file_metadata;
+        if !md.gps_speed.is_empty()      { telemetry.insert(TelemetryChannel::GpsSpeed,     md.gps_speed.clone()); }
+        if !md.gps_altitude.is_empty()   { telemetry.insert(TelemetryChannel::GpsAltitude,  md.gps_altitude.clone()); }
+        if !md.lens_positions.is_empty() { telemetry.insert(TelemetryChannel::LensPosition, md.lens_positions.clone()); }
+        if !self.gyro_rates.is_empty() {
+            // Convert to video time, so all channels use the same timeline
+            telemetry.insert(TelemetryChannel::GyroMagnitude, self.gyro_rates.iter().map(|(ts, v)| {
+                let ts_ms = *ts as f64 / 1000.0;
+                (((ts_ms + gyro.offset_at_gyro_timestamp(ts_ms)) * 1000.0).round() as i64, v.norm())
+            }).collect());
+        }
+        self.telemetry = Arc::new(telemetry);
+    }
+    pub fn set_video_info(&mut self, fps: f64, duration_ms: f64, video_speed: f64) {
+        self.fps = fps;
+        self.duration_ms = duration_ms;
+        self.video_speed = video_speed;
     }
     pub fn clear(&mut self) {
         *self = Self::new();
@@ -177,6 +400,8 @@ impl KeyframeManager {
 
     pub fn clear_type(&mut self, key: &KeyframeType) {
         self.keyframes.remove(key);
+        self.expressions.remove(key);
+        self.telemetry_bindings.remove(key);
     }
 
     pub fn serialize(&self) -> serde_json::Value {
@@ -188,6 +413,145 @@ impl KeyframeManager {
             self.keyframes = kf;
         }
     }
+    pub fn serialize_expressions(&self) -> serde_json::Value {
+        serde_json::to_value(self.expressions.iter().map(|(k, v)| (*k, v.source.clone())).collect::<BTreeMap<KeyframeType, String>>()).unwrap_or(serde_json::Value::Null)
+    }
+    pub fn deserialize_expressions(&mut self, v: &serde_json::Value) {
+        self.expressions.clear();
+        if let Ok(exprs) = serde_json::from_value::<BTreeMap<KeyframeType, String>>(v.clone()) {
+            for (typ, source) in exprs {
+                if let Err(e) = self.set_expression(&typ, &source) {
+                    log::warn!("Invalid expression for {typ:?}: {source} ({e})");
+                }
+            }
+        }
+    }
+
+    pub fn serialize_telemetry_bindings(&self) -> serde_json::Value {
+        serde_json::to_value(&self.telemetry_bindings).unwrap_or(serde_json::Value::Null)
+    }
+    pub fn deserialize_telemetry_bindings(&mut self, v: &serde_json::Value) {
+        self.telemetry_bindings.clear();
+        match serde_json::from_value::<BTreeMap<KeyframeType, TelemetryBinding>>(v.clone()) {
+            Ok(bindings) => {
+                for (typ, binding) in bindings {
+                    self.set_telemetry_binding(&typ, Some(binding));
+                }
+            },
+            Err(e) => log::warn!("Invalid telemetry bindings: {e}")
+        }
+    }
+
+    // Standalone keyframes file, uses the same keys as the project file, so tracks can be imported from both.
+    // Empty `types` exports all tracks
+    pub fn export_tracks(&self, types: &[KeyframeType]) -> serde_json::Value {
+        let selected = |k: &KeyframeType| types.is_empty() || types.contains(k);
+        serde_json::json!({
+            "keyframes":                   self.keyframes        .iter().filter(|(k, _)| selected(k)).collect::<BTreeMap<_, _>>(),
+            "keyframe_expressions":        self.expressions      .iter().filter(|(k, _)| selected(k)).map(|(k, v)| (*k, v.source.clone())).collect::<BTreeMap<_, _>>(),
+            "keyframe_telemetry_bindings": self.telemetry_bindings.iter().filter(|(k, _)| selected(k)).collect::<BTreeMap<_, _>>(),
+        })
+    }
+
+    // Imports keyframe tracks from the standalone keyframes file or a project file.
+    // Timestamps are mapped as `timestamp * time_scale + time_offset`. Imported tracks replace the existing ones of the same type.
+    pub fn import_tracks(&mut self, v: &serde_json::Value, time_offset_us: i64, time_scale: f64) -> Result<Vec<KeyframeType>, KeyframeImportError> {
+        if time_scale <= 0.0 || !time_scale.is_finite() {
+            return Err(KeyframeImportError::InvalidTimeScale(time_scale));
+        }
+        // Imported into a copy, so the current keyframes stay untouched when any part of the file is invalid
+        let mut new = self.clone();
+        let mut imported = Vec::new();
+        if let Some(kf) = v.get("keyframes") {
+            let tracks: BTreeMap<KeyframeType, BTreeMap<i64, Keyframe>> = serde_json::from_value(kf.clone())?;
+            for (typ, track) in tracks {
+                let track = track.into_iter().map(|(ts, kf)| (((ts as f64 * time_scale).round() as i64) + time_offset_us, kf)).collect();
+                new.keyframes.insert(typ, track);
+                imported.push(typ);
+            }
+        }
+        if let Some(exprs) = v.get("keyframe_expressions") {
+            let exprs: BTreeMap<KeyframeType, String> = serde_json::from_value(exprs.clone())?;
+            for (typ, source) in exprs {
+                match new.set_expression(&typ, &source) {
+                    Ok(_) => if !imported.contains(&typ) { imported.push(typ); },
+                    Err(e) => log::warn!("Invalid expression for {typ:?}: {source} ({e})")
+                }
+            }
+        }
+        if let Some(bindings) = v.get("keyframe_telemetry_bindings") {
+            let bindings: BTreeMap<KeyframeType, TelemetryBinding> = serde_json::from_value(bindings.clone())?;
+            for (typ, binding) in bindings {
+                new.set_telemetry_binding(&typ, Some(binding));
+                if !imported.contains(&typ) { imported.push(typ); }
+            }
+        }
+        if imported.is_empty() {
+            return Err(KeyframeImportError::NoKeyframes);
+        }
+        *self = new;
+        Ok(imported)
+    }
+
+    // Supported layouts (delimiter can be `,`, `;` or tab, lines starting with `#` are ignored):
+    // - long:  `type,timestamp_ms,value[,easing]`, with optional header
+    // - wide:  header `timestamp_ms,<type>,<type>,...` and one row per timestamp, empty cells are skipped.
+    //          Time column named `timestamp_us` or `time_us` is in microseconds
+    pub fn import_csv(&mut self, csv: &str, time_offset_us: i64, time_scale: f64) -> Result<Vec<KeyframeType>, KeyframeImportError> {
+        if time_scale <= 0.0 || !time_scale.is_finite() {
+            return Err(KeyframeImportError::InvalidTimeScale(time_scale));
+        }
+        let mut lines = csv.lines().enumerate()
+            .map(|(i, l)| (i + 1, l.trim()))
+            .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+            .peekable();
+
+        let delimiter = lines.peek().and_then(|(_, l)| [',', ';', '\t'].into_iter().max_by_key(|d| l.matches(*d).count())).unwrap_or(',');
+        let split = |l: &str| -> Vec<String> { l.split(delimiter).map(|x| x.trim().trim_matches('"').to_owned()).collect() };
+        let parse_num = |line: usize, s: &str| -> Result<f64, KeyframeImportError> { s.parse::<f64>().map_err(|_| KeyframeImportError::InvalidNumber(line, s.to_owned())) };
+        let map_ts = |ts_us: f64| -> i64 { (ts_us * time_scale).round() as i64 + time_offset_us };
+
+        let mut tracks: BTreeMap<KeyframeType, BTreeMap<i64, Keyframe>> = BTreeMap::new();
+        let Some((_, first)) = lines.peek() else { return Err(KeyframeImportError::NoKeyframes); };
+        let header = split(*first);
+        let is_wide = header.len() > 1 && KeyframeType::from_str(&header[0]).is_err() && header[0].parse::<f64>().is_err() && KeyframeType::from_str(&header[1]).is_ok();
+        if is_wide {
+            let (line, _) = lines.next().unwrap();
+            let time_mul = if header[0].ends_with("_us") { 1.0 } else { 1000.0 };
+            let types = header[1..].iter().map(|x| KeyframeType::from_str(x).map_err(|_| KeyframeImportError::UnknownType(line, x.clone()))).collect::<Result<Vec<_>, _>>()?;
+            for (line, l) in lines {
+                let cols = split(l);
+                let ts = map_ts(parse_num(line, &cols[0])? * time_mul);
+                for (typ, v) in types.iter().zip(cols.iter().skip(1)) {
+                    if v.is_empty() { continue; }
+                    tracks.entry(*typ).or_default().insert(ts, Keyframe { value: parse_num(line, v)?, ..Default::default() });
+                }
+            }
+        } else {
+            for (i, (line, l)) in lines.enumerate() {
+                let cols = split(l);
+                let Ok(typ) = KeyframeType::from_str(&cols[0]) else {
+                    if i == 0 { continue; } // Header
+                    return Err(KeyframeImportError::UnknownType(line, cols[0].clone()));
+                };
+                if cols.len() < 3 {
+                    return Err(KeyframeImportError::MissingColumns(line, 3));
+                }
+                let easing = match cols.get(3).filter(|x| !x.is_empty()) {
+                    Some(e) => Easing::from_str(e).map_err(|_| KeyframeImportError::InvalidEasing(line, e.clone()))?,
+                    None => Easing::NoEasing
+                };
+                let ts = map_ts(parse_num(line, &cols[1])? * 1000.0);
+                tracks.entry(typ).or_default().insert(ts, Keyframe { value: parse_num(line, &cols[2])?, easing, ..Default::default() });
+            }
+        }
+        if tracks.is_empty() {
+            return Err(KeyframeImportError::NoKeyframes);
+        }
+        let imported = tracks.keys().copied().collect();
+        self.keyframes.extend(tracks);
+        Ok(imported)
+    }
 
     pub fn next_keyframe(&self, ts: i64, typ: Option<KeyframeType>) -> Option<(KeyframeType, i64, Keyframe)> {
         if let Some(kf) = typ {
@@ -246,9 +610,54 @@ impl Easing {
             Self::EaseIn    => simple_easing::sine_in    (x as f32) as f64, // https://easings.net/#easeInSine
             Self::EaseOut   => simple_easing::sine_out   (x as f32) as f64, // https://easings.net/#easeOutSine
             Self::EaseInOut => simple_easing::sine_in_out(x as f32) as f64, // https://easings.net/#easeInOutSine
+            Self::Hold      => 0.0,
             _ => x
         };
 
         a * (1.0 - x) + b * x
     }
 }
+
+impl Keyframe {
+    pub fn interpolate_bezier(a: &Keyframe, b: &Keyframe, alpha: f64) -> f64 {
+        // Keyframes without explicit handles behave like their easing flags
+        let out = a.handle_out.unwrap_or(if a.easing == Easing::EaseOut || a.easing == Easing::EaseInOut { BezierHandle::EASE } else { BezierHandle::LINEAR });
+        let inp = b.handle_in .unwrap_or(if b.easing == Easing::EaseIn  || b.easing == Easing::EaseInOut { BezierHandle::EASE } else { BezierHandle::LINEAR });
+
+        let out_x = out.influence.clamp(0.0, 1.0);
+        let inp_x = inp.influence.clamp(0.0, 1.0);
+        let p1 = (out_x,       out_x * out.speed);
+        let p2 = (1.0 - inp_x, 1.0 - inp_x * inp.speed);
+
+        let x = cubic_bezier_y_at_x(p1, p2, alpha.clamp(0.0, 1.0));
+        a.value * (1.0 - x) + b.value * x
+    }
+}
+
+// Evaluates the 1D cubic Bézier from (0, 0) to (1, 1) with control points `p1` and `p2` (same as CSS `cubic-bezier()`)
+fn cubic_bezier_y_at_x(p1: (f64, f64), p2: (f64, f64), x: f64) -> f64 {
+    let bezier = |t: f64, c1: f64, c2: f64| { let mt = 1.0 - t; 3.0 * mt * mt * t * c1 + 3.0 * mt * t * t * c2 + t * t * t };
+    let derivative = |t: f64, c1: f64, c2: f64| { let mt = 1.0 - t; 3.0 * mt * mt * c1 + 6.0 * mt * t * (c2 - c1) + 3.0 * t * t * (1.0 - c2) };
+
+    // Newton-Raphson first, it converges in a few iterations for most curves
+    let mut t = x;
+    for _ in 0..8 {
+        let err = bezier(t, p1.0, p2.0) - x;
+        if err.abs() < 1e-7 { return bezier(t, p1.1, p2.1); }
+        let d = derivative(t, p1.0, p2.0);
+        if d.abs() < 1e-6 { break; }
+        // The curve is only defined in 0-1, a step outside of it would evaluate `y` on the extrapolated polynomial
+        t = (t - err / d).clamp(0.0, 1.0);
+    }
+
+    // Fall back to bisection, x(t) is monotonic because the control points' x are clamped to 0-1
+    let (mut lo, mut hi) = (0.0, 1.0);
+    t = x;
+    for _ in 0..64 {
+        let v = bezier(t, p1.0, p2.0);
+        if (v - x).abs() < 1e-7 { break; }
+        if v < x { lo = t; } else { hi = t; }
+        t = (lo + hi) / 2.0;
+    }
+    bezier(t, p1.1, p2.1)
+}
@@ -8,8 +8,9 @@ use serde::{ Serialize, Deserialize };
 
 use crate::stabilization::distortion_models::DistortionModel;
 
-#[cfg(feature = "opencv")]
 use super::LensCalibrator;
+use crate::calibration::JointCalibration;
+use crate::calibration::self_calibration::SelfCalibrationResult;
 
 #[derive(Deserialize, Serialize, Default, Clone, Debug)]
 pub struct Dimensions { pub w: usize, pub h: usize }
@@ -19,6 +20,41 @@ pub struct Dimensions { pub w: usize, pub h: usize }
 #[allow(non_snake_case)]
 pub struct CameraParams { pub RMS_error: f64, pub camera_matrix: Vec<[f64; 3]>, pub distortion_coeffs: Vec<f64>, pub radial_distortion_limit: Option<f64> }
 
+// Standard deviation of the estimated parameters of a self-calibrated profile
+#[derive(Deserialize, Serialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct CalibrationUncertainty { pub focal_length: f64, pub distortion_coeffs: Vec<f64>, pub inliers: usize }
+
+// Change of the effective focal length while focusing (focus breathing) or zooming, relative to the calibrated focal length
+#[derive(Deserialize, Serialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct FocusBreathing {
+    pub input: String, // "focus_distance" (meters) or "lens_position" (`lens_positions` from the metadata, usually focal length in mm)
+    #[serde(deserialize_with = "FocusBreathing::deserialize_curve")]
+    pub curve: Vec<(f64, f64)>, // (input value, focal length scale), sorted by the input value
+}
+impl FocusBreathing {
+    // The curve in the profile file can be in any order, sort it once here so `scale_at` can search it
+    fn deserialize_curve<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<(f64, f64)>, D::Error> {
+        let mut curve: Vec<(f64, f64)> = Deserialize::deserialize(deserializer)?;
+        curve.retain(|(x, y)| x.is_finite() && y.is_finite());
+        curve.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(curve)
+    }
+
+    pub fn scale_at(&self, value: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.curve.first(), self.curve.last()) else { return 1.0; };
+        if value.is_nan() { return 1.0; }
+        if value <= first.0 { return first.1; }
+        if value >= last.0 { return last.1; }
+        // First point greater than `value`, it's never the first one because of the checks above
+        let i = self.curve.partition_point(|x| x.0 <= value);
+        let ((x1, y1), (x2, y2)) = (self.curve[i - 1], self.curve[i]);
+        if x2 - x1 < 1e-9 { return y1; }
+        y1 + (y2 - y1) * (value - x1) / (x2 - x1)
+    }
+}
+
 #[derive(Deserialize, Serialize, Default, Clone, Debug)]
 #[serde(default)]
 pub struct LensProfile {
@@ -36,6 +72,7 @@ pub struct LensProfile {
     pub output_dimension: Option<Dimensions>,
 
     pub frame_readout_time: Option<f64>,
+    pub horizontal_rs: bool,
     pub gyro_lpf: Option<f64>,
 
     pub input_horizontal_stretch: f64,
@@ -52,6 +89,13 @@ pub struct LensProfile {
 
     pub fisheye_params: CameraParams,
 
+    pub vignetting: Option<Vec<f64>>, // v1, v2, v3 of the brightness falloff 1 + v1*r^2 + v2*r^4 + v3*r^6, r normalized to half of the image diagonal
+    pub chromatic_aberration: Option<Vec<f64>>, // Radial scale - 1 of the red and blue channels, relative to green
+
+    pub focus_breathing: Option<FocusBreathing>,
+
+    pub uncertainty: Option<CalibrationUncertainty>, // Only for profiles estimated from footage, without a calibration target
+
     pub identifier: String,
 
     pub calibrator_version: String,
@@ -107,18 +151,34 @@ impl LensProfile {
         Some(())
     }
 
-    #[cfg(feature = "opencv")]
     pub fn set_from_calibrator(&mut self, cal: &LensCalibrator) {
         if self.input_horizontal_stretch <= 0.01 { self.input_horizontal_stretch = 1.0; }
         if self.input_vertical_stretch   <= 0.01 { self.input_vertical_stretch   = 1.0; }
 
+        self.digital_lens = cal.digital_lens.clone();
+        self.digital_lens_params = cal.digital_lens_params.clone();
+
+        // Calibrated on the processed frame, keep the size of the real image in `orig_dimension` so the stretch is not applied again
+        let (sx, sy) = self.digital_lens.as_ref().map(|x| DistortionModel::from_name(x).digital_stretch_factors(self.digital_lens_params.as_ref())).unwrap_or((1.0, 1.0));
         self.calib_dimension = Dimensions { w: cal.width, h: cal.height };
-        self.orig_dimension  = Dimensions { w: cal.width, h: cal.height };
+        self.orig_dimension  = Dimensions { w: (cal.width as f64 / sx).round() as usize, h: (cal.height as f64 / sy).round() as usize };
         self.num_images = cal.used_points.len();
-        self.digital_lens = cal.digital_lens.clone();
+        self.distortion_model = cal.distortion_model.clone();
         self.optimal_fov = None;
 
         self.asymmetrical = cal.asymmetrical;
+        self.uncertainty = None;
+
+        if let Some(rs) = &cal.rolling_shutter {
+            // Readout time and direction always come from the same estimate
+            self.frame_readout_time = Some(rs.frame_readout_time);
+            self.horizontal_rs = rs.horizontal;
+        }
+
+        if let Some(color) = &cal.lens_color {
+            self.vignetting = color.vignetting.map(|x| x.to_vec());
+            self.chromatic_aberration = color.chromatic_aberration.map(|x| x.to_vec());
+        }
 
         self.fisheye_params = CameraParams {
             RMS_error: cal.rms,
@@ -130,6 +190,92 @@ impl LensProfile {
         self.init();
     }
 
+    // Provisional profile estimated from the footage and gyro data. It's never official, because the estimate depends on the content of the video
+    pub fn set_from_self_calibration(&mut self, result: &SelfCalibrationResult, width: usize, height: usize) {
+        self.calib_dimension = Dimensions { w: width, h: height };
+        self.orig_dimension  = Dimensions { w: width, h: height };
+        self.input_horizontal_stretch = 1.0;
+        self.input_vertical_stretch = 1.0;
+        self.distortion_model = None;
+        self.digital_lens = None;
+        self.interpolations = None;
+        self.parsed_interpolations.clear();
+        self.optimal_fov = None;
+        self.num_images = 0;
+        self.official = false;
+        self.vignetting = None;
+        self.chromatic_aberration = None;
+        self.note = format!("Estimated from footage, focal length ±{:.1} px", result.focal_length_std);
+
+        self.fisheye_params = CameraParams {
+            RMS_error: result.rms,
+            camera_matrix: result.k.row_iter().map(|x| [x[0], x[1], x[2]]).collect(),
+            distortion_coeffs: result.d.clone(),
+            radial_distortion_limit: None
+        };
+        self.uncertainty = Some(CalibrationUncertainty {
+            focal_length: result.focal_length_std,
+            distortion_coeffs: result.d_std.clone(),
+            inliers: result.inliers
+        });
+
+        self.init();
+    }
+
+    // Creates one profile for each resolution of the joint calibration, using `self` as the template for the metadata.
+    // If a resolution was calibrated at multiple focal lengths, all of them are stored in `interpolations`
+    pub fn from_joint_calibration(&self, cal: &LensCalibrator, joint: &JointCalibration) -> Vec<LensProfile> {
+        let mut by_resolution: BTreeMap<(usize, usize), Vec<&crate::calibration::JointCalibrationMode>> = BTreeMap::new();
+        for mode in &joint.modes {
+            by_resolution.entry((mode.width, mode.height)).or_default().push(mode);
+        }
+        by_resolution.into_iter().map(|((w, h), mut modes)| {
+            modes.sort_by(|a, b| a.focal_length.unwrap_or_default().total_cmp(&b.focal_length.unwrap_or_default()));
+            let base = modes[0];
+            let mut profile = self.clone();
+            if profile.input_horizontal_stretch <= 0.01 { profile.input_horizontal_stretch = 1.0; }
+            if profile.input_vertical_stretch   <= 0.01 { profile.input_vertical_stretch   = 1.0; }
+
+            profile.calib_dimension = Dimensions { w, h };
+            profile.orig_dimension  = Dimensions { w, h };
+            profile.num_images = modes.iter().map(|x| x.num_images).sum();
+            profile.digital_lens = cal.digital_lens.clone();
+            profile.distortion_model = cal.distortion_model.clone();
+            profile.asymmetrical = cal.asymmetrical;
+            profile.uncertainty = None;
+            profile.optimal_fov = None;
+            profile.focal_length = base.focal_length.or(profile.focal_length);
+
+            profile.fisheye_params = CameraParams {
+                RMS_error: modes.iter().map(|x| x.rms).fold(0.0, f64::max),
+                camera_matrix: base.k.row_iter().map(|x| [x[0], x[1], x[2]]).collect(),
+                distortion_coeffs: base.d.clone(),
+                radial_distortion_limit: if cal.r_limit > 0.0 { Some(cal.r_limit) } else { None }
+            };
+
+            let focal_lengths: HashSet<i64> = modes.iter().filter_map(|x| Some((x.focal_length? * 1000.0).round() as i64)).collect();
+            profile.interpolations = if focal_lengths.len() > 1 {
+                let mut map = serde_json::Map::new();
+                for mode in modes.iter() {
+                    if let Some(fl) = mode.focal_length {
+                        map.insert(format!("{}", fl), serde_json::json!({
+                            "camera_matrix": mode.k.row_iter().map(|x| [x[0], x[1], x[2]]).collect::<Vec<_>>(),
+                            "distortion_coeffs": mode.d,
+                            "focal_length": fl
+                        }));
+                    }
+                }
+                Some(serde_json::Value::Object(map))
+            } else {
+                None
+            };
+            profile.parsed_interpolations.clear();
+
+            profile.init();
+            profile
+        }).collect()
+    }
+
     pub fn init(&mut self) {
         self.calibrator_version = env!("CARGO_PKG_VERSION").to_string();
         self.date = time::OffsetDateTime::now_local().map(|v| v.date().to_string()).unwrap_or_default();
@@ -318,6 +464,10 @@ impl LensProfile {
                         match x.get("digital_lens").and_then(|x| x.as_str()) {
                             Some("gopro_superview") => { ratiow /= 1.33333333333; },
                             Some("gopro_hyperview") => { ratiow /= 1.55555555555; },
+                            Some(id @ "nonlinear_stretch") => {
+                                let params = x.get("digital_lens_params").and_then(|x| serde_json::from_value::<Vec<f64>>(x.clone()).ok()).or_else(|| cpy.digital_lens_params.clone());
+                                ratiow /= DistortionModel::from_name(id).digital_stretch_factors(params.as_ref()).0;
+                            },
                             _ => { }
                         }
                         fn scale(val: &mut usize, ratio: f64, pad: bool) {
@@ -356,6 +506,7 @@ impl LensProfile {
                 if x.contains_key("crop") { cpy.crop = x["crop"].as_f64(); }
                 if x.contains_key("interpolations") { cpy.interpolations = x.get("interpolations").cloned(); }
                 if x.contains_key("digital_lens")   { cpy.digital_lens   = x.get("digital_lens").and_then(|x| x.as_str().map(|x| x.to_owned())); }
+                if x.contains_key("digital_lens_params") { cpy.digital_lens_params = x.get("digital_lens_params").and_then(|x| serde_json::from_value(x.clone()).ok()); }
                 if x.contains_key("focal_length")   { cpy.focal_length   = x.get("focal_length").and_then(|x| x.as_f64()); }
                 if x.contains_key("crop_factor")    { cpy.crop_factor    = x.get("crop_factor").and_then(|x| x.as_f64()); }
 
@@ -5,12 +5,12 @@ pub mod gyro_source;
 pub mod imu_integration;
 pub mod lens_profile;
 pub mod lens_profile_database;
-#[cfg(feature = "opencv")]
 pub mod calibration;
 pub mod synchronization;
 pub mod stabilization;
 pub mod camera_identifier;
 pub mod keyframes;
+pub mod expression;
 
 pub mod zooming;
 pub mod smoothing;
@@ -41,7 +41,6 @@ use gpu::drawing::*;
 
 pub use telemetry_parser;
 
-#[cfg(feature = "opencv")]
 use calibration::LensCalibrator;
 
 #[global_allocator]
@@ -67,7 +66,6 @@ pub struct StabilizationManager {
     pub stabilization: Arc<RwLock<Stabilization>>,
 
     pub pose_estimator: Arc<synchronization::PoseEstimator>,
-    #[cfg(feature = "opencv")]
     pub lens_calibrator: Arc<RwLock<Option<LensCalibrator>>>,
 
     pub current_compute_id: Arc<AtomicU64>,
@@ -108,7 +106,6 @@ impl Default for StabilizationManager {
 
             input_file: Arc::new(RwLock::new(InputFile::default())),
 
-            #[cfg(feature = "opencv")]
             lens_calibrator: Arc::new(RwLock::new(None)),
 
             keyframes: Arc::new(RwLock::new(KeyframeManager::new())),
@@ -129,7 +126,10 @@ impl StabilizationManager {
         }
 
         self.pose_estimator.sync_results.write().clear();
-        self.keyframes.write().clear();
+        let video_speed = self.params.read().video_speed;
+        let mut keyframes = self.keyframes.write();
+        keyframes.clear();
+        keyframes.set_video_info(fps, duration_ms, video_speed);
     }
 
     pub fn load_gyro_data<F: Fn(f64)>(&self, url: &str, is_main_video: bool, options: &gyro_source::FileLoadOptions, progress_cb: F, cancel_flag: Arc<AtomicBool>) -> std::result::Result<(), GyroflowCoreError> {
@@ -385,8 +385,9 @@ impl StabilizationManager {
         let keyframes = self.keyframes.read().clone();
         let smoothing = self.smoothing.read();
         let horizon_lock = smoothing.horizon_lock.clone();
+        let trim_range_smoothing = smoothing.get_trim_range_smoothing(&params);
 
-        let (quats, org_quats, max_angles) = self.gyro.read().recompute_smoothness(smoothing.current().as_ref(), horizon_lock, &params, &keyframes);
+        let (quats, org_quats, max_angles) = self.gyro.read().recompute_smoothness(smoothing.current().as_ref(), horizon_lock, &trim_range_smoothing, &params, &keyframes);
         let mut gyro = self.gyro.write();
         gyro.max_angles = max_angles;
         gyro.org_smoothed_quaternions = org_quats;
@@ -436,11 +437,11 @@ impl StabilizationManager {
 
             let mut smoothing_changed = false;
             if smoothing.read().get_state_checksum(gyro_checksum) != smoothing_checksum.load(SeqCst) {
-                let (mut smoothing, horizon_lock) = {
+                let (mut smoothing, horizon_lock, trim_range_smoothing) = {
                     let lock = smoothing.read();
-                    (lock.current().clone(), lock.horizon_lock.clone())
+                    (lock.current().clone(), lock.horizon_lock.clone(), lock.get_trim_range_smoothing(&stabilization_params.read()))
                 };
-                let (quats, org_quats, max_angles) = gyro.read().recompute_smoothness(smoothing.as_mut(), horizon_lock, &stabilization_params.read(), &keyframes);
+                let (quats, org_quats, max_angles) = gyro.read().recompute_smoothness(smoothing.as_mut(), horizon_lock, &trim_range_smoothing, &stabilization_params.read(), &keyframes);
 
                 if current_compute_id.load(SeqCst) != compute_id { return cb((compute_id, true)); }
                 if gyro_checksum != gyro.read().get_checksum() { return cb((compute_id, true)); }
@@ -546,13 +547,21 @@ impl StabilizationManager {
                     }
                 }
             }
-            #[cfg(feature = "opencv")]
             if p.is_calibrator {
                 let lock = self.lens_calibrator.read();
                 if let Some(ref cal) = *lock {
+                    if cal.show_coverage {
+                        if let Some(ref report) = cal.report {
+                            calibration::drawing::draw_coverage(p.size.0, p.size.1, drawing, report, y_inverted);
+                        }
+                    }
                     let points = cal.all_matches.read();
                     if let Some(entry) = points.get(&(frame as i32)) {
-                        calibration::drawing::draw_chessboard_corners(cal.width, cal.height, p.size.0, p.size.1, drawing, (cal.columns, cal.rows), &entry.points, true, y_inverted);
+                        if entry.ids.is_empty() {
+                            calibration::drawing::draw_chessboard_corners(cal.width, cal.height, p.size.0, p.size.1, drawing, (cal.columns, cal.rows), &entry.points, true, y_inverted);
+                        } else {
+                            calibration::drawing::draw_partial_corners(cal.width, cal.height, p.size.0, p.size.1, drawing, (cal.columns, cal.rows), &entry.points, &entry.ids, y_inverted);
+                        }
                     }
                 }
             }
@@ -597,11 +606,19 @@ impl StabilizationManager {
 
     pub fn trim_ranges(&self) -> Vec<(f64, f64)> { self.params.read().trim_ranges.clone() }
     pub fn set_trim_ranges(&self, v: Vec<(f64, f64)>) {
-        self.params.write().trim_ranges = if v.first() == Some(&(0.0, 1.0)) {
+        let mut params = self.params.write();
+        params.trim_ranges = if v.first() == Some(&(0.0, 1.0)) {
            Vec::new()
         } else {
             v
         };
+        params.remap_trim_range_overrides();
+        drop(params);
+        self.invalidate_smoothing();
+    }
+    pub fn trim_range_overrides(&self, index: usize) -> stabilization_params::TrimRangeOverrides { self.params.read().get_trim_range_overrides(index) }
+    pub fn set_trim_range_overrides(&self, index: usize, v: stabilization_params::TrimRangeOverrides) {
+        self.params.write().set_trim_range_overrides(index, v);
         self.invalidate_smoothing();
     }
 
@@ -618,6 +635,13 @@ impl StabilizationManager {
     pub fn set_fov_overview          (&self, v: bool) { self.params.write().fov_overview           = v; }
     pub fn set_show_safe_area        (&self, v: bool) { self.params.write().show_safe_area         = v; }
     pub fn set_lens_correction_amount(&self, v: f64)  { self.params.write().lens_correction_amount = v; self.invalidate_zooming(); }
+    pub fn set_output_projection     (&self, v: i32)  { self.params.write().output_projection = stabilization_params::OutputProjection::from(v); self.invalidate_zooming(); }
+    pub fn set_spherical             (&self, v: bool) { self.params.write().spherical = v; self.invalidate_zooming(); }
+    pub fn set_motion_blur_shutter_angle(&self, v: f64) { self.params.write().motion_blur_shutter_angle = v.max(0.0).min(360.0); }
+    pub fn set_motion_blur_samples      (&self, v: u32) { self.params.write().motion_blur_samples     = v.max(1).min(64) as usize; }
+    pub fn set_deblur                (&self, v: bool) { self.params.write().deblur                 = v; }
+    pub fn set_deblur_iterations     (&self, v: u32)  { self.params.write().deblur_iterations      = v.max(1).min(50) as usize; }
+    pub fn set_deblur_exposure_ms    (&self, v: f64)  { self.params.write().deblur_exposure_ms     = v.max(0.0); }
     pub fn set_background_color      (&self, bg: Vector4<f32>) { self.params.write().background = bg; }
     pub fn set_background_mode       (&self, v: i32)  { self.params.write().background_mode = stabilization_params::BackgroundMode::from(v); }
     pub fn set_background_margin     (&self, v: f64)  { self.params.write().background_margin = v; }
@@ -628,6 +652,7 @@ impl StabilizationManager {
     pub fn set_video_speed(&self, v: f64, link_with_smoothness: bool, link_with_zooming: bool) {
         let mut params = self.params.write();
         params.video_speed = v;
+        self.keyframes.write().video_speed = v;
         params.video_speed_affects_smoothing = link_with_smoothness;
         params.video_speed_affects_zooming = link_with_zooming;
         self.invalidate_smoothing();
@@ -660,7 +685,6 @@ impl StabilizationManager {
 
     pub fn set_digital_lens_name(&self, v: String) {
         self.lens.write().digital_lens =  if !v.is_empty() { Some(v.clone()) } else { None };
-        #[cfg(feature = "opencv")]
         if let Some(ref mut calib) = *self.lens_calibrator.write() {
             calib.digital_lens = if !v.is_empty() { Some(v) } else { None };
         }
@@ -672,15 +696,116 @@ impl StabilizationManager {
             lens.digital_lens_params = Some(vec![0f64; 4]);
         }
         lens.digital_lens_params.as_mut().unwrap()[index] = value;
-        #[cfg(feature = "opencv")]
         if let Some(ref mut calib) = *self.lens_calibrator.write() {
             calib.digital_lens_params = lens.digital_lens_params.clone();
         }
         self.invalidate_zooming();
     }
+    pub fn set_calibration_pattern(&self, target: calibration::CalibrationTarget, columns: usize, rows: usize) {
+        if let Some(ref mut calib) = *self.lens_calibrator.write() {
+            calib.set_pattern(target, columns, rows);
+        }
+    }
+    pub fn set_calibration_distortion_model(&self, id: &str) {
+        if let Some(ref mut calib) = *self.lens_calibrator.write() {
+            calib.distortion_model = if id.is_empty() || id == "opencv_fisheye" { None } else { Some(id.to_owned()) };
+        }
+    }
+    pub fn set_calibration_show_coverage(&self, v: bool) {
+        if let Some(ref mut calib) = *self.lens_calibrator.write() {
+            calib.show_coverage = v;
+        }
+    }
+    pub fn calibration_report(&self) -> Option<calibration::report::CalibrationReport> {
+        self.lens_calibrator.read().as_ref()?.report.clone()
+    }
+    // Estimates the frame readout time of the calibration video from the skew of the detected targets and the gyro data
+    pub fn estimate_calibration_rolling_shutter(&self) -> Result<calibration::rolling_shutter::RollingShutterEstimate, calibration::CalibrationError> {
+        // Time step (ms) for the angular velocity
+        const DELTA: f64 = 2.0;
+
+        let gyro = self.gyro.read();
+        if gyro.quaternions.is_empty() {
+            return Err(calibration::CalibrationError::CalibrationFailed);
+        }
+        let max_readout_time = 1000.0 / self.params.read().fps.max(1.0);
+
+        let mut lock = self.lens_calibrator.write();
+        let cal = lock.as_mut().ok_or(calibration::CalibrationError::CalibrationFailed)?;
+        cal.estimate_rolling_shutter(|frame, timestamp_us| {
+            let ts = timestamp_us as f64 / 1000.0 + gyro.file_metadata.per_frame_time_offsets.get(frame as usize).unwrap_or(&0.0);
+            let r = gyro.relative_rotation(ts + DELTA, ts) * gyro.relative_rotation(ts - DELTA, ts).transpose();
+            Some(nalgebra::Rotation3::from_matrix_unchecked(r).scaled_axis() / (2.0 * DELTA))
+        }, max_readout_time)
+    }
+    // Estimates a provisional lens profile from the optical flow of the synced parts of the video and the gyro rotation between frames
+    pub fn self_calibrate_lens(&self) -> Result<LensProfile, calibration::CalibrationError> {
+        const MAX_CORRESPONDENCES: usize = 50000;
+
+        let (width, height, frame_readout_time, horizontal_rs) = {
+            let params = self.params.read();
+            (params.video_size.0, params.video_size.1, params.frame_readout_time, params.horizontal_rs)
+        };
+        let gyro = self.gyro.read();
+        if gyro.quaternions.is_empty() {
+            return Err(calibration::CalibrationError::NotEnoughData("no gyro data"));
+        }
+
+        self.pose_estimator.cache_optical_flow(1);
+
+        // Capture time of the point, including the rolling shutter
+        let point_time = |ts_us: i64, p: (f64, f64)| -> f64 {
+            let pos = if horizontal_rs { p.0 / width.max(1) as f64 } else { p.1 / height.max(1) as f64 };
+            ts_us as f64 / 1000.0 + frame_readout_time * (pos - 0.5)
+        };
+
+        let mut correspondences = Vec::new();
+        for x in self.pose_estimator.sync_results.read().values() {
+            if let Ok(of) = x.optical_flow.try_borrow() {
+                if let Some(Some(((ts_a, pts_a), (ts_b, pts_b)))) = of.get(&1) {
+                    let ratio = height as f64 / x.frame_size.1.max(1) as f64;
+                    for (a, b) in pts_a.iter().zip(pts_b.iter()) {
+                        let a = (a.0 as f64 * ratio, a.1 as f64 * ratio);
+                        let b = (b.0 as f64 * ratio, b.1 as f64 * ratio);
+                        correspondences.push(calibration::self_calibration::Correspondence {
+                            rotation: gyro.relative_rotation(point_time(*ts_b, b), point_time(*ts_a, a)),
+                            a,
+                            b
+                        });
+                    }
+                }
+            }
+        }
+        if correspondences.is_empty() {
+            return Err(calibration::CalibrationError::NotEnoughData("no optical flow, synchronize the video first"));
+        }
+        if correspondences.len() > MAX_CORRESPONDENCES {
+            let step = correspondences.len().div_ceil(MAX_CORRESPONDENCES);
+            correspondences = correspondences.into_iter().step_by(step).collect();
+        }
+
+        let result = calibration::self_calibration::calibrate(&correspondences, (width, height)).ok_or(calibration::CalibrationError::CalibrationFailed)?;
+        log::info!("Self-calibration: {:?}", result);
+
+        let mut profile = LensProfile::default();
+        if let Some(id) = self.camera_id.read().as_ref() {
+            profile.camera_brand = id.brand.clone();
+            profile.camera_model = id.model.clone();
+            profile.lens_model = id.lens_model.clone();
+            profile.camera_setting = id.camera_setting.clone();
+            profile.focal_length = id.focal_length;
+        }
+        profile.fps = self.params.read().fps;
+        profile.set_from_self_calibration(&result, width, height);
+        Ok(profile)
+    }
+    pub fn set_calibration_use_opencv(&self, v: bool) {
+        if let Some(ref mut calib) = *self.lens_calibrator.write() {
+            calib.use_opencv = v && cfg!(feature = "use-opencv");
+        }
+    }
     pub fn set_lens_is_asymmetrical(&self, v: bool) {
         self.lens.write().asymmetrical = v;
-        #[cfg(feature = "opencv")]
         if let Some(ref mut calib) = *self.lens_calibrator.write() {
             calib.asymmetrical = v;
         }
@@ -723,6 +848,7 @@ impl StabilizationManager {
     }
     pub fn recompute_gyro(&self) {
         self.gyro.write().apply_transforms();
+        self.keyframes.write().update_gyro(&self.gyro.read());
         self.invalidate_smoothing();
     }
     pub fn set_sync_lpf(&self, lpf: f64) {
@@ -747,7 +873,6 @@ impl StabilizationManager {
                 "k3" => lens.fisheye_params.distortion_coeffs[2] = value,
                 "k4" => lens.fisheye_params.distortion_coeffs[3] = value,
                 "r_limit" => {
-                    #[cfg(feature = "opencv")]
                     if let Some(ref mut calib) = *self.lens_calibrator.write() {
                         calib.r_limit = value;
                     }
@@ -942,6 +1067,13 @@ impl StabilizationManager {
                 "adaptive_zoom_center_offset": params.adaptive_zoom_center_offset,
                 "adaptive_zoom_method":   params.adaptive_zoom_method,
                 "lens_correction_amount": params.lens_correction_amount,
+                "output_projection":      params.output_projection as i32,
+                "spherical":              params.spherical,
+                "motion_blur_shutter_angle": params.motion_blur_shutter_angle,
+                "motion_blur_samples":       params.motion_blur_samples,
+                "deblur":                 params.deblur,
+                "deblur_iterations":      params.deblur_iterations,
+                "deblur_exposure_ms":     params.deblur_exposure_ms,
                 "horizon_lock_amount":    horizon_amount,
                 "horizon_lock_roll":      horizon_roll,
                 "use_gravity_vectors":    gyro.use_gravity_vectors,
@@ -965,8 +1097,11 @@ impl StabilizationManager {
 
             "offsets": gyro.get_offsets(), // timestamp, offset value
             "keyframes": self.keyframes.read().serialize(),
+            "keyframe_expressions": self.keyframes.read().serialize_expressions(),
+            "keyframe_telemetry_bindings": self.keyframes.read().serialize_telemetry_bindings(),
 
             "trim_ranges": params.trim_ranges,
+            "trim_range_overrides": params.trim_range_overrides,
         });
 
         util::merge_json(&mut obj, &serde_json::from_str(additional_data).unwrap_or_default());
@@ -1100,7 +1235,9 @@ impl StabilizationManager {
                 if let Some(v) = vid_info.get("fps_scale") { params.fps_scale = v.as_f64(); }
 
                 self.gyro.write().init_from_params(&params);
-                self.keyframes.write().timestamp_scale = params.fps_scale;
+                let mut keyframes = self.keyframes.write();
+                keyframes.timestamp_scale = params.fps_scale;
+                keyframes.set_video_info(params.fps, params.duration_ms, params.video_speed);
             }
             if let Some(serde_json::Value::Object(ref mut obj)) = obj.get_mut("gyro_source") {
                 let mut org_gyro_url = obj.get("filepath").and_then(|x| x.as_str()).unwrap_or(&"").to_string();
@@ -1241,9 +1378,16 @@ impl StabilizationManager {
                 if let Some(v) = obj.get("frame_readout_time")    .and_then(|x| x.as_f64()) { params.frame_readout_time      = v; }
                 if let Some(v) = obj.get("adaptive_zoom_window")  .and_then(|x| x.as_f64()) { params.adaptive_zoom_window    = v; }
                 if let Some(v) = obj.get("lens_correction_amount").and_then(|x| x.as_f64()) { params.lens_correction_amount  = v; }
+                if let Some(v) = obj.get("output_projection")     .and_then(|x| x.as_i64()) { params.output_projection = stabilization_params::OutputProjection::from(v as i32); }
+                if let Some(v) = obj.get("spherical")             .and_then(|x| x.as_bool()) { params.spherical              = v; }
+                if let Some(v) = obj.get("motion_blur_shutter_angle").and_then(|x| x.as_f64()) { params.motion_blur_shutter_angle = v.max(0.0).min(360.0); }
+                if let Some(v) = obj.get("motion_blur_samples")   .and_then(|x| x.as_u64()) { params.motion_blur_samples    = v.max(1).min(64) as usize; }
+                if let Some(v) = obj.get("deblur")                .and_then(|x| x.as_bool()) { params.deblur                 = v; }
+                if let Some(v) = obj.get("deblur_iterations")     .and_then(|x| x.as_u64()) { params.deblur_iterations      = v.max(1).min(50) as usize; }
+                if let Some(v) = obj.get("deblur_exposure_ms")    .and_then(|x| x.as_f64()) { params.deblur_exposure_ms     = v; }
                 if let Some(v) = obj.get("horizontal_rs")        .and_then(|x| x.as_bool()) { params.horizontal_rs          = v; }
 
-                if let Some(v) = obj.get("video_speed").and_then(|x| x.as_f64()) { params.video_speed = v; }
+                if let Some(v) = obj.get("video_speed").and_then(|x| x.as_f64()) { params.video_speed = v; self.keyframes.write().video_speed = v; }
                 if let Some(v) = obj.get("video_speed_affects_smoothing").and_then(|x| x.as_bool()) { params.video_speed_affects_smoothing = v; }
                 if let Some(v) = obj.get("video_speed_affects_zooming")  .and_then(|x| x.as_bool()) { params.video_speed_affects_zooming   = v; }
 
@@ -1319,6 +1463,12 @@ impl StabilizationManager {
             if let Some(keyframes) = obj.get("keyframes") {
                 self.keyframes.write().deserialize(keyframes);
             }
+            if let Some(expressions) = obj.get("keyframe_expressions") {
+                self.keyframes.write().deserialize_expressions(expressions);
+            }
+            if let Some(bindings) = obj.get("keyframe_telemetry_bindings") {
+                self.keyframes.write().deserialize_telemetry_bindings(bindings);
+            }
 
             if let Some(start) = obj.get("trim_start").and_then(|x| x.as_f64()) {
                 if let Some(end) = obj.get("trim_end").and_then(|x| x.as_f64()) {
@@ -1337,6 +1487,11 @@ impl StabilizationManager {
                 }).collect::<Vec<_>>();
                 self.params.write().trim_ranges = ranges;
             }
+            if let Some(overrides) = obj.get("trim_range_overrides") {
+                let mut params = self.params.write();
+                params.trim_range_overrides = serde_json::from_value(overrides.clone()).unwrap_or_default();
+                params.remap_trim_range_overrides();
+            }
 
             {
                 let mut params = self.params.write();
@@ -1453,8 +1608,13 @@ impl StabilizationManager {
                 if db.contains_id(&id_str) {
                     match self.load_lens_profile(&id_str) {
                         Ok(_) => {
-                            if let Some(fr) = self.lens.read().frame_readout_time {
-                                self.params.write().frame_readout_time = fr;
+                            let lens = self.lens.read();
+                            if let Some(fr) = lens.frame_readout_time {
+                                let mut params = self.params.write();
+                                params.frame_readout_time = fr;
+                                if lens.horizontal_rs {
+                                    params.horizontal_rs = true;
+                                }
                             }
                         }
                         Err(e) => {
@@ -1493,10 +1653,54 @@ impl StabilizationManager {
     pub fn keyframe_easing(&self, typ: &KeyframeType, timestamp_us: i64) -> Option<Easing> {
         self.keyframes.read().easing(typ, timestamp_us)
     }
+    pub fn set_keyframe_bezier_handles(&self, typ: &KeyframeType, timestamp_us: i64, handle_in: Option<BezierHandle>, handle_out: Option<BezierHandle>) {
+        self.keyframes.write().set_bezier_handles(typ, timestamp_us, handle_in, handle_out);
+        self.keyframes_updated(typ);
+    }
+    pub fn keyframe_bezier_handles(&self, typ: &KeyframeType, timestamp_us: i64) -> Option<(Option<BezierHandle>, Option<BezierHandle>)> {
+        self.keyframes.read().bezier_handles(typ, timestamp_us)
+    }
+    pub fn set_keyframe_expression(&self, typ: &KeyframeType, expression: &str) -> Result<(), expression::ExpressionError> {
+        self.keyframes.write().set_expression(typ, expression)?;
+        self.keyframes_updated(typ);
+        Ok(())
+    }
+    pub fn keyframe_expression(&self, typ: &KeyframeType) -> Option<String> {
+        self.keyframes.read().expression(typ).map(|x| x.to_owned())
+    }
+    pub fn set_keyframe_telemetry_binding(&self, typ: &KeyframeType, binding: Option<TelemetryBinding>) {
+        self.keyframes.write().set_telemetry_binding(typ, binding);
+        self.keyframes_updated(typ);
+    }
+    pub fn keyframe_telemetry_binding(&self, typ: &KeyframeType) -> Option<TelemetryBinding> {
+        self.keyframes.read().telemetry_binding(typ).cloned()
+    }
+    pub fn available_telemetry_channels(&self) -> Vec<TelemetryChannel> {
+        self.keyframes.read().available_telemetry_channels()
+    }
     pub fn remove_keyframe(&self, typ: &KeyframeType, timestamp_us: i64) {
         self.keyframes.write().remove(typ, timestamp_us);
         self.keyframes_updated(typ);
     }
+    pub fn export_keyframes(&self, types: &[KeyframeType]) -> String {
+        serde_json::to_string_pretty(&self.keyframes.read().export_tracks(types)).unwrap_or_default()
+    }
+    // Accepts a keyframes file, project file or CSV. Returns imported keyframe types
+    pub fn import_keyframes(&self, data: &[u8], time_offset_ms: f64, time_scale: f64) -> Result<Vec<KeyframeType>, GyroflowCoreError> {
+        let time_offset_us = (time_offset_ms * 1000.0).round() as i64;
+        let imported = {
+            let mut keyframes = self.keyframes.write();
+            if data.iter().find(|x| !x.is_ascii_whitespace()) == Some(&b'{') {
+                keyframes.import_tracks(&serde_json::from_slice(data)?, time_offset_us, time_scale)?
+            } else {
+                keyframes.import_csv(&String::from_utf8_lossy(data), time_offset_us, time_scale)?
+            }
+        };
+        for typ in &imported {
+            self.keyframes_updated(typ);
+        }
+        Ok(imported)
+    }
     pub fn clear_keyframes_type(&self, typ: &KeyframeType) {
         self.keyframes.write().clear_type(typ);
         self.keyframes_updated(typ);
@@ -1584,6 +1788,9 @@ pub enum GyroflowCoreError {
     #[error("JSON error {0:?}")]
     JSONError(#[from] serde_json::Error),
 
+    #[error("Keyframe import error: {0}")]
+    KeyframeImportError(#[from] keyframes::KeyframeImportError),
+
     #[error("Filesystem error {0:?}")]
     FilesystemError(#[from] crate::filesystem::FilesystemError),
 
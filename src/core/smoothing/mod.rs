@@ -16,7 +16,7 @@ use std::borrow::Cow;
 use std::hash::Hasher;
 use std::collections::hash_map::DefaultHasher;
 use crate::keyframes::*;
-use crate::stabilization_params::StabilizationParams;
+use crate::stabilization_params::{ StabilizationParams, TrimRangeOverrides };
 
 pub trait SmoothingAlgorithm: DynClone {
     fn get_name(&self) -> String;
@@ -32,6 +32,14 @@ pub trait SmoothingAlgorithm: DynClone {
 }
 clone_trait_object!(SmoothingAlgorithm);
 
+#[derive(Clone)]
+pub struct TrimRangeSmoothing {
+    pub range_ms: (f64, f64),
+    pub transition_ms: f64,
+    pub alg: Box<dyn SmoothingAlgorithm>,
+    pub horizon_lock: horizon::HorizonLock,
+}
+
 pub struct Smoothing {
     algs: Vec<Box<dyn SmoothingAlgorithm>>,
     current_id: usize,
@@ -107,6 +115,40 @@ impl Smoothing {
         self.algs.iter().map(|x| x.get_name()).collect()
     }
 
+    pub fn get_trim_range_smoothing(&self, params: &StabilizationParams) -> Vec<TrimRangeSmoothing> {
+        let duration_ms = params.get_scaled_duration_ms();
+        params.trim_range_overrides.iter().filter_map(|overrides| {
+            if !overrides.affects_smoothing() { return None; }
+
+            Some(TrimRangeSmoothing {
+                range_ms: (overrides.range.0 * duration_ms, overrides.range.1 * duration_ms),
+                transition_ms: overrides.transition_ms(),
+                alg: self.get_overridden_alg(overrides),
+                horizon_lock: {
+                    let mut lock = self.horizon_lock.clone();
+                    let amount = overrides.horizon_lock_amount.unwrap_or(if lock.lock_enabled { lock.horizonlockpercent } else { 0.0 });
+                    lock.set_horizon(amount, overrides.horizon_lock_roll.unwrap_or(lock.horizonroll));
+                    lock
+                }
+            })
+        }).collect()
+    }
+    fn get_overridden_alg(&self, overrides: &TrimRangeOverrides) -> Box<dyn SmoothingAlgorithm> {
+        let mut alg = match overrides.smoothing_method.as_deref() {
+            Some(name) if name != self.current().get_name() => {
+                self.algs.iter().find(|x| x.get_name() == name).cloned().unwrap_or_else(|| {
+                    log::warn!("Unknown smoothing method in trim range: {}", name);
+                    self.current().clone()
+                })
+            },
+            _ => self.current().clone()
+        };
+        for (name, value) in &overrides.smoothing_params {
+            alg.set_parameter(name, *value);
+        }
+        alg
+    }
+
     pub fn get_trimmed_quats<'a>(quats: &'a TimeQuat, duration: f64, trim_range_only: bool, trim_ranges: &[(f64, f64)]) -> Cow<'a, TimeQuat> {
         if trim_range_only && !trim_ranges.is_empty() {
             let mut quats_copy = quats.clone();
@@ -4,7 +4,8 @@
 use super::StabilizationManager;
 use super::distortion_models::DistortionModel;
 use crate::GyroSource;
-use crate::keyframes::KeyframeManager;
+use crate::keyframes::{ KeyframeManager, KeyframeType };
+use crate::stabilization_params::TrimRangeOverrides;
 use crate::lens_profile::LensProfile;
 use std::sync::Arc;
 use parking_lot::RwLock;
@@ -31,6 +32,13 @@ pub struct ComputeParams {
     pub video_height: usize,
     pub video_rotation: f64,
     pub lens_correction_amount: f64,
+    pub output_projection: crate::stabilization_params::OutputProjection,
+    pub spherical: bool,
+    pub motion_blur_shutter_angle: f64,
+    pub motion_blur_samples: usize,
+    pub deblur: bool,
+    pub deblur_iterations: usize,
+    pub deblur_exposure_ms: f64,
     pub video_speed: f64,
     pub video_speed_affects_smoothing: bool,
     pub video_speed_affects_zooming: bool,
@@ -40,7 +48,9 @@ pub struct ComputeParams {
     pub background_margin_feather: f64,
     pub frame_readout_time: f64,
     pub trim_ranges: Vec<(f64, f64)>,
+    pub trim_range_overrides: Vec<crate::stabilization_params::TrimRangeOverrides>,
     pub scaled_fps: f64,
+    pub scaled_duration_ms: f64,
     pub plane_scale: (f64, f64),
     pub adaptive_zoom_window: f64,
     pub adaptive_zoom_center_offset: (f64, f64),
@@ -88,11 +98,20 @@ impl ComputeParams {
             background_margin: params.background_margin,
             background_margin_feather: params.background_margin_feather,
             lens_correction_amount: params.lens_correction_amount,
+            output_projection: params.output_projection,
+            spherical: params.spherical,
+            motion_blur_shutter_angle: params.motion_blur_shutter_angle,
+            motion_blur_samples: params.motion_blur_samples,
+            deblur: params.deblur,
+            deblur_iterations: params.deblur_iterations,
+            deblur_exposure_ms: params.deblur_exposure_ms,
             framebuffer_inverted: params.framebuffer_inverted,
             horizontal_rs: params.horizontal_rs,
             frame_readout_time: params.frame_readout_time,
             trim_ranges: params.trim_ranges.clone(),
+            trim_range_overrides: params.trim_range_overrides.clone(),
             scaled_fps: params.get_scaled_fps(),
+            scaled_duration_ms: params.get_scaled_duration_ms(),
             adaptive_zoom_window: params.adaptive_zoom_window,
             adaptive_zoom_center_offset: params.adaptive_zoom_center_offset,
             adaptive_zoom_method: params.adaptive_zoom_method,
@@ -109,6 +128,28 @@ impl ComputeParams {
             zooming_debug_points: false
         }
     }
+
+    pub fn fov_scale_at_timestamp(&self, timestamp_ms: f64) -> f64 {
+        if let Some(v) = self.keyframes.value_at_video_timestamp(&KeyframeType::Fov, timestamp_ms) {
+            return v;
+        }
+        // Blend the per-trim-range FOV overrides with the global value
+        let mut weight_sum = 0.0;
+        let mut weighted_fov = 0.0;
+        for overrides in &self.trim_range_overrides {
+            if let Some(range_fov) = overrides.fov {
+                let range_ms = (overrides.range.0 * self.scaled_duration_ms, overrides.range.1 * self.scaled_duration_ms);
+                let weight = TrimRangeOverrides::blend_weight(range_ms, overrides.transition_ms(), timestamp_ms);
+                weight_sum += weight;
+                weighted_fov += range_fov * weight;
+            }
+        }
+        if weight_sum > 1.0 {
+            weighted_fov / weight_sum
+        } else {
+            self.fov_scale * (1.0 - weight_sum) + weighted_fov
+        }
+    }
 }
 
 impl std::fmt::Debug for ComputeParams {
@@ -138,11 +179,19 @@ impl std::fmt::Debug for ComputeParams {
          .field("video_height",         &self.video_height)
          .field("video_rotation",       &self.video_rotation)
          .field("lens_correction_amount",    &self.lens_correction_amount)
+         .field("output_projection",         &self.output_projection)
+         .field("spherical",                 &self.spherical)
+         .field("motion_blur_shutter_angle", &self.motion_blur_shutter_angle)
+         .field("motion_blur_samples",       &self.motion_blur_samples)
+         .field("deblur",                    &self.deblur)
+         .field("deblur_iterations",         &self.deblur_iterations)
+         .field("deblur_exposure_ms",        &self.deblur_exposure_ms)
          .field("background_mode",           &self.background_mode)
          .field("background_margin",         &self.background_margin)
          .field("background_margin_feather", &self.background_margin_feather)
          .field("frame_readout_time",        &self.frame_readout_time)
          .field("trim_ranges",               &self.trim_ranges)
+         .field("trim_range_overrides",      &self.trim_range_overrides)
          .field("scaled_fps",                &self.scaled_fps)
          .field("adaptive_zoom_window",      &self.adaptive_zoom_window)
          .field("adaptive_zoom_center_offset", &self.adaptive_zoom_center_offset)
@@ -119,7 +119,7 @@ impl Stabilization {
     // Adapted from OpenCV: initUndistortRectifyMap + remap
     // https://github.com/opencv/opencv/blob/2b60166e5c65f1caccac11964ad760d847c536e4/modules/calib3d/src/fisheye.cpp#L465-L567
     // https://github.com/opencv/opencv/blob/2b60166e5c65f1caccac11964ad760d847c536e4/modules/imgproc/src/opencl/remap.cl#L390-L498
-    pub fn undistort_image_cpu<const I: i32, T: PixelType>(buffers: &mut Buffers, params: &KernelParams, distortion_model: &DistortionModel, digital_lens: Option<&DistortionModel>, matrices: &[[f32; 12]], drawing: &[u8]) -> bool {
+    pub fn undistort_image_cpu<const I: i32, T: PixelType>(buffers: &mut Buffers, params: &KernelParams, distortion_model: &DistortionModel, digital_lens: Option<&DistortionModel>, matrices: &[[f32; 12]], drawing: &[u8], neighbours: &[(&[u8], &[[f32; 12]])]) -> bool {
         // #[cold]
         // fn draw_pixel(pix: &mut Vector4<f32>, x: i32, y: i32, is_input: bool, width: i32, params: &KernelParams, drawing: &[u8]) {
         //     if drawing.is_empty() || (params.flags & 8) == 0 { return; }
@@ -151,13 +151,45 @@ impl Stabilization {
             return (x - in_min) * (out_max - out_min) / (in_max - in_min) + out_min;
         }
 
-        fn rotate_and_distort(pos: (f32, f32), idx: usize, params: &KernelParams, matrices: &[[f32; 12]], distortion_model: &DistortionModel, digital_lens: Option<&DistortionModel>, r_limit: f32) -> Option<(f32, f32)> {
+        // Ray of the output pixel for the selected output projection, in homogeneous pixel coordinates of the rectilinear output camera
+        // so it can be multiplied by the rotation matrices as is. Points outside of the projection return a zero vector
+        fn output_projection_ray(pos: (f32, f32), params: &KernelParams) -> (f32, f32, f32) {
+            if params.output_projection == 0 { return (pos.0, pos.1, 1.0); }
+
+            let out_c = (params.output_width as f32 / 2.0, params.output_height as f32 / 2.0);
+            let n = ((pos.0 - out_c.0) / params.output_f[0], (pos.1 - out_c.1) / params.output_f[1]);
+
+            let dir = if params.output_projection == 3 { // Equirectangular
+                if n.1.abs() > std::f32::consts::FRAC_PI_2 { return (0.0, 0.0, 0.0); }
+                (n.1.cos() * n.0.sin(), n.1.sin(), n.1.cos() * n.0.cos())
+            } else {
+                let r = (n.0 * n.0 + n.1 * n.1).sqrt();
+                let theta = if params.output_projection == 2 { // Stereographic
+                    2.0 * (r / 2.0).atan()
+                } else { // Equidistant
+                    r
+                };
+                if theta > std::f32::consts::PI { return (0.0, 0.0, 0.0); }
+                let scale = if r > 0.0 { theta.sin() / r } else { 1.0 };
+                (n.0 * scale, n.1 * scale, theta.cos())
+            };
+
+            (params.output_f[0] * dir.0 + out_c.0 * dir.2, params.output_f[1] * dir.1 + out_c.1 * dir.2, dir.2)
+        }
+
+        fn rotate_and_distort(pos: (f32, f32, f32), idx: usize, params: &KernelParams, matrices: &[[f32; 12]], distortion_model: &DistortionModel, digital_lens: Option<&DistortionModel>, r_limit: f32) -> Option<(f32, f32)> {
             let matrices = matrices[idx];
-            let _x = (pos.0 * matrices[0]) + (pos.1 * matrices[1]) + matrices[2] + params.translation3d[0];
-            let _y = (pos.0 * matrices[3]) + (pos.1 * matrices[4]) + matrices[5] + params.translation3d[1];
-            let _w = (pos.0 * matrices[6]) + (pos.1 * matrices[7]) + matrices[8] + params.translation3d[2];
-            if _w > 0.0 {
-                if params.r_limit > 0.0 && ((_x / _w).powi(2) + (_y / _w).powi(2)).sqrt() > r_limit {
+            let _x = (pos.0 * matrices[0]) + (pos.1 * matrices[1]) + (pos.2 * matrices[2]) + params.translation3d[0];
+            let _y = (pos.0 * matrices[3]) + (pos.1 * matrices[4]) + (pos.2 * matrices[5]) + params.translation3d[1];
+            let _w = (pos.0 * matrices[6]) + (pos.1 * matrices[7]) + (pos.2 * matrices[8]) + params.translation3d[2];
+            if (params.flags & 32) == 32 { // Spherical input
+                return Some((
+                    params.width  as f32 * (0.5 + _x.atan2(_w) / (2.0 * std::f32::consts::PI)),
+                    params.height as f32 * (0.5 + _y.atan2((_x * _x + _w * _w).sqrt()) / std::f32::consts::PI)
+                ));
+            }
+            if _w > 0.0 || (params.flags & 64) == 64 { // Wide FOV lens
+                if params.r_limit > 0.0 && (_w <= 0.0 || ((_x / _w).powi(2) + (_y / _w).powi(2)).sqrt() > r_limit) {
                     return None;
                 }
                 let mut uv = distortion_model.distort_point(_x, _y, _w, &params);
@@ -242,6 +274,123 @@ impl Stabilization {
             )
         }
 
+        // Lateral chromatic aberration and vignetting of the lens, corrected at the source position
+        fn sample_corrected_input_at<const I: i32, T: PixelType>(uv: (f32, f32), input: &[u8], params: &KernelParams, bg: &Vector4<f32>, drawing: &[u8]) -> Vector4<f32> {
+            let mut pixel = sample_input_at::<I, T>(uv, input, params, bg, drawing);
+            let ca = params.chromatic_aberration;
+            let v = params.vignetting;
+            if ca == [0.0; 4] && v == [0.0; 4] {
+                return pixel;
+            }
+            let mut center = (params.c[0], params.c[1]);
+            if params.input_horizontal_stretch > 0.001 { center.0 /= params.input_horizontal_stretch; }
+            if params.input_vertical_stretch   > 0.001 { center.1 /= params.input_vertical_stretch; }
+
+            for i in 0..3 {
+                if ca[i] != 0.0 {
+                    let pt = (center.0 + (uv.0 - center.0) * (1.0 + ca[i]), center.1 + (uv.1 - center.1) * (1.0 + ca[i]));
+                    pixel[i] = sample_input_at::<I, T>(pt, input, params, bg, drawing)[i];
+                }
+            }
+
+            if v != [0.0; 4] {
+                let r2 = ((uv.0 - center.0).powi(2) + (uv.1 - center.1).powi(2)) / ((params.width.pow(2) + params.height.pow(2)) as f32 / 4.0);
+                let gain = (1.0 + r2 * (v[0] + r2 * (v[1] + r2 * v[2]))).max(0.1);
+                for i in 0..3 {
+                    pixel[i] = (pixel[i] / gain).min(params.pixel_value_limit);
+                }
+            }
+            pixel
+        }
+
+        // Source position for the output position, using the matrices starting at `offset`
+        fn source_position_at(ray: (f32, f32, f32), out_pos: (f32, f32), offset: usize, params: &KernelParams, matrices: &[[f32; 12]], distortion_model: &DistortionModel, digital_lens: Option<&DistortionModel>, r_limit: f32) -> Option<(f32, f32)> {
+            ///////////////////////////////////////////////////////////////////
+            // Calculate source `y` for rolling shutter
+            let mut sy = if (params.flags & 16) == 16 { // Horizontal RS
+                (out_pos.0.round() as i32).min(params.width).max(0) as usize
+            } else {
+                (out_pos.1.round() as i32).min(params.height).max(0) as usize
+            };
+            if params.matrix_count > 1 {
+                let idx = offset + params.matrix_count as usize / 2;
+                if let Some(pt) = rotate_and_distort(ray, idx, params, matrices, distortion_model, digital_lens, r_limit) {
+                    if (params.flags & 16) == 16 { // Horizontal RS
+                        sy = (pt.0.round() as i32).min(params.width).max(0) as usize;
+                    } else {
+                        sy = (pt.1.round() as i32).min(params.height).max(0) as usize;
+                    }
+                }
+            }
+            ///////////////////////////////////////////////////////////////////
+
+            let idx = offset + sy.min(params.matrix_count as usize - 1);
+            rotate_and_distort(ray, idx, params, matrices, distortion_model, digital_lens, r_limit)
+        }
+
+        // Source pixel for the output position, using the matrices starting at `offset`
+        fn sample_stabilized_at<const I: i32, T: PixelType>(ray: (f32, f32, f32), out_pos: (f32, f32), offset: usize, input: &[u8], params: &KernelParams, matrices: &[[f32; 12]], distortion_model: &DistortionModel, digital_lens: Option<&DistortionModel>, r_limit: f32, bg: &Vector4<f32>, drawing: &[u8]) -> Vector4<f32> {
+            if let Some(mut uv) = source_position_at(ray, out_pos, offset, params, matrices, distortion_model, digital_lens, r_limit) {
+                let width_f = params.width as f32;
+                let height_f = params.height as f32;
+                match params.background_mode {
+                    1 => { // Edge repeat
+                        uv = (
+                            uv.0.max(0.0).min(width_f  - 1.0),
+                            uv.1.max(0.0).min(height_f - 1.0),
+                        );
+                    },
+                    2 => { // Edge mirror
+                        let rx = uv.0.round();
+                        let ry = uv.1.round();
+                        let width3 = width_f - 3.0;
+                        let height3 = height_f - 3.0;
+                        if rx > width3  { uv.0 = width3  - (rx - width3); }
+                        if rx < 3.0     { uv.0 = 3.0 + width_f - (width3  + rx); }
+                        if ry > height3 { uv.1 = height3 - (ry - height3); }
+                        if ry < 3.0     { uv.1 = 3.0 + height_f - (height3 + ry); }
+                    },
+                    3 => { // Margin with feather
+                        let widthf  = width_f - 1.0;
+                        let heightf = height_f - 1.0;
+
+                        let feather = (params.background_margin_feather * heightf).max(0.0001);
+                        let mut pt2 = uv;
+                        let mut alpha = 1.0;
+                        if (uv.0 > widthf - feather) || (uv.0 < feather) || (uv.1 > heightf - feather) || (uv.1 < feather) {
+                            alpha = ((widthf - uv.0).min(heightf - uv.1).min(uv.0).min(uv.1) / feather).min(1.0).max(0.0);
+                            pt2 = (pt2.0 / width_f, pt2.1 / height_f);
+                            pt2 = (
+                                ((pt2.0 - 0.5) * (1.0 - params.background_margin)) + 0.5,
+                                ((pt2.1 - 0.5) * (1.0 - params.background_margin)) + 0.5
+                            );
+                            pt2 = (pt2.0 * width_f, pt2.1 * height_f);
+                        }
+
+                        let c1 = sample_corrected_input_at::<I, T>(uv, input, params, bg, drawing);
+                        let c2 = sample_corrected_input_at::<I, T>(pt2, input, params, bg, drawing);
+                        return c1 * alpha + c2 * (1.0 - alpha);
+                    },
+                    _ => { }
+                }
+
+                return sample_corrected_input_at::<I, T>(uv, input, params, bg, drawing);
+            }
+            *bg
+        }
+
+        // Opacity of the source position, fading out towards the frame edges over the feather distance
+        fn edge_alpha(uv: Option<(f32, f32)>, params: &KernelParams) -> f32 {
+            if let Some(uv) = uv {
+                let widthf  = params.width  as f32 - 1.0;
+                let heightf = params.height as f32 - 1.0;
+                let feather = (params.background_margin_feather * heightf).max(0.0001);
+                ((widthf - uv.0).min(heightf - uv.1).min(uv.0).min(uv.1) / feather).min(1.0).max(0.0)
+            } else {
+                0.0
+            }
+        }
+
         if let BufferSource::Cpu { buffer: input } = &mut buffers.input.data {
             if let BufferSource::Cpu { buffer: output } = &mut buffers.output.data {
                 let r_limit = params.r_limit * params.r_limit; // Square it so we don't have to do sqrt on the point length
@@ -274,8 +423,6 @@ impl Stabilization {
                             assert!(pix_chunk.len() == std::mem::size_of::<T>());
 
                             // let p = out_pos;
-                            let mut pixel = bg;
-
                             out_pos.0 += params.translation2d[0];
                             out_pos.1 += params.translation2d[1];
 
@@ -288,7 +435,7 @@ impl Stabilization {
 
                             ///////////////////////////////////////////////////////////////////
                             // Add lens distortion back
-                            if params.lens_correction_amount < 1.0 {
+                            if params.lens_correction_amount < 1.0 && params.output_projection == 0 {
                                 let mut new_out_pos = out_pos;
 
                                 if (params.flags & 2) == 2 { // Has digial lens
@@ -310,77 +457,35 @@ impl Stabilization {
                             }
                             ///////////////////////////////////////////////////////////////////
 
-                            ///////////////////////////////////////////////////////////////////
-                            // Calculate source `y` for rolling shutter
-                            let mut sy = if (params.flags & 16) == 16 { // Horizontal RS
-                                (out_pos.0.round() as i32).min(params.width).max(0) as usize
+                            let ray = output_projection_ray(out_pos, params);
+
+                            let mut pixel = if params.motion_blur_samples > 1 {
+                                // Average of the sub-frame warps, their matrices follow the main ones
+                                let mut sum = Vector4::<f32>::from_element(0.0);
+                                for i in 1..=params.motion_blur_samples as usize {
+                                    sum += sample_stabilized_at::<I, T>(ray, out_pos, i * params.matrix_count as usize, input, params, matrices, distortion_model, digital_lens, r_limit, &bg, drawing);
+                                }
+                                sum / params.motion_blur_samples as f32
                             } else {
-                                (out_pos.1.round() as i32).min(params.height).max(0) as usize
+                                sample_stabilized_at::<I, T>(ray, out_pos, 0, input, params, matrices, distortion_model, digital_lens, r_limit, &bg, drawing)
                             };
-                            if params.matrix_count > 1 {
-                                let idx = params.matrix_count as usize / 2;
-                                if let Some(pt) = rotate_and_distort(out_pos, idx, params, matrices, distortion_model, digital_lens, r_limit) {
-                                    if (params.flags & 16) == 16 { // Horizontal RS
-                                        sy = (pt.0.round() as i32).min(params.width).max(0) as usize;
-                                    } else {
-                                        sy = (pt.1.round() as i32).min(params.height).max(0) as usize;
-                                    }
-                                }
-                            }
-                            ///////////////////////////////////////////////////////////////////
-
-                            let idx = sy.min(params.matrix_count as usize - 1);
-                            if let Some(mut uv) = rotate_and_distort(out_pos, idx, params, matrices, distortion_model, digital_lens, r_limit) {
-                                let width_f = params.width as f32;
-                                let height_f = params.height as f32;
-                                match params.background_mode {
-                                    1 => { // Edge repeat
-                                        uv = (
-                                            uv.0.max(0.0).min(width_f  - 1.0),
-                                            uv.1.max(0.0).min(height_f - 1.0),
-                                        );
-                                    },
-                                    2 => { // Edge mirror
-                                        let rx = uv.0.round();
-                                        let ry = uv.1.round();
-                                        let width3 = width_f - 3.0;
-                                        let height3 = height_f - 3.0;
-                                        if rx > width3  { uv.0 = width3  - (rx - width3); }
-                                        if rx < 3.0     { uv.0 = 3.0 + width_f - (width3  + rx); }
-                                        if ry > height3 { uv.1 = height3 - (ry - height3); }
-                                        if ry < 3.0     { uv.1 = 3.0 + height_f - (height3 + ry); }
-                                    },
-                                    3 => { // Margin with feather
-                                        let widthf  = width_f - 1.0;
-                                        let heightf = height_f - 1.0;
-
-                                        let feather = (params.background_margin_feather * heightf).max(0.0001);
-                                        let mut pt2 = uv;
-                                        let mut alpha = 1.0;
-                                        if (uv.0 > widthf - feather) || (uv.0 < feather) || (uv.1 > heightf - feather) || (uv.1 < feather) {
-                                            alpha = ((widthf - uv.0).min(heightf - uv.1).min(uv.0).min(uv.1) / feather).min(1.0).max(0.0);
-                                            pt2 = (pt2.0 / width_f, pt2.1 / height_f);
-                                            pt2 = (
-                                                ((pt2.0 - 0.5) * (1.0 - params.background_margin)) + 0.5,
-                                                ((pt2.1 - 0.5) * (1.0 - params.background_margin)) + 0.5
-                                            );
-                                            pt2 = (pt2.0 * width_f, pt2.1 * height_f);
-                                        }
-
-                                        let c1 = sample_input_at::<I, T>(uv, input, params, &bg, drawing);
-                                        let c2 = sample_input_at::<I, T>(pt2, input, params, &bg, drawing);
-                                        pixel = c1 * alpha + c2 * (1.0 - alpha);
-                                        // draw_pixel(&mut pixel, p.0 as i32, p.1 as i32, false, params.output_width, params, drawing);
-                                        if fix_range {
-                                            remap_colorrange(&mut pixel, is_y)
+                            if params.background_mode == 4 && !neighbours.is_empty() { // Temporal fill
+                                // Composite the neighbouring frames behind the current one, nearest first
+                                let alpha = edge_alpha(source_position_at(ray, out_pos, 0, params, matrices, distortion_model, digital_lens, r_limit), params);
+                                if alpha < 1.0 {
+                                    let mut remaining = 1.0 - alpha;
+                                    pixel *= alpha;
+                                    for (n_input, n_matrices) in neighbours {
+                                        let uv = source_position_at(ray, out_pos, 0, params, n_matrices, distortion_model, digital_lens, r_limit);
+                                        let weight = remaining * edge_alpha(uv, params);
+                                        if weight > 0.0 {
+                                            pixel += sample_corrected_input_at::<I, T>(uv.unwrap(), n_input, params, &bg, drawing) * weight;
+                                            remaining -= weight;
+                                            if remaining <= 0.001 { break; }
                                         }
-                                        *pix_out = PixelType::from_float(pixel);
-                                        return;
-                                    },
-                                    _ => { }
+                                    }
+                                    pixel += bg * remaining;
                                 }
-
-                                pixel = sample_input_at::<I, T>(uv, input, params, &bg, drawing);
                             }
                             // draw_pixel(&mut pixel, p.0 as i32, p.1 as i32, false, params.output_width, params, drawing);
 
@@ -410,7 +515,7 @@ pub fn undistort_points_with_rolling_shutter(distorted: &[(f32, f32)], timestamp
 pub fn undistort_points_for_optical_flow(distorted: &[(f32, f32)], timestamp_us: i64, params: &ComputeParams, points_dims: (u32, u32)) -> Vec<(f32, f32)> {
     let img_dim_ratio = points_dims.0 as f64 / params.video_width.max(1) as f64;//FrameTransform::get_ratio(params);
 
-    let (camera_matrix, distortion_coeffs, _, _, _, _) = FrameTransform::get_lens_data_at_timestamp(params, timestamp_us as f64 / 1000.0);
+    let (camera_matrix, distortion_coeffs, _, _, _, _, _) = FrameTransform::get_lens_data_at_timestamp(params, timestamp_us as f64 / 1000.0);
 
     let scaled_k = camera_matrix * img_dim_ratio;
 
@@ -435,6 +540,11 @@ pub fn undistort_points(distorted: &[(f32, f32)], camera_matrix: Matrix3<f64>, d
         f: [f.0, f.1],
         c: [c.0, c.1],
         k: distortion_coeffs.iter().map(|x| *x as f32).collect::<Vec<_>>().try_into().unwrap(),
+        digital_lens_params: {
+            let mut p = [0f32; 4];
+            for (i, v) in params.digital_lens_params.iter().flatten().take(4).enumerate() { p[i] = *v as f32; }
+            p
+        },
 
         ..Default::default()
     };
@@ -0,0 +1,209 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Gyro-based deblur of the input frames.
+// The camera rotation while the shutter was open is known from the gyro, so the blur of every pixel is known too:
+// it's the path the pixel traveled on the sensor during the exposure. The blur is spatially varying (lens distortion, roll, rolling shutter),
+// so the path is computed on a coarse grid and interpolated. The frame is then deconvolved with Richardson-Lucy, before it's warped.
+
+use nalgebra::{ Matrix3, Vector3, Vector4 };
+use rayon::{ prelude::ParallelSliceMut, iter::{ ParallelIterator, IndexedParallelIterator, IntoParallelIterator } };
+use crate::gpu::BufferSource;
+use super::{ ComputeParams, KernelParams, PixelType };
+
+const GRID_SIZE: usize = 8; // Cells in each dimension, the path is computed at (GRID_SIZE + 1)² points
+const PATH_SAMPLES: usize = 9; // Positions along the exposure
+
+#[derive(Default, Clone, Debug)]
+pub struct BlurKernel {
+    size: (usize, usize), // Frame size the paths are in
+    paths: Vec<[(f32, f32); PATH_SAMPLES]>, // Position of each grid point during the exposure, relative to the middle of the exposure
+}
+
+impl BlurKernel {
+    pub fn at_timestamp(params: &ComputeParams, kernel_params: &KernelParams, frame: usize, start_ts: f64, row_readout_time: f64) -> Option<Self> {
+        if !params.deblur || params.deblur_iterations == 0 || params.spherical { return None; }
+
+        let gyro = params.gyro.read();
+        let exposure_ms = if params.deblur_exposure_ms > 0.0 {
+            params.deblur_exposure_ms
+        } else {
+            *gyro.file_metadata.per_frame_exposure_times.get(frame)?
+        };
+        if exposure_ms <= 0.0 { return None; }
+
+        let size = (params.width, params.height);
+        let mut kernel_params = *kernel_params;
+        kernel_params.width         = params.width as i32;
+        kernel_params.height        = params.height as i32;
+        kernel_params.output_width  = params.output_width as i32;
+        kernel_params.output_height = params.output_height as i32;
+        let f = kernel_params.f;
+        let c = kernel_params.c;
+
+        // Same as in `undistort_points`
+        let undistort = |mut pt: (f32, f32)| -> Option<(f32, f32)> {
+            if params.lens.input_horizontal_stretch > 0.001 { pt.0 *= params.lens.input_horizontal_stretch as f32; }
+            if params.lens.input_vertical_stretch   > 0.001 { pt.1 *= params.lens.input_vertical_stretch as f32; }
+            if let Some(digital) = &params.digital_lens {
+                pt = digital.undistort_point(pt, &kernel_params)?;
+            }
+            params.distortion_model.undistort_point(((pt.0 - c[0]) / f[0], (pt.1 - c[1]) / f[1]), &kernel_params)
+        };
+        // Same as `rotate_and_distort` in the kernel
+        let distort = |v: Vector3<f32>| -> Option<(f32, f32)> {
+            if v[2] <= 0.0 { return None; }
+            let mut pt = params.distortion_model.distort_point(v[0], v[1], v[2], &kernel_params);
+            pt = ((pt.0 * f[0]) + c[0], (pt.1 * f[1]) + c[1]);
+            if let Some(digital) = &params.digital_lens {
+                pt = digital.distort_point(pt.0, pt.1, 1.0, &kernel_params);
+            }
+            if params.lens.input_horizontal_stretch > 0.001 { pt.0 /= params.lens.input_horizontal_stretch as f32; }
+            if params.lens.input_vertical_stretch   > 0.001 { pt.1 /= params.lens.input_vertical_stretch as f32; }
+            Some(pt)
+        };
+
+        // Axes flip of the stabilization matrices, see `FrameTransform::at_timestamp`
+        let axes = if params.framebuffer_inverted { Vector3::new(1.0, 1.0, -1.0) } else { Vector3::new(1.0, -1.0, -1.0) };
+        let axes = Matrix3::from_diagonal(&axes);
+
+        let n = GRID_SIZE + 1;
+        let paths = (0..n * n).into_par_iter().map(|i| {
+            let mut path = [(0.0f32, 0.0f32); PATH_SAMPLES];
+            let pt = (
+                (i % n) as f32 / GRID_SIZE as f32 * size.0 as f32,
+                (i / n) as f32 / GRID_SIZE as f32 * size.1 as f32
+            );
+            let Some(ray) = undistort(pt) else { return path; };
+            let ray = Vector3::new(ray.0, ray.1, 1.0);
+            let Some(center) = distort(ray) else { return path; };
+
+            // Middle of the exposure of this row
+            let row = if params.horizontal_rs { pt.0 } else { pt.1 } as f64;
+            let mid_ts = start_ts + row_readout_time * row;
+            let mid_quat = gyro.org_quat_at_timestamp(mid_ts).inverse();
+
+            for (s, p) in path.iter_mut().enumerate() {
+                let ts = mid_ts + ((s as f64 + 0.5) / PATH_SAMPLES as f64 - 0.5) * exposure_ms;
+                let rel = (mid_quat * gyro.org_quat_at_timestamp(ts)).to_rotation_matrix();
+                let r: Matrix3<f32> = nalgebra::convert(axes * rel.matrix().transpose() * axes);
+                if let Some(pos) = distort(r * ray) {
+                    *p = (pos.0 - center.0, pos.1 - center.1);
+                }
+            }
+            path
+        }).collect::<Vec<_>>();
+
+        let kernel = Self { size, paths };
+        if kernel.max_displacement() < 0.5 {
+            return None; // Nothing to deblur
+        }
+        Some(kernel)
+    }
+
+    pub fn max_displacement(&self) -> f32 {
+        self.paths.iter().flatten().fold(0.0f32, |m, p| m.max((p.0 * p.0 + p.1 * p.1).sqrt()))
+    }
+
+    fn path_at(&self, x: f32, y: f32) -> [(f32, f32); PATH_SAMPLES] {
+        let n = GRID_SIZE + 1;
+        let gx = (x / self.size.0 as f32 * GRID_SIZE as f32).max(0.0).min(GRID_SIZE as f32 - 0.001);
+        let gy = (y / self.size.1 as f32 * GRID_SIZE as f32).max(0.0).min(GRID_SIZE as f32 - 0.001);
+        let (x0, y0) = (gx as usize, gy as usize);
+        let (fx, fy) = (gx - x0 as f32, gy - y0 as f32);
+
+        let p00 = &self.paths[y0 * n + x0];
+        let p10 = &self.paths[y0 * n + x0 + 1];
+        let p01 = &self.paths[(y0 + 1) * n + x0];
+        let p11 = &self.paths[(y0 + 1) * n + x0 + 1];
+
+        let mut ret = [(0.0f32, 0.0f32); PATH_SAMPLES];
+        for (i, r) in ret.iter_mut().enumerate() {
+            let top    = (p00[i].0 + (p10[i].0 - p00[i].0) * fx, p00[i].1 + (p10[i].1 - p00[i].1) * fx);
+            let bottom = (p01[i].0 + (p11[i].0 - p01[i].0) * fx, p01[i].1 + (p11[i].1 - p01[i].1) * fx);
+            *r = (top.0 + (bottom.0 - top.0) * fy, top.1 + (bottom.1 - top.1) * fy);
+        }
+        ret
+    }
+}
+
+fn sample_bilinear(img: &[Vector4<f32>], width: usize, height: usize, x: f32, y: f32) -> Vector4<f32> {
+    let x = x.max(0.0).min((width - 1) as f32);
+    let y = y.max(0.0).min((height - 1) as f32);
+    let (x0, y0) = (x as usize, y as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let top    = img[y0 * width + x0] * (1.0 - fx) + img[y0 * width + x1] * fx;
+    let bottom = img[y1 * width + x0] * (1.0 - fx) + img[y1 * width + x1] * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+// Richardson-Lucy deconvolution of the input buffer. The result is written to `output`, which gets the same size and layout as the input
+pub fn deblur_frame<T: PixelType>(input: &BufferSource, output: &mut Vec<u8>, params: &KernelParams, kernel: &BlurKernel, iterations: usize) -> bool {
+    let BufferSource::Cpu { buffer } = input else { return false; };
+
+    let (rect_x, rect_y) = (params.source_rect[0].max(0) as usize, params.source_rect[1].max(0) as usize);
+    let (width, height) = (params.source_rect[2].max(0) as usize, params.source_rect[3].max(0) as usize);
+    let stride = params.stride as usize;
+    let bpp = params.bytes_per_pixel as usize;
+    if width < 2 || height < 2 || kernel.size.0 == 0 || kernel.size.1 == 0 || buffer.len() < (rect_y + height - 1) * stride + (rect_x + width) * bpp {
+        return false;
+    }
+
+    // The paths are in the processing size, the buffer can be a scaled plane
+    let scale = (width as f32 / kernel.size.0 as f32, height as f32 / kernel.size.1 as f32);
+    // Richardson-Lucy can't recover from zero pixels, so work on slightly lifted values
+    let bias = params.max_pixel_value / 256.0;
+    let limit = params.pixel_value_limit;
+
+    let mut observed = vec![Vector4::<f32>::zeros(); width * height];
+    observed.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        let row_start = (rect_y + y) * stride + rect_x * bpp;
+        for (x, px) in row.iter_mut().enumerate() {
+            let pos = row_start + x * bpp;
+            let src: &T = bytemuck::from_bytes(&buffer[pos..pos + bpp]);
+            *px = PixelType::to_float(*src).add_scalar(bias);
+        }
+    });
+
+    let mut estimate = observed.clone();
+    let mut ratio = vec![Vector4::<f32>::zeros(); width * height];
+    let path_at = |x: usize, y: usize| kernel.path_at(x as f32 / scale.0, y as f32 / scale.1);
+
+    for _ in 0..iterations {
+        // observed / (estimate ⊗ blur)
+        ratio.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, r) in row.iter_mut().enumerate() {
+                let mut blurred = Vector4::<f32>::zeros();
+                for p in path_at(x, y) {
+                    blurred += sample_bilinear(&estimate, width, height, x as f32 + p.0 * scale.0, y as f32 + p.1 * scale.1);
+                }
+                blurred /= PATH_SAMPLES as f32;
+                *r = observed[y * width + x].component_div(&blurred.map(|v| v.max(bias)));
+            }
+        });
+        // estimate *= ratio ⊗ mirrored blur
+        estimate.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, e) in row.iter_mut().enumerate() {
+                let mut correction = Vector4::<f32>::zeros();
+                for p in path_at(x, y) {
+                    correction += sample_bilinear(&ratio, width, height, x as f32 - p.0 * scale.0, y as f32 - p.1 * scale.1);
+                }
+                correction /= PATH_SAMPLES as f32;
+                *e = e.component_mul(&correction).map(|v| v.max(bias).min(limit + bias));
+            }
+        });
+    }
+
+    // Pixels outside of the source rect are kept as they are
+    output.clear();
+    output.extend_from_slice(buffer);
+    output.par_chunks_mut(stride).skip(rect_y).take(height).enumerate().for_each(|(y, row)| {
+        for x in 0..width {
+            let pos = (rect_x + x) * bpp;
+            let dst: &mut T = bytemuck::from_bytes_mut(&mut row[pos..pos + bpp]);
+            *dst = PixelType::from_float(estimate[y * width + x].add_scalar(-bias));
+        }
+    });
+    true
+}
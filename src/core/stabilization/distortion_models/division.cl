@@ -0,0 +1,16 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+float2 undistort_point(float2 pos, __global KernelParams *params) {
+    float denom = 1.0f + params->k[0] * dot(pos, pos);
+    if (denom <= 0.0f) return (float2)(0.0f, 0.0f);
+
+    return pos / denom;
+}
+
+float2 distort_point(float x, float y, float z, __global KernelParams *params) {
+    float2 pos = (float2)(x, y) / z;
+
+    float scale = 2.0f / (1.0f + sqrt(fmax(1.0f - 4.0f * params->k[0] * dot(pos, pos), 0.0f)));
+
+    return pos * scale;
+}
@@ -0,0 +1,16 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+vec2 undistort_point(vec2 pos) {
+    float denom = 1.0 + params.k1.x * dot(pos, pos);
+    if (denom <= 0.0) return vec2(0.0, 0.0);
+
+    return pos / denom;
+}
+
+vec2 distort_point(float x, float y, float z) {
+    vec2 pos = vec2(x, y) / z;
+
+    float scale = 2.0 / (1.0 + sqrt(max(1.0 - 4.0 * params.k1.x * dot(pos, pos), 0.0)));
+
+    return pos * scale;
+}
@@ -0,0 +1,47 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// One-parameter division model: r_u = r_d / (1 + k1*r_d^2)
+// Fitzgibbon, "Simultaneous linear estimation of multiple view geometry and lens distortion": https://doi.org/10.1109/CVPR.2001.990465
+
+use crate::stabilization::KernelParams;
+
+#[derive(Default, Clone)]
+pub struct Division { }
+
+impl Division {
+    pub fn undistort_point(&self, point: (f32, f32), params: &KernelParams) -> Option<(f32, f32)> {
+        let rd2 = point.0 * point.0 + point.1 * point.1;
+        let denom = 1.0 + params.k[0] * rd2;
+        if denom <= 0.0 {
+            // Beyond the horizon of the lens
+            return None;
+        }
+
+        Some((
+            point.0 / denom,
+            point.1 / denom
+        ))
+    }
+
+    pub fn distort_point(&self, x: f32, y: f32, z: f32, params: &KernelParams) -> (f32, f32) {
+        let x = x / z;
+        let y = y / z;
+        let ru2 = x.powi(2) + y.powi(2);
+
+        // Solve k1*ru*rd^2 - rd + ru = 0 for rd. The numerically stable form of the smaller root is rd = 2*ru / (1 + sqrt(1 - 4*k1*ru^2)).
+        // For k1 > 0 there's no solution past the maximum radius, so clamp the discriminant
+        let scale = 2.0 / (1.0 + (1.0 - 4.0 * params.k[0] * ru2).max(0.0).sqrt());
+
+        (
+            x * scale,
+            y * scale
+        )
+    }
+    pub fn adjust_lens_profile(&self, _profile: &mut crate::LensProfile) { }
+
+    pub fn id() -> &'static str { "division" }
+    pub fn name() -> &'static str { "Division" }
+
+    pub fn opencl_functions(&self) -> &'static str { include_str!("division.cl") }
+    pub fn wgsl_functions(&self)   -> &'static str { include_str!("division.wgsl") }
+}
@@ -0,0 +1,16 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+fn undistort_point(pos: vec2<f32>) -> vec2<f32> {
+    let denom = 1.0 + params.k1.x * dot(pos, pos);
+    if (denom <= 0.0) { return vec2<f32>(0.0, 0.0); }
+
+    return pos / denom;
+}
+
+fn distort_point(x: f32, y: f32, z: f32) -> vec2<f32> {
+    let pos = vec2<f32>(x, y) / z;
+
+    let scale = 2.0 / (1.0 + sqrt(max(1.0 - 4.0 * params.k1.x * dot(pos, pos), 0.0)));
+
+    return pos * scale;
+}
@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+float kb_poly(float theta2, __global KernelParams *params) {
+    float theta4 = theta2*theta2,
+          theta6 = theta4*theta2,
+          theta8 = theta4*theta4;
+    return 1.0f + params->k[0] * theta2         + params->k[1] * theta4         + params->k[2] * theta6         + params->k[3] * theta8
+                + params->k[4] * theta8*theta2  + params->k[5] * theta8*theta4  + params->k[6] * theta8*theta6  + params->k[7] * theta8*theta8;
+}
+float kb_dpoly(float theta2, __global KernelParams *params) {
+    float theta4 = theta2*theta2,
+          theta6 = theta4*theta2,
+          theta8 = theta4*theta4;
+    return 1.0f + 3.0f  * params->k[0] * theta2        + 5.0f  * params->k[1] * theta4        + 7.0f  * params->k[2] * theta6        + 9.0f  * params->k[3] * theta8
+                + 11.0f * params->k[4] * theta8*theta2 + 13.0f * params->k[5] * theta8*theta4 + 15.0f * params->k[6] * theta8*theta6 + 17.0f * params->k[7] * theta8*theta8;
+}
+bool kb_is_zero(__global KernelParams *params) {
+    return params->k[0] == 0.0f && params->k[1] == 0.0f && params->k[2] == 0.0f && params->k[3] == 0.0f &&
+           params->k[4] == 0.0f && params->k[5] == 0.0f && params->k[6] == 0.0f && params->k[7] == 0.0f;
+}
+
+float2 undistort_point(float2 pos, __global KernelParams *params) {
+    if (kb_is_zero(params)) return pos;
+
+    float theta_d = fmin(length(pos), 3.141592653589793f);
+    if (theta_d < 1e-6f) return pos;
+
+    bool converged = false;
+    float theta = theta_d;
+    for (int i = 0; i < 10; ++i) {
+        float theta2 = theta*theta;
+        // new_theta = theta - theta_fix, theta_fix = f0(theta) / f0'(theta)
+        float theta_fix = clamp((theta * kb_poly(theta2, params) - theta_d) / kb_dpoly(theta2, params), -0.9f, 0.9f);
+
+        theta -= theta_fix;
+        if (fabs(theta_fix) < 1e-6f) {
+            converged = true;
+            break;
+        }
+    }
+
+    if (converged && theta > 0.0f && theta < 1.5707963267948966f) {
+        return pos * (tan(theta) / theta_d);
+    }
+    return (float2)(0.0f, 0.0f);
+}
+
+float2 distort_point(float x, float y, float z, __global KernelParams *params) {
+    if (kb_is_zero(params)) return (float2)(x, y) / z;
+
+    float r = length((float2)(x, y));
+    if (r == 0.0f) return (float2)(0.0f, 0.0f);
+
+    float theta = atan2(r, z);
+    float theta_d = theta * kb_poly(theta*theta, params);
+
+    return (float2)(x, y) * (theta_d / r);
+}
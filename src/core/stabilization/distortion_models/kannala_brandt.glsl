@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+float kb_poly(float theta2) {
+    float theta4 = theta2*theta2;
+    float theta8 = theta4*theta4;
+    vec4 lo = vec4(theta2, theta4, theta4*theta2, theta8);
+    return 1.0 + dot(params.k1, lo) + dot(params.k2, lo * theta8);
+}
+float kb_dpoly(float theta2) {
+    float theta4 = theta2*theta2;
+    float theta8 = theta4*theta4;
+    vec4 lo = vec4(theta2, theta4, theta4*theta2, theta8);
+    return 1.0 + dot(params.k1 * vec4(3.0, 5.0, 7.0, 9.0), lo) + dot(params.k2 * vec4(11.0, 13.0, 15.0, 17.0), lo * theta8);
+}
+
+vec2 undistort_point(vec2 pos) {
+    if (params.k1 == vec4(0.0, 0.0, 0.0, 0.0) && params.k2 == vec4(0.0, 0.0, 0.0, 0.0)) return pos;
+
+    float theta_d = min(length(pos), 3.141592653589793);
+    if (theta_d < 1e-6) return pos;
+
+    bool converged = false;
+    float theta = theta_d;
+    for (int i = 0; i < 10; ++i) {
+        float theta2 = theta*theta;
+        // new_theta = theta - theta_fix, theta_fix = f0(theta) / f0'(theta)
+        float theta_fix = clamp((theta * kb_poly(theta2) - theta_d) / kb_dpoly(theta2), -0.9, 0.9);
+
+        theta -= theta_fix;
+        if (abs(theta_fix) < 1e-6) {
+            converged = true;
+            break;
+        }
+    }
+
+    if (converged && theta > 0.0 && theta < 1.5707963267948966) {
+        return pos * (tan(theta) / theta_d);
+    }
+    return vec2(0.0, 0.0);
+}
+
+vec2 distort_point(float x, float y, float z) {
+    if (params.k1 == vec4(0.0, 0.0, 0.0, 0.0) && params.k2 == vec4(0.0, 0.0, 0.0, 0.0)) return vec2(x, y) / z;
+
+    float r = length(vec2(x, y));
+    if (r == 0.0) return vec2(0.0, 0.0);
+
+    float theta = atan(r, z);
+    float theta_d = theta * kb_poly(theta*theta);
+
+    return vec2(x, y) * (theta_d / r);
+}
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Kannala-Brandt generic camera model with 8 radial coefficients: theta_d = theta * (1 + k1*theta^2 + k2*theta^4 + ... + k8*theta^16)
+// With k5..k8 = 0 it's equivalent to OpenCV Fisheye, the higher orders are needed for circular fisheye lenses with more than 180° FOV
+// https://doi.org/10.1109/TPAMI.2006.153
+
+use crate::stabilization::KernelParams;
+
+#[derive(Default, Clone)]
+pub struct KannalaBrandt { }
+
+impl KannalaBrandt {
+    pub fn undistort_point(&self, point: (f32, f32), params: &KernelParams) -> Option<(f32, f32)> {
+        if params.k[..8].iter().all(|x| *x == 0.0) { return Some(point); }
+
+        const EPS: f32 = 1e-6;
+
+        let theta_d = (point.0 * point.0 + point.1 * point.1).sqrt().min(std::f32::consts::PI);
+        if theta_d < EPS { return Some(point); }
+
+        let mut converged = false;
+        let mut theta = theta_d;
+
+        // compensate distortion iteratively
+        for _ in 0..10 {
+            let theta2 = theta*theta;
+            let theta4 = theta2*theta2;
+            let theta6 = theta4*theta2;
+            let theta8 = theta4*theta4;
+            let theta10 = theta8*theta2;
+            let theta12 = theta8*theta4;
+            let theta14 = theta8*theta6;
+            let theta16 = theta8*theta8;
+            let poly = 1.0 + params.k[0] * theta2  + params.k[1] * theta4  + params.k[2] * theta6  + params.k[3] * theta8
+                           + params.k[4] * theta10 + params.k[5] * theta12 + params.k[6] * theta14 + params.k[7] * theta16;
+            let dpoly = 1.0 + 3.0  * params.k[0] * theta2  + 5.0  * params.k[1] * theta4  + 7.0  * params.k[2] * theta6  + 9.0  * params.k[3] * theta8
+                            + 11.0 * params.k[4] * theta10 + 13.0 * params.k[5] * theta12 + 15.0 * params.k[6] * theta14 + 17.0 * params.k[7] * theta16;
+            // new_theta = theta - theta_fix, theta_fix = f0(theta) / f0'(theta)
+            let theta_fix = ((theta * poly - theta_d) / dpoly).max(-0.9).min(0.9);
+
+            theta = theta - theta_fix;
+            if theta_fix.abs() < EPS {
+                converged = true;
+                break;
+            }
+        }
+
+        // Rays at or beyond 90° can't be represented on the undistorted plane
+        if converged && theta > 0.0 && theta < std::f32::consts::FRAC_PI_2 {
+            let scale = theta.tan() / theta_d;
+            return Some((point.0 * scale, point.1 * scale));
+        }
+        None
+    }
+
+    pub fn distort_point(&self, x: f32, y: f32, z: f32, params: &KernelParams) -> (f32, f32) {
+        if params.k[..8].iter().all(|x| *x == 0.0) { return (x / z, y / z); }
+
+        let r = (x.powi(2) + y.powi(2)).sqrt();
+        if r == 0.0 { return (0.0, 0.0); }
+
+        // Angle from the optical axis, valid also for points behind the camera plane
+        let theta = r.atan2(z);
+        let theta2 = theta*theta;
+        let theta4 = theta2*theta2;
+        let theta6 = theta4*theta2;
+        let theta8 = theta4*theta4;
+
+        let theta_d = theta * (1.0 + params.k[0] * theta2         + params.k[1] * theta4         + params.k[2] * theta6         + params.k[3] * theta8
+                                   + params.k[4] * theta8*theta2  + params.k[5] * theta8*theta4  + params.k[6] * theta8*theta6  + params.k[7] * theta8*theta8);
+
+        let scale = theta_d / r;
+
+        (
+            x * scale,
+            y * scale
+        )
+    }
+    pub fn adjust_lens_profile(&self, _profile: &mut crate::LensProfile) { }
+
+    pub fn id() -> &'static str { "kannala_brandt" }
+    pub fn name() -> &'static str { "Kannala-Brandt" }
+
+    pub fn opencl_functions(&self) -> &'static str { include_str!("kannala_brandt.cl") }
+    pub fn wgsl_functions(&self)   -> &'static str { include_str!("kannala_brandt.wgsl") }
+}
@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+fn kb_poly(theta2: f32) -> f32 {
+    let theta4 = theta2*theta2;
+    let theta8 = theta4*theta4;
+    let lo = vec4<f32>(theta2, theta4, theta4*theta2, theta8);
+    return 1.0 + dot(params.k1, lo) + dot(params.k2, lo * theta8);
+}
+fn kb_dpoly(theta2: f32) -> f32 {
+    let theta4 = theta2*theta2;
+    let theta8 = theta4*theta4;
+    let lo = vec4<f32>(theta2, theta4, theta4*theta2, theta8);
+    return 1.0 + dot(params.k1 * vec4<f32>(3.0, 5.0, 7.0, 9.0), lo) + dot(params.k2 * vec4<f32>(11.0, 13.0, 15.0, 17.0), lo * theta8);
+}
+
+fn undistort_point(pos: vec2<f32>) -> vec2<f32> {
+    if (all(params.k1 == vec4<f32>(0.0)) && all(params.k2 == vec4<f32>(0.0))) { return pos; }
+
+    let theta_d = min(length(pos), 3.141592653589793);
+    if (theta_d < 1e-6) { return pos; }
+
+    var converged = false;
+    var theta = theta_d;
+    for (var i: i32 = 0; i < 10; i = i + 1) {
+        let theta2 = theta*theta;
+        // new_theta = theta - theta_fix, theta_fix = f0(theta) / f0'(theta)
+        let theta_fix = clamp((theta * kb_poly(theta2) - theta_d) / kb_dpoly(theta2), -0.9, 0.9);
+
+        theta -= theta_fix;
+        if (abs(theta_fix) < 1e-6) {
+            converged = true;
+            break;
+        }
+    }
+
+    if (converged && theta > 0.0 && theta < 1.5707963267948966) {
+        return pos * (tan(theta) / theta_d);
+    }
+    return vec2<f32>(0.0, 0.0);
+}
+
+fn distort_point(x: f32, y: f32, z: f32) -> vec2<f32> {
+    if (all(params.k1 == vec4<f32>(0.0)) && all(params.k2 == vec4<f32>(0.0))) { return vec2<f32>(x, y) / z; }
+
+    let r = length(vec2<f32>(x, y));
+    if (r == 0.0) { return vec2<f32>(0.0, 0.0); }
+
+    let theta = atan2(r, z);
+    let theta_d = theta * kb_poly(theta*theta);
+
+    return vec2<f32>(x, y) * (theta_d / r);
+}
@@ -7,10 +7,14 @@ mod poly3;
 mod poly5;
 mod ptlens;
 mod insta360;
+mod kannala_brandt;
+mod division;
+mod scaramuzza;
 
 mod gopro_superview;
 mod gopro_hyperview;
 mod digital_stretch;
+mod nonlinear_stretch;
 
 use super::KernelParams;
 
@@ -67,9 +71,28 @@ impl_models! {
     Poly5          => poly5::Poly5,
     PtLens         => ptlens::PtLens,
     Insta360       => insta360::Insta360,
+    KannalaBrandt  => kannala_brandt::KannalaBrandt,
+    Division       => division::Division,
+    Scaramuzza     => scaramuzza::Scaramuzza,
 
     // Digital lenses (ie. post-processing)
     GoProSuperview => gopro_superview::GoProSuperview,
     GoProHyperview => gopro_hyperview::GoProHyperview,
     DigitalStretch => digital_stretch::DigitalStretch,
+    NonlinearStretch => nonlinear_stretch::NonlinearStretch,
+}
+
+impl DistortionModel {
+    /// Constant part of the stretch applied by a digital lens, ie. processed frame size / real image size
+    pub fn digital_stretch_factors(&self, params: Option<&Vec<f64>>) -> (f64, f64) {
+        match &self.inner {
+            DistortionModels::NonlinearStretch(_) => nonlinear_stretch::stretch_factors(params),
+            _ => (1.0, 1.0)
+        }
+    }
+
+    /// Whether `distort_point` is valid also for points at or behind the camera plane (field of view of 180° and more)
+    pub fn supports_wide_fov(&self) -> bool {
+        matches!(self.inner, DistortionModels::KannalaBrandt(_) | DistortionModels::Scaramuzza(_))
+    }
 }
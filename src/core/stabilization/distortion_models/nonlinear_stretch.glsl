@@ -0,0 +1,39 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+vec2 nonlinear_stretch_factors() {
+    return vec2(params.digital_lens_params.x > 0.01? params.digital_lens_params.x : 1.0,
+                params.digital_lens_params.y > 0.01? params.digital_lens_params.y : 1.0);
+}
+
+vec2 digital_undistort_point(vec2 uv) {
+    vec2 size = vec2(params.width, params.height);
+    vec2 s = nonlinear_stretch_factors();
+    float a = params.digital_lens_params.z;
+    float b = params.digital_lens_params.w;
+    float norm = max(1.0 + a + b, 1e-6);
+
+    uv = (uv / size) * 2.0 - 1.0;
+    float r = uv.x;
+    for (int i = 0; i < 8; ++i) {
+        float r2 = r * r;
+        float poly  = r * (1.0 + a * r2 + b * r2 * r2) / norm;
+        float dpoly = max((1.0 + 3.0 * a * r2 + 5.0 * b * r2 * r2) / norm, 1e-3);
+        r -= clamp((poly - uv.x) / dpoly, -0.5, 0.5);
+    }
+    uv.x = r;
+
+    return ((uv / s) * 0.5 + 0.5) * size;
+}
+
+vec2 digital_distort_point(vec2 uv) {
+    vec2 size = vec2(params.width, params.height);
+    vec2 s = nonlinear_stretch_factors();
+    float a = params.digital_lens_params.z;
+    float b = params.digital_lens_params.w;
+
+    uv = ((uv / size) * 2.0 - 1.0) * s;
+    float r2 = uv.x * uv.x;
+    uv.x = uv.x * (1.0 + a * r2 + b * r2 * r2) / max(1.0 + a + b, 1e-6);
+
+    return (uv * 0.5 + 0.5) * size;
+}
@@ -0,0 +1,161 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Generic non-linear stretch, covers anamorphic desqueeze (1.33x, 1.5x, 1.8x) and the "wide stretch" modes of DJI/Sony cameras
+// `digital_lens_params`: [horizontal stretch, vertical stretch, a, b]
+// The horizontal axis follows the curve g(r) = r * (1 + a*r^2 + b*r^4) / (1 + a + b), where r is -1..1 over the real image,
+// so the edges of the real image always land on the edges of the processed frame. With a = b = 0 it's a constant stretch
+// Both directions are centered on the input frame, so they stay the inverse of each other also when the output size is different
+
+use crate::{ stabilization::KernelParams, lens_profile::{ LensProfile, Dimensions } };
+
+#[derive(Default, Clone)]
+pub struct NonlinearStretch { }
+
+impl NonlinearStretch {
+    /// `uv` range: (0,0)...(width, height)
+    /// From processed to real
+    pub fn undistort_point(&self, uv: (f32, f32), params: &KernelParams) -> Option<(f32, f32)> {
+        let size = (params.width as f32, params.height as f32);
+        let (sx, sy) = Self::kernel_stretch(params);
+        let (a, b) = (params.digital_lens_params[2], params.digital_lens_params[3]);
+        let norm = (1.0 + a + b).max(1e-6);
+
+        let p = (uv.0 / size.0) * 2.0 - 1.0;
+        let mut r = p;
+        // Invert the curve iteratively
+        for _ in 0..8 {
+            let r2 = r * r;
+            let poly  = r * (1.0 + a * r2 + b * r2 * r2) / norm;
+            let dpoly = ((1.0 + 3.0 * a * r2 + 5.0 * b * r2 * r2) / norm).max(1e-3);
+            r -= ((poly - p) / dpoly).max(-0.5).min(0.5);
+        }
+
+        Some((((r / sx) * 0.5 + 0.5) * size.0,
+              ((((uv.1 / size.1) * 2.0 - 1.0) / sy) * 0.5 + 0.5) * size.1))
+    }
+
+    /// `uv` range: (0,0)...(width, height)
+    /// From real to processed
+    pub fn distort_point(&self, x: f32, y: f32, _z: f32, params: &KernelParams) -> (f32, f32) {
+        let size = (params.width as f32, params.height as f32);
+        let (sx, sy) = Self::kernel_stretch(params);
+        let (a, b) = (params.digital_lens_params[2], params.digital_lens_params[3]);
+
+        let r = ((x / size.0) * 2.0 - 1.0) * sx;
+        let r2 = r * r;
+        let p = r * (1.0 + a * r2 + b * r2 * r2) / (1.0 + a + b).max(1e-6);
+
+        ((p * 0.5 + 0.5) * size.0,
+         ((((y / size.1) * 2.0 - 1.0) * sy) * 0.5 + 0.5) * size.1)
+    }
+    pub fn adjust_lens_profile(&self, profile: &mut LensProfile) {
+        stretch_lens_profile(profile);
+    }
+
+    fn kernel_stretch(params: &KernelParams) -> (f32, f32) {
+        (if params.digital_lens_params[0] > 0.01 { params.digital_lens_params[0] } else { 1.0 },
+         if params.digital_lens_params[1] > 0.01 { params.digital_lens_params[1] } else { 1.0 })
+    }
+
+    pub fn id()   -> &'static str { "nonlinear_stretch" }
+    pub fn name() -> &'static str { "Non-linear stretch" }
+
+    pub fn opencl_functions(&self) -> &'static str {
+        r#"
+        float2 nonlinear_stretch_factors(__global KernelParams *params) {
+            return (float2)(params->digital_lens_params.x > 0.01f? params->digital_lens_params.x : 1.0f,
+                            params->digital_lens_params.y > 0.01f? params->digital_lens_params.y : 1.0f);
+        }
+        float2 digital_undistort_point(float2 uv, __global KernelParams *params) {
+            float2 size = (float2)(params->width, params->height);
+            float2 s = nonlinear_stretch_factors(params);
+            float a = params->digital_lens_params.z;
+            float b = params->digital_lens_params.w;
+            float norm = max(1.0f + a + b, 1e-6f);
+
+            uv = (uv / size) * 2.0f - 1.0f;
+            float r = uv.x;
+            for (int i = 0; i < 8; ++i) {
+                float r2 = r * r;
+                float poly  = r * (1.0f + a * r2 + b * r2 * r2) / norm;
+                float dpoly = max((1.0f + 3.0f * a * r2 + 5.0f * b * r2 * r2) / norm, 1e-3f);
+                r -= clamp((poly - uv.x) / dpoly, -0.5f, 0.5f);
+            }
+            uv.x = r;
+
+            return ((uv / s) * 0.5f + 0.5f) * size;
+        }
+        float2 digital_distort_point(float2 uv, __global KernelParams *params) {
+            float2 size = (float2)(params->width, params->height);
+            float2 s = nonlinear_stretch_factors(params);
+            float a = params->digital_lens_params.z;
+            float b = params->digital_lens_params.w;
+
+            uv = ((uv / size) * 2.0f - 1.0f) * s;
+            float r2 = uv.x * uv.x;
+            uv.x = uv.x * (1.0f + a * r2 + b * r2 * r2) / max(1.0f + a + b, 1e-6f);
+
+            return (uv * 0.5f + 0.5f) * size;
+        }"#
+    }
+    pub fn wgsl_functions(&self) -> &'static str {
+        r#"
+        fn nonlinear_stretch_factors() -> vec2<f32> {
+            return vec2<f32>(select(1.0, params.digital_lens_params.x, params.digital_lens_params.x > 0.01),
+                             select(1.0, params.digital_lens_params.y, params.digital_lens_params.y > 0.01));
+        }
+        fn digital_undistort_point(_uv: vec2<f32>) -> vec2<f32> {
+            let size = vec2<f32>(f32(params.width), f32(params.height));
+            let s = nonlinear_stretch_factors();
+            let a = params.digital_lens_params.z;
+            let b = params.digital_lens_params.w;
+            let norm = max(1.0 + a + b, 1e-6);
+
+            var uv = (_uv / size) * 2.0 - 1.0;
+            var r = uv.x;
+            for (var i: i32 = 0; i < 8; i = i + 1) {
+                let r2 = r * r;
+                let poly  = r * (1.0 + a * r2 + b * r2 * r2) / norm;
+                let dpoly = max((1.0 + 3.0 * a * r2 + 5.0 * b * r2 * r2) / norm, 1e-3);
+                r = r - clamp((poly - uv.x) / dpoly, -0.5, 0.5);
+            }
+            uv.x = r;
+
+            return ((uv / s) * 0.5 + 0.5) * size;
+        }
+        fn digital_distort_point(_uv: vec2<f32>) -> vec2<f32> {
+            let size = vec2<f32>(f32(params.width), f32(params.height));
+            let s = nonlinear_stretch_factors();
+            let a = params.digital_lens_params.z;
+            let b = params.digital_lens_params.w;
+
+            var uv = ((_uv / size) * 2.0 - 1.0) * s;
+            let r2 = uv.x * uv.x;
+            uv.x = uv.x * (1.0 + a * r2 + b * r2 * r2) / max(1.0 + a + b, 1e-6);
+
+            return (uv * 0.5 + 0.5) * size;
+        }"#
+    }
+}
+
+/// Constant part of the stretch, ie. processed frame size / real image size
+pub fn stretch_factors(params: Option<&Vec<f64>>) -> (f64, f64) {
+    let get = |i: usize| params.and_then(|x| x.get(i).copied()).filter(|x| *x > 0.01).unwrap_or(1.0);
+    (get(0), get(1))
+}
+
+/// Stretch the calibration dimensions of a profile calibrated on the real image to the size of the processed frame.
+/// `orig_dimension` keeps the real image size, which makes it safe to call multiple times on the same profile
+pub fn stretch_lens_profile(profile: &mut LensProfile) {
+    let (sx, sy) = stretch_factors(profile.digital_lens_params.as_ref());
+    let (cw, ch) = (profile.calib_dimension.w as f64, profile.calib_dimension.h as f64);
+    let (ow, oh) = (profile.orig_dimension.w as f64, profile.orig_dimension.h as f64);
+    if cw <= 0.0 || ch <= 0.0 { return; }
+
+    let already_stretched = ow > 0.0 && oh > 0.0 && (cw - ow * sx).abs() <= 2.0 && (ch - oh * sy).abs() <= 2.0;
+    let is_real_image = ow <= 0.0 || oh <= 0.0 || ((cw / ch) - (ow / oh)).abs() < 0.01;
+    if !already_stretched && is_real_image {
+        profile.orig_dimension  = Dimensions { w: cw as usize, h: ch as usize };
+        profile.calib_dimension = Dimensions { w: (cw * sx).round() as usize, h: (ch * sy).round() as usize };
+    }
+}
@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+float scaramuzza_poly(float rho, __global KernelParams *params) {
+    return 1.0f + rho * rho * (params->k[0] + rho * (params->k[1] + rho * (params->k[2] + rho * params->k[3])));
+}
+float scaramuzza_dpoly(float rho, __global KernelParams *params) {
+    return rho * (2.0f * params->k[0] + rho * (3.0f * params->k[1] + rho * (4.0f * params->k[2] + rho * 5.0f * params->k[3])));
+}
+
+float2 undistort_point(float2 pos, __global KernelParams *params) {
+    float z = scaramuzza_poly(length(pos), params);
+    if (z <= 1e-6f) return (float2)(0.0f, 0.0f);
+
+    return pos / z;
+}
+
+float2 distort_point(float x, float y, float z, __global KernelParams *params) {
+    float r = length((float2)(x, y));
+    if (r == 0.0f) return (float2)(0.0f, 0.0f);
+
+    float len = length((float2)(r, z));
+    float s = r / len;
+    float c = z / len;
+    float rho = atan2(s, c);
+    for (int i = 0; i < 10; ++i) {
+        float d = s * scaramuzza_dpoly(rho, params) - c;
+        if (d == 0.0f) break;
+        float rho_fix = (s * scaramuzza_poly(rho, params) - c * rho) / d;
+        rho -= rho_fix;
+        if (fabs(rho_fix) < 1e-6f) break;
+    }
+
+    return (float2)(x, y) * (fmax(rho, 0.0f) / r);
+}
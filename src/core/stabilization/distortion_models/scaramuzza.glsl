@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+float scaramuzza_poly(float rho) {
+    return 1.0 + rho * rho * (params.k1.x + rho * (params.k1.y + rho * (params.k1.z + rho * params.k1.w)));
+}
+float scaramuzza_dpoly(float rho) {
+    return rho * (2.0 * params.k1.x + rho * (3.0 * params.k1.y + rho * (4.0 * params.k1.z + rho * 5.0 * params.k1.w)));
+}
+
+vec2 undistort_point(vec2 pos) {
+    float z = scaramuzza_poly(length(pos));
+    if (z <= 1e-6) return vec2(0.0, 0.0);
+
+    return pos / z;
+}
+
+vec2 distort_point(float x, float y, float z) {
+    float r = length(vec2(x, y));
+    if (r == 0.0) return vec2(0.0, 0.0);
+
+    float len = length(vec2(r, z));
+    float s = r / len;
+    float c = z / len;
+    float rho = atan(s, c);
+    for (int i = 0; i < 10; ++i) {
+        float d = s * scaramuzza_dpoly(rho) - c;
+        if (d == 0.0) break;
+        float rho_fix = (s * scaramuzza_poly(rho) - c * rho) / d;
+        rho -= rho_fix;
+        if (abs(rho_fix) < 1e-6) break;
+    }
+
+    return vec2(x, y) * (max(rho, 0.0) / r);
+}
@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Scaramuzza omnidirectional camera model (OCamCalib): the ray of a distorted point at radius rho is (x, y, f(rho)),
+// with f(rho) = 1 + k1*rho^2 + k2*rho^3 + k3*rho^4 + k4*rho^5, in coordinates normalized by the focal length.
+// OCamCalib's pixel polynomial `a0 + a2*rho^2 + ... + a5*rho^5` converts to fx = fy = -a0 and k[i] = -a[i+2] * (-a0)^(i+1)
+// https://sites.google.com/site/scarabotix/ocamcalib-omnidirectional-camera-calibration-toolbox-for-matlab
+
+use crate::stabilization::KernelParams;
+
+#[derive(Default, Clone)]
+pub struct Scaramuzza { }
+
+const NEWTON_EPS: f32 = 1e-6;
+
+impl Scaramuzza {
+    fn poly(rho: f32, params: &KernelParams) -> f32 {
+        let rho2 = rho * rho;
+        1.0 + rho2 * (params.k[0] + rho * (params.k[1] + rho * (params.k[2] + rho * params.k[3])))
+    }
+    fn dpoly(rho: f32, params: &KernelParams) -> f32 {
+        rho * (2.0 * params.k[0] + rho * (3.0 * params.k[1] + rho * (4.0 * params.k[2] + rho * 5.0 * params.k[3])))
+    }
+
+    pub fn undistort_point(&self, point: (f32, f32), params: &KernelParams) -> Option<(f32, f32)> {
+        let rho = (point.0 * point.0 + point.1 * point.1).sqrt();
+        let z = Self::poly(rho, params);
+        if z <= NEWTON_EPS {
+            // The ray is at or beyond 90° from the optical axis
+            return None;
+        }
+
+        Some((
+            point.0 / z,
+            point.1 / z
+        ))
+    }
+
+    pub fn distort_point(&self, x: f32, y: f32, z: f32, params: &KernelParams) -> (f32, f32) {
+        let r = (x.powi(2) + y.powi(2)).sqrt();
+        if r == 0.0 { return (0.0, 0.0); }
+
+        // Find rho where (rho, f(rho)) is parallel to (r, z): r*f(rho) - z*rho = 0
+        let len = (r * r + z * z).sqrt();
+        let (s, c) = (r / len, z / len);
+        let mut rho = s.atan2(c);
+        for _ in 0..10 {
+            let d = s * Self::dpoly(rho, params) - c;
+            if d == 0.0 { break; }
+            let rho_fix = (s * Self::poly(rho, params) - c * rho) / d;
+            rho -= rho_fix;
+            if rho_fix.abs() < NEWTON_EPS {
+                break;
+            }
+        }
+        let scale = rho.max(0.0) / r;
+
+        (
+            x * scale,
+            y * scale
+        )
+    }
+    pub fn adjust_lens_profile(&self, _profile: &mut crate::LensProfile) { }
+
+    pub fn id() -> &'static str { "scaramuzza" }
+    pub fn name() -> &'static str { "Scaramuzza (OCamCalib)" }
+
+    pub fn opencl_functions(&self) -> &'static str { include_str!("scaramuzza.cl") }
+    pub fn wgsl_functions(&self)   -> &'static str { include_str!("scaramuzza.wgsl") }
+}
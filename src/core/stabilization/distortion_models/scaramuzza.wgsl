@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+fn scaramuzza_poly(rho: f32) -> f32 {
+    return 1.0 + rho * rho * (params.k1.x + rho * (params.k1.y + rho * (params.k1.z + rho * params.k1.w)));
+}
+fn scaramuzza_dpoly(rho: f32) -> f32 {
+    return rho * (2.0 * params.k1.x + rho * (3.0 * params.k1.y + rho * (4.0 * params.k1.z + rho * 5.0 * params.k1.w)));
+}
+
+fn undistort_point(pos: vec2<f32>) -> vec2<f32> {
+    let z = scaramuzza_poly(length(pos));
+    if (z <= 1e-6) { return vec2<f32>(0.0, 0.0); }
+
+    return pos / z;
+}
+
+fn distort_point(x: f32, y: f32, z: f32) -> vec2<f32> {
+    let r = length(vec2<f32>(x, y));
+    if (r == 0.0) { return vec2<f32>(0.0, 0.0); }
+
+    let len = length(vec2<f32>(r, z));
+    let s = r / len;
+    let c = z / len;
+    var rho = atan2(s, c);
+    for (var i: i32 = 0; i < 10; i = i + 1) {
+        let d = s * scaramuzza_dpoly(rho) - c;
+        if (d == 0.0) { break; }
+        let rho_fix = (s * scaramuzza_poly(rho) - c * rho) / d;
+        rho -= rho_fix;
+        if (abs(rho_fix) < 1e-6) { break; }
+    }
+
+    return vec2<f32>(x, y) * (max(rho, 0.0) / r);
+}
@@ -3,8 +3,10 @@
 
 use nalgebra::Matrix3;
 use super::{ ComputeParams, KernelParams };
+use crate::stabilization_params::{ BackgroundMode, OutputProjection };
 use rayon::iter::{ ParallelIterator, IntoParallelIterator };
 use crate::keyframes::KeyframeType;
+use crate::lens_profile::LensProfile;
 
 #[derive(Default, Clone)]
 pub struct FrameTransform {
@@ -13,8 +15,13 @@ pub struct FrameTransform {
     pub fov: f64,
     pub minimal_fov: f64,
     pub focal_length: Option<f64>,
+    pub blur_kernel: Option<super::deblur::BlurKernel>,
+    pub neighbour_matrices: Vec<(i64, Vec<[f32; 12]>)>, // Timestamp of a neighbouring frame and the matrices reprojecting this frame's output to it, nearest first
 }
 
+// Number of previous and next frames used to fill the borders in `BackgroundMode::TemporalFill`, in each direction
+pub const TEMPORAL_FILL_FRAMES: usize = 4;
+
 impl FrameTransform {
     fn get_frame_readout_time(params: &ComputeParams, can_invert: bool) -> f64 {
         let mut frame_readout_time = params.frame_readout_time;
@@ -24,6 +31,15 @@ impl FrameTransform {
         frame_readout_time
     }
     fn get_new_k(params: &ComputeParams, camera_matrix: &Matrix3<f64>, fov: f64) -> Matrix3<f64> {
+        if params.spherical {
+            // Equirectangular output covering the full sphere, independent of the lens and without any zoom
+            let out_dim = (params.output_width as f64, params.output_height as f64);
+            return Matrix3::new(
+                out_dim.0 / (2.0 * std::f64::consts::PI), 0.0, out_dim.0 / 2.0,
+                0.0, out_dim.1 / std::f64::consts::PI, out_dim.1 / 2.0,
+                0.0, 0.0, 1.0
+            );
+        }
         let horizontal_ratio = if params.lens.input_horizontal_stretch > 0.01 { params.lens.input_horizontal_stretch } else { 1.0 };
 
         let img_dim_ratio = Self::get_ratio(params) / horizontal_ratio;
@@ -38,11 +54,17 @@ impl FrameTransform {
         new_k[(1, 2)] = /*(params.video_height as f64 / 2.0 - new_k[(1, 2)]) * img_dim_ratio / fov + */out_dim.1 / 2.0;
         new_k
     }
+    fn without_focus_breathing(camera_matrix: &Matrix3<f64>, scale: f64) -> Matrix3<f64> {
+        let mut k = *camera_matrix;
+        k[(0, 0)] /= scale;
+        k[(1, 1)] /= scale;
+        k
+    }
     pub fn get_ratio(params: &ComputeParams) -> f64 {
         params.width as f64 / params.video_width.max(1) as f64
     }
     fn get_fov(params: &ComputeParams, frame: usize, use_fovs: bool, timestamp_ms: f64, for_ui: bool) -> f64 {
-        let mut fov_scale = params.keyframes.value_at_video_timestamp(&KeyframeType::Fov, timestamp_ms).unwrap_or(params.fov_scale);
+        let mut fov_scale = params.fov_scale_at_timestamp(timestamp_ms);
         fov_scale += if params.fov_overview && use_fovs && !for_ui { 1.0 } else { 0.0 };
         let mut fov = if use_fovs { params.fovs.get(frame).unwrap_or(&1.0) * fov_scale } else { 1.0 }.max(0.001);
         if !for_ui {
@@ -52,7 +74,24 @@ impl FrameTransform {
         fov
     }
 
-    pub fn get_lens_data_at_timestamp(params: &ComputeParams, timestamp_ms: f64) -> (Matrix3<f64>, [f64; 12], f64, f64, f64, Option<f64>) {
+    // Focal length scale from the focus breathing curve of the lens at the focus distance or zoom position of this frame.
+    // `lens` is the profile interpolated for this frame, because every focal length variant can have its own curve
+    pub fn get_focus_breathing_scale(params: &ComputeParams, lens: &LensProfile, timestamp_ms: f64) -> f64 {
+        if let Some(fb) = &lens.focus_breathing {
+            use crate::util::MapClosest;
+            let gyro = params.gyro.read();
+            let values = if fb.input == "focus_distance" { &gyro.file_metadata.focus_distances } else { &gyro.file_metadata.lens_positions };
+            if let Some(val) = values.get_closest(&((timestamp_ms * 1000.0).round() as i64), 100000) { // closest within 100ms
+                let scale = fb.scale_at(*val);
+                if scale > 0.0 && scale.is_finite() {
+                    return scale;
+                }
+            }
+        }
+        1.0
+    }
+
+    pub fn get_lens_data_at_timestamp(params: &ComputeParams, timestamp_ms: f64) -> (Matrix3<f64>, [f64; 12], f64, f64, f64, Option<f64>, f64) {
         let mut interpolated_lens = None;
         let gyro = params.gyro.read();
         if !gyro.file_metadata.lens_positions.is_empty() {
@@ -88,6 +127,10 @@ impl FrameTransform {
         }
         drop(gyro);
 
+        let breathing_scale = Self::get_focus_breathing_scale(params, lens, timestamp_ms);
+        camera_matrix[(0, 0)] *= breathing_scale;
+        camera_matrix[(1, 1)] *= breathing_scale;
+
         let radial_distortion_limit = lens.fisheye_params.radial_distortion_limit.unwrap_or_default();
 
         let (calib_width, calib_height) = if lens.calib_dimension.w > 0 && lens.calib_dimension.h > 0 {
@@ -107,7 +150,7 @@ impl FrameTransform {
             camera_matrix[(0, 2)] *= lens_ratiox;
             camera_matrix[(1, 2)] *= lens_ratioy;
         }
-        (camera_matrix, distortion_coeffs, radial_distortion_limit, input_horizontal_stretch, input_vertical_stretch, focal_length)
+        (camera_matrix, distortion_coeffs, radial_distortion_limit, input_horizontal_stretch, input_vertical_stretch, focal_length, breathing_scale)
     }
 
     pub fn at_timestamp(params: &ComputeParams, timestamp_ms: f64, frame: usize) -> Self {
@@ -126,7 +169,8 @@ impl FrameTransform {
             radial_distortion_limit,
             input_horizontal_stretch,
             input_vertical_stretch,
-            focal_length) = Self::get_lens_data_at_timestamp(params, timestamp_ms);
+            focal_length,
+            breathing_scale) = Self::get_lens_data_at_timestamp(params, timestamp_ms);
         // ----------- Lens -----------
 
         let img_dim_ratio = Self::get_ratio(params);
@@ -141,7 +185,8 @@ impl FrameTransform {
         }
 
         let scaled_k = camera_matrix * img_dim_ratio;
-        let new_k = Self::get_new_k(&params, &camera_matrix, fov);
+        // Output uses the focal length without breathing, so the breathing is cancelled
+        let new_k = Self::get_new_k(&params, &Self::without_focus_breathing(&camera_matrix, breathing_scale), fov);
 
 
         let gyro = params.gyro.read();
@@ -152,6 +197,7 @@ impl FrameTransform {
         let row_readout_time = frame_readout_time / if params.horizontal_rs { params.width } else { params.height } as f64;
         let timestamp_ms = timestamp_ms + gyro.file_metadata.per_frame_time_offsets.get(frame).unwrap_or(&0.0);
         let start_ts = timestamp_ms - (frame_readout_time / 2.0);
+        let org_timestamp_ms = timestamp_ms - gyro.file_metadata.per_frame_time_offsets.get(frame).unwrap_or(&0.0);
         // ----------- Rolling shutter correction -----------
 
         let image_rotation = Matrix3::new_rotation(video_rotation * (std::f64::consts::PI / 180.0));
@@ -163,7 +209,7 @@ impl FrameTransform {
         // Only compute 1 matrix if not using rolling shutter correction
         let rows = if frame_readout_time.abs() > 0.0 { if params.horizontal_rs { params.width } else { params.height } } else { 1 };
 
-        let matrices = (0..rows).into_par_iter().map(|y| {
+        let compute_matrices = |smoothed_quat1, start_ts: f64| (0..rows).into_par_iter().map(|y| {
             let quat_time = if frame_readout_time.abs() > 0.0 {
                 start_ts + row_readout_time * y as f64
             } else {
@@ -195,6 +241,39 @@ impl FrameTransform {
                 0.0, 0.0, 0.0
             ]
         }).collect::<Vec<[f32; 12]>>();
+
+        let mut matrices = compute_matrices(smoothed_quat1, start_ts);
+
+        // ----------- Motion blur -----------
+        // Sub-frame warps of the same input frame, following the smoothed camera path over the virtual shutter.
+        // Their matrices are appended after the main ones and averaged in the kernel
+        let motion_blur_samples = if params.motion_blur_shutter_angle > 0.0 && params.motion_blur_samples > 1 { params.motion_blur_samples } else { 0 };
+        if motion_blur_samples > 1 {
+            let exposure_ms = params.motion_blur_shutter_angle.min(360.0) / 360.0 * 1000.0 / params.scaled_fps.max(1.0);
+            for i in 0..motion_blur_samples {
+                let offset_ms = ((i as f64 + 0.5) / motion_blur_samples as f64 - 0.5) * exposure_ms;
+                matrices.extend(compute_matrices(gyro.smoothed_quat_at_timestamp(timestamp_ms + offset_ms), start_ts));
+            }
+        }
+        // ----------- Motion blur -----------
+
+        // ----------- Temporal fill -----------
+        // Same output camera, but the rows of the previous and next frames. Frames which weren't decoded are skipped when rendering
+        let mut neighbour_matrices = Vec::new();
+        if params.background_mode == BackgroundMode::TemporalFill && !params.spherical {
+            let frame_duration_ms = 1000.0 / params.scaled_fps.max(1.0);
+            for i in 1..=TEMPORAL_FILL_FRAMES {
+                let neighbours = [
+                    (i <= frame).then(|| (frame - i, org_timestamp_ms - i as f64 * frame_duration_ms)),
+                    Some((frame + i, org_timestamp_ms + i as f64 * frame_duration_ms))
+                ];
+                for (neighbour_frame, neighbour_ts) in neighbours.into_iter().flatten() {
+                    let neighbour_start_ts = neighbour_ts + gyro.file_metadata.per_frame_time_offsets.get(neighbour_frame).unwrap_or(&0.0) - (frame_readout_time / 2.0);
+                    neighbour_matrices.push(((neighbour_ts * 1000.0).round() as i64, compute_matrices(smoothed_quat1, neighbour_start_ts)));
+                }
+            }
+        }
+        // ----------- Temporal fill -----------
         drop(gyro);
 
         let mut digital_lens_params = [0f32; 4];
@@ -208,7 +287,7 @@ impl FrameTransform {
         }
 
         let kernel_params = KernelParams {
-            matrix_count:  matrices.len() as i32,
+            matrix_count:  rows as i32,
             f:             [scaled_k[(0, 0)] as f32, scaled_k[(1, 1)] as f32],
             c:             [scaled_k[(0, 2)] as f32, scaled_k[(1, 2)] as f32],
             k:             distortion_coeffs.iter().map(|x| *x as f32).collect::<Vec<f32>>().try_into().unwrap(),
@@ -217,21 +296,28 @@ impl FrameTransform {
             lens_correction_amount:   lens_correction_amount as f32,
             input_vertical_stretch:   input_vertical_stretch as f32,
             input_horizontal_stretch: input_horizontal_stretch as f32,
-            background_mode:          params.background_mode as i32,
+            background_mode:          if params.spherical { BackgroundMode::RepeatPixels } else { params.background_mode } as i32, // Repeat the edges to hide the seam of a 360° frame
             background_margin:        background_margin as f32,
             background_margin_feather:background_feather as f32,
-            translation2d: [(adaptive_zoom_center_x * params.width as f64 / fov) as f32, (adaptive_zoom_center_y * params.height as f64 / fov) as f32],
+            translation2d: if params.spherical { [0.0, 0.0] } else { [(adaptive_zoom_center_x * params.width as f64 / fov) as f32, (adaptive_zoom_center_y * params.height as f64 / fov) as f32] },
             translation3d: [0.0, 0.0, 0.0, 0.0], // currently unused
             digital_lens_params,
+            output_f:          [new_k[(0, 0)] as f32, new_k[(1, 1)] as f32],
+            output_projection: if params.spherical { OutputProjection::Equirectangular } else { params.output_projection } as i32,
+            motion_blur_samples: motion_blur_samples as i32,
             ..Default::default()
         };
 
+        let blur_kernel = super::deblur::BlurKernel::at_timestamp(params, &kernel_params, frame, start_ts, row_readout_time);
+
         Self {
             matrices,
             kernel_params,
             fov: ui_fov,
             minimal_fov: *params.minimal_fovs.get(frame).unwrap_or(&1.0),
-            focal_length
+            focal_length,
+            blur_kernel,
+            neighbour_matrices
         }
     }
 
@@ -242,13 +328,13 @@ impl FrameTransform {
 
         let frame = crate::frame_at_timestamp(timestamp_ms, params.scaled_fps) as usize;
 
-        let (camera_matrix, distortion_coeffs, _, _, _, _) = Self::get_lens_data_at_timestamp(params, timestamp_ms);
+        let (camera_matrix, distortion_coeffs, _, _, _, _, breathing_scale) = Self::get_lens_data_at_timestamp(params, timestamp_ms);
 
         let img_dim_ratio = Self::get_ratio(params);
         let fov = Self::get_fov(params, 0, use_fovs, timestamp_ms, false);
 
         let scaled_k = camera_matrix * img_dim_ratio;
-        let new_k = Self::get_new_k(params, &camera_matrix, fov);
+        let new_k = Self::get_new_k(params, &Self::without_focus_breathing(&camera_matrix, breathing_scale), fov);
 
         let gyro = params.gyro.read();
 
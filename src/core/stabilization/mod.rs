@@ -15,11 +15,12 @@ use drawing::DrawCanvas;
 mod compute_params;
 mod frame_transform;
 mod cpu_undistort;
+mod deblur;
 mod pixel_formats;
 pub mod distortion_models;
 pub use pixel_formats::*;
 pub use compute_params::ComputeParams;
-pub use frame_transform::FrameTransform;
+pub use frame_transform::{ FrameTransform, TEMPORAL_FILL_FRAMES };
 pub use cpu_undistort::*;
 
 #[derive(Default, Clone, Copy, Debug)]
@@ -54,6 +55,8 @@ bitflags::bitflags! {
         const FILL_WITH_BACKGROUND = 4;
         const DRAWING_ENABLED      = 8;
         const HORIZONTAL_RS        = 16; // right-to-left or left-to-right rolling shutter
+        const SPHERICAL_INPUT      = 32; // full 360° equirectangular input frame
+        const WIDE_FOV_LENS        = 64; // lens model which can project rays at and beyond 90° from the optical axis
     }
 }
 
@@ -98,6 +101,11 @@ pub struct KernelParams {
     pub distortion_model:         stabilize_spirv::DistortionModel, // 8
     pub digital_lens:             stabilize_spirv::DistortionModel, // 12
     pub pixel_value_limit:        f32, // 16
+    pub vignetting:               [f32; 4], // 16 - v1, v2, v3 of the falloff 1 + v1*r^2 + v2*r^4 + v3*r^6, r normalized to half of the input diagonal
+    pub chromatic_aberration:     [f32; 4], // 16 - radial scale - 1 of each channel, relative to the lens center
+    pub output_f:                 [f32; 2], // 8  - focal length of the output camera in pixels
+    pub output_projection:        i32, // 12 - 0: rectilinear, 1: equidistant fisheye, 2: stereographic, 3: equirectangular
+    pub motion_blur_samples:      i32, // 16 - sub-frame warps averaged for the motion blur, 0 or 1 = disabled. Their matrices follow the main ones
 }
 unsafe impl bytemuck::Zeroable for KernelParams {}
 unsafe impl bytemuck::Pod for KernelParams {}
@@ -141,7 +149,10 @@ pub struct Stabilization {
     pub pending_device_change: Option<isize>,
 
     pub share_wgpu_instances: bool,
-    next_backend: Option<&'static str>
+    next_backend: Option<&'static str>,
+
+    frame_history: parking_lot::Mutex<std::collections::VecDeque<(i64, Vec<u8>)>>, // Neighbouring input frames for `BackgroundMode::TemporalFill`
+    deblur_buffer: parking_lot::Mutex<Vec<u8>>, // Deblurred input frame, so the caller's input buffer stays unchanged
 }
 
 #[derive(Debug)]
@@ -181,6 +192,7 @@ impl Stabilization {
 
         self.kernel_flags.set(KernelParamsFlags::HAS_DIGITAL_LENS, self.compute_params.digital_lens.is_some());
         self.kernel_flags.set(KernelParamsFlags::HORIZONTAL_RS, self.compute_params.horizontal_rs);
+        self.kernel_flags.set(KernelParamsFlags::SPHERICAL_INPUT, self.compute_params.spherical);
 
         let mut transform = FrameTransform::at_timestamp(&self.compute_params, timestamp_ms, frame);
         transform.kernel_params.pixel_value_limit = T::default_max_value().unwrap_or(f32::MAX);
@@ -200,13 +212,17 @@ impl Stabilization {
         transform.kernel_params.pix_element_count = T::COUNT as i32;
         transform.kernel_params.canvas_scale = self.drawing.scale as f32;
         transform.kernel_params.flags = self.kernel_flags.bits();
+        // With all coefficients zero these models are a plain pinhole, which can't see behind the camera plane
+        if self.compute_params.distortion_model.supports_wide_fov() && transform.kernel_params.k.iter().any(|x| *x != 0.0) {
+            transform.kernel_params.flags |= KernelParamsFlags::WIDE_FOV_LENS.bits();
+        }
 
         transform.kernel_params.stride        = buffers.input.size.2 as i32;
         transform.kernel_params.output_stride = buffers.output.size.2 as i32;
 
         let sa_fov =
             if self.compute_params.show_safe_area || self.compute_params.fov_overview  {
-                let fov = self.compute_params.keyframes.value_at_video_timestamp(&crate::keyframes::KeyframeType::Fov, timestamp_ms).unwrap_or(self.compute_params.fov_scale) as f32;
+                let fov = self.compute_params.fov_scale_at_timestamp(timestamp_ms) as f32;
                 if self.compute_params.fov_overview {
                     (if self.compute_params.adaptive_zoom_window == 0.0 { 1.0 } else { 1.0 / fov }) + 1.0
                 } else {
@@ -232,6 +248,26 @@ impl Stabilization {
         transform.kernel_params.source_rect = Self::get_rect(&buffers.input);
         transform.kernel_params.output_rect = Self::get_rect(&buffers.output);
 
+        // Vignetting and chromatic aberration are corrected per channel, so only in RGB formats. The render converts YUV frames to RGB for them
+        if let Some((mut r, mut b)) = T::red_blue_channels() {
+            if self.initialized_backend.is_wgpu() && T::wgpu_format().map(|x| x.2).unwrap_or_default() {
+                // Normalized textures are always sampled as RGBA
+                (r, b) = (0, 2);
+            }
+            let lens = &self.compute_params.lens;
+            if let Some(v) = &lens.vignetting {
+                for (i, x) in v.iter().take(3).enumerate() {
+                    transform.kernel_params.vignetting[i] = *x as f32;
+                }
+            }
+            if let Some(ca) = &lens.chromatic_aberration {
+                if ca.len() >= 2 {
+                    transform.kernel_params.chromatic_aberration[r] = ca[0] as f32;
+                    transform.kernel_params.chromatic_aberration[b] = ca[1] as f32;
+                }
+            }
+        }
+
         transform
     }
 
@@ -264,12 +300,13 @@ impl Stabilization {
 
     pub fn get_current_key(&self, buffers: &Buffers) -> String {
         format!(
-            "{}|{}|{}|{}|{}|{:?}|{:?}|{:?}|{:?}",
+            "{}|{}|{}|{}|{}|{}|{:?}|{:?}|{:?}|{:?}",
             buffers.get_checksum(),
             self.compute_params.distortion_model.id(),
             self.compute_params.digital_lens.as_ref().map(|x| x.id()).unwrap_or_default(),
             self.interpolation as u32,
             self.kernel_flags.bits(),
+            if self.compute_params.motion_blur_shutter_angle > 0.0 { self.compute_params.motion_blur_samples } else { 0 }, // Size of the matrices buffer
             self.size,
             self.output_size,
             self.interpolation,
@@ -296,8 +333,19 @@ impl Stabilization {
         self.stab_data.clear();
     }
 
+    /// Keeps a decoded input frame which comes after the processed ones, so `BackgroundMode::TemporalFill` can use the next frames too
+    pub fn add_lookahead_frame(&self, timestamp_us: i64, input: &BufferDescription) {
+        if let BufferSource::Cpu { buffer } = &input.data {
+            let mut history = self.frame_history.lock();
+            if !history.iter().any(|(ts, _)| *ts == timestamp_us) {
+                history.push_back((timestamp_us, buffer.to_vec()));
+            }
+        }
+    }
+
     pub fn clear_stab_data(&mut self) {
         self.stab_data.clear();
+        self.frame_history.lock().clear();
     }
 
     pub fn get_undistortion_data(&self, timestamp_us: i64) -> Option<&FrameTransform> {
@@ -470,7 +518,7 @@ impl Stabilization {
         let itm = frame_transform.map(|x| Some(x)).unwrap_or_else(|| self.stab_data.get(&timestamp_us));
 
         if let Some(itm) = itm {
-            let mut ret = ProcessedInfo {
+            let ret = ProcessedInfo {
                 fov: itm.fov,
                 minimal_fov: itm.minimal_fov,
                 focal_length: itm.focal_length,
@@ -484,62 +532,108 @@ impl Stabilization {
             if self.size.0 as i32        > itm.kernel_params.stride        { return Err(GyroflowCoreError::InvalidStride(itm.kernel_params.stride, self.size.0 as i32)); }
             if self.output_size.0 as i32 > itm.kernel_params.output_stride { return Err(GyroflowCoreError::InvalidStride(itm.kernel_params.output_stride, self.output_size.0 as i32)); }
 
-            // OpenCL path
-            #[cfg(feature = "use-opencl")]
-            if let Some(ref cl) = self.cl {
-                if opencl::is_buffer_supported(buffers) {
-                    if let Err(err) = cl.undistort_image(buffers, &itm, drawing_buffer) {
-                        log::error!("OpenCL error undistort: {:?}", err);
-                    } else {
-                        ret.backend = "OpenCL";
-                        return Ok(ret);
-                    }
+            // Gyro-based deblur of the input frame, before it's warped
+            if let Some(ref blur_kernel) = itm.blur_kernel {
+                let mut deblurred = self.deblur_buffer.lock();
+                if deblur::deblur_frame::<T>(&buffers.input.data, &mut deblurred, &itm.kernel_params, blur_kernel, self.compute_params.deblur_iterations) {
+                    let mut buffers = Buffers {
+                        input: BufferDescription {
+                            size: buffers.input.size,
+                            rect: buffers.input.rect,
+                            rotation: buffers.input.rotation,
+                            data: BufferSource::Cpu { buffer: &mut deblurred },
+                            texture_copy: buffers.input.texture_copy
+                        },
+                        output: buffers.output.reborrow()
+                    };
+                    return self.undistort_frame::<T>(timestamp_us, &mut buffers, itm, ret, drawing_buffer);
                 }
+                log::warn!("Deblur is only supported for CPU input buffers");
             }
 
-            // wgpu path
-            if wgpu::is_buffer_supported(buffers) {
-                if self.share_wgpu_instances {
-                    let hash = self.get_current_checksum(buffers);
-                    let has_any_cache = CACHED_WGPU.with(|x| !x.0.borrow().is_empty());
-                    if has_any_cache {
-                        return CACHED_WGPU.with(|x| {
-                            let mut cached = x.0.borrow_mut();
-                            if let Some(wgpu) = cached.get(&hash) {
-                                wgpu.undistort_image(buffers, &itm, drawing_buffer);
-                                ret.backend = "wgpu";
-                                Ok(ret)
-                            } else {
-                                Err(GyroflowCoreError::NoCachedWgpuInstance(self.get_current_key(buffers)))
-                            }
-                        });
-                    } else {
-                        log::error!("No cached wgpu found for key: {}", self.get_current_key(buffers));
-                    }
+            self.undistort_frame::<T>(timestamp_us, buffers, itm, ret, drawing_buffer)
+        } else {
+            log::warn!("No stab data at {timestamp_us}");
+            Err(GyroflowCoreError::NoStabilizationData(timestamp_us))
+        }
+    }
+
+    fn undistort_frame<T: PixelType>(&self, timestamp_us: i64, buffers: &mut Buffers, itm: &FrameTransform, mut ret: ProcessedInfo, drawing_buffer: &[u8]) -> Result<ProcessedInfo, GyroflowCoreError> {
+        // Filling from the neighbouring frames needs them in memory, so it's only done on the CPU
+        let temporal_fill = itm.kernel_params.background_mode == crate::stabilization_params::BackgroundMode::TemporalFill as i32 && matches!(buffers.input.data, BufferSource::Cpu { .. });
+
+        // OpenCL path
+        #[cfg(feature = "use-opencl")]
+        if let Some(ref cl) = self.cl {
+            if !temporal_fill && opencl::is_buffer_supported(buffers) {
+                if let Err(err) = cl.undistort_image(buffers, itm, drawing_buffer) {
+                    log::error!("OpenCL error undistort: {:?}", err);
                 } else {
-                    if let Some(ref wgpu) = self.wgpu {
-                        wgpu.undistort_image(buffers, &itm, drawing_buffer);
-                        ret.backend = "wgpu";
-                        return Ok(ret);
-                    } else {
-                        log::error!("No wgpu instance!");
-                    }
+                    ret.backend = "OpenCL";
+                    return Ok(ret);
                 }
             }
+        }
 
-            // CPU path
-            let ok = match self.interpolation {
-                Interpolation::Bilinear => { Self::undistort_image_cpu::<2, T>(buffers, &itm.kernel_params, &self.compute_params.distortion_model, self.compute_params.digital_lens.as_ref(), &itm.matrices, drawing_buffer) },
-                Interpolation::Bicubic  => { Self::undistort_image_cpu::<4, T>(buffers, &itm.kernel_params, &self.compute_params.distortion_model, self.compute_params.digital_lens.as_ref(), &itm.matrices, drawing_buffer) },
-                Interpolation::Lanczos4 => { Self::undistort_image_cpu::<8, T>(buffers, &itm.kernel_params, &self.compute_params.distortion_model, self.compute_params.digital_lens.as_ref(), &itm.matrices, drawing_buffer) },
-            };
-            if ok {
-                ret.backend = "CPU";
-                return Ok(ret);
+        // wgpu path
+        if !temporal_fill && wgpu::is_buffer_supported(buffers) {
+            if self.share_wgpu_instances {
+                let hash = self.get_current_checksum(buffers);
+                let has_any_cache = CACHED_WGPU.with(|x| !x.0.borrow().is_empty());
+                if has_any_cache {
+                    return CACHED_WGPU.with(|x| {
+                        let mut cached = x.0.borrow_mut();
+                        if let Some(wgpu) = cached.get(&hash) {
+                            wgpu.undistort_image(buffers, itm, drawing_buffer);
+                            ret.backend = "wgpu";
+                            Ok(ret)
+                        } else {
+                            Err(GyroflowCoreError::NoCachedWgpuInstance(self.get_current_key(buffers)))
+                        }
+                    });
+                } else {
+                    log::error!("No cached wgpu found for key: {}", self.get_current_key(buffers));
+                }
+            } else {
+                if let Some(ref wgpu) = self.wgpu {
+                    wgpu.undistort_image(buffers, itm, drawing_buffer);
+                    ret.backend = "wgpu";
+                    return Ok(ret);
+                } else {
+                    log::error!("No wgpu instance!");
+                }
             }
-        } else {
-            log::warn!("No stab data at {timestamp_us}");
-            return Err(GyroflowCoreError::NoStabilizationData(timestamp_us));
+        }
+
+        // CPU path
+        let mut history = self.frame_history.lock();
+        let ok = {
+            let input_len = if let BufferSource::Cpu { buffer } = &buffers.input.data { buffer.len() } else { 0 };
+            let max_diff_us = (500_000.0 / self.compute_params.scaled_fps.max(1.0)) as i64; // Half of the frame duration
+            let neighbours = itm.neighbour_matrices.iter().filter_map(|(ts, matrices)| {
+                history.iter()
+                    .find(|(hts, data)| (hts - ts).abs() < max_diff_us && data.len() == input_len)
+                    .map(|(_, data)| (data.as_slice(), matrices.as_slice()))
+            }).collect::<Vec<_>>();
+
+            match self.interpolation {
+                Interpolation::Bilinear => { Self::undistort_image_cpu::<2, T>(buffers, &itm.kernel_params, &self.compute_params.distortion_model, self.compute_params.digital_lens.as_ref(), &itm.matrices, drawing_buffer, &neighbours) },
+                Interpolation::Bicubic  => { Self::undistort_image_cpu::<4, T>(buffers, &itm.kernel_params, &self.compute_params.distortion_model, self.compute_params.digital_lens.as_ref(), &itm.matrices, drawing_buffer, &neighbours) },
+                Interpolation::Lanczos4 => { Self::undistort_image_cpu::<8, T>(buffers, &itm.kernel_params, &self.compute_params.distortion_model, self.compute_params.digital_lens.as_ref(), &itm.matrices, drawing_buffer, &neighbours) },
+            }
+        };
+        if ok && temporal_fill {
+            if let BufferSource::Cpu { buffer } = &buffers.input.data {
+                // Keep only the frames which can still be a neighbour of the next processed frames
+                let max_distance_us = ((frame_transform::TEMPORAL_FILL_FRAMES as f64 + 1.5) * 1_000_000.0 / self.compute_params.scaled_fps.max(1.0)) as i64;
+                history.retain(|(ts, _)| *ts != timestamp_us && (ts - timestamp_us).abs() < max_distance_us);
+                history.push_back((timestamp_us, buffer.to_vec()));
+            }
+        }
+        drop(history);
+        if ok {
+            ret.backend = "CPU";
+            return Ok(ret);
         }
         Err(GyroflowCoreError::Unknown)
     }
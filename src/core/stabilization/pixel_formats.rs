@@ -15,6 +15,7 @@ pub trait PixelType: Default + Copy + Send + Sync + bytemuck::Pod {
     fn from_rgb_color(v: Vector4<f32>, ind: &[usize], is_limited: bool) -> Vector4<f32>;
 
     fn default_max_value() -> Option<f32>;
+    fn red_blue_channels() -> Option<(usize, usize)> { None } // Indices of the red and blue channels, None if it's not an RGB format
 
     fn ocl_names() -> (&'static str, &'static str, &'static str, &'static str);
     fn wgpu_format() -> Option<(wgpu::TextureFormat, &'static str, bool)>; // texture format, shader data type, is_unorm
@@ -114,6 +115,7 @@ impl PixelType for RGB8 {
     #[inline] fn ocl_names() -> (&'static str, &'static str, &'static str, &'static str) { ("uchar3", "convert_uchar3_sat", "float4", "convert_float4") } // FIXME: uchar3 can't be converted to float4
     #[inline] fn wgpu_format() -> Option<(wgpu::TextureFormat, &'static str, bool)> { None }
     #[inline] fn default_max_value() -> Option<f32> { Some(255.0) }
+    #[inline] fn red_blue_channels() -> Option<(usize, usize)> { Some((0, 2)) }
 }
 unsafe impl bytemuck::Zeroable for RGBA8 { }
 unsafe impl bytemuck::Pod for RGBA8 { }
@@ -132,6 +134,7 @@ impl PixelType for RGBA8 {
     #[inline] fn ocl_names() -> (&'static str, &'static str, &'static str, &'static str) { ("uchar4", "convert_uchar4_sat", "float4", "convert_float4") }
     #[inline] fn wgpu_format() -> Option<(wgpu::TextureFormat, &'static str, bool)> { Some((wgpu::TextureFormat::Rgba8Unorm, "f32", true)) }
     #[inline] fn default_max_value() -> Option<f32> { Some(255.0) }
+    #[inline] fn red_blue_channels() -> Option<(usize, usize)> { Some((0, 2)) }
 }
 unsafe impl bytemuck::Zeroable for BGRA8 { }
 unsafe impl bytemuck::Pod for BGRA8 { }
@@ -150,6 +153,7 @@ impl PixelType for BGRA8 {
     #[inline] fn ocl_names() -> (&'static str, &'static str, &'static str, &'static str) { ("uchar4", "convert_uchar4_sat", "float4", "convert_float4") }
     #[inline] fn wgpu_format() -> Option<(wgpu::TextureFormat, &'static str, bool)> { Some((wgpu::TextureFormat::Bgra8Unorm, "f32", true)) }
     #[inline] fn default_max_value() -> Option<f32> { Some(255.0) }
+    #[inline] fn red_blue_channels() -> Option<(usize, usize)> { Some((2, 0)) }
 }
 unsafe impl bytemuck::Zeroable for RGB16 { }
 unsafe impl bytemuck::Pod for RGB16 { }
@@ -168,6 +172,7 @@ impl PixelType for RGB16 {
     #[inline] fn ocl_names() -> (&'static str, &'static str, &'static str, &'static str) { ("ushort3", "convert_ushort3_sat", "float4", "convert_float4") }
     #[inline] fn wgpu_format() -> Option<(wgpu::TextureFormat, &'static str, bool)> { None }
     #[inline] fn default_max_value() -> Option<f32> { Some(65535.0) }
+    #[inline] fn red_blue_channels() -> Option<(usize, usize)> { Some((0, 2)) }
 }
 unsafe impl bytemuck::Zeroable for RGBA16 { }
 unsafe impl bytemuck::Pod for RGBA16 { }
@@ -186,6 +191,7 @@ impl PixelType for RGBA16 {
     #[inline] fn ocl_names() -> (&'static str, &'static str, &'static str, &'static str) { ("ushort4", "convert_ushort4_sat", "float4", "convert_float4") }
     #[inline] fn wgpu_format() -> Option<(wgpu::TextureFormat, &'static str, bool)> { Some((wgpu::TextureFormat::Rgba16Uint, "u32", false)) }
     #[inline] fn default_max_value() -> Option<f32> { Some(65535.0) }
+    #[inline] fn red_blue_channels() -> Option<(usize, usize)> { Some((0, 2)) }
 }
 unsafe impl bytemuck::Zeroable for AYUV16 { }
 unsafe impl bytemuck::Pod for AYUV16 { }
@@ -222,6 +228,7 @@ impl PixelType for RGBAf {
     #[inline] fn ocl_names() -> (&'static str, &'static str, &'static str, &'static str) { ("float4", "convert_float4", "float4", "convert_float4") }
     #[inline] fn wgpu_format() -> Option<(wgpu::TextureFormat, &'static str, bool)> { Some((wgpu::TextureFormat::Rgba32Float, "f32", false)) }
     #[inline] fn default_max_value() -> Option<f32> { None }
+    #[inline] fn red_blue_channels() -> Option<(usize, usize)> { Some((0, 2)) }
 }
 #[derive(Default, Copy, Clone, PartialEq, PartialOrd)]
 pub struct Ff16(half::f16);
@@ -245,6 +252,7 @@ impl PixelType for RGBAf16 {
     #[inline] fn ocl_names() -> (&'static str, &'static str, &'static str, &'static str) { ("half4", "convert_half4", "float4", "convert_half4_to_float4") }
     #[inline] fn wgpu_format() -> Option<(wgpu::TextureFormat, &'static str, bool)> { Some((wgpu::TextureFormat::Rgba16Float, "f32", false)) }
     #[inline] fn default_max_value() -> Option<f32> { None }
+    #[inline] fn red_blue_channels() -> Option<(usize, usize)> { Some((0, 2)) }
 }
 unsafe impl bytemuck::Zeroable for R32f { }
 unsafe impl bytemuck::Pod for R32f { }
@@ -7,13 +7,14 @@ use nalgebra::Vector4;
 
 use crate::keyframes::*;
 
-#[derive(Default, Clone, Copy, Debug)]
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
 pub enum BackgroundMode {
     #[default]
     SolidColor = 0,
     RepeatPixels = 1,
     MirrorPixels = 2,
     MarginWithFeather = 3,
+    TemporalFill = 4, // Reproject the previous and next frames into the empty areas
 }
 impl From<i32> for BackgroundMode {
     fn from(v: i32) -> Self {
@@ -21,11 +22,68 @@ impl From<i32> for BackgroundMode {
             1 => Self::RepeatPixels,
             2 => Self::MirrorPixels,
             3 => Self::MarginWithFeather,
+            4 => Self::TemporalFill,
             _ => Self::SolidColor
         }
     }
 }
 
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum OutputProjection {
+    #[default]
+    Rectilinear = 0,
+    Equidistant = 1, // Fisheye
+    Stereographic = 2,
+    Equirectangular = 3,
+}
+impl From<i32> for OutputProjection {
+    fn from(v: i32) -> Self {
+        match v {
+            1 => Self::Equidistant,
+            2 => Self::Stereographic,
+            3 => Self::Equirectangular,
+            _ => Self::Rectilinear
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
+#[serde(default)]
+pub struct TrimRangeOverrides {
+    pub range: (f64, f64), // Trim range these settings belong to, follows the range when it's moved, resized or reordered
+    pub smoothing_method: Option<String>,
+    pub smoothing_params: BTreeMap<String, f64>,
+    pub horizon_lock_amount: Option<f64>,
+    pub horizon_lock_roll: Option<f64>,
+    pub fov: Option<f64>,
+    pub transition_ms: Option<f64>, // Length of the blend at range boundaries
+}
+impl TrimRangeOverrides {
+    pub const DEFAULT_TRANSITION_MS: f64 = 500.0;
+
+    pub fn is_empty(&self) -> bool {
+        !self.affects_smoothing() && self.fov.is_none()
+    }
+    pub fn affects_smoothing(&self) -> bool {
+        self.smoothing_method.is_some() || !self.smoothing_params.is_empty() || self.horizon_lock_amount.is_some() || self.horizon_lock_roll.is_some()
+    }
+    pub fn transition_ms(&self) -> f64 {
+        self.transition_ms.unwrap_or(Self::DEFAULT_TRANSITION_MS).max(0.0)
+    }
+
+    // Weight of the range settings at given timestamp. It's 1.0 inside the range, 0.0 outside of it,
+    // and smoothly transitions across the boundaries (0.5 exactly at the boundary), so adjacent ranges cross-fade
+    pub fn blend_weight(range_ms: (f64, f64), transition_ms: f64, timestamp_ms: f64) -> f64 {
+        if transition_ms <= 0.0 {
+            return if timestamp_ms >= range_ms.0 && timestamp_ms <= range_ms.1 { 1.0 } else { 0.0 };
+        }
+        let smoothstep = |x: f64| { let x = x.clamp(0.0, 1.0); x * x * (3.0 - 2.0 * x) };
+        let fade_in  = smoothstep((timestamp_ms - (range_ms.0 - transition_ms / 2.0)) / transition_ms);
+        let fade_out = smoothstep(((range_ms.1 + transition_ms / 2.0) - timestamp_ms) / transition_ms);
+        fade_in * fade_out
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct StabilizationParams {
     pub size: (usize, usize), // Processing input size
@@ -56,10 +114,18 @@ pub struct StabilizationParams {
     pub duration_ms: f64,
 
     pub trim_ranges: Vec<(f64, f64)>,
+    pub trim_range_overrides: Vec<TrimRangeOverrides>, // Matched to `trim_ranges` by `TrimRangeOverrides::range`
 
     pub video_rotation: f64,
 
     pub lens_correction_amount: f64,
+    pub output_projection: OutputProjection,
+    pub spherical: bool, // Input is a full 360° equirectangular frame, stabilized as a sphere and rendered as equirectangular
+    pub motion_blur_shutter_angle: f64, // Virtual shutter angle in degrees for the synthetic motion blur, 0 = disabled
+    pub motion_blur_samples: usize,
+    pub deblur: bool, // Deconvolve the shake blur of the input frames, using the camera motion during the exposure
+    pub deblur_iterations: usize,
+    pub deblur_exposure_ms: f64, // Exposure time override, 0 = from the file metadata
     pub background_mode: BackgroundMode,
     pub background_margin: f64,
     pub background_margin_feather: f64,
@@ -102,6 +168,13 @@ impl Default for StabilizationParams {
             video_rotation: 0.0,
 
             lens_correction_amount: 1.0,
+            output_projection: OutputProjection::Rectilinear,
+            spherical: false,
+            motion_blur_shutter_angle: 0.0,
+            motion_blur_samples: 8,
+            deblur: false,
+            deblur_iterations: 10,
+            deblur_exposure_ms: 0.0,
             background_mode: BackgroundMode::SolidColor,
             background_margin: 0.0,
             background_margin_feather: 0.0,
@@ -111,6 +184,7 @@ impl Default for StabilizationParams {
             is_calibrator: false,
 
             trim_ranges: Vec::new(),
+            trim_range_overrides: Vec::new(),
 
             zooming_debug_points: BTreeMap::new(),
 
@@ -141,6 +215,36 @@ impl StabilizationParams {
             self.trim_ranges.iter().fold(0.0, |acc, &x| acc + (x.1 - x.0))
         }
     }
+    pub fn get_trim_range_overrides(&self, index: usize) -> TrimRangeOverrides {
+        let Some(&range) = self.trim_ranges.get(index) else { return Default::default(); };
+        self.trim_range_overrides.iter().find(|x| x.range == range).cloned().unwrap_or(TrimRangeOverrides { range, ..Default::default() })
+    }
+    pub fn set_trim_range_overrides(&mut self, index: usize, mut overrides: TrimRangeOverrides) {
+        let Some(&range) = self.trim_ranges.get(index) else { return; };
+        overrides.range = range;
+        self.trim_range_overrides.retain(|x| x.range != range);
+        if !overrides.is_empty() {
+            self.trim_range_overrides.push(overrides);
+        }
+    }
+    // Moves the overrides to the current trim range which overlaps the most with their previous range.
+    // Overrides of deleted ranges are removed
+    pub fn remap_trim_range_overrides(&mut self) {
+        let overlap = |a: (f64, f64), b: (f64, f64)| a.1.min(b.1) - a.0.max(b.0);
+        let mut previous = std::mem::take(&mut self.trim_range_overrides);
+        for &range in &self.trim_ranges {
+            let best = previous.iter().enumerate()
+                .map(|(i, x)| (i, overlap(x.range, range)))
+                .filter(|x| x.1 > 0.0)
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((i, _)) = best {
+                let mut overrides = previous.swap_remove(i);
+                overrides.range = range;
+                self.trim_range_overrides.push(overrides);
+            }
+        }
+    }
+
     pub fn get_scaled_duration_ms(&self) -> f64 {
         match self.fps_scale {
             Some(scale) => self.duration_ms / scale,
@@ -230,6 +334,13 @@ impl StabilizationParams {
             adaptive_zoom_window:      self.adaptive_zoom_window,
             framebuffer_inverted:      self.framebuffer_inverted,
             lens_correction_amount:    self.lens_correction_amount,
+            output_projection:         self.output_projection,
+            spherical:                 self.spherical,
+            motion_blur_shutter_angle: self.motion_blur_shutter_angle,
+            motion_blur_samples:       self.motion_blur_samples,
+            deblur:                    self.deblur,
+            deblur_iterations:         self.deblur_iterations,
+            deblur_exposure_ms:        self.deblur_exposure_ms,
             video_speed:               self.video_speed,
             video_speed_affects_smoothing: self.video_speed_affects_smoothing,
             video_speed_affects_zooming:   self.video_speed_affects_zooming,
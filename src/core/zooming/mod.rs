@@ -10,6 +10,7 @@ use std::collections::BTreeMap;
 
 use crate::stabilization::ComputeParams;
 use crate::keyframes::*;
+use crate::stabilization_params::OutputProjection;
 
 #[derive(Default, Clone, Copy, Debug)]
 pub struct Point2D(f32, f32);
@@ -38,8 +39,14 @@ pub fn calculate_fovs(compute_params: &ComputeParams, timestamps: &[f64], keyfra
         return Default::default();
     }
 
+    // The FOV search undistorts the rectilinear corners of the output frame, so it doesn't apply to other output projections and to the whole sphere
+    if compute_params.spherical || compute_params.output_projection != OutputProjection::Rectilinear {
+        return (vec![1.0; timestamps.len()], vec![1.0; timestamps.len()], BTreeMap::new());
+    }
+
     let mut compute_params = compute_params.clone();
     compute_params.fov_scale = 1.0;
+    compute_params.trim_range_overrides.iter_mut().for_each(|x| x.fov = None);
     compute_params.fovs.clear();
     compute_params.minimal_fovs.clear();
 
@@ -86,6 +93,8 @@ pub fn get_checksum(compute_params: &ComputeParams) -> u64 {
     }
     hasher.write_u64(compute_params.video_rotation.to_bits());
     hasher.write_u64(compute_params.adaptive_zoom_window.to_bits());
+    hasher.write_i32(compute_params.output_projection as i32);
+    hasher.write_u8(compute_params.spherical as u8);
 
     hasher.finish()
 }
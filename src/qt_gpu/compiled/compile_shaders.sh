@@ -3,8 +3,8 @@ QSB='../../../ext/6.4.3/msvc2019_64/bin/qsb.exe --glsl "120,300 es,310 es,320 es
 
 NO_DIGITAL_LENS="vec2 digital_undistort_point(vec2 uv) { return uv; } vec2 digital_distort_point(vec2 uv) { return uv; }"
 
-DISTORTION_MODELS=( "opencv_fisheye" "opencv_standard" "poly3" "poly5" "ptlens" "insta360" )
-DIGITAL_LENSES=( "" "gopro_superview" "gopro_hyperview" "digital_stretch" )
+DISTORTION_MODELS=( "opencv_fisheye" "opencv_standard" "poly3" "poly5" "ptlens" "insta360" "kannala_brandt" "division" "scaramuzza" )
+DIGITAL_LENSES=( "" "gopro_superview" "gopro_hyperview" "digital_stretch" "nonlinear_stretch" )
 
 for i in "${DISTORTION_MODELS[@]}"
 do
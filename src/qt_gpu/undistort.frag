@@ -48,6 +48,11 @@ layout(std140, binding = 2) uniform KernelParams {
     float reserved1;                // 8
     float reserved2;                // 12
     float reserved3;                // 16
+    vec4 vignetting;                // 16 - v1, v2, v3 of the falloff 1 + v1*r^2 + v2*r^4 + v3*r^6, r normalized to half of the input diagonal
+    vec4 chromatic_aberration;      // 16 - radial scale - 1 of each channel, relative to the lens center
+    vec2 output_f;                  // 8  - focal length of the output camera in pixels
+    int output_projection;          // 12 - 0: rectilinear, 1: equidistant fisheye, 2: stereographic, 3: equirectangular
+    int motion_blur_samples;        // 16 - sub-frame warps averaged for the motion blur, 0 or 1 = disabled. Their matrices follow the main ones
 } params;
 
 LENS_MODEL_FUNCTIONS;
@@ -110,13 +115,40 @@ float get_param(float row, float idx) {
     return texture(texParams, vec2(idx / 11.0, row / float(size - 1))).r;
 }
 
-vec2 rotate_and_distort(vec2 pos, float idx) {
-    float _x = (float(pos.x) * get_param(idx, 0)) + (float(pos.y) * get_param(idx, 1)) + get_param(idx, 2) + params.translation3d.x;
-    float _y = (float(pos.x) * get_param(idx, 3)) + (float(pos.y) * get_param(idx, 4)) + get_param(idx, 5) + params.translation3d.y;
-    float _w = (float(pos.x) * get_param(idx, 6)) + (float(pos.y) * get_param(idx, 7)) + get_param(idx, 8) + params.translation3d.z;
+// Ray of the output pixel for the selected output projection, in homogeneous pixel coordinates of the rectilinear output camera
+// so it can be multiplied by the rotation matrices as is. Points outside of the projection return a zero vector
+vec3 output_projection_ray(vec2 pos) {
+    if (params.output_projection == 0) return vec3(pos, 1.0);
 
-    if (_w > 0) {
-        if (params.r_limit > 0.0 && length(vec2(_x, _y) / _w) > params.r_limit) {
+    vec2 out_c = vec2(params.output_width / 2.0, params.output_height / 2.0);
+    vec2 n = (pos - out_c) / params.output_f;
+
+    vec3 dir;
+    if (params.output_projection == 3) { // Equirectangular
+        if (abs(n.y) > 1.5707963267948966) return vec3(0.0, 0.0, 0.0);
+        dir = vec3(cos(n.y) * sin(n.x), sin(n.y), cos(n.y) * cos(n.x));
+    } else {
+        float r = length(n);
+        float theta = params.output_projection == 2? 2.0 * atan(r / 2.0) : r; // Stereographic : Equidistant
+        if (theta > 3.141592653589793) return vec3(0.0, 0.0, 0.0);
+        float scale = r > 0.0? sin(theta) / r : 1.0;
+        dir = vec3(n * scale, cos(theta));
+    }
+
+    return vec3(params.output_f * dir.xy + out_c * dir.z, dir.z);
+}
+
+vec2 rotate_and_distort(vec3 pos, float idx) {
+    float _x = (pos.x * get_param(idx, 0)) + (pos.y * get_param(idx, 1)) + (pos.z * get_param(idx, 2)) + params.translation3d.x;
+    float _y = (pos.x * get_param(idx, 3)) + (pos.y * get_param(idx, 4)) + (pos.z * get_param(idx, 5)) + params.translation3d.y;
+    float _w = (pos.x * get_param(idx, 6)) + (pos.y * get_param(idx, 7)) + (pos.z * get_param(idx, 8)) + params.translation3d.z;
+
+    if ((params.flags & 32) == 32) { // Spherical input
+        return vec2(float(params.width)  * (0.5 + atan(_x, _w) / (2.0 * 3.14159265359)),
+                    float(params.height) * (0.5 + atan(_y, sqrt(_x * _x + _w * _w)) / 3.14159265359));
+    }
+    if (_w > 0 || bool(params.flags & 64)) { // Wide FOV lens
+        if (params.r_limit > 0.0 && (_w <= 0 || length(vec2(_x, _y) / _w) > params.r_limit)) {
             return vec2(-99999.0, -99999.0);
         }
         vec2 uv = params.f * distort_point(_x, _y, _w) + params.c;
@@ -133,6 +165,31 @@ vec2 rotate_and_distort(vec2 pos, float idx) {
     return vec2(-99999.0, -99999.0);
 }
 
+// Lateral chromatic aberration and vignetting of the lens, corrected at the source position
+vec4 sample_corrected_input_at(vec2 uv) {
+    vec2 size = vec2(params.width, params.height);
+    vec4 pixel = texture(texIn, uv / size);
+    vec4 ca = params.chromatic_aberration;
+    vec4 v = params.vignetting;
+    if (ca == vec4(0.0) && v == vec4(0.0)) {
+        return pixel;
+    }
+    vec2 center = params.c;
+    if (params.input_horizontal_stretch > 0.001) { center.x /= params.input_horizontal_stretch; }
+    if (params.input_vertical_stretch   > 0.001) { center.y /= params.input_vertical_stretch; }
+
+    if (ca.x != 0.0) { pixel.x = texture(texIn, (center + (uv - center) * (1.0 + ca.x)) / size).x; }
+    if (ca.y != 0.0) { pixel.y = texture(texIn, (center + (uv - center) * (1.0 + ca.y)) / size).y; }
+    if (ca.z != 0.0) { pixel.z = texture(texIn, (center + (uv - center) * (1.0 + ca.z)) / size).z; }
+
+    if (v != vec4(0.0)) {
+        float r2 = dot(uv - center, uv - center) / (dot(size, size) / 4.0);
+        float gain = max(0.1, 1.0 + r2 * (v.x + r2 * (v.y + r2 * v.z)));
+        pixel.xyz = min(pixel.xyz / gain, vec3(1.0));
+    }
+    return pixel;
+}
+
 void main() {
     vec2 texPos = v_texcoord.xy * vec2(params.output_width, params.output_height) + params.translation2d;
     vec2 outPos = v_texcoord.xy * vec2(params.output_width, params.output_height);
@@ -144,7 +201,7 @@ void main() {
 
     ///////////////////////////////////////////////////////////////////
     // Add lens distortion back
-    if (params.lens_correction_amount < 1.0) {
+    if (params.lens_correction_amount < 1.0 && params.output_projection == 0) {
         float factor = max(1.0 - params.lens_correction_amount, 0.001); // FIXME: this is close but wrong
         vec2 out_c = vec2(params.output_width / 2.0, params.output_height / 2.0);
         vec2 out_f = (params.f / params.fov) / factor;
@@ -163,6 +220,8 @@ void main() {
     }
     ///////////////////////////////////////////////////////////////////
 
+    vec3 ray = output_projection_ray(texPos);
+
     ///////////////////////////////////////////////////////////////////
     // Calculate source `y` for rolling shutter
     float sy = texPos.y;
@@ -171,7 +230,7 @@ void main() {
     }
     if (params.matrix_count > 1) {
         float idx = params.matrix_count / 2.0; // Use middle matrix
-        vec2 uv = rotate_and_distort(texPos, idx);
+        vec2 uv = rotate_and_distort(ray, idx);
         if (uv.x > -99998.0) {
             if (bool(params.flags & 16)) { // Horizontal RS
                 sy = min(params.width, max(0, floor(0.5 + uv.x)));
@@ -184,7 +243,7 @@ void main() {
 
     float idx = min(sy, params.matrix_count - 1.0);
 
-    vec2 uv = rotate_and_distort(texPos, idx);
+    vec2 uv = rotate_and_distort(ray, idx);
     if (uv.x > -99998.0) {
         if (params.background_mode == 1) { // edge repeat
             uv = max(vec2(0, 0), min(vec2(params.width - 1, params.height - 1), uv));
@@ -209,8 +268,8 @@ void main() {
                 pt2 *= vec2(widthf, heightf);
             }
 
-            vec4 c1 = texture(texIn, vec2(uv.x / params.width, uv.y / params.height));
-            vec4 c2 = texture(texIn, vec2(pt2.x / params.width, pt2.y / params.height));
+            vec4 c1 = sample_corrected_input_at(uv);
+            vec4 c2 = sample_corrected_input_at(pt2);
             fragColor = c1 * alpha + c2 * (1.0 - alpha);
             fragColor.a = 1.0;
             if (!((pt2.x >= 0 && pt2.x < params.width) && (pt2.y >= 0 && pt2.y < params.height))) {
@@ -223,7 +282,7 @@ void main() {
         }
 
         if ((uv.x >= 0 && uv.x < params.width) && (uv.y >= 0 && uv.y < params.height)) {
-            fragColor = texture(texIn, vec2(uv.x / params.width, uv.y / params.height));
+            fragColor = sample_corrected_input_at(uv);
             draw_pixel(fragColor, uv.x, uv.y, true);
             draw_pixel(fragColor, outPos.x, outPos.y, false);
             draw_safe_area(fragColor, outPos.x, outPos.y);
@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Before/after comparison render: the original and stabilized frames in a single output video.
+
+use gyroflow_core::gpu::{ BufferDescription, BufferSource };
+use gyroflow_core::stabilization::PixelType;
+use nalgebra::Vector4;
+use rayon::{ prelude::ParallelSliceMut, iter::{ ParallelIterator, IndexedParallelIterator } };
+use super::overlay;
+
+type Rect = (usize, usize, usize, usize); // x, y, width, height
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum ComparisonLayout {
+    #[default]
+    None,
+    SideBySide, // Original on the left
+    TopBottom,  // Original on the top
+    Split,      // Left half of the original over the stabilized frame
+}
+
+impl ComparisonLayout {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "Side by side"   => Self::SideBySide,
+            "Top and bottom" => Self::TopBottom,
+            "Split"          => Self::Split,
+            _ => Self::None
+        }
+    }
+
+    // Size of the encoded frame for the given stabilized frame size
+    pub fn frame_size(&self, size: (usize, usize)) -> (usize, usize) {
+        match self {
+            Self::SideBySide => (size.0 * 2, size.1),
+            Self::TopBottom  => (size.0, size.1 * 2),
+            _ => size
+        }
+    }
+
+    // Rect of the stabilized frame, rect of the whole original frame and its visible part, in a plane of the encoded frame
+    pub fn rects(&self, size: (usize, usize)) -> (Rect, Rect, Rect) {
+        match self {
+            Self::SideBySide => {
+                let half = size.0 / 2;
+                ((half, 0, size.0 - half, size.1), (0, 0, half, size.1), (0, 0, half, size.1))
+            },
+            Self::TopBottom => {
+                let half = size.1 / 2;
+                ((0, half, size.0, size.1 - half), (0, 0, size.0, half), (0, 0, size.0, half))
+            },
+            Self::Split => ((0, 0, size.0, size.1), (0, 0, size.0, size.1), (0, 0, size.0 / 2, size.1)),
+            Self::None  => ((0, 0, size.0, size.1), (0, 0, 0, 0), (0, 0, 0, 0))
+        }
+    }
+
+    // Draws the original frame, the divider and the labels. The stabilized frame has to be already rendered in its rect
+    pub fn draw<T: PixelType>(&self, input: &BufferDescription, output: &mut BufferDescription, labels: bool, color: Vector4<f32>, shadow: Vector4<f32>) -> bool {
+        if *self == Self::None { return true; }
+
+        let size = (output.size.0, output.size.1);
+        let (stab_rect, image_rect, visible_rect) = self.rects(size);
+        if !draw_scaled::<T>(input, output, image_rect, visible_rect) {
+            return false;
+        }
+
+        if *self == Self::Split {
+            let line_width = (size.0 / 480).max(1);
+            overlay::fill_rect::<T>(output, (visible_rect.2.saturating_sub(line_width / 2), 0, line_width, size.1), color, 1.0);
+        }
+
+        if labels {
+            let pixel_size = stab_rect.3 as f32 / 200.0;
+            let margin = pixel_size * 4.0;
+            overlay::draw_text::<T>(output, (visible_rect.0 as f32 + margin, visible_rect.1 as f32 + margin), pixel_size, "Original", color, Some(shadow));
+            let stab_x = if *self == Self::Split { visible_rect.2 } else { stab_rect.0 };
+            overlay::draw_text::<T>(output, (stab_x as f32 + margin, stab_rect.1 as f32 + margin), pixel_size, "Stabilized", color, Some(shadow));
+        }
+        true
+    }
+}
+
+// Scales the input plane to `image_rect` of the output plane, only the `visible_rect` part is written
+fn draw_scaled<T: PixelType>(input: &BufferDescription, output: &mut BufferDescription, image_rect: Rect, visible_rect: Rect) -> bool {
+    let BufferSource::Cpu { buffer: src } = &input.data else { return false; };
+    let (in_width, in_height, in_stride) = input.size;
+    let out_stride = output.size.2;
+    let BufferSource::Cpu { buffer: dst } = &mut output.data else { return false; };
+    if in_width < 2 || in_height < 2 || image_rect.2 == 0 || image_rect.3 == 0 || out_stride == 0 { return false; }
+
+    let bpp = std::mem::size_of::<T>();
+    let read = |x: usize, y: usize| -> Vector4<f32> {
+        let pos = y * in_stride + x * bpp;
+        PixelType::to_float(*bytemuck::from_bytes::<T>(&src[pos..pos + bpp]))
+    };
+    let scale = (in_width as f32 / image_rect.2 as f32, in_height as f32 / image_rect.3 as f32);
+
+    dst.par_chunks_mut(out_stride).enumerate().skip(visible_rect.1).take(visible_rect.3).for_each(|(y, row)| {
+        let sy = (((y - image_rect.1) as f32 + 0.5) * scale.1 - 0.5).max(0.0).min((in_height - 1) as f32);
+        let (y0, fy) = (sy as usize, sy.fract());
+        let y1 = (y0 + 1).min(in_height - 1);
+        for x in visible_rect.0..(visible_rect.0 + visible_rect.2) {
+            let sx = (((x - image_rect.0) as f32 + 0.5) * scale.0 - 0.5).max(0.0).min((in_width - 1) as f32);
+            let (x0, fx) = (sx as usize, sx.fract());
+            let x1 = (x0 + 1).min(in_width - 1);
+
+            let top    = read(x0, y0) * (1.0 - fx) + read(x1, y0) * fx;
+            let bottom = read(x0, y1) * (1.0 - fx) + read(x1, y1) * fx;
+
+            if let Some(bytes) = row.get_mut(x * bpp..(x + 1) * bpp) {
+                *bytemuck::from_bytes_mut::<T>(bytes) = PixelType::from_float(top * (1.0 - fy) + bottom * fy);
+            }
+        }
+    });
+    true
+}
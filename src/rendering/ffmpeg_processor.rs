@@ -81,6 +81,7 @@ pub enum FFmpegError {
     InternalError(ffmpeg_next::Error),
     CannotOpenInputFile((String, FilesystemError)),
     CannotOpenOutputFile((String, FilesystemError)),
+    CannotMergeSegments(String),
 }
 
 impl std::fmt::Display for FFmpegError {
@@ -106,6 +107,7 @@ impl std::fmt::Display for FFmpegError {
             FFmpegError::InternalError(e)     => write!(f, "ffmpeg error: {:?}", e),
             FFmpegError::CannotOpenInputFile((url, e))   => write!(f, "Cannot open input file {url}: {e:?}"),
             FFmpegError::CannotOpenOutputFile((url, e))   => write!(f, "Cannot open output file {url}: {e:?}"),
+            FFmpegError::CannotMergeSegments(e)          => write!(f, "Cannot merge the rendered segments: {e}"),
         }
     }
 }
@@ -277,6 +279,10 @@ impl<'a> FfmpegProcessor<'a> {
         } else {
             format::output_as_with(&file.path, &output_format, output_options)
         }?;
+        if self.video.encoder_params.spherical {
+            // mov muxer writes the sv3d spherical atom only in unofficial mode
+            unsafe { (*octx.as_mut_ptr()).strict_std_compliance = ffi::FF_COMPLIANCE_UNOFFICIAL; }
+        }
 
         for (i, stream) in self.input_context.streams().enumerate() {
             let medium = stream.parameters().medium();
@@ -611,6 +617,31 @@ impl<'a> FfmpegProcessor<'a> {
         }
         Err(ffmpeg_next::Error::StreamNotFound)
     }
+
+    // Timestamps of the video keyframes in milliseconds. Reads all packets of the file, but doesn't decode them
+    pub fn get_keyframes(url: &str) -> Result<Vec<f64>, ffmpeg_next::Error> {
+        let base = filesystem::get_engine_base();
+        let mut file = FfmpegPathWrapper::new(&base, url, false).map_err(|_| ffmpeg_next::Error::ProtocolNotFound)?;
+        let mut dict = Dictionary::new();
+        if file.path.starts_with("fd:") {
+            dict.set("fd", &file.path[3..]);
+            file.path = "fd:".into();
+        }
+
+        let mut context = format::input_with_dictionary(&file.path, dict)?;
+        let (index, time_base) = context.streams().best(media::Type::Video).map(|x| (x.index(), x.time_base())).ok_or(ffmpeg_next::Error::StreamNotFound)?;
+
+        let mut keyframes = Vec::new();
+        for (stream, packet) in context.packets() {
+            if stream.index() == index && packet.is_key() {
+                if let Some(pts) = packet.pts() {
+                    keyframes.push(pts.rescale(time_base, (1, 1000000)) as f64 / 1000.0);
+                }
+            }
+        }
+        keyframes.sort_by(|a, b| a.total_cmp(b));
+        Ok(keyframes)
+    }
 }
 
 /* unsafe extern "C" fn get_hw_format(ctx: *mut ffi::AVCodecContext, pix_fmts: *const ffi::AVPixelFormat) -> ffi::AVPixelFormat {
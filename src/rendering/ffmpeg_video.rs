@@ -43,6 +43,7 @@ pub struct EncoderParams<'a> {
     pub frame_rate: Option<Rational>,
     pub time_base: Option<Rational>,
     pub keyframe_distance_s: f64,
+    pub spherical: bool,
 }
 #[derive(Default)]
 pub struct VideoTranscoder<'a> {
@@ -167,6 +168,22 @@ impl<'a> VideoTranscoder<'a> {
             unsafe { (*ost.parameters().as_mut_ptr()).codec_tag = hvc1_tag; }
         }
 
+        if params.spherical {
+            // Tag the stream as equirectangular 360° video, so players and uploaders recognize it
+            unsafe {
+                let mut size = 0;
+                let mapping = ffi::av_spherical_alloc(&mut size);
+                if !mapping.is_null() {
+                    (*mapping).projection = ffi::AVSphericalProjection::AV_SPHERICAL_EQUIRECTANGULAR;
+                    let par = ost.parameters().as_mut_ptr();
+                    if ffi::av_packet_side_data_add(&mut (*par).coded_side_data, &mut (*par).nb_coded_side_data, ffi::AVPacketSideDataType::AV_PKT_DATA_SPHERICAL, mapping as *mut _, size, 0).is_null() {
+                        ffi::av_free(mapping as *mut _);
+                        super::append_log("Failed to add spherical metadata.\n");
+                    }
+                }
+            }
+        }
+
         Ok(context.encoder().video()?)
     }
 
@@ -0,0 +1,85 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Frames ahead of the rendered one, for `BackgroundMode::TemporalFill`.
+// The render decodes and encodes one frame at a time, so the next frames are decoded by a second decoder on a separate thread.
+// It's limited by a bounded channel, so it never runs more than a few frames ahead.
+
+use super::*;
+use std::collections::VecDeque;
+use std::sync::mpsc::{ sync_channel, Receiver };
+use std::thread::JoinHandle;
+use std::sync::atomic::Ordering::SeqCst;
+
+pub struct LookaheadDecoder {
+    receiver: Option<Receiver<(i64, Video)>>,
+    frames: VecDeque<(i64, Video)>,
+    finished: bool,
+    cancel_flag: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LookaheadDecoder {
+    pub fn new(input_file: &gyroflow_core::InputFile, ranges_ms: &[(Option<f64>, Option<f64>)], frame_count: usize) -> Self {
+        let (sender, receiver) = sync_channel(frame_count.max(1));
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        let input_file = input_file.clone();
+        // Same as `FfmpegProcessor::ranges_ms`, with the open ends filled in
+        let ranges: Vec<(f64, f64)> = ranges_ms.iter().filter(|x| x.0.is_some() || x.1.is_some()).map(|x| (x.0.unwrap_or(0.0), x.1.unwrap_or(f64::MAX))).collect();
+
+        let cancel = cancel_flag.clone();
+        let thread = std::thread::spawn(move || {
+            let fs_base = gyroflow_core::filesystem::get_engine_base();
+            let mut proc = match FfmpegProcessor::from_file(&fs_base, &input_file.url, false, 0, Some(decoder_options(&input_file))) {
+                Ok(proc) => proc,
+                Err(e) => { ::log::error!("Failed to open the look-ahead decoder: {e:?}"); return; }
+            };
+            let cancel2 = cancel.clone();
+            proc.on_frame(move |timestamp_us, input_frame, _output_frame, _converter, _rate_control| {
+                if sender.send((timestamp_us, input_frame.clone())).is_err() {
+                    // The render is done
+                    cancel2.store(true, SeqCst);
+                }
+                Ok(())
+            });
+            if let Err(e) = proc.start_decoder_only(ranges, cancel) {
+                ::log::error!("Look-ahead decoder error: {e:?}");
+            }
+        });
+
+        Self {
+            receiver: Some(receiver),
+            frames: VecDeque::new(),
+            finished: false,
+            cancel_flag,
+            thread: Some(thread)
+        }
+    }
+
+    /// Decoded frames after `timestamp_us` (source timestamp), until `until_us` or the end of the video
+    pub fn frames_after(&mut self, timestamp_us: i64, until_us: i64) -> impl Iterator<Item = &mut (i64, Video)> {
+        self.frames.retain(|(ts, _)| *ts > timestamp_us);
+        while !self.finished && self.frames.back().map_or(true, |(ts, _)| *ts < until_us) {
+            match self.receiver.as_ref().map(|x| x.recv()) {
+                Some(Ok(frame)) => {
+                    if frame.0 > timestamp_us {
+                        self.frames.push_back(frame);
+                    }
+                },
+                _ => { self.finished = true; }
+            }
+        }
+        // The frames after `until_us` are kept for the next calls
+        self.frames.iter_mut().take_while(move |(ts, _)| *ts <= until_us)
+    }
+}
+
+impl Drop for LookaheadDecoder {
+    fn drop(&mut self) {
+        self.cancel_flag.store(true, SeqCst);
+        // Unblocks the decoder thread if it's waiting on the full channel
+        self.receiver = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
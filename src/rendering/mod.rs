@@ -5,6 +5,11 @@ mod ffmpeg_audio;
 mod ffmpeg_video;
 mod ffmpeg_video_converter;
 mod audio_resampler;
+mod overlay;
+pub mod comparison;
+pub mod telemetry;
+pub mod segments;
+mod lookahead;
 pub mod ffmpeg_processor;
 pub mod ffmpeg_hw;
 pub mod render_queue;
@@ -167,7 +172,7 @@ pub fn get_possible_encoders(codec: &str, use_gpu: bool) -> Vec<(&'static str, b
     encoders
 }
 
-pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &gyroflow_core::InputFile, render_options: &RenderOptions, gpu_decoder_index: i32, trim_range_ind: Option<usize>, cancel_flag: Arc<AtomicBool>, pause_flag: Arc<AtomicBool>, encoder_initialized: F2) -> Result<(), FFmpegError>
+pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &gyroflow_core::InputFile, render_options: &RenderOptions, gpu_decoder_index: i32, trim_range_ind: Option<usize>, cancel_flag: Arc<AtomicBool>, pause_flag: Arc<AtomicBool>, encoder_initialized: F2, segment: Option<&segments::RenderSegment>) -> Result<(), FFmpegError>
     where F: Fn((f64, usize, usize, bool, bool)) + Send + Sync + Clone,
           F2: Fn(String) + Send + Sync + Clone
 {
@@ -184,6 +189,14 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
     let total_frame_count = params.frame_count;
     let fps_scale = params.fps_scale;
     let has_alpha = params.background[3] < 1.0;
+    let spherical = params.spherical;
+    let temporal_fill = params.background_mode == gyroflow_core::stabilization_params::BackgroundMode::TemporalFill && !spherical;
+    let deblur = params.deblur && params.deblur_iterations > 0 && !spherical;
+    // Vignetting and chromatic aberration are corrected per color channel, so YUV frames are converted to RGB for them
+    let lens_color_correction = {
+        let lens = stab.lens.read();
+        lens.vignetting.is_some() || lens.chromatic_aberration.is_some()
+    };
 
     let mut pixel_format = render_options.pixel_format.clone();
 
@@ -192,8 +205,11 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
     #[cfg(any(target_os = "ios", target_os = "android"))]
     let _prevent_system_sleep = keep_awake::inhibit_display("Gyroflow", "Rendering video");
 
-    let mut output_width = render_options.output_width;
-    let mut output_height = render_options.output_height;
+    let comparison = comparison::ComparisonLayout::from_name(&render_options.comparison_layout);
+    let comparison_labels = render_options.comparison_labels;
+    let overlays = telemetry::TelemetryOverlay::new(&render_options.overlays);
+
+    let (mut output_width, mut output_height) = comparison.frame_size((render_options.output_width, render_options.output_height));
     if cfg!(target_os = "android") {
         // Workaround for MediaCodec alignment requirement, until more proper fix is found
         // TODO: investigate and find proper fix in the MediaCodec encoder
@@ -207,7 +223,7 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
     let video_speed = params.video_speed;
 
     let render_duration = params.duration_ms * trim_ratio;
-    let render_frame_count = (total_frame_count as f64 * trim_ratio).round() as usize;
+    let render_frame_count = segment.map(|x| x.frame_count).unwrap_or_else(|| (total_frame_count as f64 * trim_ratio).round() as usize);
 
     // Only use post-conversion processing when background is not opaque
     let order = if params.background[3] < 1.0 {
@@ -218,32 +234,16 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
 
     drop(params);
 
-    let mut decoder_options = ffmpeg_next::Dictionary::new();
-    if input_file.image_sequence_fps > 0.0 {
-        let fps = fps_to_rational(input_file.image_sequence_fps);
-        decoder_options.set("framerate", &format!("{}/{}", fps.numerator(), fps.denominator()));
-    }
-    if input_file.image_sequence_start > 0 {
-        decoder_options.set("start_number", &format!("{}", input_file.image_sequence_start));
-    }
-    if cfg!(target_os = "android") {
-        decoder_options.set("ndk_codec", "1");
-    }
-    let gpu_decoding = *GPU_DECODING.read();
+    // The original frame and the overlays are drawn on the CPU. Temporal fill also needs the frames in the same format as the look-ahead decoder,
+    // and the lens color correction converts the frames to RGB on the CPU. Deblur deconvolves the input frame on the CPU too
+    let gpu_decoding = *GPU_DECODING.read() && comparison == comparison::ComparisonLayout::None && !overlays.is_enabled() && !temporal_fill && !lens_color_correction && !deblur;
     let fs_base = gyroflow_core::filesystem::get_engine_base();
-    let mut proc = FfmpegProcessor::from_file(&fs_base, &input_file.url, gpu_decoding && gpu_decoder_index >= 0, gpu_decoder_index as usize, Some(decoder_options))?;
+    let mut proc = FfmpegProcessor::from_file(&fs_base, &input_file.url, gpu_decoding && gpu_decoder_index >= 0, gpu_decoder_index as usize, Some(decoder_options(input_file)))?;
 
     let render_options_dict = render_options.get_encoder_options_dict();
     let hwaccel_device = render_options_dict.get("hwaccel_device");
 
-    match render_options.audio_codec.as_ref() {
-        "AAC"         => proc.audio_codec = ffmpeg_next::codec::Id::AAC,
-        "PCM (s16le)" => proc.audio_codec = ffmpeg_next::codec::Id::PCM_S16LE,
-        "PCM (s16be)" => proc.audio_codec = ffmpeg_next::codec::Id::PCM_S16BE,
-        "PCM (s24le)" => proc.audio_codec = ffmpeg_next::codec::Id::PCM_S24LE,
-        "PCM (s24be)" => proc.audio_codec = ffmpeg_next::codec::Id::PCM_S24BE,
-        _ => { }
-    }
+    proc.audio_codec = render_options.get_audio_codec();
 
     let interpolation = match render_options.interpolation.as_ref() {
         "Bilinear"  => Interpolation::Bilinear,
@@ -271,6 +271,10 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
     if !render_options.pad_with_black && !render_options.preserve_other_tracks && !trim_ranges.is_empty() {
         proc.ranges_ms = trim_ranges.iter().map(|x| (if x.0 > 0.0 { Some(x.0 * duration_ms) } else { None }, if x.1 < 1.0 { Some(x.1 * duration_ms) } else { None })).collect();
     }
+    if let Some(segment) = segment {
+        proc.ranges_ms = segment.ranges_ms.clone();
+    }
+    let mut lookahead = temporal_fill.then(|| lookahead::LookaheadDecoder::new(input_file, &proc.ranges_ms, TEMPORAL_FILL_FRAMES));
 
     match proc.video_codec.as_deref() {
         Some("prores_ks") | Some("prores_videotoolbox") => {
@@ -370,6 +374,7 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
     }
 
     proc.video.encoder_params.keyframe_distance_s = render_options.keyframe_distance.max(0.0001);
+    proc.video.encoder_params.spherical = spherical;
 
     proc.preserve_other_tracks = render_options.preserve_other_tracks;
 
@@ -398,7 +403,7 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
 
     log::debug!("start_us: {}, render_duration: {}, render_frame_count: {}", start_us, render_duration, render_frame_count);
 
-    let mut planes = Vec::<Box<dyn FnMut(i64, &mut Video, &mut Video, usize, bool)>>::new();
+    let mut planes = Vec::<Box<dyn FnMut(i64, &mut Video, &mut Video, usize, bool, &mut [(i64, &mut Video)])>>::new();
 
     let is_prores_videotoolbox = proc.video_codec.as_deref() == Some("prores_videotoolbox");
 
@@ -432,9 +437,9 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
             !trim_ranges.iter().any(|x| timestamp_us >= (x.0 * duration_ms * 1000.0).round() as i64 &&
                                         timestamp_us <= (x.1 * duration_ms * 1000.0).round() as i64);
 
-        if let Some(scale) = fps_scale {
-            timestamp_us = (timestamp_us as f64 / scale).round() as i64;
-        }
+        let scale_timestamp = |ts: i64| fps_scale.map(|scale| (ts as f64 / scale).round() as i64).unwrap_or(ts);
+        let source_timestamp_us = timestamp_us;
+        timestamp_us = scale_timestamp(timestamp_us);
 
         if is_speed_changed {
             let vid_speed = stab.keyframes.read().value_at_video_timestamp(&gyroflow_core::keyframes::KeyframeType::VideoSpeed, timestamp_us as f64 / 1000.0).unwrap_or(video_speed);
@@ -458,12 +463,16 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
         }
 
         let output_frame = output_frame.unwrap();
+        let process_in_rgb = lens_color_correction && !matches!(input_frame.format(), Pixel::RGB24 | Pixel::RGBA | Pixel::RGB48BE | Pixel::RGBA64BE);
 
         macro_rules! create_planes_proc {
             ($planes:ident, $(($t:tt, $in_frame:expr, $out_frame:expr, $ind:expr, $yuvi:expr, $max_val:expr), )*) => {
                 $({
                     let in_size = zero_copy::get_plane_size($in_frame, $ind);
-                    let out_size = zero_copy::get_plane_size($out_frame, $ind);
+                    let frame_out_size = zero_copy::get_plane_size($out_frame, $ind);
+                    // The stabilized frame is only a part of the output frame in the comparison layouts
+                    let stab_rect = comparison.rects(frame_out_size).0;
+                    let out_size = (stab_rect.2, stab_rect.3);
 
                     let org_sizes = {
                         let params = stab.params.read();
@@ -500,18 +509,44 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
 
                     let is_limited_range = $out_frame.color_range() == ffmpeg_next::util::color::Range::MPEG;
                     compute_params.background = <$t as PixelType>::from_rgb_color(compute_params.background, &$yuvi, is_limited_range);
+                    let label_color  = <$t as PixelType>::from_rgb_color(nalgebra::Vector4::new(1.0, 1.0, 1.0, 1.0), &$yuvi, is_limited_range) * $max_val;
+                    let shadow_color = <$t as PixelType>::from_rgb_color(nalgebra::Vector4::new(0.0, 0.0, 0.0, 1.0), &$yuvi, is_limited_range) * $max_val;
+                    let rgb = |r: f32, g: f32, b: f32| <$t as PixelType>::from_rgb_color(nalgebra::Vector4::new(r, g, b, 1.0), &$yuvi, is_limited_range) * $max_val;
+                    let overlay_colors = telemetry::OverlayColors {
+                        text:         label_color,
+                        shadow:       shadow_color,
+                        horizon:      rgb(0.0, 0.8, 1.0),
+                        crop:         rgb(1.0, 0.0, 0.0),
+                        features:     rgb(0.0, 1.0, 0.0),
+                        optical_flow: rgb(1.0, 1.0, 0.0),
+                    };
+                    let overlays = overlays.clone();
+                    let stab = stab.clone();
+
+                    // The features and the optical flow are moved to the stabilized frame with the parameters of this plane
+                    let points_params = overlays.draws_points().then(|| compute_params.clone());
 
                     plane.init_size(in_size, out_size);
                     plane.set_compute_params(compute_params);
                     let render_globals = render_globals.clone();
-                    $planes.push(Box::new(move |timestamp_us: i64, in_frame_data: &mut Video, out_frame_data: &mut Video, plane_index: usize, fill_with_background: bool| {
+                    $planes.push(Box::new(move |timestamp_us: i64, in_frame_data: &mut Video, out_frame_data: &mut Video, plane_index: usize, fill_with_background: bool, next_frames: &mut [(i64, &mut Video)]| {
                         let mut g = render_globals.borrow_mut();
                         let wgpu_format = $t::wgpu_format().map(|x| x.0);
 
+                        for (ts, next_frame) in next_frames.iter_mut() {
+                            // Only the frames decoded to the same format as the rendered one can be used
+                            if next_frame.format() == in_frame_data.format() && next_frame.width() == in_frame_data.width() && next_frame.height() == in_frame_data.height() {
+                                plane.add_lookahead_frame(*ts, &get_plane_buffer(next_frame, in_size, plane_index, &mut g, wgpu_format));
+                            }
+                        }
+
                         let mut buffers = Buffers {
                             input:  get_plane_buffer(in_frame_data, in_size, plane_index, &mut g, wgpu_format),
-                            output: get_plane_buffer(out_frame_data, out_size, plane_index, &mut g, wgpu_format)
+                            output: get_plane_buffer(out_frame_data, frame_out_size, plane_index, &mut g, wgpu_format)
                         };
+                        if comparison != comparison::ComparisonLayout::None {
+                            buffers.output.rect = Some(stab_rect);
+                        }
 
                         if plane.initialized_backend.is_none() || plane.pending_device_change.is_some() {
                             plane.ensure_ready_for_processing::<$t>(timestamp_us, &mut buffers);
@@ -530,11 +565,26 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
                         if let Err(e) = plane.process_pixels::<$t>(timestamp_us, &mut buffers, Some(&transform)) {
                             ::log::error!("Failed to process pixels: {e:?}");
                         }
+                        if !comparison.draw::<$t>(&buffers.input, &mut buffers.output, comparison_labels, label_color, shadow_color) {
+                            ::log::error!("Comparison render is only supported for frames in the CPU memory");
+                        }
+                        if overlays.is_enabled() {
+                            let frame_telemetry = overlays.frame_telemetry(&stab, timestamp_us, &transform, out_size);
+                            overlays.draw::<$t>(&mut buffers.output, stab_rect, &frame_telemetry, &overlay_colors);
+                            if let Some(ref points_params) = points_params {
+                                overlays.draw_points::<$t>(&stab, timestamp_us, points_params, &mut buffers.output, stab_rect, &overlay_colors);
+                            }
+                        }
                     }));
                 })*
             };
         }
 
+        if planes.is_empty() && process_in_rgb {
+            converter.convert_pixel_format(input_frame, output_frame, Pixel::RGBA64BE, ffmpeg_interpolation, |converted_frame, converted_output| {
+                create_planes_proc!(planes, (RGBA16, converted_frame, converted_output, 0, [], 65535.0), );
+            })?;
+        }
         if planes.is_empty() {
             // Good reference about video formats: https://source.chromium.org/chromium/chromium/src/+/master:media/base/video_frame.cc
             // https://gist.github.com/Jim-Bar/3cbba684a71d1a9d468a6711a6eddbeb
@@ -638,14 +688,26 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
             return Err(FFmpegError::UnknownPixelFormat(input_frame.format()));
         }
 
+        let mut next_frames: Vec<(i64, &mut Video)> = match lookahead.as_mut() {
+            Some(lookahead) => {
+                let until_us = source_timestamp_us + interval * TEMPORAL_FILL_FRAMES as i64 + interval / 2;
+                lookahead.frames_after(source_timestamp_us, until_us).map(|(ts, frame)| (scale_timestamp(*ts), frame)).collect()
+            },
+            None => Vec::new()
+        };
         let mut undistort_frame = |frame: &mut Video, out_frame: &mut Video| {
             for (i, cb) in planes.iter_mut().enumerate() {
-                (*cb)(timestamp_us, frame, out_frame, i, fill_with_background);
+                (*cb)(timestamp_us, frame, out_frame, i, fill_with_background, &mut next_frames);
             }
             progress2((process_frame as f64 / render_frame_count as f64, process_frame, render_frame_count, false, false));
         };
 
         match input_frame.format() {
+            _ if process_in_rgb => {
+                converter.convert_pixel_format(input_frame, output_frame, Pixel::RGBA64BE, ffmpeg_interpolation, |converted_frame, converted_output| {
+                    undistort_frame(converted_frame, converted_output);
+                })?;
+            },
             Pixel::VIDEOTOOLBOX | // Pixel::D3D11 |
             Pixel::NV12 | Pixel::NV21 | Pixel::YUV420P | Pixel::YUVJ420P |
             Pixel::P010LE | Pixel::P016LE | Pixel::P210LE | Pixel::P216LE | Pixel::P410LE | Pixel::P416LE |
@@ -695,7 +757,7 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
         ::log::debug!("Removing {output_url}");
         let _ = gyroflow_core::filesystem::remove_file(&output_url);
     }
-    if trim_range_ind.is_none() || trim_range_ind == Some(org_trim_ranges.len() - 1) {
+    if segment.is_none() && (trim_range_ind.is_none() || trim_range_ind == Some(org_trim_ranges.len() - 1)) {
         progress((1.0, render_frame_count, render_frame_count, true, false));
     }
 
@@ -704,6 +766,21 @@ pub fn render<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &
     Ok(())
 }
 
+pub fn decoder_options(input_file: &gyroflow_core::InputFile) -> ffmpeg_next::Dictionary<'static> {
+    let mut decoder_options = ffmpeg_next::Dictionary::new();
+    if input_file.image_sequence_fps > 0.0 {
+        let fps = fps_to_rational(input_file.image_sequence_fps);
+        decoder_options.set("framerate", &format!("{}/{}", fps.numerator(), fps.denominator()));
+    }
+    if input_file.image_sequence_start > 0 {
+        decoder_options.set("start_number", &format!("{}", input_file.image_sequence_start));
+    }
+    if cfg!(target_os = "android") {
+        decoder_options.set("ndk_codec", "1");
+    }
+    decoder_options
+}
+
 pub fn init_log() {
 	unsafe {
         ffi::av_log_set_level(ffi::AV_LOG_INFO);
@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Simple drawing into the CPU frame planes: rectangles and text with a built-in 5x7 bitmap font.
+// Positions and sizes are in the pixels of the plane, so the chroma planes just use smaller values.
+
+use gyroflow_core::gpu::{ BufferDescription, BufferSource };
+use gyroflow_core::stabilization::PixelType;
+use nalgebra::Vector4;
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '°' => [0x0C, 0x12, 0x12, 0x0C, 0x00, 0x00, 0x00],
+        _   => [0x00; GLYPH_HEIGHT]
+    }
+}
+
+// Size of the text in pixels, `pixel_size` is the size of one font pixel
+pub fn text_size(text: &str, pixel_size: f32) -> (usize, usize) {
+    let chars = text.chars().count();
+    if chars == 0 { return (0, 0); }
+    (((chars * (GLYPH_WIDTH + 1) - 1) as f32 * pixel_size).ceil() as usize, (GLYPH_HEIGHT as f32 * pixel_size).ceil() as usize)
+}
+
+fn blend_pixel<T: PixelType>(buffer: &mut [u8], pos: usize, color: &Vector4<f32>, alpha: f32) {
+    let size = std::mem::size_of::<T>();
+    if let Some(bytes) = buffer.get_mut(pos..pos + size) {
+        let px: &mut T = bytemuck::from_bytes_mut(bytes);
+        *px = if alpha >= 1.0 { PixelType::from_float(*color) } else { PixelType::from_float(PixelType::to_float(*px) * (1.0 - alpha) + color * alpha) };
+    }
+}
+
+pub fn fill_rect<T: PixelType>(desc: &mut BufferDescription, rect: (usize, usize, usize, usize), color: Vector4<f32>, alpha: f32) {
+    let (width, height, stride) = desc.size;
+    if let BufferSource::Cpu { buffer } = &mut desc.data {
+        for y in rect.1..(rect.1 + rect.3).min(height) {
+            for x in rect.0..(rect.0 + rect.2).min(width) {
+                blend_pixel::<T>(buffer, y * stride + x * std::mem::size_of::<T>(), &color, alpha);
+            }
+        }
+    }
+}
+
+// Draws a line made of squares with the side of `width` pixels
+pub fn draw_line<T: PixelType>(desc: &mut BufferDescription, from: (f32, f32), to: (f32, f32), width: f32, color: Vector4<f32>) {
+    let width = width.max(1.0);
+    let steps = ((to.0 - from.0).abs().max((to.1 - from.1).abs()) / (width / 2.0).max(1.0)).ceil().max(1.0) as usize;
+    let (size_w, size_h) = (desc.size.0 as f32, desc.size.1 as f32);
+    for i in 0..=steps {
+        let t = i as f32 / steps as f32;
+        let x = from.0 + (to.0 - from.0) * t - width / 2.0;
+        let y = from.1 + (to.1 - from.1) * t - width / 2.0;
+        if x + width < 0.0 || y + width < 0.0 || x >= size_w || y >= size_h { continue; }
+        let (x0, y0) = (x.max(0.0) as usize, y.max(0.0) as usize);
+        let (x1, y1) = ((x + width).max(0.0).ceil() as usize, (y + width).max(0.0).ceil() as usize);
+        fill_rect::<T>(desc, (x0, y0, x1 - x0, y1 - y0), color, 1.0);
+    }
+}
+
+pub fn draw_rect_outline<T: PixelType>(desc: &mut BufferDescription, rect: (usize, usize, usize, usize), width: usize, color: Vector4<f32>) {
+    let width = width.max(1).min(rect.2 / 2).min(rect.3 / 2);
+    fill_rect::<T>(desc, (rect.0, rect.1, rect.2, width), color, 1.0);
+    fill_rect::<T>(desc, (rect.0, rect.1 + rect.3 - width, rect.2, width), color, 1.0);
+    fill_rect::<T>(desc, (rect.0, rect.1, width, rect.3), color, 1.0);
+    fill_rect::<T>(desc, (rect.0 + rect.2 - width, rect.1, width, rect.3), color, 1.0);
+}
+
+// Draws the text with its top left corner at `pos`, with an optional 1 font pixel shadow
+pub fn draw_text<T: PixelType>(desc: &mut BufferDescription, pos: (f32, f32), pixel_size: f32, text: &str, color: Vector4<f32>, shadow: Option<Vector4<f32>>) {
+    let (width, height, stride) = desc.size;
+    let pixel_size = pixel_size.max(0.5);
+    let BufferSource::Cpu { buffer } = &mut desc.data else { return; };
+
+    let mut draw_pass = |offset: f32, color: &Vector4<f32>| {
+        for (i, c) in text.chars().enumerate() {
+            let rows = glyph(c);
+            let char_x = pos.0 + offset + (i * (GLYPH_WIDTH + 1)) as f32 * pixel_size;
+            let char_y = pos.1 + offset;
+            let (w, h) = ((GLYPH_WIDTH as f32 * pixel_size).ceil() as usize, (GLYPH_HEIGHT as f32 * pixel_size).ceil() as usize);
+            for py in 0..h {
+                let gy = ((py as f32 / pixel_size) as usize).min(GLYPH_HEIGHT - 1);
+                let y = (char_y + py as f32) as isize;
+                if y < 0 || y >= height as isize { continue; }
+                for px in 0..w {
+                    let gx = ((px as f32 / pixel_size) as usize).min(GLYPH_WIDTH - 1);
+                    if rows[gy] & (0x10 >> gx) == 0 { continue; }
+                    let x = (char_x + px as f32) as isize;
+                    if x < 0 || x >= width as isize { continue; }
+                    blend_pixel::<T>(buffer, y as usize * stride + x as usize * std::mem::size_of::<T>(), color, 1.0);
+                }
+            }
+        }
+    };
+    if let Some(shadow) = shadow {
+        draw_pass(pixel_size.max(1.0), &shadow);
+    }
+    draw_pass(0.0, &color);
+}
@@ -61,6 +61,40 @@ pub struct RenderMetadata {
     pub comment: String,
 }
 
+// Telemetry and debug overlays burned into the render
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RenderOverlays {
+    pub timecode: bool,
+    pub frame_number: bool,
+    pub horizon: bool,
+    pub gyro_rates: bool,
+    pub gps_speed: bool,
+    pub fov: bool,
+    pub crop_rect: bool,
+    pub features: bool,
+    pub optical_flow: bool,
+    pub position: String, // "Top left", "Top right", "Bottom left" or "Bottom right"
+    pub text_size: f64, // Percent of the frame height
+}
+impl Default for RenderOverlays {
+    fn default() -> Self {
+        Self {
+            timecode: false,
+            frame_number: false,
+            horizon: false,
+            gyro_rates: false,
+            gps_speed: false,
+            fov: false,
+            crop_rect: false,
+            features: false,
+            optical_flow: false,
+            position: "Top left".into(),
+            text_size: 2.5,
+        }
+    }
+}
+
 #[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(default)]
 pub struct RenderOptions {
@@ -85,6 +119,11 @@ pub struct RenderOptions {
     pub export_trims_separately: bool,
     pub audio_codec: String,
     pub interpolation: String,
+    pub comparison_layout: String,
+    pub comparison_labels: bool,
+    pub overlays: RenderOverlays,
+    pub parallel_segments: usize, // Split the video into this many segments rendered concurrently, 0 or 1 = disabled
+    pub resumable: bool, // Render in closed segments and keep the progress next to the output, so an interrupted render can be resumed
 }
 impl RenderOptions {
     pub fn settings_string(&self, fps: f64) -> String {
@@ -98,6 +137,16 @@ impl RenderOptions {
         format!("{}x{} {:.3}fps | {}", self.output_width, self.output_height, fps, codec_info)
     }
 
+    pub fn get_audio_codec(&self) -> ffmpeg_next::codec::Id {
+        match self.audio_codec.as_ref() {
+            "PCM (s16le)" => ffmpeg_next::codec::Id::PCM_S16LE,
+            "PCM (s16be)" => ffmpeg_next::codec::Id::PCM_S16BE,
+            "PCM (s24le)" => ffmpeg_next::codec::Id::PCM_S24LE,
+            "PCM (s24be)" => ffmpeg_next::codec::Id::PCM_S24BE,
+            _ => ffmpeg_next::codec::Id::AAC
+        }
+    }
+
     pub fn get_encoder_options_dict(&self) -> ffmpeg_next::Dictionary {
         let re = Regex::new(r#"-([^\s"]+)\s+("[^"]+"|[^\s"]+)"#).unwrap();
 
@@ -137,10 +186,20 @@ impl RenderOptions {
             if let Some(v) = obj.get("export_trims_separately").and_then(|x| x.as_bool()) { self.export_trims_separately = v; }
             if let Some(v) = obj.get("audio_codec")            .and_then(|x| x.as_str())  { self.audio_codec = v.to_string(); }
             if let Some(v) = obj.get("interpolation")          .and_then(|x| x.as_str())  { self.interpolation = v.to_string(); }
+            if let Some(v) = obj.get("comparison_layout")      .and_then(|x| x.as_str())  { self.comparison_layout = v.to_string(); }
+            if let Some(v) = obj.get("comparison_labels")      .and_then(|x| x.as_bool()) { self.comparison_labels = v; }
+            if let Some(v) = obj.get("parallel_segments")      .and_then(|x| x.as_u64())  { self.parallel_segments = v as usize; }
+            if let Some(v) = obj.get("resumable")              .and_then(|x| x.as_bool()) { self.resumable = v; }
 
             if let Some(v) = obj.get("metadata").and_then(|x| x.as_object())  {
                 if let Some(s) = v.get("comment").and_then(|x| x.as_str()) { self.metadata.comment = s.to_string(); }
             }
+            if let Some(v) = obj.get("overlays") {
+                match serde_json::from_value(v.clone()) {
+                    Ok(v) => self.overlays = v,
+                    Err(e) => ::log::error!("Invalid overlays options: {e:?}")
+                }
+            }
 
             // Backwards compatibility
             if let Some(v) = obj.get("output_path").and_then(|x| x.as_str()) {
@@ -945,7 +1004,11 @@ impl RenderQueue {
                     if cancel_flag.load(SeqCst) { break; }
                     let mut i = 0;
                     loop {
-                        let result = rendering::render(stab.clone(), progress.clone(), &input_file, &render_options, i, range, cancel_flag.clone(), pause_flag.clone(), encoder_initialized.clone());
+                        let result = if range.is_none() && rendering::segments::can_render_segmented(&stab, &render_options) {
+                            rendering::segments::render_segmented(stab.clone(), progress.clone(), &input_file, &render_options, i, cancel_flag.clone(), pause_flag.clone(), encoder_initialized.clone())
+                        } else {
+                            rendering::render(stab.clone(), progress.clone(), &input_file, &render_options, i, range, cancel_flag.clone(), pause_flag.clone(), encoder_initialized.clone(), None)
+                        };
                         if let Err(e) = result {
                             if let rendering::FFmpegError::PixelFormatNotSupported((fmt, supported)) = e {
                                 convert_format((format!("{:?}", fmt), supported.into_iter().map(|v| format!("{:?}", v)).collect::<Vec<String>>().join(",")));
@@ -1017,7 +1080,8 @@ impl RenderQueue {
         });
         let processing_done = util::qt_queued_callback_mut(self, move |this, _: ()| {
             if let Some(job) = this.jobs.get(&job_id) {
-                if core::filesystem::exists_in_folder(&job.render_options.output_folder, &job.render_options.output_filename.replace("_%05d", "_00001")) {
+                // An interrupted resumable render continues into the same output file
+                if core::filesystem::exists_in_folder(&job.render_options.output_folder, &job.render_options.output_filename.replace("_%05d", "_00001")) && !rendering::segments::can_resume(&job.stab, &job.render_options) {
                     let msg = QString::from(format!("file_exists:{}", serde_json::json!({ "filename": job.render_options.output_filename, "folder": job.render_options.output_folder })));
                     update_model!(this, job_id, itm {
                         itm.error_string = msg.clone();
@@ -1058,6 +1122,13 @@ impl RenderQueue {
                             background:             params.background,
                             adaptive_zoom_window:   params.adaptive_zoom_window,
                             lens_correction_amount: params.lens_correction_amount,
+                            output_projection:      params.output_projection,
+                            spherical:              params.spherical,
+                            motion_blur_shutter_angle: params.motion_blur_shutter_angle,
+                            motion_blur_samples:       params.motion_blur_samples,
+                            deblur:                    params.deblur,
+                            deblur_iterations:         params.deblur_iterations,
+                            deblur_exposure_ms:        params.deblur_exposure_ms,
                             background_mode:           params.background_mode,
                             background_margin:         params.background_margin,
                             background_margin_feather: params.background_margin_feather,
@@ -1442,7 +1513,7 @@ impl RenderQueue {
                         itm.output_folder   = QString::from(job.render_options.output_folder.as_str());
                         itm.display_output_path = QString::from(core::filesystem::display_folder_filename(job.render_options.output_folder.as_str(), job.render_options.output_filename.as_str()));
                         job.project_data = Self::get_gyroflow_data_internal(&job.stab, &job.additional_data, &job.render_options);
-                        if core::filesystem::exists_in_folder(&job.render_options.output_folder, &job.render_options.output_filename.replace("_%05d", "_00001")) {
+                        if core::filesystem::exists_in_folder(&job.render_options.output_folder, &job.render_options.output_filename.replace("_%05d", "_00001")) && !rendering::segments::can_resume(&job.stab, &job.render_options) {
                             let msg = QString::from(format!("file_exists:{}", serde_json::json!({ "filename": job.render_options.output_filename, "folder": job.render_options.output_folder })));
                             itm.error_string = msg.clone();
                             itm.status = JobStatus::Error;
@@ -0,0 +1,451 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Chunked rendering of a single video: the source is split at the keyframes into segments, which are rendered
+// concurrently with independent decoders and encoders, and then losslessly joined with mp4-merge.
+// The segments are rendered without audio, the audio is encoded once for the whole file when muxing the joined video.
+// Resumable renders keep the completed segments and a checkpoint next to the output, so an interrupted render
+// continues from the last completed segment, as long as the project and render settings are the same.
+
+use super::*;
+use super::ffmpeg_audio::AudioTranscoder;
+use super::ffmpeg_processor::{ FrameTimestamps, Status };
+use gyroflow_core::filesystem::{ self, FfmpegPathWrapper };
+use gyroflow_core::keyframes::KeyframeType;
+use gyroflow_core::stabilization_params::BackgroundMode;
+use ffmpeg_next::{ encoder, format, media, Dictionary, Rational, rescale::Rescale };
+use std::sync::atomic::{ AtomicUsize, Ordering::SeqCst };
+use parking_lot::Mutex;
+
+const MIN_SEGMENT_MS: f64 = 1000.0;
+const RESUMABLE_SEGMENT_MS: f64 = 60000.0; // At most this much is rendered again after a resumable render is interrupted
+const CHECKPOINT_VERSION: u32 = 1; // Checkpoints with a different version are discarded and the render starts from the beginning
+
+#[derive(Default, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RenderSegment {
+    pub index: usize,
+    pub ranges_ms: Vec<(Option<f64>, Option<f64>)>, // Source ranges, same as `FfmpegProcessor::ranges_ms`
+    pub frame_count: usize,
+}
+
+// Progress of a resumable render, saved next to the output file
+#[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RenderCheckpoint {
+    pub version: u32,
+    pub checksum: String, // `render_checksum` of the render which wrote the segments
+    pub segments: Vec<RenderSegment>,
+    pub completed: Vec<usize>, // Indices of the segments which are fully written
+}
+impl RenderCheckpoint {
+    pub fn filename(output_filename: &str) -> String {
+        format!("{output_filename}.render.json")
+    }
+    pub fn load(url: &str) -> Option<Self> {
+        if !filesystem::exists(url) { return None; }
+        let data = filesystem::read(url).ok()?;
+        serde_json::from_slice(&data).map_err(|e| ::log::warn!("Invalid render checkpoint {url}: {e:?}")).ok()
+    }
+    pub fn save(&self, url: &str) {
+        let result = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())
+            .and_then(|data| filesystem::write(url, &data).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            ::log::error!("Failed to save the render checkpoint {url}: {e}");
+        }
+    }
+}
+
+// Checksum of everything which affects the rendered segments: the project, the gyro data, the input file and the render settings
+pub fn render_checksum(stab: &StabilizationManager, input_file: &gyroflow_core::InputFile, render_options: &RenderOptions) -> String {
+    let mut hasher = crc32fast::Hasher::new();
+    if let Some(mut project) = stab.export_gyroflow_data(gyroflow_core::GyroflowProjectType::Simple, "{}", None).ok().and_then(|x| serde_json::from_str::<serde_json::Value>(&x).ok()) {
+        // These change with every export
+        if let Some(obj) = project.as_object_mut() {
+            obj.remove("date");
+            obj.remove("app_version");
+        }
+        hasher.update(project.to_string().as_bytes());
+    }
+    hasher.update(&stab.gyro.read().get_checksum().to_le_bytes());
+    hasher.update(input_file.url.as_bytes());
+    // The number of concurrent segments doesn't change the output
+    let options = RenderOptions { parallel_segments: 0, resumable: false, ..render_options.clone() };
+    hasher.update(serde_json::to_string(&options).unwrap_or_default().as_bytes());
+    format!("{:08x}", hasher.finalize())
+}
+
+// Segments are joined with mp4-merge, so only the mp4 and mov outputs can be split
+pub fn can_render_segmented(stab: &StabilizationManager, render_options: &RenderOptions) -> bool {
+    let ext = render_options.output_filename.rsplit('.').next().unwrap_or_default().to_ascii_lowercase();
+    let is_speed_changed = stab.params.read().video_speed != 1.0 || stab.keyframes.read().is_keyframed(&KeyframeType::VideoSpeed);
+    // Temporal fill needs the neighbouring frames, which are not available at the segment boundaries
+    let is_temporal_fill = stab.params.read().background_mode == BackgroundMode::TemporalFill;
+
+    (render_options.parallel_segments > 1 || render_options.resumable) && (ext == "mp4" || ext == "mov") && !render_options.preserve_other_tracks && !is_speed_changed && !is_temporal_fill
+}
+
+// Whether there's an interrupted resumable render of the same project and settings, which will be continued
+pub fn can_resume(stab: &StabilizationManager, render_options: &RenderOptions) -> bool {
+    if !render_options.resumable || !can_render_segmented(stab, render_options) { return false; }
+    let checkpoint_url = filesystem::get_file_url(&render_options.output_folder, &RenderCheckpoint::filename(&render_options.output_filename), true);
+    let input_file = stab.input_file.read().clone();
+    RenderCheckpoint::load(&checkpoint_url).map_or(false, |x| x.version == CHECKPOINT_VERSION && x.checksum == render_checksum(stab, &input_file, render_options))
+}
+
+// Name of the file of a single segment, next to the output file
+pub fn segment_filename(output_filename: &str, suffix: &str) -> String {
+    match output_filename.rfind('.') {
+        Some(pos) => format!("{}.part-{}{}", &output_filename[..pos], suffix, &output_filename[pos..]),
+        None => format!("{output_filename}.part-{suffix}")
+    }
+}
+
+// Source ranges in milliseconds which are rendered, in the same form as `FfmpegProcessor::ranges_ms`
+pub fn source_ranges(stab: &StabilizationManager, render_options: &RenderOptions) -> Vec<(Option<f64>, Option<f64>)> {
+    let params = stab.params.read();
+    let duration_ms = params.duration_ms;
+    if render_options.pad_with_black || params.trim_ranges.is_empty() {
+        return vec![(None, None)];
+    }
+    params.trim_ranges.iter().map(|x| (if x.0 > 0.0 { Some(x.0 * duration_ms) } else { None }, if x.1 < 1.0 { Some(x.1 * duration_ms) } else { None })).collect()
+}
+
+// Splits the source ranges into `count` segments of similar length, cut at the nearest keyframes
+pub fn split_ranges(ranges_ms: &[(Option<f64>, Option<f64>)], duration_ms: f64, keyframes: &[f64], fps: f64, count: usize) -> Vec<RenderSegment> {
+    let ranges: Vec<(f64, f64)> = ranges_ms.iter().map(|x| (x.0.unwrap_or(0.0), x.1.unwrap_or(duration_ms))).collect();
+    let total: f64 = ranges.iter().map(|x| x.1 - x.0).sum();
+    let frame_duration = 1000.0 / fps.max(1.0);
+
+    let mut cuts: Vec<f64> = Vec::new();
+    for i in 1..count {
+        let mut target = total * i as f64 / count as f64;
+        let Some(&(start, end)) = ranges.iter().find(|r| if target <= r.1 - r.0 { true } else { target -= r.1 - r.0; false }) else { continue; };
+        let position = start + target;
+
+        // Without a keyframe nearby the decoder just starts from the previous one
+        let cut = keyframes.iter().copied()
+            .filter(|&k| k > start + MIN_SEGMENT_MS && k < end - MIN_SEGMENT_MS)
+            .min_by(|a, b| (a - position).abs().total_cmp(&(b - position).abs()))
+            .unwrap_or(position);
+
+        if cut > start + MIN_SEGMENT_MS && cut < end - MIN_SEGMENT_MS && cuts.last().map_or(true, |&x| cut - x >= MIN_SEGMENT_MS) {
+            cuts.push(cut);
+        }
+    }
+
+    let to_option = |start: f64, end: f64| (if start > 0.0 { Some(start) } else { None }, if end < duration_ms { Some(end) } else { None });
+
+    let mut segments = Vec::new();
+    let mut current = Vec::new();
+    let mut length = 0.0;
+    for &(start, end) in &ranges {
+        let mut from = start;
+        for &cut in cuts.iter().filter(|&&x| x > start && x < end) {
+            // The frame at the cut belongs to the next segment
+            current.push(to_option(from, cut - frame_duration / 2.0));
+            length += cut - from;
+            segments.push(RenderSegment { index: segments.len(), ranges_ms: std::mem::take(&mut current), frame_count: (length / frame_duration).round() as usize });
+            length = 0.0;
+            from = cut;
+        }
+        current.push(to_option(from, end));
+        length += end - from;
+    }
+    segments.push(RenderSegment { index: segments.len(), ranges_ms: current, frame_count: (length / frame_duration).round() as usize });
+    segments
+}
+
+pub fn render_segmented<F, F2>(stab: Arc<StabilizationManager>, progress: F, input_file: &gyroflow_core::InputFile, render_options: &RenderOptions, gpu_decoder_index: i32, cancel_flag: Arc<AtomicBool>, pause_flag: Arc<AtomicBool>, encoder_initialized: F2) -> Result<(), FFmpegError>
+    where F: Fn((f64, usize, usize, bool, bool)) + Send + Sync + Clone,
+          F2: Fn(String) + Send + Sync + Clone
+{
+    let ranges = source_ranges(&stab, render_options);
+    let (duration_ms, fps, spherical) = {
+        let params = stab.params.read();
+        (params.duration_ms, params.fps, params.spherical)
+    };
+    let keyframes = FfmpegProcessor::get_keyframes(&input_file.url).unwrap_or_else(|e| {
+        ::log::warn!("Failed to read the keyframes: {e:?}");
+        Vec::new()
+    });
+    let concurrency = render_options.parallel_segments.max(1);
+    let count = if render_options.resumable {
+        // Split by the duration only, so the render can be resumed with a different concurrency
+        let total_ms: f64 = ranges.iter().map(|x| x.1.unwrap_or(duration_ms) - x.0.unwrap_or(0.0)).sum();
+        ((total_ms / RESUMABLE_SEGMENT_MS).ceil() as usize).max(2)
+    } else {
+        concurrency
+    };
+    let segments = split_ranges(&ranges, duration_ms, &keyframes, fps, count);
+    ::log::debug!("Rendering in segments: {:?}", segments);
+    if segments.len() < 2 {
+        return render(stab, progress, input_file, render_options, gpu_decoder_index, None, cancel_flag, pause_flag, encoder_initialized, None);
+    }
+
+    let folder = render_options.output_folder.clone();
+    let part_url = |index: usize| filesystem::get_file_url(&folder, &segment_filename(&render_options.output_filename, &format!("{:03}", index + 1)), true);
+    let part_urls: Vec<String> = segments.iter().map(|x| part_url(x.index)).collect();
+    let remove_parts = || for url in &part_urls { let _ = filesystem::remove_file(url); };
+
+    let checkpoint_url = filesystem::get_file_url(&folder, &RenderCheckpoint::filename(&render_options.output_filename), true);
+    let mut checkpoint = RenderCheckpoint { version: CHECKPOINT_VERSION, segments: segments.clone(), ..Default::default() };
+    if render_options.resumable {
+        checkpoint.checksum = render_checksum(&stab, input_file, render_options);
+        match RenderCheckpoint::load(&checkpoint_url) {
+            Some(saved) if saved.version == CHECKPOINT_VERSION && saved.checksum == checkpoint.checksum && saved.segments == segments => {
+                checkpoint.completed = saved.completed.into_iter().filter(|&i| i < segments.len() && filesystem::exists(&part_urls[i])).collect();
+                ::log::info!("Resuming the render, completed segments: {:?}", checkpoint.completed);
+            },
+            Some(saved) => {
+                ::log::info!("The project or render settings changed since the render was interrupted, starting from the beginning");
+                for x in &saved.segments { let _ = filesystem::remove_file(&part_url(x.index)); }
+            },
+            None => { }
+        }
+        checkpoint.save(&checkpoint_url);
+    }
+
+    let total_frames: usize = segments.iter().map(|x| x.frame_count).sum();
+    let rendered_frames: Vec<AtomicUsize> = segments.iter().map(|x| AtomicUsize::new(if checkpoint.completed.contains(&x.index) { x.frame_count } else { 0 })).collect();
+    let pending: Vec<&RenderSegment> = segments.iter().filter(|x| !checkpoint.completed.contains(&x.index)).collect();
+    let next_pending = AtomicUsize::new(0);
+    let checkpoint = Mutex::new(checkpoint);
+    let abort = Arc::new(AtomicBool::new(false));
+
+    let results = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..concurrency.min(pending.len())).map(|_| {
+            let (rendered_frames, progress, pending, next_pending, checkpoint, checkpoint_url, stab) = (&rendered_frames, &progress, &pending, &next_pending, &checkpoint, &checkpoint_url, &stab);
+            let (abort, pause_flag, encoder_initialized) = (abort.clone(), pause_flag.clone(), encoder_initialized.clone());
+
+            s.spawn(move || -> Result<(), FFmpegError> {
+                // Every worker takes the next segment which isn't rendered yet
+                while let Some(&segment) = pending.get(next_pending.fetch_add(1, SeqCst)) {
+                    if abort.load(SeqCst) { break; }
+                    let segment_progress = move |(_, current_frame, _, _, _): (f64, usize, usize, bool, bool)| {
+                        rendered_frames[segment.index].store(current_frame, SeqCst);
+                        let done: usize = rendered_frames.iter().map(|x| x.load(SeqCst)).sum();
+                        progress((done as f64 / total_frames.max(1) as f64, done, total_frames, false, false));
+                    };
+                    // Every segment needs its own manager, because `render` changes the sizes in the params
+                    let segment_stab = Arc::new(StabilizationManager {
+                        pose_estimator: stab.pose_estimator.clone(),
+                        ..stab.get_cloned()
+                    });
+                    let mut segment_options = render_options.clone();
+                    segment_options.output_filename = segment_filename(&render_options.output_filename, &format!("{:03}", segment.index + 1));
+                    segment_options.audio = false;
+
+                    if let Err(e) = render(segment_stab, segment_progress, input_file, &segment_options, gpu_decoder_index, None, abort.clone(), pause_flag.clone(), encoder_initialized.clone(), Some(segment)) {
+                        abort.store(true, SeqCst);
+                        return Err(e);
+                    }
+                    // `render` also returns when cancelled, so the segment can be incomplete
+                    if abort.load(SeqCst) { break; }
+                    if render_options.resumable {
+                        let mut checkpoint = checkpoint.lock();
+                        checkpoint.completed.push(segment.index);
+                        checkpoint.save(checkpoint_url);
+                    }
+                }
+                Ok(())
+            })
+        }).collect();
+
+        while !handles.iter().all(|x| x.is_finished()) {
+            if cancel_flag.load(SeqCst) {
+                abort.store(true, SeqCst);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        handles.into_iter().map(|x| x.join().unwrap_or(Err(FFmpegError::InternalError(Error::Bug)))).collect::<Vec<_>>()
+    });
+
+    // Resumable renders keep the completed segments
+    if let Some(e) = results.into_iter().find_map(|x| x.err()) {
+        if !render_options.resumable { remove_parts(); }
+        return Err(e);
+    }
+    if cancel_flag.load(SeqCst) {
+        if !render_options.resumable { remove_parts(); }
+        return Ok(());
+    }
+
+    let output_url = filesystem::get_file_url(&folder, &render_options.output_filename, true);
+    let result = join_segments(&part_urls, &folder, &render_options.output_filename, render_options.audio.then(|| (input_file.url.as_str(), ranges.as_slice(), render_options.get_audio_codec())), spherical, cancel_flag);
+    if let Ok(false) = result {
+        // Cancelled while muxing, the output is incomplete. Resumable renders only need to be joined again
+        let _ = filesystem::remove_file(&output_url);
+        if !render_options.resumable { remove_parts(); }
+        return Ok(());
+    }
+    if result.is_ok() || !render_options.resumable {
+        remove_parts();
+        if render_options.resumable { let _ = filesystem::remove_file(&checkpoint_url); }
+    }
+    result?;
+
+    crate::util::update_file_times(&output_url, &input_file.url);
+    progress((1.0, total_frames, total_frames, true, false));
+    Ok(())
+}
+
+// Joins the rendered segments into the output file. With `audio`, the audio of the source file is encoded once for the whole output.
+// Returns false when cancelled before the output was completed
+pub fn join_segments(part_urls: &[String], folder: &str, output_filename: &str, audio: Option<(&str, &[(Option<f64>, Option<f64>)], codec::Id)>, spherical: bool, cancel_flag: Arc<AtomicBool>) -> Result<bool, FFmpegError> {
+    let base = filesystem::get_engine_base();
+    let output_url = filesystem::get_file_url(folder, output_filename, true);
+    let video_url = if audio.is_some() { filesystem::get_file_url(folder, &segment_filename(output_filename, "video"), true) } else { output_url.clone() };
+
+    {
+        let mut opened = Vec::with_capacity(part_urls.len());
+        for url in part_urls {
+            opened.push(filesystem::open_file(&base, url, false).map_err(|e| FFmpegError::CannotOpenInputFile((url.clone(), e)))?);
+        }
+        let mut file_references: Vec<(&mut std::fs::File, usize)> = opened.iter_mut().map(|x| { let s = x.size; (x.get_file(), s) }).collect();
+        let mut opened_output = filesystem::open_file(&base, &video_url, true).map_err(|e| FFmpegError::CannotOpenOutputFile((video_url.clone(), e)))?;
+        mp4_merge::join_file_streams(&mut file_references, opened_output.get_file(), |_| { }).map_err(|e| FFmpegError::CannotMergeSegments(e.to_string()))?;
+    }
+
+    if let Some((source_url, ranges_ms, audio_codec)) = audio {
+        let result = mux_audio(&video_url, source_url, ranges_ms, audio_codec, &output_url, spherical, cancel_flag);
+        let _ = filesystem::remove_file(&video_url);
+        return result;
+    }
+    Ok(true)
+}
+
+fn open_input(file: &FfmpegPathWrapper) -> Result<format::context::Input, FFmpegError> {
+    let mut dict = Dictionary::new();
+    let mut path = file.path.as_str();
+    if let Some(fd) = path.strip_prefix("fd:") {
+        dict.set("fd", fd);
+        path = "fd:";
+    }
+    Ok(format::input_with_dictionary(&path, dict)?)
+}
+
+// Audio of the source file, decoded in the same ranges as the video
+struct AudioSource {
+    input_context: format::context::Input,
+    stream_index: usize,
+    transcoder: AudioTranscoder,
+    ranges_ms: Vec<(Option<f64>, Option<f64>)>,
+    current_range: usize,
+    frame_ts: FrameTimestamps,
+}
+impl AudioSource {
+    fn seek_to_range(&mut self, index: usize) -> Result<(), FFmpegError> {
+        self.current_range = index;
+        if let Some(start) = self.ranges_ms[index].0 {
+            let position = (start as i64).rescale((1, 1000), ffmpeg_next::rescale::TIME_BASE);
+            self.input_context.seek(position, ..position)?;
+        }
+        if index > 0 {
+            self.frame_ts.add_audio = self.frame_ts.last_audio.unwrap_or_default() + self.frame_ts.last_duration_audio;
+            self.frame_ts.first = None;
+        }
+        Ok(())
+    }
+
+    // Processes the next packet, returns false when all ranges are done
+    fn process_next(&mut self, octx: &mut format::context::Output, ost_time_base: Rational) -> Result<bool, FFmpegError> {
+        let (start_ms, end_ms) = self.ranges_ms[self.current_range];
+        let mut range_finished = true;
+        if let Some((stream, mut packet)) = self.input_context.packets().next() {
+            if stream.index() != self.stream_index {
+                return Ok(true);
+            }
+            packet.rescale_ts(stream.time_base(), self.transcoder.decoder.time_base());
+            self.transcoder.decoder.send_packet(&packet)?;
+            range_finished = self.transcoder.receive_and_process_decoded_frames(octx, ost_time_base, start_ms, end_ms, &mut self.frame_ts)? == Status::Finish;
+        }
+        if !range_finished {
+            return Ok(true);
+        }
+        if self.current_range + 1 < self.ranges_ms.len() {
+            self.seek_to_range(self.current_range + 1)?;
+            return Ok(true);
+        }
+        self.transcoder.flush(octx, ost_time_base, start_ms, end_ms, &mut self.frame_ts)?;
+        Ok(false)
+    }
+}
+
+// Copies the video stream of `video_url` and adds the audio of `source_url` in the `ranges_ms`, encoded with `audio_codec`
+fn mux_audio(video_url: &str, source_url: &str, ranges_ms: &[(Option<f64>, Option<f64>)], audio_codec: codec::Id, output_url: &str, spherical: bool, cancel_flag: Arc<AtomicBool>) -> Result<bool, FFmpegError> {
+    let base = filesystem::get_engine_base();
+    let video_file  = FfmpegPathWrapper::new(&base, video_url,  false).map_err(|e| FFmpegError::CannotOpenInputFile((video_url.to_string(), e)))?;
+    let source_file = FfmpegPathWrapper::new(&base, source_url, false).map_err(|e| FFmpegError::CannotOpenInputFile((source_url.to_string(), e)))?;
+    let mut output_file = FfmpegPathWrapper::new(&base, output_url, true).map_err(|e| FFmpegError::CannotOpenOutputFile((output_url.to_string(), e)))?;
+
+    let mut video_context = open_input(&video_file)?;
+    let source_context = open_input(&source_file)?;
+
+    let mut output_options = Dictionary::new();
+    let output_format = output_url.rsplit('.').next().unwrap_or("mp4").to_ascii_lowercase();
+    if output_file.path.starts_with("fd:") {
+        output_options.set("fd", &output_file.path[3..]);
+        output_file.path = "fd:".into();
+    }
+    let mut octx = format::output_as_with(&output_file.path, &output_format, output_options)?;
+    if spherical {
+        // mov muxer writes the sv3d spherical atom only in unofficial mode
+        unsafe { (*octx.as_mut_ptr()).strict_std_compliance = ffi::FF_COMPLIANCE_UNOFFICIAL; }
+    }
+
+    let video_index = video_context.streams().best(media::Type::Video).map(|x| x.index()).ok_or(Error::StreamNotFound)?;
+    {
+        let ist = video_context.stream(video_index).ok_or(Error::StreamNotFound)?;
+        let mut ost = octx.add_stream(encoder::find(codec::Id::None))?;
+        ost.set_parameters(ist.parameters());
+        unsafe { (*ost.parameters().as_mut_ptr()).codec_tag = 0; }
+        ost.set_time_base(ist.time_base());
+        ost.set_avg_frame_rate(ist.avg_frame_rate());
+    }
+
+    let audio_stream = source_context.streams().best(media::Type::Audio).map(|x| x.index());
+    let mut audio = match audio_stream {
+        Some(stream_index) => {
+            let transcoder = AudioTranscoder::new(audio_codec, &source_context.stream(stream_index).ok_or(Error::StreamNotFound)?, &mut octx, 1)?;
+            let mut source = AudioSource { input_context: source_context, stream_index, transcoder, ranges_ms: ranges_ms.to_vec(), current_range: 0, frame_ts: FrameTimestamps::default() };
+            source.seek_to_range(0)?;
+            Some(source)
+        },
+        None => None
+    };
+
+    octx.set_metadata(video_context.metadata().to_owned());
+    octx.write_header()?;
+
+    let video_time_base = octx.stream(0).ok_or(Error::StreamNotFound)?.time_base();
+    let audio_time_base = octx.stream(1).map(|x| x.time_base()).unwrap_or(video_time_base);
+
+    for (stream, mut packet) in video_context.packets() {
+        if cancel_flag.load(SeqCst) { break; }
+        if stream.index() != video_index { continue; }
+
+        // Keep the audio ahead of the video, so the muxer doesn't have to buffer the whole file
+        let video_us = packet.dts().or(packet.pts()).unwrap_or_default().rescale(stream.time_base(), (1, 1000000));
+        while let Some(source) = &mut audio {
+            if source.frame_ts.last_audio.map_or(false, |x| x >= video_us) { break; }
+            if !source.process_next(&mut octx, audio_time_base)? {
+                audio = None;
+            }
+        }
+
+        packet.rescale_ts(stream.time_base(), video_time_base);
+        packet.set_position(-1);
+        packet.set_stream(0);
+        packet.write_interleaved(&mut octx)?;
+    }
+    while let Some(source) = &mut audio {
+        if cancel_flag.load(SeqCst) || !source.process_next(&mut octx, audio_time_base)? {
+            audio = None;
+        }
+    }
+    if cancel_flag.load(SeqCst) {
+        return Ok(false);
+    }
+
+    octx.write_trailer()?;
+    Ok(true)
+}
@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+// Telemetry and debug overlays burned into the rendered frames: timecode, frame number, gyro rates, GPS speed,
+// current FOV, artificial horizon, crop rectangle, and the detected features and optical flow used for the sync.
+
+use gyroflow_core::{ StabilizationManager, gpu::BufferDescription, util::MapClosest };
+use gyroflow_core::stabilization::{ ComputeParams, FrameTransform, PixelType, undistort_points_with_rolling_shutter };
+use gyroflow_core::smoothing::horizon::{ HorizonLock, lock_horizon_angle };
+use nalgebra::Vector4;
+use super::{ overlay, render_queue::RenderOverlays };
+
+type Rect = (usize, usize, usize, usize); // x, y, width, height
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum OverlayPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+impl OverlayPosition {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "Top right"    => Self::TopRight,
+            "Bottom left"  => Self::BottomLeft,
+            "Bottom right" => Self::BottomRight,
+            _ => Self::TopLeft
+        }
+    }
+}
+
+// Overlay colors, already converted to the plane format
+#[derive(Default, Clone, Copy, Debug)]
+pub struct OverlayColors {
+    pub text: Vector4<f32>,
+    pub shadow: Vector4<f32>,
+    pub horizon: Vector4<f32>,
+    pub crop: Vector4<f32>,
+    pub features: Vector4<f32>,
+    pub optical_flow: Vector4<f32>,
+}
+
+// Values for a single frame, the same for all planes
+#[derive(Default, Clone, Debug)]
+pub struct FrameTelemetry {
+    pub lines: Vec<String>,
+    pub horizon_angle: Option<f64>, // Radians, clockwise
+    pub crop_scale: Option<f64>, // Size of the crop rectangle relative to the output frame
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct TelemetryOverlay {
+    options: RenderOverlays,
+    position: OverlayPosition,
+}
+
+impl TelemetryOverlay {
+    pub fn new(options: &RenderOverlays) -> Self {
+        Self {
+            options: options.clone(),
+            position: OverlayPosition::from_name(&options.position)
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        let o = &self.options;
+        o.timecode || o.frame_number || o.horizon || o.gyro_rates || o.gps_speed || o.fov || o.crop_rect || o.features || o.optical_flow
+    }
+
+    pub fn frame_telemetry(&self, stab: &StabilizationManager, timestamp_us: i64, transform: &FrameTransform, out_size: (usize, usize)) -> FrameTelemetry {
+        let mut ret = FrameTelemetry::default();
+        if !self.is_enabled() { return ret; }
+
+        let timestamp_ms = timestamp_us as f64 / 1000.0;
+        let fps = stab.params.read().fps;
+        let gyro = stab.gyro.read();
+        let gyro_timestamp_ms = timestamp_ms - gyro.offset_at_video_timestamp(timestamp_ms);
+
+        if self.options.timecode {
+            let total_ms = timestamp_ms.max(0.0);
+            let seconds = (total_ms / 1000.0).floor() as u64;
+            let frames = ((total_ms - seconds as f64 * 1000.0) * fps / 1000.0).floor() as u64;
+            ret.lines.push(format!("{:02}:{:02}:{:02}:{:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60, frames));
+        }
+        if self.options.frame_number {
+            ret.lines.push(format!("Frame {}", gyroflow_core::frame_at_timestamp(timestamp_ms, fps)));
+        }
+        if self.options.gyro_rates {
+            let imu = &gyro.file_metadata.raw_imu;
+            let i = imu.partition_point(|x| x.timestamp_ms < gyro_timestamp_ms);
+            let closest = [i.checked_sub(1), Some(i)].into_iter().flatten()
+                .filter_map(|i| imu.get(i))
+                .min_by(|a, b| (a.timestamp_ms - gyro_timestamp_ms).abs().total_cmp(&(b.timestamp_ms - gyro_timestamp_ms).abs()));
+            if let Some(g) = closest.and_then(|x| x.gyro) {
+                ret.lines.push(format!("Gyro {:.1} {:.1} {:.1} °/s", g[0], g[1], g[2]));
+            }
+        }
+        if self.options.gps_speed {
+            let gyro_timestamp_us = (gyro_timestamp_ms * 1000.0).round() as i64;
+            if let Some(speed) = gyro.file_metadata.gps_speed.get_closest(&gyro_timestamp_us, 2_000_000) { // closest within 2s
+                ret.lines.push(format!("GPS {:.1} km/h", speed));
+            }
+        }
+        if self.options.fov {
+            let f = transform.kernel_params.output_f[0] as f64;
+            if f > 0.0 {
+                let r = out_size.0 as f64 / 2.0 / f;
+                let angle = match transform.kernel_params.output_projection {
+                    1 => 2.0 * r,              // Equidistant fisheye
+                    2 => 4.0 * (r / 2.0).atan(), // Stereographic
+                    3 => 2.0 * r,              // Equirectangular
+                    _ => 2.0 * r.atan()
+                };
+                ret.lines.push(format!("FOV {:.1}° ({:.2}x)", angle.to_degrees(), transform.fov));
+            }
+        }
+        if self.options.horizon {
+            // Rotation around the view axis which makes the stabilized view level, same as in `HorizonLock::lock`
+            let smoothed = gyro.smoothed_quat_at_timestamp(timestamp_ms);
+            let gravity = gyro.file_metadata.gravity_vectors.as_ref()
+                .filter(|x| gyro.use_gravity_vectors && !x.is_empty())
+                .and_then(|x| HorizonLock::interpolate_gravity_vector(x, (gyro_timestamp_ms * 1000.0).round() as i64));
+            ret.horizon_angle = Some(if let Some(gv) = gravity {
+                let correction = gyro.org_quat_at_timestamp(timestamp_ms).inverse() * smoothed;
+                let correction = correction.to_rotation_matrix();
+                gv[0].atan2(gv[1]) - (-correction[(0, 1)]).atan2(correction[(0, 0)])
+            } else {
+                let level = (smoothed.inverse() * lock_horizon_angle(&smoothed, 0.0)).to_rotation_matrix();
+                level[(1, 0)].atan2(level[(0, 0)])
+            });
+        }
+        if self.options.crop_rect && transform.fov > 0.0 {
+            // Part of the output which is always covered by the image, the same as the safe area in the preview
+            ret.crop_scale = Some((transform.minimal_fov / transform.fov).min(1.0));
+        }
+        ret
+    }
+
+    // Features and optical flow are drawn on the input, so they go through the stabilization like the image
+    pub fn draws_points(&self) -> bool {
+        self.options.features || self.options.optical_flow
+    }
+
+    // Draws the detected features and the optical flow in the `rect` of the output plane.
+    // The points are in the input frame, so they are moved to the stabilized frame with the same parameters the plane uses.
+    // They are not drawn on the input frame, because it's also used for the original frame in the comparison layouts and for the temporal fill
+    pub fn draw_points<T: PixelType>(&self, stab: &StabilizationManager, timestamp_us: i64, params: &ComputeParams, output: &mut BufferDescription, rect: Rect, colors: &OverlayColors) {
+        if !self.draws_points() || rect.2 == 0 || rect.3 == 0 { return; }
+        let size = (params.width, params.height);
+        let dot = (rect.3 / 360).max(1);
+
+        let mut points = Vec::new(); // (x, y, size, color, alpha)
+        if self.options.optical_flow {
+            let of_method = stab.params.read().of_method;
+            if let Some(pxs) = stab.get_opticalflow_pixels(timestamp_us, if of_method == 2 { 1 } else { 3 }, size) {
+                points.extend(pxs.into_iter().map(|(x, y, frame)| (x, y, dot, colors.optical_flow, 1.0 - frame as f32 * 0.3)));
+            }
+        }
+        if self.options.features {
+            if let Some(pxs) = stab.get_features_pixels(timestamp_us, size) {
+                points.extend(pxs.into_iter().map(|(x, y)| (x, y, dot * 3, colors.features, 1.0)));
+            }
+        }
+        if points.is_empty() { return; }
+
+        let distorted = points.iter().map(|(x, y, ..)| (*x as f32, *y as f32)).collect::<Vec<_>>();
+        let undistorted = undistort_points_with_rolling_shutter(&distorted, timestamp_us as f64 / 1000.0, params, params.lens_correction_amount, true);
+        for ((x, y), (_, _, s, color, alpha)) in undistorted.into_iter().zip(points.into_iter()) {
+            let (x, y) = (x - (s / 2) as f32, y - (s / 2) as f32);
+            if x < 0.0 || y < 0.0 || x as usize + s > rect.2 || y as usize + s > rect.3 { continue; }
+            overlay::fill_rect::<T>(output, (rect.0 + x as usize, rect.1 + y as usize, s, s), color, alpha);
+        }
+    }
+
+    // Draws the overlays in the `rect` of the output plane, where the stabilized frame is
+    pub fn draw<T: PixelType>(&self, output: &mut BufferDescription, rect: Rect, telemetry: &FrameTelemetry, colors: &OverlayColors) {
+        if rect.2 == 0 || rect.3 == 0 { return; }
+        let line_width = (rect.3 as f32 / 360.0).max(1.0);
+
+        if let Some(scale) = telemetry.crop_scale {
+            let (w, h) = ((rect.2 as f64 * scale).round() as usize, (rect.3 as f64 * scale).round() as usize);
+            let crop = (rect.0 + (rect.2 - w) / 2, rect.1 + (rect.3 - h) / 2, w, h);
+            overlay::draw_rect_outline::<T>(output, crop, line_width as usize, colors.crop);
+        }
+
+        if let Some(angle) = telemetry.horizon_angle {
+            let center = (rect.0 as f32 + rect.2 as f32 / 2.0, rect.1 as f32 + rect.3 as f32 / 2.0);
+            let (sin, cos) = (angle as f32).sin_cos();
+            // Clip the line to the stabilized frame
+            let half_len = (rect.2 as f32 / 2.0 / cos.abs().max(0.0001)).min(rect.3 as f32 / 2.0 / sin.abs().max(0.0001)) - line_width;
+            let from = (center.0 - cos * half_len, center.1 - sin * half_len);
+            let to   = (center.0 + cos * half_len, center.1 + sin * half_len);
+            overlay::draw_line::<T>(output, (from.0 + line_width, from.1 + line_width), (to.0 + line_width, to.1 + line_width), line_width, colors.shadow);
+            overlay::draw_line::<T>(output, from, to, line_width, colors.horizon);
+        }
+
+        if !telemetry.lines.is_empty() {
+            let pixel_size = (rect.3 as f64 * self.options.text_size / 100.0) as f32 / 7.0;
+            let margin = pixel_size * 4.0;
+            let line_height = (pixel_size * 10.0).ceil();
+            let block_height = line_height * telemetry.lines.len() as f32 - pixel_size * 3.0;
+            let top = match self.position {
+                OverlayPosition::TopLeft | OverlayPosition::TopRight => rect.1 as f32 + margin,
+                _ => rect.1 as f32 + rect.3 as f32 - margin - block_height
+            };
+            for (i, line) in telemetry.lines.iter().enumerate() {
+                let x = match self.position {
+                    OverlayPosition::TopLeft | OverlayPosition::BottomLeft => rect.0 as f32 + margin,
+                    _ => rect.0 as f32 + rect.2 as f32 - margin - overlay::text_size(line, pixel_size).0 as f32
+                };
+                overlay::draw_text::<T>(output, (x, top + line_height * i as f32), pixel_size, line, colors.text, Some(colors.shadow));
+            }
+        }
+    }
+}
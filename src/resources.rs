@@ -69,17 +69,32 @@ qrc!(pub rsrc,
         "src/qt_gpu/compiled/undistort_opencv_fisheye.frag.qsb",
         "src/qt_gpu/compiled/undistort_opencv_fisheye_gopro_hyperview.frag.qsb",
         "src/qt_gpu/compiled/undistort_opencv_fisheye_gopro_superview.frag.qsb",
+        "src/qt_gpu/compiled/undistort_opencv_standard_nonlinear_stretch.frag.qsb",
         "src/qt_gpu/compiled/undistort_opencv_standard_digital_stretch.frag.qsb",
+        "src/qt_gpu/compiled/undistort_opencv_fisheye_nonlinear_stretch.frag.qsb",
         "src/qt_gpu/compiled/undistort_opencv_fisheye_digital_stretch.frag.qsb",
         "src/qt_gpu/compiled/undistort_opencv_standard.frag.qsb",
+        "src/qt_gpu/compiled/undistort_poly3_nonlinear_stretch.frag.qsb",
         "src/qt_gpu/compiled/undistort_poly3_digital_stretch.frag.qsb",
         "src/qt_gpu/compiled/undistort_poly3.frag.qsb",
+        "src/qt_gpu/compiled/undistort_poly5_nonlinear_stretch.frag.qsb",
         "src/qt_gpu/compiled/undistort_poly5_digital_stretch.frag.qsb",
         "src/qt_gpu/compiled/undistort_poly5.frag.qsb",
+        "src/qt_gpu/compiled/undistort_ptlens_nonlinear_stretch.frag.qsb",
         "src/qt_gpu/compiled/undistort_ptlens_digital_stretch.frag.qsb",
         "src/qt_gpu/compiled/undistort_ptlens.frag.qsb",
+        "src/qt_gpu/compiled/undistort_insta360_nonlinear_stretch.frag.qsb",
         "src/qt_gpu/compiled/undistort_insta360_digital_stretch.frag.qsb",
         "src/qt_gpu/compiled/undistort_insta360.frag.qsb",
+        "src/qt_gpu/compiled/undistort_kannala_brandt_nonlinear_stretch.frag.qsb",
+        "src/qt_gpu/compiled/undistort_kannala_brandt_digital_stretch.frag.qsb",
+        "src/qt_gpu/compiled/undistort_kannala_brandt.frag.qsb",
+        "src/qt_gpu/compiled/undistort_division_nonlinear_stretch.frag.qsb",
+        "src/qt_gpu/compiled/undistort_division_digital_stretch.frag.qsb",
+        "src/qt_gpu/compiled/undistort_division.frag.qsb",
+        "src/qt_gpu/compiled/undistort_scaramuzza_nonlinear_stretch.frag.qsb",
+        "src/qt_gpu/compiled/undistort_scaramuzza_digital_stretch.frag.qsb",
+        "src/qt_gpu/compiled/undistort_scaramuzza.frag.qsb",
 
         "resources/translations/cs.qm",
         "resources/translations/da.qm",
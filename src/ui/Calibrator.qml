@@ -86,6 +86,7 @@ Window {
             videoArea.videoLoader.cancelable = true;
             if (!videoArea.videoLoader.active) {
                 Qt.callLater(controller.recompute_threaded);
+                lensCalib.updateReport();
                 let model = [];
                 model[QT_TRANSLATE_NOOP("TableList", "Reprojection error")] = rms == 0? "---" : rms.toLocaleString(Qt.locale(), "f", 5);
                 model[QT_TRANSLATE_NOOP("TableList", "Good frames")] = good;
@@ -31,9 +31,9 @@ Modal {
             "IMU orientation":    ["imu_orientation"],
             "Integration method": ["integration_method"],
         },
-        "Trim range": ["trim_ranges"],
+        "Trim range": ["trim_ranges", "trim_range_overrides"],
         "Offsets":    ["offsets"],
-        "Keyframes":  ["keyframes"]
+        "Keyframes":  ["keyframes", "keyframe_expressions", "keyframe_telemetry_bindings"]
     },
     { // Right column
         "Synchronization|synchronization": {
@@ -50,6 +50,10 @@ Modal {
             "Rolling shutter correction": ["frame_readout_time"],
             "Zooming":                    ["adaptive_zoom_window", "adaptive_zoom_center_offset", "adaptive_zoom_method"],
             "Lens correction strength":   ["lens_correction_amount"],
+            "Output projection":          ["output_projection"],
+            "360° video":                 ["spherical"],
+            "Motion blur":                ["motion_blur_shutter_angle", "motion_blur_samples"],
+            "Deblur":                     ["deblur", "deblur_iterations", "deblur_exposure_ms"],
             "Video speed":                ["video_speed", "video_speed_affects_smoothing", "video_speed_affects_zooming"],
         },
         "Export settings|output": {
@@ -66,7 +70,7 @@ Modal {
         }
     }];
 
-    property var defaultOff: ["trim_ranges", "offsets", "video_infofps_scale", "video_inforotation", "synchronizationdo_autosync"];
+    property var defaultOff: ["trim_ranges", "trim_range_overrides", "offsets", "video_infofps_scale", "video_inforotation", "synchronizationdo_autosync"];
 
     text: isPreset? qsTr("Select settings you want to include in the preset")
                   : qsTr("Select settings you want to apply to all items in the render queue");
@@ -113,6 +117,10 @@ Modal {
             QT_TR_NOOP("Rolling shutter correction");
             QT_TR_NOOP("Zooming");
             QT_TR_NOOP("Lens correction strength");
+            QT_TR_NOOP("Output projection");
+            QT_TR_NOOP("360° video");
+            QT_TR_NOOP("Motion blur");
+            QT_TR_NOOP("Deblur");
             QT_TR_NOOP("Video speed");
         QT_TR_NOOP("Export settings");
             QT_TR_NOOP("Codec");
@@ -88,6 +88,71 @@ Row {
                     text: qsTr("Add keyframe");
                     onTriggered: controller.set_keyframe(root.keyframe, window.videoArea.timeline.getTimestampUs(), root.value);
                 }
+                Action {
+                    enabled: root.keyframe.length > 0;
+                    text: qsTr("Set expression...");
+                    onTriggered: {
+                        const variables = "t, time_ms, frame, fps, duration, speed, gyro, gyro_x, gyro_y, gyro_z, gps_speed, altitude, lens_position, pi, e";
+                        const functions = "sin, cos, tan, asin, acos, atan, atan2, abs, sqrt, exp, ln, log10, pow, min, max, clamp, floor, ceil, round, sign, lerp, smoothstep";
+                        const help = qsTr("Enter the expression for this value, or leave empty to disable it.") + "\n" +
+                                     qsTr("Operators: %1").arg("+ - * / % ^ ( )") + "\n" +
+                                     qsTr("Available variables: %1").arg(variables) + "\n" +
+                                     qsTr("Functions: %1").arg(functions) + "\n" +
+                                     qsTr("Other keyframed values can be used by their name, e.g. %1").arg("Fov, VideoSpeed");
+                        const dlg = window.messageBox(Modal.NoIcon, help, [
+                            { text: qsTr("Ok"), accent: true, clicked: function() {
+                                const err = controller.set_keyframe_expression(root.keyframe, dlg.mainColumn.children[1].text);
+                                if (err) {
+                                    window.messageBox(Modal.Error, qsTr("Invalid expression: %1").arg(err), [ { "text": qsTr("Ok") } ]);
+                                    return false;
+                                }
+                            } },
+                            { text: qsTr("Cancel") },
+                        ]);
+                        const tf = Qt.createComponent("TextField.qml").createObject(dlg.mainColumn, { text: controller.keyframe_expression(root.keyframe), width: 300 * dpiScale });
+                        tf.anchors.horizontalCenter = dlg.mainColumn.horizontalCenter;
+                    }
+                }
+                Action {
+                    enabled: root.keyframe.length > 0;
+                    text: qsTr("Bind to telemetry...");
+                    onTriggered: {
+                        const names = { "GpsSpeed": qsTr("GPS speed (km/h)"), "GpsAltitude": qsTr("Altitude (m)"), "LensPosition": qsTr("Lens position"), "GyroMagnitude": qsTr("Gyro rotation speed (°/s)") };
+                        const channels = controller.available_telemetry_channels();
+                        if (!channels.length) {
+                            window.messageBox(Modal.Warning, qsTr("This file doesn't contain any usable telemetry data."), [ { "text": qsTr("Ok") } ]);
+                            return;
+                        }
+                        const current = JSON.parse(controller.keyframe_telemetry_binding(root.keyframe) || "null");
+                        const dlg = window.messageBox(Modal.NoIcon, qsTr("Select the telemetry channel and the mapping curve.\nThe curve is a list of `telemetry value:parameter value` points, e.g. `0:%1, 50:%2`. Leave empty to remove the binding.").arg(root.value.toFixed(2)).arg((root.value * 0.5).toFixed(2)), [
+                            { text: qsTr("Ok"), accent: true, clicked: function() {
+                                const curveText = tf.text.trim();
+                                if (!curveText) {
+                                    controller.set_keyframe_telemetry_binding(root.keyframe, "");
+                                    return;
+                                }
+                                const curve = curveText.split(",").map(x => x.split(":").map(y => parseFloat(y.trim())));
+                                if (curve.some(x => x.length != 2 || isNaN(x[0]) || isNaN(x[1]))) {
+                                    window.messageBox(Modal.Error, qsTr("Invalid curve: %1").arg(curveText), [ { "text": qsTr("Ok") } ]);
+                                    return false;
+                                }
+                                const err = controller.set_keyframe_telemetry_binding(root.keyframe, JSON.stringify({ channel: channels[cb.currentIndex], curve: curve, smoothing_ms: nf.value }));
+                                if (err) {
+                                    window.messageBox(Modal.Error, err, [ { "text": qsTr("Ok") } ]);
+                                    return false;
+                                }
+                            } },
+                            { text: qsTr("Cancel") },
+                        ]);
+                        const cb = Qt.createComponent("ComboBox.qml").createObject(dlg.mainColumn, { model: channels.map(x => names[x] || x), width: 300 * dpiScale });
+                        cb.anchors.horizontalCenter = dlg.mainColumn.horizontalCenter;
+                        if (current) cb.currentIndex = Math.max(0, channels.indexOf(current.channel));
+                        const tf = Qt.createComponent("TextField.qml").createObject(dlg.mainColumn, { text: current? current.curve.map(x => x[0] + ":" + x[1]).join(", ") : "", width: 300 * dpiScale });
+                        tf.anchors.horizontalCenter = dlg.mainColumn.horizontalCenter;
+                        const nf = Qt.createComponent("NumberField.qml").createObject(dlg.mainColumn, { precision: 0, unit: "ms", value: current? current.smoothing_ms : 1000, width: 300 * dpiScale });
+                        nf.anchors.horizontalCenter = dlg.mainColumn.horizontalCenter;
+                    }
+                }
             }
         }
         ContextMenuLoader {
@@ -393,16 +393,40 @@ Item {
                                 checkable: true;
                                 onTriggered: keyframeContextMenu.updateEasing();
                             }
+                            Action {
+                                id: bezierEase;
+                                text: qsTr("Bezier ease");
+                                checkable: true;
+                                onTriggered: {
+                                    const handle = { "influence": 1/3, "speed": 0.0 };
+                                    const handles = bezierEase.checked? { "in": easeIn.checked? handle : null, "out": easeOut.checked? handle : null } : { };
+                                    controller.set_keyframe_bezier_handles(keyframeContextMenu.pressedKeyframe, keyframeContextMenu.pressedKeyframeTs, JSON.stringify(handles));
+                                }
+                            }
+                            Action {
+                                text: qsTr("Edit Bezier handles...");
+                                onTriggered: root.editBezierHandles(keyframeContextMenu.pressedKeyframe, keyframeContextMenu.pressedKeyframeTs);
+                            }
+                            Action {
+                                id: holdKeyframe;
+                                text: qsTr("Hold");
+                                checkable: true;
+                                onTriggered: keyframeContextMenu.updateEasing();
+                            }
                             function updateEasingMenu() {
                                 let e = controller.keyframe_easing(pressedKeyframe, pressedKeyframeTs);
                                 easeIn.checked  = e == "EaseIn"  || e == "EaseInOut";
                                 easeOut.checked = e == "EaseOut" || e == "EaseInOut";
+                                holdKeyframe.checked = e == "Hold";
+                                const handles = JSON.parse(controller.keyframe_bezier_handles(pressedKeyframe, pressedKeyframeTs) || "{}");
+                                bezierEase.checked = !!(handles.in || handles.out);
                             }
                             function updateEasing() {
                                 let e = "NoEasing";
                                 if (easeIn.checked) e = "EaseIn";
                                 if (easeOut.checked) e = "EaseOut";
                                 if (easeIn.checked && easeOut.checked) e = "EaseInOut";
+                                if (holdKeyframe.checked) e = "Hold";
                                 controller.set_keyframe_easing(pressedKeyframe, pressedKeyframeTs, e);
                             }
                         }
@@ -692,6 +716,12 @@ Item {
                             root.cleanupTrimRanges();
                         }
                     }
+                    Action {
+                        enabled: trimRangeMenu.currentTrimRange != -1;
+                        iconName: "settings";
+                        text: qsTr("Settings for this range...");
+                        onTriggered: root.editTrimRangeOverrides(trimRangeMenu.currentTrimRange);
+                    }
                     Action {
                         enabled: root.trimActive;
                         iconName: "close;#f67575";
@@ -706,6 +736,26 @@ Item {
                         onTriggered: root.restrictTrim = !root.restrictTrim;
                     }
                 }
+                Menu {
+                    font.pixelSize: 11.5 * dpiScale;
+                    title: qsTr("Keyframes");
+                    Action {
+                        text: qsTr("Export keyframes...");
+                        onTriggered: keyframesExportDialog.open2();
+                    }
+                    Action {
+                        text: qsTr("Import keyframes...");
+                        onTriggered: keyframesImportDialog.open2();
+                    }
+                    Action {
+                        text: qsTr("Copy keyframes");
+                        onTriggered: controller.copy_keyframes("");
+                    }
+                    Action {
+                        text: qsTr("Paste keyframes...");
+                        onTriggered: root.askKeyframeTiming((offset, scale) => controller.paste_keyframes(offset, scale));
+                    }
+                }
                 QQC.MenuSeparator { verticalPadding: 5 * dpiScale; }
                 Menu {
                     font.pixelSize: 11.5 * dpiScale;
@@ -904,6 +954,101 @@ Item {
         }
     }
 
+    function askKeyframeTiming(cb: var) {
+        const dlg = window.messageBox(Modal.NoIcon, qsTr("Imported keyframe timestamps will be multiplied by the time scale and shifted by the offset."), [
+            { text: qsTr("Ok"), accent: true, clicked: function() { cb(offsetField.value, scaleField.value); } },
+            { text: qsTr("Cancel") },
+        ]);
+        const offsetField = Qt.createComponent("NumberField.qml").createObject(dlg.mainColumn, { precision: 3, unit: qsTr("ms"), value: 0.0, width: 150 * dpiScale });
+        offsetField.anchors.horizontalCenter = dlg.mainColumn.horizontalCenter;
+        const scaleField = Qt.createComponent("NumberField.qml").createObject(dlg.mainColumn, { precision: 3, unit: "x", value: 1.0, from: 0.001, width: 150 * dpiScale });
+        scaleField.anchors.horizontalCenter = dlg.mainColumn.horizontalCenter;
+    }
+    function editBezierHandles(keyframe: string, timestamp: real) {
+        let handles = {};
+        try { handles = JSON.parse(controller.keyframe_bezier_handles(keyframe, timestamp) || "{}"); } catch (e) { }
+        const fields = [];
+        const dlg = window.messageBox(Modal.NoIcon, qsTr("Influence is the part of the segment covered by the handle, speed is the slope relative to the linear interpolation (1 = linear, 0 = flat). Unchecked handles use the keyframe easing."), [
+            { text: qsTr("Ok"), accent: true, clicked: function() {
+                const result = {};
+                for (const [key, check, influence, speed] of fields) {
+                    if (check.checked) {
+                        result[key] = { "influence": influence.value / 100.0, "speed": speed.value };
+                    }
+                }
+                controller.set_keyframe_bezier_handles(keyframe, timestamp, JSON.stringify(result));
+            } },
+            { text: qsTr("Cancel") },
+        ]);
+        const addHandle = function(key: string, text: string) {
+            const handle = handles[key];
+            const check = Qt.createComponent("CheckBox.qml").createObject(dlg.mainColumn, { text: text, checked: !!handle });
+            const influence = Qt.createComponent("NumberField.qml").createObject(dlg.mainColumn, { precision: 0, unit: "%", from: 0, to: 100, value: handle? handle.influence * 100 : 33, width: 200 * dpiScale });
+            const speed = Qt.createComponent("NumberField.qml").createObject(dlg.mainColumn, { precision: 2, unit: "x", from: -5, to: 5, value: handle? handle.speed : 0, width: 200 * dpiScale });
+            for (const field of [influence, speed]) {
+                field.anchors.horizontalCenter = dlg.mainColumn.horizontalCenter;
+                field.enabled = Qt.binding(() => check.checked);
+            }
+            fields.push([key, check, influence, speed]);
+        };
+        addHandle("in",  qsTr("Incoming handle"));
+        addHandle("out", qsTr("Outgoing handle"));
+    }
+    function editTrimRangeOverrides(index: int) {
+        let overrides = {};
+        try { overrides = JSON.parse(controller.get_trim_range_overrides(index)); } catch (e) { }
+        const has = (key) => overrides[key] !== undefined && overrides[key] !== null;
+        const fields = [];
+        const dlg = window.messageBox(Modal.NoIcon, qsTr("Settings used only in this trim range. Unchecked values use the global settings."), [
+            { text: qsTr("Ok"), accent: true, clicked: function() {
+                for (const [key, check, field] of fields) {
+                    if (check.checked) {
+                        overrides[key] = key == "smoothing_method"? field.currentText : field.value;
+                    } else {
+                        delete overrides[key];
+                    }
+                }
+                controller.set_trim_range_overrides(index, JSON.stringify(overrides));
+            } },
+            { text: qsTr("Cancel") },
+        ]);
+        const addField = function(key: string, text: string, component: string, props: var) {
+            const check = Qt.createComponent("CheckBox.qml").createObject(dlg.mainColumn, { text: text, checked: has(key) });
+            const field = Qt.createComponent(component).createObject(dlg.mainColumn, props);
+            field.width = 200 * dpiScale;
+            field.anchors.horizontalCenter = dlg.mainColumn.horizontalCenter;
+            field.enabled = Qt.binding(() => check.checked);
+            fields.push([key, check, field]);
+            return field;
+        };
+        const algs = controller.get_smoothing_algs();
+        const method = addField("smoothing_method", qsTr("Smoothing method"), "ComboBox.qml", { model: algs });
+        method.currentIndex = Math.max(0, algs.indexOf(overrides.smoothing_method));
+        addField("horizon_lock_amount", qsTr("Horizon lock amount"), "NumberField.qml", { precision: 0, unit: "%", from: 0, to: 100, value: has("horizon_lock_amount")? overrides.horizon_lock_amount : 100 });
+        addField("horizon_lock_roll",   qsTr("Horizon roll"),        "NumberField.qml", { precision: 1, unit: "°", from: -180, to: 180, value: has("horizon_lock_roll")? overrides.horizon_lock_roll : 0 });
+        addField("fov",                 qsTr("FOV"),                 "NumberField.qml", { precision: 2, from: 0.1, to: 3, value: has("fov")? overrides.fov : 1 });
+        addField("transition_ms",       qsTr("Transition"),          "NumberField.qml", { precision: 0, unit: qsTr("ms"), from: 0, to: 5000, value: has("transition_ms")? overrides.transition_ms : 500 });
+    }
+    FileDialog {
+        id: keyframesExportDialog;
+        fileMode: FileDialog.SaveFile;
+        defaultSuffix: "json";
+        title: qsTr("Export keyframes");
+        nameFilters: Qt.platform.os == "android"? undefined : [qsTr("Keyframes") + " (*.json)"];
+        type: "keyframes";
+        onAccepted: controller.export_keyframes(selectedFile, "");
+    }
+    FileDialog {
+        id: keyframesImportDialog;
+        title: qsTr("Import keyframes");
+        nameFilters: Qt.platform.os == "android"? undefined : [qsTr("Keyframes") + " (*.json *.gyroflow *.csv *.txt)"];
+        type: "keyframes";
+        onAccepted: {
+            const url = selectedFile;
+            root.askKeyframeTiming((offset, scale) => controller.import_keyframes(url, offset, scale));
+        }
+    }
+
     property bool editingSyncPoint: false;
     property real additionalHeight: editingSyncPoint? 35 : 0;
     Ease on additionalHeight { }
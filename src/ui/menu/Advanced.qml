@@ -77,7 +77,7 @@ MenuItem {
         text: qsTr("Background mode");
         ComboBox {
             id: backgroundMode;
-            model: [QT_TRANSLATE_NOOP("Popup", "Solid color"), QT_TRANSLATE_NOOP("Popup", "Repeat edge pixels"), QT_TRANSLATE_NOOP("Popup", "Mirror edge pixels"), QT_TRANSLATE_NOOP("Popup", "Margin with feather")];
+            model: [QT_TRANSLATE_NOOP("Popup", "Solid color"), QT_TRANSLATE_NOOP("Popup", "Repeat edge pixels"), QT_TRANSLATE_NOOP("Popup", "Mirror edge pixels"), QT_TRANSLATE_NOOP("Popup", "Margin with feather"), QT_TRANSLATE_NOOP("Popup", "Fill from neighbouring frames")];
             font.pixelSize: 12 * dpiScale;
             width: parent.width;
             currentIndex: 0;
@@ -86,9 +86,10 @@ MenuItem {
     }
     Column {
         width: parent.width;
-        visible: backgroundMode.currentIndex == 3;
+        visible: backgroundMode.currentIndex == 3 || backgroundMode.currentIndex == 4;
         Label {
             text: qsTr("Margin");
+            visible: backgroundMode.currentIndex == 3;
             SliderWithField {
                 id: marginPixels;
                 value: 0.20;
@@ -120,9 +121,13 @@ MenuItem {
             }
         }
     }
+    InfoMessageSmall {
+        show: backgroundMode.currentIndex == 4;
+        text: qsTr("The empty areas are filled with the previous and next frames reprojected to the current camera position. This is only done on the CPU, so it's slower, and the GPU preview doesn't show it.");
+    }
     Label {
         position: Label.LeftPosition;
-        visible: backgroundMode.currentIndex == 0;
+        visible: backgroundMode.currentIndex == 0 || backgroundMode.currentIndex == 4;
         text: qsTr("Render background");
 
         TextField {
@@ -88,6 +88,8 @@ MenuItem {
         property alias metadataComment: metadataComment.text;
         property alias audioCodec: audioCodec.currentIndex;
         property alias interpolationMethod: interpolationMethod.currentIndex;
+        property alias parallelSegments: parallelSegments.value;
+        property alias resumableRenders: resumableRenders.checked;
         property alias preserveOutputSettings: preserveOutputSettings.checked;
         property alias preserveOutputPath: preserveOutputPath.checked;
     }
@@ -135,7 +137,24 @@ MenuItem {
             pad_with_black:        padWithBlack.checked,
             export_trims_separately: exportTrimsSeparately.checked,
             audio_codec:           audioCodec.currentText,
-            interpolation:         interpolationMethod.currentText
+            interpolation:         interpolationMethod.currentText,
+            parallel_segments:     parallelSegments.value,
+            resumable:             resumableRenders.checked,
+            comparison_layout:     comparisonLayout.currentText,
+            comparison_labels:     comparisonLabels.checked,
+            overlays: {
+                timecode:     overlayTimecode.checked,
+                frame_number: overlayFrameNumber.checked,
+                horizon:      overlayHorizon.checked,
+                gyro_rates:   overlayGyroRates.checked,
+                gps_speed:    overlayGpsSpeed.checked,
+                fov:          overlayFov.checked,
+                crop_rect:    overlayCropRect.checked,
+                features:     overlayFeatures.checked,
+                optical_flow: overlayOpticalFlow.checked,
+                position:     overlayPosition.currentText,
+                text_size:    overlayTextSize.value
+            }
         };
     }
 
@@ -232,6 +251,24 @@ MenuItem {
             if (output.hasOwnProperty("export_trims_separately")) exportTrimsSeparately.checked = output.export_trims_separately;
             if (output.hasOwnProperty("audio_codec"))           Util.setComboValue(audioCodec, output.audio_codec);
             if (output.hasOwnProperty("interpolation"))         Util.setComboValue(interpolationMethod, output.interpolation);
+            if (output.hasOwnProperty("parallel_segments"))     parallelSegments.value      = +output.parallel_segments;
+            if (output.hasOwnProperty("resumable"))             resumableRenders.checked    = output.resumable;
+            if (output.hasOwnProperty("comparison_layout"))     Util.setComboValue(comparisonLayout, output.comparison_layout);
+            if (output.hasOwnProperty("comparison_labels"))     comparisonLabels.checked    = output.comparison_labels;
+            if (output.hasOwnProperty("overlays")) {
+                const o = output.overlays;
+                overlayTimecode.checked    = !!o.timecode;
+                overlayFrameNumber.checked = !!o.frame_number;
+                overlayHorizon.checked     = !!o.horizon;
+                overlayGyroRates.checked   = !!o.gyro_rates;
+                overlayGpsSpeed.checked    = !!o.gps_speed;
+                overlayFov.checked         = !!o.fov;
+                overlayCropRect.checked    = !!o.crop_rect;
+                overlayFeatures.checked    = !!o.features;
+                overlayOpticalFlow.checked = !!o.optical_flow;
+                if (o.position)  Util.setComboValue(overlayPosition, o.position);
+                if (o.text_size) overlayTextSize.value = +o.text_size;
+            }
             if (output.hasOwnProperty("metadata")) {
                 metadataComment.text = output.metadata.comment || "";
             }
@@ -552,6 +589,30 @@ MenuItem {
             width: parent.width;
             Component.onCompleted: contentItem.wrapMode = Text.WordWrap;
         }
+        Label {
+            position: Label.LeftPosition;
+            text: qsTr("Parallel segments");
+
+            NumberField {
+                id: parallelSegments;
+                width: parent.width;
+                height: 25 * dpiScale;
+                value: 1;
+                defaultValue: 1;
+                from: 1;
+                to: 32;
+                precision: 0;
+                tooltip: qsTr("Split the video at keyframes into this many segments, render them at the same time and join them losslessly.\nOnly for .mp4 and .mov outputs.");
+            }
+        }
+        CheckBox {
+            id: resumableRenders;
+            text: qsTr("Resume interrupted renders");
+            checked: false;
+            width: parent.width;
+            tooltip: qsTr("Render in segments and keep the progress next to the output file, so a cancelled or crashed render continues from the last completed segment.\nOnly for .mp4 and .mov outputs.");
+            Component.onCompleted: contentItem.wrapMode = Text.WordWrap;
+        }
         Label {
             position: Label.LeftPosition;
             text: qsTr("Audio codec");
@@ -575,6 +636,70 @@ MenuItem {
                 currentIndex: 2;
             }
         }
+        Label {
+            position: Label.LeftPosition;
+            text: qsTr("Comparison layout");
+            ComboBox {
+                id: comparisonLayout;
+                model: ["None", "Side by side", "Top and bottom", "Split"];
+                font.pixelSize: 12 * dpiScale;
+                width: parent.width;
+                currentIndex: 0;
+                tooltip: qsTr("Render the original and stabilized video together in a single file.\nSide by side and top and bottom double the output size.");
+            }
+        }
+        CheckBox {
+            id: comparisonLabels;
+            text: qsTr("Add labels to the comparison");
+            checked: true;
+            visible: comparisonLayout.currentIndex > 0;
+        }
+        Label {
+            position: Label.TopPosition;
+            text: qsTr("Burn-in overlays");
+            Flow {
+                width: parent.width;
+                spacing: 5 * dpiScale;
+                CheckBox { id: overlayTimecode;    text: qsTr("Timecode"); }
+                CheckBox { id: overlayFrameNumber; text: qsTr("Frame number"); }
+                CheckBox { id: overlayGyroRates;   text: qsTr("Gyro rates"); }
+                CheckBox { id: overlayGpsSpeed;    text: qsTr("GPS speed"); }
+                CheckBox { id: overlayFov;         text: qsTr("FOV"); }
+                CheckBox { id: overlayHorizon;     text: qsTr("Horizon line"); tooltip: qsTr("Uses the gravity vectors when they are available and enabled"); }
+                CheckBox { id: overlayCropRect;    text: qsTr("Crop rectangle"); tooltip: qsTr("Part of the frame which is always covered by the image"); }
+                CheckBox { id: overlayFeatures;    text: qsTr("Detected features"); tooltip: qsTr("Features used for the synchronization, useful for debugging the sync"); }
+                CheckBox { id: overlayOpticalFlow; text: qsTr("Optical flow"); tooltip: qsTr("Optical flow used for the synchronization, useful for debugging the sync"); }
+            }
+        }
+        Label {
+            id: overlayPositionLabel;
+            position: Label.LeftPosition;
+            text: qsTr("Overlay position");
+            visible: overlayTimecode.checked || overlayFrameNumber.checked || overlayGyroRates.checked || overlayGpsSpeed.checked || overlayFov.checked;
+            ComboBox {
+                id: overlayPosition;
+                model: ["Top left", "Top right", "Bottom left", "Bottom right"];
+                font.pixelSize: 12 * dpiScale;
+                width: parent.width;
+                currentIndex: 0;
+            }
+        }
+        Label {
+            position: Label.LeftPosition;
+            text: qsTr("Overlay text size");
+            visible: overlayPositionLabel.visible;
+            NumberField {
+                id: overlayTextSize;
+                width: parent.width;
+                height: 25 * dpiScale;
+                value: 2.5;
+                from: 0.5;
+                to: 20;
+                precision: 1;
+                unit: "%";
+                tooltip: qsTr("Height of the text relative to the frame height");
+            }
+        }
         Label {
             position: Label.TopPosition;
             text: qsTr("Device for rendering");
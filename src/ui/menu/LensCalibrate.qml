@@ -22,6 +22,10 @@ MenuItem {
     property alias infoList: infoList;
     property alias maxSharpness: maxSharpness;
     property var calibrationInfo: ({});
+    property var report: ({});
+    property var jointSources: [];
+    property bool estimatingRs: false;
+    property string rsEstimate: "";
 
     property int videoWidth: 0;
     property int videoHeight: 0;
@@ -53,6 +57,19 @@ MenuItem {
             yStretch.valueChanged();
         }
     }
+    function updateReport() {
+        const json = controller.calibration_report();
+        calib.report = json? JSON.parse(json) : ({});
+    }
+    function dropOutlierFrames() {
+        for (const f of calib.report.frames || []) {
+            if (f.is_outlier) controller.remove_calibration_point(f.timestamp_us);
+        }
+        calib.updateReport();
+    }
+    function updatePattern() {
+        controller.set_calibration_pattern(calibTarget.currentIndex, patternColumns.value, patternRows.value);
+    }
     function resetMetadata() {
         calib.calibrationInfo = {
             "calibrated_by": calib.calibrationInfo.calibrated_by || settings.value("calibratedBy", "") || controller.get_username(),
@@ -122,6 +139,17 @@ MenuItem {
             shutterCb.checked = Math.abs(rolling_shutter) > 0;
             bottomToTop.checked = rolling_shutter < 0;
         }
+        function onCalib_rolling_shutter_estimated(frame_readout_time: real, horizontal: bool, rms: real, rms_global: real) {
+            calib.estimatingRs = false;
+            calib.calibrationInfo.horizontal_rs = horizontal;
+            shutter.value = Math.abs(frame_readout_time);
+            shutterCb.checked = Math.abs(frame_readout_time) > 0;
+            bottomToTop.checked = frame_readout_time < 0;
+            calib.rsEstimate = qsTr("Reprojection error: %1 px (global shutter: %2 px)").arg(rms.toFixed(3)).arg(rms_global.toFixed(3)) + (horizontal? "\n" + qsTr("Sensor is read horizontally") : "");
+        }
+        function onError() {
+            calib.estimatingRs = false;
+        }
     }
 
     Settings {
@@ -132,6 +160,24 @@ MenuItem {
         property alias calib_maxSharpness: maxSharpness.value;
     }
 
+    FileDialog {
+        id: jointDialog;
+        fileMode: FileDialog.SaveFile;
+        defaultSuffix: "json";
+        title: qsTr("Export lens profiles");
+        nameFilters: Qt.platform.os == "android"? undefined : [qsTr("Lens profiles") + " (*.json)"];
+        type: "output-preset";
+        onAccepted: calib.exportProfile((upload) => controller.export_joint_lens_profiles(selectedFile, calib.calibrationInfo, upload));
+    }
+    FileDialog {
+        id: reportDialog;
+        fileMode: FileDialog.SaveFile;
+        defaultSuffix: "json";
+        title: qsTr("Export calibration report");
+        nameFilters: Qt.platform.os == "android"? undefined : ["JSON (*.json)"];
+        type: "output-preset";
+        onAccepted: controller.export_calibration_report(selectedFile);
+    }
     FileDialog {
         id: fileDialog;
         fileMode: FileDialog.SaveFile;
@@ -140,15 +186,22 @@ MenuItem {
         title: qsTr("Export lens profile");
         nameFilters: Qt.platform.os == "android"? undefined : [qsTr("Lens profiles") + " (*.json)"];
         type: "output-preset";
-        onAccepted: {
-            if (uploadProfile.checked) {
-                messageBox(Modal.Info, qsTr("By uploading your lens profile to the database, you agree to publish and distribute it with Gyroflow under GPLv3 terms.\nDo you want to submit your profile?"), [
-                    { text: qsTr("Yes"), accent: true, clicked: () => controller.export_lens_profile(selectedFile, calib.calibrationInfo, true) },
-                    { text: qsTr("No"),                clicked: () => controller.export_lens_profile(selectedFile, calib.calibrationInfo, false) }
-                ]);
-            } else {
-                controller.export_lens_profile(selectedFile, calib.calibrationInfo, uploadProfile.checked);
-            }
+        onAccepted: calib.exportProfile((upload) => controller.export_lens_profile(selectedFile, calib.calibrationInfo, upload));
+    }
+    // Asks for the consent before uploading to the database, and doesn't allow uploading when the quality report found problems
+    function exportProfile(save) {
+        if (uploadProfile.checked && calib.report.suggestions && calib.report.suggestions.some(x => x.kind != "few_frames")) {
+            messageBox(Modal.Warning, qsTr("The calibration quality report found some problems:\n%1\n\nPlease fix them before uploading the profile to the database.").arg(calib.report.suggestions.map(x => "- " + x.message).join("\n")), [
+                { text: qsTr("Save without uploading"), accent: true, clicked: () => save(false) },
+                { text: qsTr("Cancel") }
+            ]);
+        } else if (uploadProfile.checked) {
+            messageBox(Modal.Info, qsTr("By uploading your lens profile to the database, you agree to publish and distribute it with Gyroflow under GPLv3 terms.\nDo you want to submit your profile?"), [
+                { text: qsTr("Yes"), accent: true, clicked: () => save(true) },
+                { text: qsTr("No"),                clicked: () => save(false) }
+            ]);
+        } else {
+            save(false);
         }
     }
 
@@ -168,6 +221,30 @@ MenuItem {
             });
         }
     }
+    InfoMessageSmall {
+        show: !!calib.report.suggestions && calib.report.suggestions.length > 0 && infoList.rms > 0;
+        text: (calib.report.suggestions || []).map(x => x.message).join("\n");
+    }
+    Row {
+        visible: infoList.rms > 0 && !!calib.report.frames;
+        anchors.horizontalCenter: parent.horizontalCenter;
+        spacing: 10 * dpiScale;
+        LinkButton {
+            text: qsTr("Drop outlier frames");
+            visible: (calib.report.frames || []).some(x => x.is_outlier);
+            onClicked: calib.dropOutlierFrames();
+        }
+        LinkButton {
+            text: qsTr("Export report");
+            onClicked: reportDialog.open2();
+        }
+    }
+    CheckBox {
+        text: qsTr("Show coverage heatmap");
+        visible: infoList.rms > 0 && !!calib.report.frames;
+        checked: false;
+        onCheckedChanged: controller.set_calibration_show_coverage(checked);
+    }
 
     Button {
         id: autoCalibBtn;
@@ -176,6 +253,9 @@ MenuItem {
         iconName: "spinner"
         anchors.horizontalCenter: parent.horizontalCenter;
         onClicked: {
+            calib.updatePattern();
+            controller.set_calibration_distortion_model(distortionModel.ids[distortionModel.currentIndex]);
+            controller.set_calibration_use_opencv(!builtinSolver.checked);
             controller.start_autocalibrate(maxPoints.value, everyNthFrame.value, iterations.value, maxSharpness.value, -1, noMarker.checked);
         }
     }
@@ -295,6 +375,21 @@ MenuItem {
                 }
             }
         }
+        LinkButton {
+            text: qsTr("Estimate from the calibration target");
+            enabled: infoList.rms > 0 && infoList.rms < 100 && !calib.estimatingRs;
+            anchors.horizontalCenter: parent.horizontalCenter;
+            onClicked: {
+                calib.estimatingRs = true;
+                calib.rsEstimate = "";
+                controller.estimate_calibration_rolling_shutter();
+            }
+        }
+        InfoMessageSmall {
+            show: calib.rsEstimate.length > 0;
+            type: InfoMessage.Info;
+            text: calib.rsEstimate;
+        }
     }
     Item { width: 1; height: 1; }
     Button {
@@ -314,6 +409,50 @@ MenuItem {
         text: qsTr("Upload lens profile to the database");
         checked: true;
     }
+    CheckBoxWithContent {
+        id: jointCb;
+        text: qsTr("Joint calibration of multiple videos");
+        Component.onCompleted: calib.jointSources = controller.calibration_sources();
+
+        BasicText {
+            width: parent.width;
+            wrapMode: Text.WordWrap;
+            text: calib.jointSources.length > 0? calib.jointSources.map(x => "- " + x.name + " (" + x.width + "x" + x.height + (x.focal_length? ", " + x.focal_length + " mm" : "") + ", " + qsTr("%1 frames").arg(x.frames) + ")").join("\n")
+                                               : qsTr("Calibrate each video (different resolutions, crops or focal lengths of the same camera) and add it to the list. The lens model will be solved for all of them together.");
+        }
+        Row {
+            anchors.horizontalCenter: parent.horizontalCenter;
+            spacing: 10 * dpiScale;
+            LinkButton {
+                text: qsTr("Add current video");
+                enabled: infoList.rms > 0 && infoList.rms < 100;
+                onClicked: {
+                    const name = calib.calibrationInfo.camera_setting || (calib.videoWidth + "x" + calib.videoHeight);
+                    controller.add_calibration_source(name, flcb.checked? fl.value : 0);
+                    calib.jointSources = controller.calibration_sources();
+                }
+            }
+            LinkButton {
+                text: qsTr("Clear");
+                enabled: calib.jointSources.length > 0;
+                onClicked: {
+                    controller.clear_calibration_sources();
+                    calib.jointSources = controller.calibration_sources();
+                }
+            }
+        }
+        Button {
+            text: qsTr("Export joint lens profiles");
+            enabled: calib.jointSources.length > 1;
+            iconName: "save";
+            anchors.horizontalCenter: parent.horizontalCenter;
+            onClicked: {
+                list.commitAll();
+                jointDialog.selectedFile = controller.export_lens_profile_filename(calib.calibrationInfo);
+                jointDialog.open2();
+            }
+        }
+    }
     AdvancedSection {
         Label {
             position: Label.LeftPosition;
@@ -363,10 +502,22 @@ MenuItem {
 
             ComboBox {
                 id: digitalLens;
+                function stretchParams(x: real) {
+                    return [
+                        { "label": QT_TR_NOOP("Horizontal stretch"), "from": 10,   "to": 250, "scale": 100, "value": x,   "unit": "%" },
+                        { "label": QT_TR_NOOP("Vertical stretch"),   "from": 10,   "to": 250, "scale": 100, "value": 100, "unit": "%" },
+                        { "label": QT_TR_NOOP("Curve a"),            "from": -100, "to": 100, "scale": 100, "value": 0,   "unit": "" },
+                        { "label": QT_TR_NOOP("Curve b"),            "from": -100, "to": 100, "scale": 100, "value": 0,   "unit": "" }
+                    ];
+                }
                 property var lenses: [
                     ["None", ""],
                     ["GoPro Superview", "gopro_superview"],
-                    ["GoPro Hyperview", "gopro_hyperview"]/*,
+                    ["GoPro Hyperview", "gopro_hyperview"],
+                    ["Non-linear stretch", "nonlinear_stretch", stretchParams(133.33)],
+                    ["Anamorphic 1.33x", "nonlinear_stretch", stretchParams(75.19)],
+                    ["Anamorphic 1.5x",  "nonlinear_stretch", stretchParams(66.67)],
+                    ["Anamorphic 1.8x",  "nonlinear_stretch", stretchParams(55.56)]/*,
                     ["Stretch", "digital_stretch", [
                         { "label": QT_TR_NOOP("X"), "from": 10, "to": 200, "scale": 100, "value": 100, "unit": "%" },
                         { "label": QT_TR_NOOP("Y"), "from": 10, "to": 200, "scale": 100, "value": 100, "unit": "%" }
@@ -381,6 +532,8 @@ MenuItem {
                     if (lenses[currentIndex][2]) {
                         digitalParams.model = lenses[currentIndex][2];
                         digitalParamsCol.visible = true;
+                        calib.calibrationInfo.digital_lens_params = lenses[currentIndex][2].map(x => x.value / x.scale);
+                        calib.calibrationInfo.digital_lens_params.forEach((v, i) => controller.set_digital_lens_param(i, v));
                     } else {
                         digitalParams.model = [];
                         digitalParamsCol.visible = false;
@@ -617,12 +770,99 @@ MenuItem {
             width: parent.width;
             onCheckedChanged: calib.calibrationInfo.global_shutter = checked;
         }
+        Label {
+            position: Label.LeftPosition;
+            text: qsTr("Distortion model");
+            ComboBox {
+                id: distortionModel;
+                property var ids: ["opencv_fisheye", "opencv_standard", "poly3", "poly5", "ptlens", "kannala_brandt"];
+                model: ["OpenCV Fisheye", "OpenCV Standard", "Poly3", "Poly5", "PTLens", "Kannala-Brandt"];
+                font.pixelSize: 12 * dpiScale;
+                width: parent.width;
+                currentIndex: 0;
+                onCurrentIndexChanged: controller.set_calibration_distortion_model(ids[currentIndex]);
+            }
+        }
+        InfoMessageSmall {
+            show: distortionModel.currentIndex > 0 && distortionModel.ids[distortionModel.currentIndex] != "kannala_brandt";
+            text: qsTr("Fisheye model is recommended for wide angle lenses. Other models are suitable for rectilinear lenses.");
+        }
+        Label {
+            position: Label.LeftPosition;
+            text: qsTr("Calibration target");
+            enabled: !builtinSolver.checked;
+            ComboBox {
+                id: calibTarget;
+                model: [QT_TRANSLATE_NOOP("Popup", "Chessboard"), QT_TRANSLATE_NOOP("Popup", "ChArUco board"), QT_TRANSLATE_NOOP("Popup", "Asymmetric circle grid")];
+                font.pixelSize: 12 * dpiScale;
+                width: parent.width;
+                currentIndex: 0;
+                onCurrentIndexChanged: {
+                    // Default pattern sizes of the commonly used targets
+                    switch (currentIndex) {
+                        case 0: patternColumns.value = 14; patternRows.value = 8; break;
+                        case 1: patternColumns.value = 10; patternRows.value = 7; break;
+                        case 2: patternColumns.value = 4;  patternRows.value = 11; break;
+                    }
+                    calib.updatePattern();
+                }
+            }
+        }
+        Label {
+            position: Label.LeftPosition;
+            text: calibTarget.currentIndex == 2? qsTr("Circles (columns x rows)") : qsTr("Inner corners (columns x rows)");
+            Row {
+                width: parent.width;
+                spacing: 5 * dpiScale;
+                NumberField {
+                    id: patternColumns;
+                    width: (parent.width - parent.spacing) / 2;
+                    height: 25 * dpiScale;
+                    value: 14;
+                    from: 2;
+                    onValueChanged: calib.updatePattern();
+                }
+                NumberField {
+                    id: patternRows;
+                    width: (parent.width - parent.spacing) / 2;
+                    height: 25 * dpiScale;
+                    value: 8;
+                    from: 2;
+                    onValueChanged: calib.updatePattern();
+                }
+            }
+        }
+        InfoMessageSmall {
+            show: calibTarget.currentIndex == 1;
+            text: qsTr("ChArUco board doesn't need to be fully visible, so you can cover the corners of the frame. The board should use the DICT_4X4_250 dictionary.");
+        }
         CheckBox {
             id: noMarker;
             text: qsTr("Plain chessboard pattern (previous version without dots in the middle)");
             checked: false;
             width: parent.width;
+            visible: calibTarget.currentIndex == 0;
+            enabled: !builtinSolver.checked;
             Component.onCompleted: contentItem.wrapMode = Text.WordWrap;
         }
+        CheckBox {
+            id: builtinSolver;
+            text: qsTr("Use built-in corner detector and solver");
+            checked: !controller.has_opencv();
+            enabled: controller.has_opencv();
+            width: parent.width;
+            onCheckedChanged: {
+                controller.set_calibration_use_opencv(!checked);
+                if (checked) calibTarget.currentIndex = 0;
+            }
+            Component.onCompleted: {
+                contentItem.wrapMode = Text.WordWrap;
+                if (checked) calibTarget.currentIndex = 0;
+            }
+        }
+        InfoMessageSmall {
+            show: builtinSolver.checked;
+            text: qsTr("The built-in detector supports only the chessboard target, with or without the dots in the middle.");
+        }
     }
 }
@@ -28,6 +28,7 @@ MenuItem {
     property string profileName;
     property string profileOriginalJson;
     property string profileChecksum;
+    property bool selfCalibrating: false;
 
     FileDialog {
         id: fileDialog;
@@ -82,6 +83,7 @@ MenuItem {
             profilesUpdateTimer.start();
         }
         function onLens_profile_loaded(json_str: string, filepath: string, checksum: string) {
+            root.selfCalibrating = false;
             if (json_str) {
                 const obj = JSON.parse(json_str);
                 if (obj) {
@@ -99,6 +101,19 @@ MenuItem {
                     if (obj.asymmetrical) lensInfo["Asymmetrical"] = qsTr("Yes");
                     if (obj.distortion_model && obj.distortion_model != "opencv_fisheye") lensInfo["Distortion model"] = obj.distortion_model;
                     if (obj.digital_lens) lensInfo["Digital lens"] = obj.digital_lens;
+                    if (obj.uncertainty) lensInfo["Uncertainty"] = qsTr("Focal length ±%1 px").arg(obj.uncertainty.focal_length.toFixed(1));
+                    if (obj.focus_breathing && obj.focus_breathing.curve && obj.focus_breathing.curve.length > 1) {
+                        lensInfo["Focus breathing"] = obj.focus_breathing.input == "focus_distance"? qsTr("Compensated from focus distance") : qsTr("Compensated from lens position");
+                    }
+                    if (obj.vignetting && obj.vignetting.length >= 3) {
+                        const v = obj.vignetting;
+                        lensInfo["Vignetting"] = qsTr("%1% darker in the corners").arg(((-v[0] - v[1] - v[2]) * 100).toFixed(0));
+                    }
+                    if (obj.chromatic_aberration && obj.chromatic_aberration.length >= 2 && obj.calib_dimension) {
+                        const halfDiagonal = Math.sqrt(obj.calib_dimension.w * obj.calib_dimension.w + obj.calib_dimension.h * obj.calib_dimension.h) / 2;
+                        const ca = obj.chromatic_aberration;
+                        lensInfo["Chromatic aberration"] = qsTr("Red %1 px, blue %2 px in the corners").arg((ca[0] * halfDiagonal).toFixed(2)).arg((ca[1] * halfDiagonal).toFixed(2));
+                    }
 
                     info.model = lensInfo;
 
@@ -156,6 +171,9 @@ MenuItem {
                 Qt.callLater(controller.recompute_threaded);
             }
         }
+        function onError() {
+            root.selfCalibrating = false;
+        }
     }
 
     property int currentVideoAspectRatio: Math.round((root.videoWidth / Math.max(1, root.videoHeight)) * 1000);
@@ -222,6 +240,20 @@ MenuItem {
             }
         }
     }
+    LinkButton {
+        text: qsTr("Estimate from footage");
+        anchors.horizontalCenter: parent.horizontalCenter;
+        enabled: root.videoWidth > 0 && !root.selfCalibrating;
+        onClicked: {
+            root.selfCalibrating = true;
+            controller.self_calibrate_lens();
+        }
+    }
+    InfoMessageSmall {
+        type: InfoMessage.Info;
+        show: root.selfCalibrating;
+        text: qsTr("Estimating the lens from the optical flow and gyro data. The video has to be synchronized first, and footage with lots of rotation and a static scene gives the best results.");
+    }
 
     InfoMessageSmall {
         id: officialInfo;
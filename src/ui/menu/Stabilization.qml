@@ -62,6 +62,13 @@ MenuItem {
             if (typeof stab.lens_correction_amount !== "undefined") {
                 correctionAmount.value = +stab.lens_correction_amount;
             }
+            if (stab.hasOwnProperty("output_projection")) outputProjection.currentIndex = +stab.output_projection;
+            if (stab.hasOwnProperty("spherical")) spherical.checked = !!stab.spherical;
+            if (stab.hasOwnProperty("motion_blur_shutter_angle")) motionBlurAngle.value = +stab.motion_blur_shutter_angle;
+            if (stab.hasOwnProperty("motion_blur_samples")) motionBlurSamples.value = +stab.motion_blur_samples;
+            if (stab.hasOwnProperty("deblur")) deblur.checked = !!stab.deblur;
+            if (stab.hasOwnProperty("deblur_iterations")) deblurIterations.value = +stab.deblur_iterations;
+            if (stab.hasOwnProperty("deblur_exposure_ms")) deblurExposure.value = +stab.deblur_exposure_ms;
 
             const az = +stab.adaptive_zoom_window;
             if (az < -0.9) {
@@ -207,11 +214,17 @@ MenuItem {
         QT_TRANSLATE_NOOP("Stabilization", "Roll angle");
     }
 
+    property bool zoomingSupported: outputProjection.currentIndex == 0 && !spherical.checked;
+
     InfoMessageSmall {
         id: fovWarning;
-        show: fov.value > 1.0 && croppingMode.currentIndex > 0;
+        show: fov.value > 1.0 && croppingMode.currentIndex > 0 && root.zoomingSupported;
         text: qsTr("FOV is greater than 1.0, you may see black borders");
     }
+    InfoMessageSmall {
+        show: !root.zoomingSupported;
+        text: qsTr("Zooming is only available with the rectilinear output projection.");
+    }
 
     Label {
         position: Label.LeftPosition;
@@ -223,6 +236,7 @@ MenuItem {
             value: 1.0;
             defaultValue: 1.0;
             width: parent.width;
+            enabled: !spherical.checked;
             keyframe: "Fov";
             onValueChanged: controller.fov = value;
         }
@@ -431,6 +445,7 @@ MenuItem {
         currentIndex: 1;
         font.pixelSize: 12 * dpiScale;
         width: parent.width;
+        enabled: root.zoomingSupported;
         model: [QT_TRANSLATE_NOOP("Popup", "No zooming"), QT_TRANSLATE_NOOP("Popup", "Dynamic zooming"), QT_TRANSLATE_NOOP("Popup", "Static zoom")];
         Component.onCompleted: currentIndexChanged();
         onCurrentIndexChanged: {
@@ -456,6 +471,7 @@ MenuItem {
             to: 15;
             unit: qsTr("s");
             width: parent.width;
+            enabled: root.zoomingSupported;
             keyframe: "ZoomingSpeed";
             onValueChanged: controller.adaptive_zoom = value;
             onKeyframesEnabledChanged: Qt.callLater(zoomingMethod.adjustMethod);
@@ -478,6 +494,7 @@ MenuItem {
                 // font.pixelSize: 12 * dpiScale;
                 width: parent.width;
                 currentIndex: 0;
+                enabled: root.zoomingSupported;
                 onCurrentIndexChanged: controller.zooming_method = currentIndex;
                 property bool zoomingSpeedKeyframed: adaptiveZoom.keyframesEnabled || (videoSpeed.keyframesEnabled && videoSpeedAffectsSmoothing.checked);
                 function adjustMethod() {
@@ -580,6 +597,109 @@ MenuItem {
         }
     }
 
+    Label {
+        position: Label.LeftPosition;
+        text: qsTr("Output projection");
+        ComboBox {
+            id: outputProjection;
+            model: [QT_TRANSLATE_NOOP("Popup", "Rectilinear"), QT_TRANSLATE_NOOP("Popup", "Fisheye (equidistant)"), QT_TRANSLATE_NOOP("Popup", "Stereographic (little planet)"), QT_TRANSLATE_NOOP("Popup", "Equirectangular")];
+            font.pixelSize: 12 * dpiScale;
+            width: parent.width;
+            currentIndex: 0;
+            enabled: !spherical.checked;
+            onCurrentIndexChanged: controller.output_projection = currentIndex;
+        }
+    }
+    CheckBox {
+        id: spherical;
+        text: qsTr("360° equirectangular video");
+        tooltip: qsTr("Input is a full 360° equirectangular frame. The whole sphere is rotated without any cropping or zooming and the output stays equirectangular.");
+        checked: false;
+        onCheckedChanged: controller.spherical = checked;
+    }
+    InfoMessageSmall {
+        show: outputProjection.currentIndex > 0 && correctionAmount.value < 1.0;
+        text: qsTr("Lens correction strength is only applied to the rectilinear projection.");
+    }
+
+    Label {
+        text: qsTr("Motion blur shutter angle");
+        SliderWithField {
+            id: motionBlurAngle;
+            from: 0;
+            to: 360;
+            value: 0;
+            defaultValue: 0;
+            unit: "°";
+            precision: 0;
+            width: parent.width;
+            onValueChanged: Qt.callLater(() => { controller.motion_blur_shutter_angle = value; });
+        }
+    }
+    Label {
+        position: Label.LeftPosition;
+        text: qsTr("Motion blur samples");
+        visible: motionBlurAngle.value > 0;
+        NumberField {
+            id: motionBlurSamples;
+            width: parent.width;
+            height: 25 * dpiScale;
+            value: 8;
+            defaultValue: 8;
+            from: 2;
+            to: 64;
+            precision: 0;
+            intNoThousandSep: true;
+            onValueChanged: controller.motion_blur_samples = value;
+        }
+    }
+    InfoMessageSmall {
+        show: motionBlurAngle.value > 0;
+        text: qsTr("Motion blur is only visible in the rendered video, the preview shows a single sample.");
+    }
+
+    CheckBox {
+        id: deblur;
+        text: qsTr("Deblur using gyro data");
+        tooltip: qsTr("Removes the camera shake blur from each frame before stabilizing it. The blur is calculated from the gyro motion during the exposure.\nThis is slow and only works on the CPU input frames.");
+        checked: false;
+        onCheckedChanged: controller.deblur = checked;
+    }
+    Column {
+        width: parent.width;
+        visible: deblur.checked;
+        Label {
+            text: qsTr("Deblur iterations");
+            SliderWithField {
+                id: deblurIterations;
+                from: 1;
+                to: 50;
+                value: 10;
+                defaultValue: 10;
+                precision: 0;
+                width: parent.width;
+                onValueChanged: Qt.callLater(() => { controller.deblur_iterations = value; });
+            }
+        }
+        Label {
+            position: Label.LeftPosition;
+            text: qsTr("Exposure time");
+            NumberField {
+                id: deblurExposure;
+                width: parent.width;
+                height: 25 * dpiScale;
+                value: 0;
+                defaultValue: 0;
+                from: 0;
+                to: 1000;
+                precision: 2;
+                unit: qsTr("ms");
+                tooltip: qsTr("0 = use the exposure time from the file metadata (Sony, Insta360).");
+                onValueChanged: controller.deblur_exposure_ms = value;
+            }
+        }
+    }
+
     Label {
         text: qsTr("Video speed");
         SliderWithField {
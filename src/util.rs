@@ -408,6 +408,9 @@ pub fn set_setting(key: &str, value: &str) {
 pub fn copy_to_clipboard(text: QString) {
     cpp!(unsafe [text as "QString"] { QGuiApplication::clipboard()->setText(text); })
 }
+pub fn clipboard_text() -> QString {
+    cpp!(unsafe [] -> QString as "QString" { return QGuiApplication::clipboard()->text(); })
+}
 
 pub fn save_exe_location() {
     if let Ok(exe_path) = std::env::current_exe() {